@@ -24,7 +24,9 @@ use tracing_subscriber::{
     util::SubscriberInitExt as _,
 };
 use transit_model::{
-    configuration,
+    co2, configuration,
+    report::Report,
+    stop_area_clustering::{cluster_stop_areas, StopAreaClusteringConfiguration},
     transfers::{generates_transfers, TransfersConfiguration},
     PrefixConfiguration, Result,
 };
@@ -117,6 +119,59 @@ struct Opt {
     ///     NTFS trip short name is always None
     #[arg(long)]
     read_trip_short_name: bool,
+
+    /// Read the input leniently: the invalid objects are skipped instead of
+    /// failing, and all the problems found are written in this JSON file.
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Compute the journey patterns of the vehicle journeys (same route and
+    /// same stop times) and write them in the output.
+    #[arg(long)]
+    journey_patterns: bool,
+
+    /// Generate the grid calendars of the lines (day types like "Monday to
+    /// Friday" used in line timetables) from the dates of their trips.
+    #[arg(long)]
+    grid_calendars: bool,
+
+    /// Group into stop areas the close stop points with similar names that
+    /// have no parent station.
+    #[arg(long)]
+    cluster_stop_areas: bool,
+
+    /// Maximum distance in meters between a stop point and the nearest other
+    /// stop point of its stop area (with --cluster-stop-areas).
+    #[arg(long, default_value_t = StopAreaClusteringConfiguration::default().max_distance)]
+    cluster_max_distance: f64,
+
+    /// Minimum similarity, between 0 and 1, of the names of 2 stop points of
+    /// the same stop area (with --cluster-stop-areas).
+    #[arg(long, default_value_t = StopAreaClusteringConfiguration::default().min_name_similarity)]
+    cluster_min_name_similarity: f64,
+
+    /// Only report the stop areas that would be created, without modifying
+    /// the data (with --cluster-stop-areas and --cluster-report).
+    #[arg(long)]
+    cluster_report_only: bool,
+
+    /// JSON file where the stop areas created (or suggested with
+    /// --cluster-report-only) are written (with --cluster-stop-areas).
+    #[arg(long)]
+    cluster_report: Option<PathBuf>,
+
+    /// CSV file of emission factors (columns `physical_mode_id` and
+    /// `co2_emission`, in gCO2-eq/km) replacing the CO2 emissions of the
+    /// physical modes.
+    #[arg(long)]
+    emission_factors: Option<PathBuf>,
+
+    /// OpenStreetMap `.osm.pbf` file used to generate the geometries of the
+    /// trips without a shape, following the roads or the rails between their
+    /// stops.
+    #[cfg(feature = "osm")]
+    #[arg(long)]
+    osm: Option<PathBuf>,
 }
 
 fn run(opt: Opt) -> Result<()> {
@@ -141,7 +196,31 @@ fn run(opt: Opt) -> Result<()> {
         read_trip_short_name: opt.read_trip_short_name,
     };
 
-    let model = transit_model::gtfs::Reader::new(configuration).parse(opt.input)?;
+    let reader = transit_model::gtfs::Reader::new(configuration);
+    let model = match opt.report {
+        Some(report_path) => {
+            let (model, report) = reader.parse_with_report(opt.input)?;
+            serde_json::to_writer_pretty(std::fs::File::create(report_path)?, &report)?;
+            model
+        }
+        None => reader.parse(opt.input)?,
+    };
+
+    let model = if opt.cluster_stop_areas {
+        let config = StopAreaClusteringConfiguration {
+            max_distance: opt.cluster_max_distance,
+            min_name_similarity: opt.cluster_min_name_similarity,
+            report_only: opt.cluster_report_only,
+        };
+        let mut report = Report::default();
+        let collections = cluster_stop_areas(model, &config, Some(&mut report))?;
+        if let Some(report_path) = opt.cluster_report {
+            serde_json::to_writer_pretty(std::fs::File::create(report_path)?, &report)?;
+        }
+        transit_model::Model::new(collections)?
+    } else {
+        model
+    };
 
     let model = if opt.ignore_transfers {
         model
@@ -157,6 +236,48 @@ fn run(opt: Opt) -> Result<()> {
         transit_model::Model::new(collections)?
     };
 
+    let model = if opt.journey_patterns {
+        let mut collections = model.into_collections();
+        collections.compute_journey_patterns();
+        transit_model::Model::new(collections)?
+    } else {
+        model
+    };
+
+    let model = if opt.grid_calendars {
+        let mut collections = model.into_collections();
+        collections.generate_grid_calendars();
+        transit_model::Model::new(collections)?
+    } else {
+        model
+    };
+
+    #[cfg(feature = "osm")]
+    let model = match opt.osm {
+        Some(osm_path) => {
+            use transit_model::map_matching::{self, MapMatchingConfiguration};
+            let networks = map_matching::read_osm(osm_path)?;
+            let mut collections = model.into_collections();
+            map_matching::generate_geometries(
+                &mut collections,
+                &networks,
+                &MapMatchingConfiguration::default(),
+            )?;
+            transit_model::Model::new(collections)?
+        }
+        None => model,
+    };
+
+    let model = match opt.emission_factors {
+        Some(emission_factors_path) => {
+            let factors = co2::read_emission_factors(emission_factors_path)?;
+            let mut collections = model.into_collections();
+            co2::set_emission_factors(&mut collections, &factors);
+            transit_model::Model::new(collections)?
+        }
+        None => model,
+    };
+
     match opt.output.extension() {
         Some(ext) if ext == "zip" => {
             transit_model::ntfs::write_to_zip(&model, opt.output, opt.current_datetime)?;
@@ -0,0 +1,21 @@
+use assert_cmd::{cargo_bin, prelude::*};
+use std::process::Command;
+use tempfile::TempDir;
+
+#[test]
+fn test_hrdf2ntfs() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    Command::new(cargo_bin!("hrdf2ntfs"))
+        .arg("--input")
+        .arg("../tests/fixtures/hrdf/input")
+        .arg("--output")
+        .arg(output_dir.path().to_str().unwrap())
+        .arg("--current-datetime")
+        .arg("2019-04-03T17:19:00Z")
+        .assert()
+        .success();
+    assert!(output_dir.path().join("feed_infos.txt").is_file());
+    let model = transit_model::ntfs::read(output_dir).unwrap();
+    assert_eq!(3, model.vehicle_journeys.len());
+    assert_eq!(4, model.stop_points.len());
+}
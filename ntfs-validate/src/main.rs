@@ -0,0 +1,137 @@
+// Copyright 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see
+// <http://www.gnu.org/licenses/>.
+
+use clap::Parser;
+use std::path::PathBuf;
+use tracing::info;
+use tracing_subscriber::{
+    filter::{EnvFilter, LevelFilter},
+    layer::SubscriberExt as _,
+    util::SubscriberInitExt as _,
+};
+use transit_model::{
+    report::Severity,
+    validator::{OverlappingStops, StopPointsFarFromStopArea, Validator},
+    Error, Result,
+};
+
+lazy_static::lazy_static! {
+    pub static ref GIT_VERSION: String = transit_model::binary_full_version(env!("CARGO_PKG_VERSION"));
+}
+
+fn get_version() -> &'static str {
+    &GIT_VERSION
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "ntfs-validate", about = "Check the quality of an NTFS.", version = get_version())]
+struct Opt {
+    /// Input directory or zip file.
+    #[arg(short = 'i', long = "input", default_value = ".")]
+    input: PathBuf,
+
+    /// JSON file where the report is written. The report is written on the
+    /// standard output if not set.
+    #[arg(short = 'o', long = "output")]
+    output: Option<PathBuf>,
+
+    /// Fails if there are more errors.
+    #[arg(long, default_value_t = 0)]
+    max_errors: usize,
+
+    /// Fails if there are more warnings. No limit if not set.
+    #[arg(long)]
+    max_warnings: Option<usize>,
+
+    /// Minimum distance in meters between 2 stop points of a line.
+    #[arg(long, default_value_t = OverlappingStops::default().min_distance)]
+    min_stop_distance: f64,
+
+    /// Maximum distance in meters between a stop point and its stop area.
+    #[arg(long, default_value_t = StopPointsFarFromStopArea::default().max_distance)]
+    max_stop_area_distance: f64,
+}
+
+fn init_logger() {
+    let default_level = LevelFilter::INFO;
+    let rust_log =
+        std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| default_level.to_string());
+    let env_filter_subscriber = EnvFilter::try_new(rust_log).unwrap_or_else(|e| {
+        eprintln!(
+            "invalid {}, falling back to level '{}' - {}",
+            EnvFilter::DEFAULT_ENV,
+            default_level,
+            e,
+        );
+        EnvFilter::new(default_level.to_string())
+    });
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .with(env_filter_subscriber)
+        .init();
+}
+
+fn run(opt: Opt) -> Result<()> {
+    info!("Launching ntfs-validate...");
+
+    // The collections are checked before the model is built, as some
+    // problems can't exist in a model. The invalid objects are skipped and
+    // reported instead of stopping the validation.
+    let (collections, read_errors) = transit_model::ntfs::read_collections_with_errors(opt.input)?;
+    let validator = Validator::with_all_checks(
+        OverlappingStops {
+            min_distance: opt.min_stop_distance,
+        },
+        StopPointsFarFromStopArea {
+            max_distance: opt.max_stop_area_distance,
+        },
+    );
+    let (_, report) = validator.validate_read_collections(collections, read_errors)?;
+
+    match opt.output {
+        Some(output) => serde_json::to_writer_pretty(std::fs::File::create(output)?, &report)?,
+        None => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+
+    let errors = report.count(Severity::Error);
+    let warnings = report.count(Severity::Warning);
+    info!("{} errors and {} warnings found", errors, warnings);
+    if errors > opt.max_errors {
+        return Err(Error::msg(format!(
+            "{} errors found, the maximum is {}",
+            errors, opt.max_errors
+        )));
+    }
+    if let Some(max_warnings) = opt.max_warnings {
+        if warnings > max_warnings {
+            return Err(Error::msg(format!(
+                "{} warnings found, the maximum is {}",
+                warnings, max_warnings
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    init_logger();
+    if let Err(err) = run(Opt::parse()) {
+        for cause in err.chain() {
+            eprintln!("{cause}");
+        }
+        std::process::exit(1);
+    }
+}
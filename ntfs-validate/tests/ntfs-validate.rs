@@ -0,0 +1,30 @@
+use assert_cmd::{cargo_bin, prelude::*};
+use std::process::Command;
+use tempfile::TempDir;
+
+#[test]
+fn test_ntfs_validate() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    let report = output_dir.path().join("report.json");
+    Command::new(cargo_bin!("ntfs-validate"))
+        .arg("--input")
+        .arg("../tests/fixtures/minimal_ntfs/")
+        .arg("--output")
+        .arg(report.to_str().unwrap())
+        .assert()
+        .success();
+    let report = std::fs::read_to_string(report).unwrap();
+    // 'CDGM' is far from its stop area 'CDG'
+    assert!(report.contains("StopPointFarFromStopArea"));
+}
+
+#[test]
+fn test_ntfs_validate_max_warnings() {
+    Command::new(cargo_bin!("ntfs-validate"))
+        .arg("--input")
+        .arg("../tests/fixtures/minimal_ntfs/")
+        .arg("--max-warnings")
+        .arg("0")
+        .assert()
+        .failure();
+}
@@ -55,6 +55,17 @@ struct Opt {
                 https://developers.google.com/transit/gtfs/reference/extended-route-types"
     )]
     extend_route_type: bool,
+
+    /// Compute the `shape_dist_traveled` of the stop times of the trips
+    /// without any, along the shapes or following straight lines between the
+    /// stops.
+    #[arg(long)]
+    shape_dist_traveled: bool,
+
+    /// Read the input leniently: the invalid objects are skipped instead of
+    /// failing, and all the problems found are written in this JSON file.
+    #[arg(long)]
+    report: Option<PathBuf>,
 }
 
 fn init_logger() {
@@ -78,9 +89,19 @@ fn init_logger() {
 
 fn run(opt: Opt) -> Result<()> {
     info!("Launching ntfs2gtfs...");
-    let mut collections = transit_model::ntfs::read_collections(opt.input)?;
+    let mut collections = match opt.report {
+        Some(report_path) => {
+            let (model, report) = transit_model::ntfs::read_with_report(opt.input)?;
+            serde_json::to_writer_pretty(std::fs::File::create(report_path)?, &report)?;
+            model.into_collections()
+        }
+        None => transit_model::ntfs::read_collections(opt.input)?,
+    };
     collections.remove_stop_zones();
     collections.remove_route_points();
+    if opt.shape_dist_traveled {
+        collections.compute_shape_dist_traveled();
+    }
     let mut model = Model::new(collections)?;
 
     if opt.mode_in_route_short_name {
@@ -15,6 +15,7 @@
 // <http://www.gnu.org/licenses/>.
 
 use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
 use clap::Parser;
 use std::path::PathBuf;
 use tracing::info;
@@ -24,6 +25,9 @@ use tracing_subscriber::{
     util::SubscriberInitExt as _,
 };
 use transit_model::{
+    co2,
+    holidays::{self, HolidayKind, ReferenceCalendar},
+    report::Report,
     transfers::{generates_transfers, TransfersConfiguration},
     Result,
 };
@@ -76,6 +80,49 @@ struct Opt {
     /// Don't compute transfers even the transfers of the stop point to itself (max_distance = 0.0)
     #[arg(long)]
     ignore_transfers: bool,
+
+    /// Read the input leniently: the invalid objects are skipped instead of
+    /// failing, and all the problems found are written in this JSON file.
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Compute the journey patterns of the vehicle journeys (same route and
+    /// same stop times) and write them in the output.
+    #[arg(long)]
+    journey_patterns: bool,
+
+    /// CSV file of emission factors (columns `physical_mode_id` and
+    /// `co2_emission`, in gCO2-eq/km) replacing the CO2 emissions of the
+    /// physical modes.
+    #[arg(long)]
+    emission_factors: Option<PathBuf>,
+
+    /// Compute the CO2 emissions of the trips, lines and networks, from the
+    /// distances between the stops, and write them in this JSON file.
+    #[arg(long)]
+    co2_emissions: Option<PathBuf>,
+
+    /// Express the stop times of all the trips in this timezone (e.g.
+    /// `Europe/Paris`), which becomes the timezone of all the networks.
+    #[arg(long)]
+    timezone: Option<Tz>,
+
+    /// iCalendar (`.ics`) or CSV file (columns `name`, `start_date` and
+    /// `end_date`) of public holidays. The trips are tagged with the holidays
+    /// they run on in their object properties.
+    #[arg(long)]
+    public_holidays: Vec<PathBuf>,
+
+    /// iCalendar (`.ics`) or CSV file (columns `name`, `start_date` and
+    /// `end_date`) of school holidays. The trips are tagged with the holidays
+    /// they run on in their object properties.
+    #[arg(long)]
+    school_holidays: Vec<PathBuf>,
+
+    /// Run the service of this day of the week (e.g. `Sun`) on the public
+    /// holidays.
+    #[arg(long, requires = "public_holidays")]
+    public_holidays_service: Option<chrono::Weekday>,
 }
 
 fn init_logger() {
@@ -100,7 +147,14 @@ fn init_logger() {
 fn run(opt: Opt) -> Result<()> {
     info!("Launching ntfs2ntfs...");
 
-    let model = transit_model::ntfs::read(opt.input)?;
+    let model = match opt.report {
+        Some(report_path) => {
+            let (model, report) = transit_model::ntfs::read_with_report(opt.input)?;
+            serde_json::to_writer_pretty(std::fs::File::create(report_path)?, &report)?;
+            model
+        }
+        None => transit_model::ntfs::read(opt.input)?,
+    };
     let model = if opt.ignore_transfers {
         model
     } else {
@@ -115,6 +169,65 @@ fn run(opt: Opt) -> Result<()> {
         transit_model::Model::new(collections)?
     };
 
+    let model = if let Some(timezone) = opt.timezone {
+        let mut collections = model.into_collections();
+        collections.normalize_timezones(timezone)?;
+        transit_model::Model::new(collections)?
+    } else {
+        model
+    };
+
+    let model = if opt.journey_patterns {
+        let mut collections = model.into_collections();
+        collections.compute_journey_patterns();
+        transit_model::Model::new(collections)?
+    } else {
+        model
+    };
+
+    let model = if opt.emission_factors.is_some() || opt.co2_emissions.is_some() {
+        let mut collections = model.into_collections();
+        if let Some(emission_factors_path) = opt.emission_factors {
+            let factors = co2::read_emission_factors(emission_factors_path)?;
+            co2::set_emission_factors(&mut collections, &factors);
+        }
+        if let Some(co2_emissions_path) = opt.co2_emissions {
+            let mut report = Report::default();
+            let emissions = co2::compute_co2_emissions(&mut collections, Some(&mut report));
+            serde_json::to_writer_pretty(
+                std::fs::File::create(co2_emissions_path)?,
+                &serde_json::json!({ "emissions": emissions, "report": report }),
+            )?;
+        }
+        transit_model::Model::new(collections)?
+    } else {
+        model
+    };
+
+    let model = if opt.public_holidays.is_empty() && opt.school_holidays.is_empty() {
+        model
+    } else {
+        let mut reference = ReferenceCalendar::default();
+        for path in opt.public_holidays {
+            reference.read(path, HolidayKind::Public)?;
+        }
+        for path in opt.school_holidays {
+            reference.read(path, HolidayKind::School)?;
+        }
+        let mut collections = model.into_collections();
+        if let Some(weekday) = opt.public_holidays_service {
+            holidays::apply_holiday_service(
+                &mut collections,
+                &reference,
+                HolidayKind::Public,
+                weekday,
+            );
+        }
+        let tags = holidays::tag_calendars(&collections, &reference);
+        holidays::set_calendar_tags(&mut collections, &tags);
+        transit_model::Model::new(collections)?
+    };
+
     if let Some(output) = opt.output {
         match output.extension() {
             Some(ext) if ext == "zip" => {
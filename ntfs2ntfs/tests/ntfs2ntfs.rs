@@ -84,3 +84,22 @@ fn test_ntfs2ntfs_without_transfers() {
     let collections = transit_model::ntfs::read(output_dir).unwrap();
     assert_eq!(0, collections.transfers.len());
 }
+
+#[test]
+fn test_ntfs2ntfs_report() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    let report = output_dir.path().join("report.json");
+    Command::new(cargo_bin!("ntfs2ntfs"))
+        .arg("--input")
+        .arg("../tests/fixtures/minimal_ntfs/")
+        .arg("--output")
+        .arg(output_dir.path().to_str().unwrap())
+        .arg("--report")
+        .arg(report.to_str().unwrap())
+        .arg("--current-datetime")
+        .arg("2019-04-03T17:19:00Z")
+        .assert()
+        .success();
+    assert!(output_dir.path().join("feed_infos.txt").is_file());
+    assert!(report.is_file());
+}
@@ -23,7 +23,7 @@ use tracing_subscriber::{
     layer::SubscriberExt as _,
     util::SubscriberInitExt as _,
 };
-use transit_model::{Model, Result};
+use transit_model::{objects::TimeWindow, Model, Result};
 
 #[derive(Debug, Parser)]
 #[command(
@@ -44,6 +44,15 @@ struct Opt {
     #[arg(short, long)]
     end_validity_date: NaiveDate,
 
+    /// weekly time window to restrict the trips to, e.g.
+    /// "Fri 22:00:00-Sat 06:00:00"
+    #[arg(short, long)]
+    time_window: Option<TimeWindow>,
+
+    /// keep only the stop times of the trips in the time window
+    #[arg(long, requires = "time_window")]
+    truncate: bool,
+
     /// output directory
     #[arg(short, long)]
     output: PathBuf,
@@ -82,6 +91,9 @@ fn run(opt: Opt) -> Result<()> {
     let model = transit_model::ntfs::read(opt.input)?;
     let mut collections = model.into_collections();
     collections.restrict_period(opt.start_validity_date, opt.end_validity_date)?;
+    if let Some(time_window) = opt.time_window {
+        collections.restrict_time_window(&time_window, opt.truncate)?;
+    }
     let model = Model::new(collections)?;
 
     match opt.output.extension() {
@@ -201,6 +201,7 @@ impl AddPrefix for Collections {
         self.ticket_use_restrictions.prefix(prefix_conf);
         self.pathways.prefix(prefix_conf);
         self.levels.prefix(prefix_conf);
+        self.journey_patterns.prefix(prefix_conf);
         self.grid_calendars.prefix(prefix_conf);
         self.grid_exception_dates.prefix(prefix_conf);
         self.grid_periods.prefix(prefix_conf);
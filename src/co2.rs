@@ -0,0 +1,347 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Computation of the CO2 emissions of the vehicle journeys from the distances
+//! between their stop points and the emission factors of their physical modes.
+
+use crate::{
+    enhancers,
+    model::Collections,
+    objects::{Properties, VehicleJourney},
+    report::{Co2ReportCategory, Report},
+    Result,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+use tracing::info;
+use typed_index_collection::Idx;
+
+/// Key of the `object_properties` of the vehicle journeys and the lines
+/// containing their CO2 emissions.
+pub const CO2_EMISSION_PROPERTY: &str = "co2_emission";
+
+#[derive(Debug, Deserialize)]
+struct EmissionFactor {
+    physical_mode_id: String,
+    co2_emission: f32,
+}
+
+/// Read a CSV file of emission factors, with the columns `physical_mode_id`
+/// and `co2_emission` (in gCO2-eq/km).
+pub fn read_emission_factors<P: AsRef<Path>>(path: P) -> Result<BTreeMap<String, f32>> {
+    let path = path.as_ref();
+    info!("Reading emission factors from {:?}", path);
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .with_context(|| format!("Error reading {path:?}"))?;
+    let mut factors = BTreeMap::new();
+    for factor in rdr.deserialize() {
+        let factor: EmissionFactor = factor.with_context(|| format!("Error reading {path:?}"))?;
+        factors.insert(factor.physical_mode_id, factor.co2_emission);
+    }
+    Ok(factors)
+}
+
+/// Replace the CO2 emission of the physical modes by the given emission
+/// factors (in gCO2-eq/km). The physical modes without a factor are kept
+/// unchanged.
+pub fn set_emission_factors(collections: &mut Collections, factors: &BTreeMap<String, f32>) {
+    for (physical_mode_id, &co2_emission) in factors {
+        if let Some(mut physical_mode) = collections.physical_modes.get_mut(physical_mode_id) {
+            physical_mode.co2_emission = Some(co2_emission);
+        }
+    }
+}
+
+/// CO2 emissions, in gCO2-eq.
+#[derive(Debug, Default, Serialize)]
+pub struct Co2Emissions {
+    /// Emissions of one run of each vehicle journey.
+    pub vehicle_journeys: BTreeMap<String, f64>,
+    /// Emissions of all the runs, on all their dates, of the vehicle journeys
+    /// of each line.
+    pub lines: BTreeMap<String, f64>,
+    /// Emissions of all the runs, on all their dates, of the vehicle journeys
+    /// of each network.
+    pub networks: BTreeMap<String, f64>,
+}
+
+/// Emissions of each section between 2 consecutive stop times.
+fn section_emissions(distances: &[f64], co2_emission: f32) -> impl Iterator<Item = f64> + '_ {
+    distances
+        .windows(2)
+        .map(move |w| (w[1] - w[0]).max(0.) / 1000. * f64::from(co2_emission))
+}
+
+/// Number of departures per day of the vehicle journeys with frequencies.
+/// Like [`Collections::convert_frequencies_to_stoptimes`], a frequency departs
+/// every `headway_secs` from its `start_time` until before its `end_time`.
+fn departures_by_frequencies(collections: &Collections) -> BTreeMap<&str, usize> {
+    let mut departures: BTreeMap<&str, usize> = BTreeMap::new();
+    for frequency in collections.frequencies.values() {
+        let duration = frequency
+            .end_time
+            .total_seconds()
+            .saturating_sub(frequency.start_time.total_seconds());
+        let count = if frequency.headway_secs == 0 {
+            0
+        } else {
+            duration.div_ceil(frequency.headway_secs)
+        };
+        *departures
+            .entry(frequency.vehicle_journey_id.as_str())
+            .or_default() += count as usize;
+    }
+    departures
+}
+
+/// Compute the CO2 emissions of the vehicle journeys, summing the emissions
+/// of their sections between 2 stop points: the distance of the section,
+/// along the geometry of the vehicle journey when available (see
+/// [`Collections::compute_shape_dist_traveled`]), multiplied by the CO2
+/// emission of the physical mode.
+///
+/// The emissions of the lines and the networks count each run of the vehicle
+/// journeys: each date of their calendar, and each departure of their
+/// frequencies if any.
+///
+/// The emissions, rounded to the gram, are also set in the
+/// [`CO2_EMISSION_PROPERTY`] of the `object_properties` of the vehicle
+/// journeys (for one run) and of the lines (for all the runs).
+pub fn compute_co2_emissions(
+    collections: &mut Collections,
+    mut report_opt: Option<&mut Report<Co2ReportCategory>>,
+) -> Co2Emissions {
+    let mut emissions = Co2Emissions::default();
+    let mut vj_emissions: Vec<(Idx<VehicleJourney>, f64)> = Vec::new();
+    let mut trips_without_emission_factor: BTreeMap<&str, usize> = BTreeMap::new();
+    let departures_by_frequencies = departures_by_frequencies(collections);
+    for (vj_idx, vj) in collections.vehicle_journeys.iter() {
+        let co2_emission = match collections
+            .physical_modes
+            .get(&vj.physical_mode_id)
+            .and_then(|physical_mode| physical_mode.co2_emission)
+        {
+            Some(co2_emission) => co2_emission,
+            None => {
+                *trips_without_emission_factor
+                    .entry(&vj.physical_mode_id)
+                    .or_default() += 1;
+                continue;
+            }
+        };
+        let distances = match enhancers::stop_time_distances(collections, vj) {
+            Some(distances) => distances,
+            None => {
+                if let Some(report) = report_opt.as_deref_mut() {
+                    report.add_warning(
+                        format!("trip {:?} has a stop point without coordinates", vj.id),
+                        Co2ReportCategory::MissingDistance,
+                    );
+                }
+                continue;
+            }
+        };
+        let vj_emission: f64 = section_emissions(&distances, co2_emission).sum();
+        vj_emissions.push((vj_idx, vj_emission));
+        emissions
+            .vehicle_journeys
+            .insert(vj.id.clone(), vj_emission);
+
+        // A vehicle journey with frequencies runs once per departure of its
+        // frequencies on each date
+        let departures = departures_by_frequencies
+            .get(vj.id.as_str())
+            .copied()
+            .unwrap_or(1);
+        let runs = collections
+            .calendars
+            .get(&vj.service_id)
+            .map_or(0, |calendar| calendar.dates.len() * departures);
+        let line = collections
+            .routes
+            .get(&vj.route_id)
+            .and_then(|route| collections.lines.get(&route.line_id));
+        if let Some(line) = line {
+            let total = vj_emission * runs as f64;
+            *emissions.lines.entry(line.id.clone()).or_default() += total;
+            *emissions
+                .networks
+                .entry(line.network_id.clone())
+                .or_default() += total;
+        }
+    }
+
+    if let Some(report) = report_opt {
+        for (physical_mode_id, trips) in trips_without_emission_factor {
+            report.add_warning(
+                format!(
+                    "physical mode {physical_mode_id:?} has no emission factor, \
+                     the emissions of its {trips} trips are unknown"
+                ),
+                Co2ReportCategory::MissingEmissionFactor,
+            );
+        }
+    }
+
+    for (vj_idx, vj_emission) in vj_emissions {
+        collections
+            .vehicle_journeys
+            .index_mut(vj_idx)
+            .properties_mut()
+            .insert(
+                CO2_EMISSION_PROPERTY.to_string(),
+                format!("{vj_emission:.0}"),
+            );
+    }
+    for (line_id, line_emission) in &emissions.lines {
+        if let Some(mut line) = collections.lines.get_mut(line_id) {
+            line.properties_mut().insert(
+                CO2_EMISSION_PROPERTY.to_string(),
+                format!("{line_emission:.0}"),
+            );
+        }
+    }
+    emissions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        objects::{Coord, Frequency, Time},
+        report::Severity,
+        ModelBuilder,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn collections() -> Collections {
+        ModelBuilder::default()
+            .stop_area("SA", |_| {})
+            .stop_point("SP1", |sp| {
+                sp.coord = Coord {
+                    lon: 2.0,
+                    lat: 48.0,
+                };
+                sp.stop_area_id = "SA".to_string();
+            })
+            .stop_point("SP2", |sp| {
+                sp.coord = Coord {
+                    lon: 2.0,
+                    lat: 48.1,
+                };
+                sp.stop_area_id = "SA".to_string();
+            })
+            .stop_point("SP3", |sp| {
+                sp.stop_area_id = "SA".to_string();
+            })
+            .calendar("C", &["2020-01-01", "2020-01-02"])
+            .vj("VJ1", |vj| {
+                vj.route("R1")
+                    .physical_mode("Bus")
+                    .calendar("C")
+                    .st("SP1", "10:00:00")
+                    .st("SP2", "10:10:00");
+            })
+            .vj("VJ2", |vj| {
+                vj.route("R1")
+                    .physical_mode("Bus")
+                    .calendar("C")
+                    .st("SP2", "11:00:00")
+                    .st("SP1", "11:10:00");
+            })
+            .vj("VJ3", |vj| {
+                vj.route("R1")
+                    .physical_mode("Bus")
+                    .st("SP1", "12:00:00")
+                    .st("SP3", "12:10:00");
+            })
+            // No default emission factor for this physical mode
+            .vj("VJ4", |vj| {
+                vj.route("R1")
+                    .physical_mode("Gondola")
+                    .st("SP1", "12:00:00")
+                    .st("SP2", "12:10:00");
+            })
+            .vj("VJ5", |vj| {
+                vj.route("R1")
+                    .physical_mode("Gondola")
+                    .st("SP2", "13:00:00")
+                    .st("SP1", "13:10:00");
+            })
+            .build()
+            .into_collections()
+    }
+
+    #[test]
+    fn emissions_by_vehicle_journey_and_line() {
+        let mut collections = collections();
+        let mut report = Report::default();
+        let emissions = compute_co2_emissions(&mut collections, Some(&mut report));
+
+        // 0.1° of latitude is ~11.12 km, at 132 gCO2-eq/km for the buses
+        let vj_emission = emissions.vehicle_journeys["VJ1"];
+        assert_eq!(1468, vj_emission.round() as i64);
+        assert_eq!(vj_emission, emissions.vehicle_journeys["VJ2"]);
+        assert_eq!(2, emissions.vehicle_journeys.len());
+        let line_id = &collections.routes.get("R1").unwrap().line_id;
+        assert_eq!(4. * vj_emission, emissions.lines[line_id]);
+        assert_eq!(1, emissions.networks.len());
+
+        assert_eq!(
+            "1468",
+            collections
+                .vehicle_journeys
+                .get("VJ1")
+                .unwrap()
+                .object_properties[CO2_EMISSION_PROPERTY]
+        );
+        assert_eq!(
+            "5871",
+            collections.lines.get(line_id).unwrap().object_properties[CO2_EMISSION_PROPERTY]
+        );
+        assert_eq!(2, report.count(Severity::Warning));
+    }
+
+    #[test]
+    fn emission_factors_override_physical_modes() {
+        let mut collections = collections();
+        let factors = BTreeMap::from([("Gondola".to_string(), 10.), ("Bus".to_string(), 100.)]);
+        set_emission_factors(&mut collections, &factors);
+        let emissions = compute_co2_emissions(&mut collections, None);
+        assert_eq!(1112, emissions.vehicle_journeys["VJ1"].round() as i64);
+        assert_eq!(111, emissions.vehicle_journeys["VJ4"].round() as i64);
+    }
+
+    #[test]
+    fn frequencies_are_runs() {
+        let mut collections = collections();
+        // Departures at 10:00, 10:20 and 10:40
+        collections.frequencies.push(Frequency {
+            vehicle_journey_id: "VJ2".to_string(),
+            start_time: Time::new(10, 0, 0),
+            end_time: Time::new(11, 0, 0),
+            headway_secs: 1200,
+        });
+        let emissions = compute_co2_emissions(&mut collections, None);
+
+        let vj_emission = emissions.vehicle_journeys["VJ1"];
+        let line_id = &collections.routes.get("R1").unwrap().line_id;
+        assert_eq!(8. * vj_emission, emissions.lines[line_id]);
+        let network_id = &collections.lines.get(line_id).unwrap().network_id;
+        assert_eq!(8. * vj_emission, emissions.networks[network_id]);
+    }
+}
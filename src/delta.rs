@@ -0,0 +1,1641 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Incremental updates of a [`Collections`].
+//!
+//! A [`Delta`] is a set of changes (add, update or delete) on objects. It can
+//! be computed between two [`Collections`] with [`diff`], written to and read
+//! from NTFS-like csv files holding an additional `action` column, and applied
+//! on a [`Collections`] with [`Delta::apply`].
+//!
+//! The following files are handled (see `documentation/delta.md`):
+//! `contributors.txt`, `datasets.txt`, `networks.txt`, `commercial_modes.txt`,
+//! `physical_modes.txt`, `companies.txt`, `trip_properties.txt`,
+//! `equipments.txt`, `lines.txt`, `routes.txt`, `calendar_dates.txt`,
+//! `trips.txt` and `stop_times.txt`.
+//!
+//! The other objects of a [`Collections`] (stop areas, stop points and the
+//! other objects of `stops.txt`, comments, booking rules, transfers,
+//! frequencies, geometries, pathways, levels, fares, grid calendars...) are
+//! out of the scope of a delta: [`diff`] fails when they differ, [`Delta::read`]
+//! fails when their files are present, and [`Delta::apply`] keeps them
+//! unchanged, apart from the ones depending on a deleted object.
+
+use crate::{
+    file_handler::{FileHandler, PathFileHandler},
+    model::Collections,
+    objects::*,
+    report::{DeltaReportCategory, Report},
+    serde_utils::*,
+    Result,
+};
+use anyhow::{anyhow, bail, Context};
+use csv::StringRecord;
+use rust_decimal::Decimal;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    path::Path,
+    str::FromStr,
+};
+use tracing::info;
+use typed_index_collection::{CollectionWithId, Id, Idx};
+
+const ACTION_COLUMN: &str = "action";
+const CALENDAR_DATES_FILE: &str = "calendar_dates.txt";
+const STOP_TIMES_FILE: &str = "stop_times.txt";
+/// Files of a NTFS which can't be part of a delta.
+const OUT_OF_SCOPE_FILES: &[&str] = &[
+    "stops.txt",
+    "comments.txt",
+    "comment_links.txt",
+    "booking_rules.txt",
+    "object_codes.txt",
+    "object_properties.txt",
+    "transfers.txt",
+    "pathways.txt",
+    "levels.txt",
+    "frequencies.txt",
+    "geometries.txt",
+    "journey_patterns.txt",
+    "tickets.txt",
+    "ticket_uses.txt",
+    "ticket_prices.txt",
+    "ticket_use_perimeters.txt",
+    "ticket_use_restrictions.txt",
+    "prices.csv",
+    "od_fares.csv",
+    "fares.csv",
+    "grid_calendars.txt",
+    "grid_exception_dates.txt",
+    "grid_periods.txt",
+    "grid_rel_calendar_line.txt",
+    "admin_stations.txt",
+    "addresses.txt",
+    "administrative_regions.txt",
+    "occupancies.txt",
+    "object_locks.txt",
+];
+
+/// Action to apply on an object.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Add a new object.
+    Add,
+    /// Replace an existing object.
+    Update,
+    /// Delete an existing object.
+    Delete,
+}
+
+impl FromStr for Action {
+    type Err = crate::Error;
+    fn from_str(action: &str) -> Result<Self> {
+        match action {
+            "add" => Ok(Action::Add),
+            "update" => Ok(Action::Update),
+            "delete" => Ok(Action::Delete),
+            _ => bail!("unknown action {:?}", action),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Add => write!(f, "add"),
+            Action::Update => write!(f, "update"),
+            Action::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+/// A change on an object identified by an id.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change<T> {
+    /// Add the object.
+    Add(T),
+    /// Replace the object with the same id.
+    Update(T),
+    /// Delete the object with this id.
+    Delete(String),
+}
+
+/// A change on a date of a calendar.
+///
+/// Adding a date to an unknown calendar creates the calendar, and a
+/// calendar without dates is removed (along with its vehicle journeys).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CalendarDateChange {
+    /// Either `add` or `delete`.
+    pub action: Action,
+    /// Identifier of the calendar.
+    pub service_id: String,
+    /// Date to add to or to remove from the calendar.
+    #[serde(
+        deserialize_with = "de_from_date_string",
+        serialize_with = "ser_from_naive_date"
+    )]
+    pub date: Date,
+}
+
+/// A change on a stop time, identified by its trip and its sequence.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StopTimeChange {
+    /// Action to apply on the stop time.
+    pub action: Action,
+    /// Identifier of the vehicle journey.
+    pub trip_id: String,
+    /// Sequence of the stop time in the vehicle journey.
+    pub stop_sequence: u32,
+    /// Identifier of the stop point (not needed for a deletion).
+    pub stop_id: Option<String>,
+    /// Arrival time.
+    pub arrival_time: Option<Time>,
+    /// Departure time.
+    pub departure_time: Option<Time>,
+    /// Boarding duration in seconds.
+    #[serde(default)]
+    pub boarding_duration: u16,
+    /// Alighting duration in seconds.
+    #[serde(default)]
+    pub alighting_duration: u16,
+    /// Pickup type.
+    #[serde(default)]
+    pub pickup_type: u8,
+    /// Drop off type.
+    #[serde(default)]
+    pub drop_off_type: u8,
+    /// Distance in meters from the start of the shape of the trip.
+    #[serde(default, deserialize_with = "de_option_positive_decimal")]
+    pub shape_dist_traveled: Option<Decimal>,
+    /// Continuous pickup from this stop to the next one.
+    #[serde(default, deserialize_with = "de_with_invalid_option")]
+    pub continuous_pickup: Option<ContinuousStopping>,
+    /// Continuous drop off from this stop to the next one.
+    #[serde(default, deserialize_with = "de_with_invalid_option")]
+    pub continuous_drop_off: Option<ContinuousStopping>,
+}
+
+impl StopTimeChange {
+    fn new(
+        action: Action,
+        trip_id: &str,
+        stop_time: &StopTime,
+        stop_points: &CollectionWithId<StopPoint>,
+    ) -> Self {
+        StopTimeChange {
+            action,
+            trip_id: trip_id.to_string(),
+            stop_sequence: stop_time.sequence,
+            stop_id: Some(stop_points[stop_time.stop_point_idx].id.clone()),
+            arrival_time: stop_time.arrival_time,
+            departure_time: stop_time.departure_time,
+            boarding_duration: stop_time.boarding_duration,
+            alighting_duration: stop_time.alighting_duration,
+            pickup_type: stop_time.pickup_type,
+            drop_off_type: stop_time.drop_off_type,
+            shape_dist_traveled: stop_time.shape_dist_traveled,
+            continuous_pickup: stop_time.continuous_pickup,
+            continuous_drop_off: stop_time.continuous_drop_off,
+        }
+    }
+}
+
+/// A set of changes to apply on a [`Collections`].
+#[derive(Debug, Default)]
+pub struct Delta {
+    /// Changes of `contributors.txt`.
+    pub contributors: Vec<Change<Contributor>>,
+    /// Changes of `datasets.txt`.
+    pub datasets: Vec<Change<Dataset>>,
+    /// Changes of `networks.txt`.
+    pub networks: Vec<Change<Network>>,
+    /// Changes of `commercial_modes.txt`.
+    pub commercial_modes: Vec<Change<CommercialMode>>,
+    /// Changes of `physical_modes.txt`.
+    pub physical_modes: Vec<Change<PhysicalMode>>,
+    /// Changes of `companies.txt`.
+    pub companies: Vec<Change<Company>>,
+    /// Changes of `trip_properties.txt`.
+    pub trip_properties: Vec<Change<TripProperty>>,
+    /// Changes of `equipments.txt`.
+    pub equipments: Vec<Change<Equipment>>,
+    /// Changes of `lines.txt`.
+    pub lines: Vec<Change<Line>>,
+    /// Changes of `routes.txt`.
+    pub routes: Vec<Change<Route>>,
+    /// Changes of `calendar_dates.txt`.
+    pub calendar_dates: Vec<CalendarDateChange>,
+    /// Changes of `trips.txt`.
+    pub vehicle_journeys: Vec<Change<VehicleJourney>>,
+    /// Changes of `stop_times.txt`.
+    pub stop_times: Vec<StopTimeChange>,
+}
+
+/// Object types that can be changed by a [`Delta`].
+trait DeltaObject: Sized + Id<Self> + Serialize + DeserializeOwned + Clone {
+    /// Name of the file holding the changes.
+    const FILE_NAME: &'static str;
+    /// Name of the column holding the identifier of the object.
+    const ID_COLUMN: &'static str;
+    /// Human readable name of the object type, used in the report.
+    const OBJECT_NAME: &'static str;
+
+    fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self>;
+
+    /// Returns a message for each reference to an unknown object.
+    fn missing_references(&self, _collections: &Collections) -> Vec<String> {
+        vec![]
+    }
+
+    /// Restores the properties that are not part of the csv representation
+    /// (codes, object properties, links, stop times...).
+    fn merge_unserialized(&mut self, _previous: Option<Self>) {}
+}
+
+macro_rules! impl_delta_object {
+    ($ty:ty, $collection:ident, $file:expr, $id_column:expr, $name:expr) => {
+        impl DeltaObject for $ty {
+            const FILE_NAME: &'static str = $file;
+            const ID_COLUMN: &'static str = $id_column;
+            const OBJECT_NAME: &'static str = $name;
+
+            fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self> {
+                &mut collections.$collection
+            }
+        }
+    };
+}
+
+impl_delta_object!(
+    Contributor,
+    contributors,
+    "contributors.txt",
+    "contributor_id",
+    "contributor"
+);
+impl_delta_object!(
+    CommercialMode,
+    commercial_modes,
+    "commercial_modes.txt",
+    "commercial_mode_id",
+    "commercial mode"
+);
+impl_delta_object!(
+    PhysicalMode,
+    physical_modes,
+    "physical_modes.txt",
+    "physical_mode_id",
+    "physical mode"
+);
+impl_delta_object!(
+    TripProperty,
+    trip_properties,
+    "trip_properties.txt",
+    "trip_property_id",
+    "trip property"
+);
+impl_delta_object!(
+    Equipment,
+    equipments,
+    "equipments.txt",
+    "equipment_id",
+    "equipment"
+);
+
+fn missing_reference<T: Id<T>>(
+    collection: &CollectionWithId<T>,
+    object_name: &str,
+    id: &str,
+) -> Option<String> {
+    if collection.contains_id(id) {
+        None
+    } else {
+        Some(format!("{} {:?} not found", object_name, id))
+    }
+}
+
+impl DeltaObject for Dataset {
+    const FILE_NAME: &'static str = "datasets.txt";
+    const ID_COLUMN: &'static str = "dataset_id";
+    const OBJECT_NAME: &'static str = "dataset";
+
+    fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self> {
+        &mut collections.datasets
+    }
+
+    fn missing_references(&self, collections: &Collections) -> Vec<String> {
+        missing_reference(
+            &collections.contributors,
+            "contributor",
+            &self.contributor_id,
+        )
+        .into_iter()
+        .collect()
+    }
+}
+
+impl DeltaObject for Network {
+    const FILE_NAME: &'static str = "networks.txt";
+    const ID_COLUMN: &'static str = "network_id";
+    const OBJECT_NAME: &'static str = "network";
+
+    fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self> {
+        &mut collections.networks
+    }
+
+    fn merge_unserialized(&mut self, previous: Option<Self>) {
+        if let Some(previous) = previous {
+            self.codes = previous.codes;
+        }
+    }
+}
+
+impl DeltaObject for Company {
+    const FILE_NAME: &'static str = "companies.txt";
+    const ID_COLUMN: &'static str = "company_id";
+    const OBJECT_NAME: &'static str = "company";
+
+    fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self> {
+        &mut collections.companies
+    }
+
+    fn merge_unserialized(&mut self, previous: Option<Self>) {
+        if let Some(previous) = previous {
+            self.codes = previous.codes;
+        }
+    }
+}
+
+impl DeltaObject for Line {
+    const FILE_NAME: &'static str = "lines.txt";
+    const ID_COLUMN: &'static str = "line_id";
+    const OBJECT_NAME: &'static str = "line";
+
+    fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self> {
+        &mut collections.lines
+    }
+
+    fn missing_references(&self, collections: &Collections) -> Vec<String> {
+        vec![
+            missing_reference(&collections.networks, "network", &self.network_id),
+            missing_reference(
+                &collections.commercial_modes,
+                "commercial mode",
+                &self.commercial_mode_id,
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    fn merge_unserialized(&mut self, previous: Option<Self>) {
+        if let Some(previous) = previous {
+            self.codes = previous.codes;
+            self.object_properties = previous.object_properties;
+            self.comment_links = previous.comment_links;
+            self.booking_rule_links = previous.booking_rule_links;
+        }
+    }
+}
+
+impl DeltaObject for Route {
+    const FILE_NAME: &'static str = "routes.txt";
+    const ID_COLUMN: &'static str = "route_id";
+    const OBJECT_NAME: &'static str = "route";
+
+    fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self> {
+        &mut collections.routes
+    }
+
+    fn missing_references(&self, collections: &Collections) -> Vec<String> {
+        missing_reference(&collections.lines, "line", &self.line_id)
+            .into_iter()
+            .collect()
+    }
+
+    fn merge_unserialized(&mut self, previous: Option<Self>) {
+        if let Some(previous) = previous {
+            self.codes = previous.codes;
+            self.object_properties = previous.object_properties;
+            self.comment_links = previous.comment_links;
+        }
+    }
+}
+
+impl DeltaObject for VehicleJourney {
+    const FILE_NAME: &'static str = "trips.txt";
+    const ID_COLUMN: &'static str = "trip_id";
+    const OBJECT_NAME: &'static str = "trip";
+
+    fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self> {
+        &mut collections.vehicle_journeys
+    }
+
+    fn missing_references(&self, collections: &Collections) -> Vec<String> {
+        let mut missing_references = vec![
+            missing_reference(&collections.routes, "route", &self.route_id),
+            missing_reference(
+                &collections.physical_modes,
+                "physical mode",
+                &self.physical_mode_id,
+            ),
+            missing_reference(&collections.datasets, "dataset", &self.dataset_id),
+            missing_reference(&collections.companies, "company", &self.company_id),
+            missing_reference(&collections.calendars, "calendar", &self.service_id),
+        ];
+        if let Some(trip_property_id) = &self.trip_property_id {
+            missing_references.push(missing_reference(
+                &collections.trip_properties,
+                "trip property",
+                trip_property_id,
+            ));
+        }
+        missing_references.into_iter().flatten().collect()
+    }
+
+    fn merge_unserialized(&mut self, previous: Option<Self>) {
+        match previous {
+            Some(previous) => {
+                self.codes = previous.codes;
+                self.object_properties = previous.object_properties;
+                self.comment_links = previous.comment_links;
+                self.booking_rule_links = previous.booking_rule_links;
+                self.stop_times = previous.stop_times;
+            }
+            // Stop times of a new vehicle journey come from `stop_times.txt`
+            None => self.stop_times.clear(),
+        }
+    }
+}
+
+impl Delta {
+    /// Returns `true` if the delta contains no change.
+    pub fn is_empty(&self) -> bool {
+        self.contributors.is_empty()
+            && self.datasets.is_empty()
+            && self.networks.is_empty()
+            && self.commercial_modes.is_empty()
+            && self.physical_modes.is_empty()
+            && self.companies.is_empty()
+            && self.trip_properties.is_empty()
+            && self.equipments.is_empty()
+            && self.lines.is_empty()
+            && self.routes.is_empty()
+            && self.calendar_dates.is_empty()
+            && self.vehicle_journeys.is_empty()
+            && self.stop_times.is_empty()
+    }
+
+    /// Reads a delta from a directory. Every file is optional, the files of a
+    /// NTFS out of the scope of a delta are rejected.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let out_of_scope: Vec<&str> = OUT_OF_SCOPE_FILES
+            .iter()
+            .copied()
+            .filter(|file| path.join(file).exists())
+            .collect();
+        if !out_of_scope.is_empty() {
+            bail!(
+                "{} can't be part of a delta, the whole dataset has to be reprocessed",
+                out_of_scope.join(", ")
+            );
+        }
+        let mut file_handler = PathFileHandler::new(path.to_path_buf());
+        Ok(Delta {
+            contributors: read_changes(&mut file_handler)?,
+            datasets: read_changes(&mut file_handler)?,
+            networks: read_changes(&mut file_handler)?,
+            commercial_modes: read_changes(&mut file_handler)?,
+            physical_modes: read_changes(&mut file_handler)?,
+            companies: read_changes(&mut file_handler)?,
+            trip_properties: read_changes(&mut file_handler)?,
+            equipments: read_changes(&mut file_handler)?,
+            lines: read_changes(&mut file_handler)?,
+            routes: read_changes(&mut file_handler)?,
+            calendar_dates: crate::parser::read_objects(
+                &mut file_handler,
+                CALENDAR_DATES_FILE,
+                false,
+            )?,
+            vehicle_journeys: read_changes(&mut file_handler)?,
+            stop_times: crate::parser::read_objects(&mut file_handler, STOP_TIMES_FILE, false)?,
+        })
+    }
+
+    /// Writes the delta in an existing directory. Only the files with at
+    /// least one change are written.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        write_changes(path, &self.contributors)?;
+        write_changes(path, &self.datasets)?;
+        write_changes(path, &self.networks)?;
+        write_changes(path, &self.commercial_modes)?;
+        write_changes(path, &self.physical_modes)?;
+        write_changes(path, &self.companies)?;
+        write_changes(path, &self.trip_properties)?;
+        write_changes(path, &self.equipments)?;
+        write_changes(path, &self.lines)?;
+        write_changes(path, &self.routes)?;
+        write_rows(path, CALENDAR_DATES_FILE, &self.calendar_dates)?;
+        write_changes(path, &self.vehicle_journeys)?;
+        write_rows(path, STOP_TIMES_FILE, &self.stop_times)?;
+        Ok(())
+    }
+
+    /// Applies the delta on `collections`.
+    ///
+    /// Parent objects are processed before their children, so a delta can
+    /// add a whole line with its routes and trips. A change referencing an
+    /// unknown object, adding an already existing object, or updating or
+    /// deleting an unknown object is not applied and recorded in `report`.
+    /// Objects depending on a deleted object are removed too. The other
+    /// objects are kept, even if they aren't used anymore (the collections
+    /// aren't sanitized).
+    pub fn apply(
+        self,
+        collections: &mut Collections,
+        report: &mut Report<DeltaReportCategory>,
+    ) -> Result<()> {
+        apply_changes(collections, self.contributors, report)?;
+        apply_changes(collections, self.datasets, report)?;
+        apply_changes(collections, self.networks, report)?;
+        apply_changes(collections, self.commercial_modes, report)?;
+        apply_changes(collections, self.physical_modes, report)?;
+        apply_changes(collections, self.companies, report)?;
+        apply_changes(collections, self.trip_properties, report)?;
+        apply_changes(collections, self.equipments, report)?;
+        apply_changes(collections, self.lines, report)?;
+        apply_changes(collections, self.routes, report)?;
+        apply_calendar_date_changes(collections, self.calendar_dates, report)?;
+        apply_changes(collections, self.vehicle_journeys, report)?;
+        apply_stop_time_changes(collections, self.stop_times, report);
+        remove_orphans(collections, report);
+        Ok(())
+    }
+}
+
+fn same<'a, T: 'a + Serialize>(
+    old: impl IntoIterator<Item = &'a T>,
+    new: impl IntoIterator<Item = &'a T>,
+) -> Result<bool> {
+    let old: Vec<&T> = old.into_iter().collect();
+    let new: Vec<&T> = new.into_iter().collect();
+    Ok(serde_json::to_vec(&old)? == serde_json::to_vec(&new)?)
+}
+
+/// Fails with the files which would be needed to go from `old` to `new` and
+/// which are out of the scope of a delta. The objects of `old` depending on an
+/// object deleted in `new` are ignored, as applying the delta removes them.
+fn check_out_of_scope(old: &Collections, new: &Collections) -> Result<()> {
+    let kept_vj = |vj_id: &String| new.vehicle_journeys.contains_id(vj_id);
+    let kept_line = |line_id: &String| new.lines.contains_id(line_id);
+    let same_stop_time_values =
+        |old: &HashMap<(String, u32), String>, new: &HashMap<(String, u32), String>| {
+            let old: BTreeMap<_, _> = old
+                .iter()
+                .filter(|((vj_id, _), _)| kept_vj(vj_id))
+                .collect();
+            let new: BTreeMap<_, _> = new.iter().collect();
+            old == new
+        };
+    let checks = [
+        (
+            "stops.txt",
+            same(old.stop_areas.values(), new.stop_areas.values())?
+                && same(old.stop_points.values(), new.stop_points.values())?
+                && same(old.stop_locations.values(), new.stop_locations.values())?,
+        ),
+        (
+            "stop_times.txt (headsigns, identifiers and comments)",
+            same_stop_time_values(&old.stop_time_headsigns, &new.stop_time_headsigns)
+                && same_stop_time_values(&old.stop_time_ids, &new.stop_time_ids)
+                && same_stop_time_values(&old.stop_time_comments, &new.stop_time_comments),
+        ),
+        (
+            "comments.txt",
+            same(old.comments.values(), new.comments.values())?,
+        ),
+        (
+            "booking_rules.txt",
+            same(old.booking_rules.values(), new.booking_rules.values())?,
+        ),
+        (
+            "transfers.txt",
+            same(old.transfers.values(), new.transfers.values())?,
+        ),
+        (
+            "pathways.txt",
+            same(old.pathways.values(), new.pathways.values())?,
+        ),
+        (
+            "levels.txt",
+            same(old.levels.values(), new.levels.values())?,
+        ),
+        (
+            "frequencies.txt",
+            same(
+                old.frequencies
+                    .values()
+                    .filter(|frequency| kept_vj(&frequency.vehicle_journey_id)),
+                new.frequencies.values(),
+            )?,
+        ),
+        (
+            "geometries.txt",
+            same(old.geometries.values(), new.geometries.values())?,
+        ),
+        (
+            "journey_patterns.txt",
+            same(
+                old.journey_patterns
+                    .values()
+                    .filter(|journey_pattern| new.routes.contains_id(&journey_pattern.route_id)),
+                new.journey_patterns.values(),
+            )?,
+        ),
+        (
+            "fares",
+            same(old.tickets.values(), new.tickets.values())?
+                && same(old.ticket_uses.values(), new.ticket_uses.values())?
+                && same(old.ticket_prices.values(), new.ticket_prices.values())?
+                && same(
+                    old.ticket_use_perimeters.values(),
+                    new.ticket_use_perimeters.values(),
+                )?
+                && same(
+                    old.ticket_use_restrictions.values(),
+                    new.ticket_use_restrictions.values(),
+                )?
+                && same(old.prices_v1.values(), new.prices_v1.values())?
+                && same(old.od_fares_v1.values(), new.od_fares_v1.values())?
+                && same(old.fares_v1.values(), new.fares_v1.values())?,
+        ),
+        (
+            "grid calendars",
+            same(old.grid_calendars.values(), new.grid_calendars.values())?
+                && same(
+                    old.grid_exception_dates.values(),
+                    new.grid_exception_dates.values(),
+                )?
+                && same(old.grid_periods.values(), new.grid_periods.values())?
+                && same(
+                    old.grid_rel_calendar_line
+                        .values()
+                        .filter(|rel| kept_line(&rel.line_id)),
+                    new.grid_rel_calendar_line.values(),
+                )?,
+        ),
+        (
+            "admin_stations.txt",
+            same(old.admin_stations.values(), new.admin_stations.values())?,
+        ),
+        (
+            "addresses.txt",
+            same(old.addresses.values(), new.addresses.values())?,
+        ),
+        (
+            "administrative_regions.txt",
+            same(
+                old.administrative_regions.values(),
+                new.administrative_regions.values(),
+            )?,
+        ),
+        (
+            "occupancies.txt",
+            same(
+                old.occupancies
+                    .values()
+                    .filter(|occupancy| kept_line(&occupancy.line_id)),
+                new.occupancies.values(),
+            )?,
+        ),
+        (
+            "object_locks.txt",
+            same(old.object_locks.values(), new.object_locks.values())?,
+        ),
+    ];
+    let changed: Vec<&str> = checks
+        .iter()
+        .filter(|(_, same)| !same)
+        .map(|(file, _)| *file)
+        .collect();
+    if !changed.is_empty() {
+        bail!(
+            "{} can't be changed by a delta, the whole dataset has to be reprocessed",
+            changed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Computes the delta to apply on `old` to get `new`.
+///
+/// Only the information described in the delta files is compared (codes,
+/// object properties or comments are ignored). Fails if the objects out of the
+/// scope of a delta differ, apart from the ones depending on an object deleted
+/// in `new`.
+pub fn diff(old: &Collections, new: &Collections) -> Result<Delta> {
+    check_out_of_scope(old, new)?;
+    Ok(Delta {
+        contributors: diff_collection(&old.contributors, &new.contributors)?,
+        datasets: diff_collection(&old.datasets, &new.datasets)?,
+        networks: diff_collection(&old.networks, &new.networks)?,
+        commercial_modes: diff_collection(&old.commercial_modes, &new.commercial_modes)?,
+        physical_modes: diff_collection(&old.physical_modes, &new.physical_modes)?,
+        companies: diff_collection(&old.companies, &new.companies)?,
+        trip_properties: diff_collection(&old.trip_properties, &new.trip_properties)?,
+        equipments: diff_collection(&old.equipments, &new.equipments)?,
+        lines: diff_collection(&old.lines, &new.lines)?,
+        routes: diff_collection(&old.routes, &new.routes)?,
+        calendar_dates: diff_calendar_dates(&old.calendars, &new.calendars),
+        vehicle_journeys: diff_collection(&old.vehicle_journeys, &new.vehicle_journeys)?
+            .into_iter()
+            .map(|change| match change {
+                Change::Add(mut vj) => {
+                    vj.stop_times.clear();
+                    Change::Add(vj)
+                }
+                Change::Update(mut vj) => {
+                    vj.stop_times.clear();
+                    Change::Update(vj)
+                }
+                delete => delete,
+            })
+            .collect(),
+        stop_times: diff_stop_times(old, new),
+    })
+}
+
+fn to_csv_row<T: Serialize>(object: &T) -> Result<Vec<u8>> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    wtr.serialize(object)?;
+    wtr.into_inner().map_err(|e| anyhow!("{}", e))
+}
+
+fn diff_collection<T: DeltaObject>(
+    old: &CollectionWithId<T>,
+    new: &CollectionWithId<T>,
+) -> Result<Vec<Change<T>>> {
+    let mut changes = Vec::new();
+    for object in new.values() {
+        match old.get(object.id()) {
+            None => changes.push(Change::Add(object.clone())),
+            Some(old_object) => {
+                if to_csv_row(old_object)? != to_csv_row(object)? {
+                    changes.push(Change::Update(object.clone()));
+                }
+            }
+        }
+    }
+    changes.extend(
+        old.values()
+            .filter(|object| !new.contains_id(object.id()))
+            .map(|object| Change::Delete(object.id().to_string())),
+    );
+    Ok(changes)
+}
+
+fn diff_calendar_dates(
+    old: &CollectionWithId<Calendar>,
+    new: &CollectionWithId<Calendar>,
+) -> Vec<CalendarDateChange> {
+    fn date_changes(
+        action: Action,
+        from: &CollectionWithId<Calendar>,
+        to: &CollectionWithId<Calendar>,
+    ) -> Vec<CalendarDateChange> {
+        let no_dates = BTreeSet::new();
+        let mut changes = Vec::new();
+        for calendar in from.values() {
+            let to_dates = to.get(&calendar.id).map_or(&no_dates, |c| &c.dates);
+            changes.extend(
+                calendar
+                    .dates
+                    .difference(to_dates)
+                    .map(|date| CalendarDateChange {
+                        action,
+                        service_id: calendar.id.clone(),
+                        date: *date,
+                    }),
+            );
+        }
+        changes
+    }
+    let mut changes = date_changes(Action::Add, new, old);
+    changes.extend(date_changes(Action::Delete, old, new));
+    changes
+}
+
+fn diff_stop_times(old: &Collections, new: &Collections) -> Vec<StopTimeChange> {
+    let mut changes = Vec::new();
+    for vj in new.vehicle_journeys.values() {
+        let old_stop_times: BTreeMap<u32, StopTimeChange> = old
+            .vehicle_journeys
+            .get(&vj.id)
+            .map(|old_vj| {
+                old_vj
+                    .stop_times
+                    .iter()
+                    .map(|st| {
+                        let change =
+                            StopTimeChange::new(Action::Update, &vj.id, st, &old.stop_points);
+                        (st.sequence, change)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        for stop_time in &vj.stop_times {
+            match old_stop_times.get(&stop_time.sequence) {
+                None => changes.push(StopTimeChange::new(
+                    Action::Add,
+                    &vj.id,
+                    stop_time,
+                    new.stop_points.values(),
+                )),
+                Some(old_change) => {
+                    let change =
+                        StopTimeChange::new(Action::Update, &vj.id, stop_time, &new.stop_points);
+                    if *old_change != change {
+                        changes.push(change);
+                    }
+                }
+            }
+        }
+        let sequences: BTreeSet<u32> = vj.stop_times.iter().map(|st| st.sequence).collect();
+        changes.extend(
+            old_stop_times
+                .into_iter()
+                .filter(|(sequence, _)| !sequences.contains(sequence))
+                .map(|(_, change)| StopTimeChange {
+                    action: Action::Delete,
+                    ..change
+                }),
+        );
+    }
+    changes
+}
+
+fn read_changes<H, T>(file_handler: &mut H) -> Result<Vec<Change<T>>>
+where
+    for<'a> &'a mut H: FileHandler,
+    T: DeltaObject,
+{
+    let (reader, path) = file_handler.get_file_if_exists(T::FILE_NAME)?;
+    let reader = match reader {
+        None => {
+            info!(file_name = %T::FILE_NAME, "Skipping");
+            return Ok(vec![]);
+        }
+        Some(reader) => reader,
+    };
+    info!(file_name = %T::FILE_NAME, "Reading");
+    let mut rdr = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let headers = rdr
+        .headers()
+        .with_context(|| format!("Error reading {path:?}"))?
+        .clone();
+    let column_position = |column: &str| {
+        headers
+            .iter()
+            .position(|header| header == column)
+            .ok_or_else(|| anyhow!("column {:?} not found in {:?}", column, path))
+    };
+    let action_position = column_position(ACTION_COLUMN)?;
+    let id_position = column_position(T::ID_COLUMN)?;
+    let without_action = |record: &StringRecord| -> StringRecord {
+        record
+            .iter()
+            .enumerate()
+            .filter(|(position, _)| *position != action_position)
+            .map(|(_, field)| field)
+            .collect()
+    };
+    let object_headers = without_action(&headers);
+
+    let mut changes = Vec::new();
+    for record in rdr.records() {
+        let record = record.with_context(|| format!("Error reading {path:?}"))?;
+        let action: Action = record
+            .get(action_position)
+            .unwrap_or_default()
+            .parse()
+            .with_context(|| format!("Error reading {path:?}"))?;
+        let change = match action {
+            Action::Delete => {
+                let id = record.get(id_position).unwrap_or_default();
+                if id.is_empty() {
+                    bail!("Error reading {:?}: missing {}", path, T::ID_COLUMN);
+                }
+                Change::Delete(id.to_string())
+            }
+            Action::Add | Action::Update => {
+                let object: T = without_action(&record)
+                    .deserialize(Some(&object_headers))
+                    .with_context(|| format!("Error reading {path:?}"))?;
+                if action == Action::Add {
+                    Change::Add(object)
+                } else {
+                    Change::Update(object)
+                }
+            }
+        };
+        changes.push(change);
+    }
+    Ok(changes)
+}
+
+fn write_changes<T: DeltaObject>(path: &Path, changes: &[Change<T>]) -> Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    info!(file_name = %T::FILE_NAME, "Writing");
+    // Serialize the objects first to get the csv headers of the type
+    let mut buffer = csv::Writer::from_writer(vec![]);
+    for change in changes {
+        match change {
+            Change::Add(object) | Change::Update(object) => buffer.serialize(object)?,
+            Change::Delete(_) => {}
+        }
+    }
+    let buffer = buffer.into_inner().map_err(|e| anyhow!("{}", e))?;
+    let mut rdr = csv::Reader::from_reader(buffer.as_slice());
+    let mut object_headers = rdr.headers()?.clone();
+    if object_headers.is_empty() {
+        object_headers = StringRecord::from(vec![T::ID_COLUMN]);
+    }
+    let id_position = object_headers
+        .iter()
+        .position(|header| header == T::ID_COLUMN)
+        .ok_or_else(|| anyhow!("column {:?} not found", T::ID_COLUMN))?;
+    let mut records = rdr.records();
+
+    let path = path.join(T::FILE_NAME);
+    let mut wtr =
+        csv::Writer::from_path(&path).with_context(|| format!("Error writing {path:?}"))?;
+    let mut headers = StringRecord::from(vec![ACTION_COLUMN]);
+    headers.extend(object_headers.iter());
+    wtr.write_record(&headers)
+        .with_context(|| format!("Error writing {path:?}"))?;
+    for change in changes {
+        let mut record = StringRecord::new();
+        match change {
+            Change::Add(_) | Change::Update(_) => {
+                let action = if let Change::Add(_) = change {
+                    Action::Add
+                } else {
+                    Action::Update
+                };
+                record.push_field(&action.to_string());
+                let object_record = records
+                    .next()
+                    .ok_or_else(|| anyhow!("missing serialized {}", T::OBJECT_NAME))??;
+                record.extend(object_record.iter());
+            }
+            Change::Delete(id) => {
+                record.push_field(&Action::Delete.to_string());
+                for position in 0..object_headers.len() {
+                    record.push_field(if position == id_position {
+                        id.as_str()
+                    } else {
+                        ""
+                    });
+                }
+            }
+        }
+        wtr.write_record(&record)
+            .with_context(|| format!("Error writing {path:?}"))?;
+    }
+    wtr.flush()
+        .with_context(|| format!("Error writing {path:?}"))?;
+    Ok(())
+}
+
+fn write_rows<T: Serialize>(path: &Path, file_name: &str, rows: &[T]) -> Result<()> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+    info!(file_name = %file_name, "Writing");
+    let path = path.join(file_name);
+    let mut wtr =
+        csv::Writer::from_path(&path).with_context(|| format!("Error writing {path:?}"))?;
+    for row in rows {
+        wtr.serialize(row)
+            .with_context(|| format!("Error writing {path:?}"))?;
+    }
+    wtr.flush()
+        .with_context(|| format!("Error writing {path:?}"))?;
+    Ok(())
+}
+
+fn apply_changes<T: DeltaObject>(
+    collections: &mut Collections,
+    changes: Vec<Change<T>>,
+    report: &mut Report<DeltaReportCategory>,
+) -> Result<()> {
+    for change in changes {
+        if let Change::Add(object) | Change::Update(object) = &change {
+            let missing_references = object.missing_references(collections);
+            if !missing_references.is_empty() {
+                report.add_error(
+                    format!(
+                        "{} {:?} not applied: {}",
+                        T::OBJECT_NAME,
+                        object.id(),
+                        missing_references.join(", ")
+                    ),
+                    DeltaReportCategory::MissingReference,
+                );
+                continue;
+            }
+        }
+        match change {
+            Change::Add(mut object) => {
+                let collection = T::collection_mut(collections);
+                if collection.contains_id(object.id()) {
+                    report.add_error(
+                        format!("{} {:?} already exists", T::OBJECT_NAME, object.id()),
+                        DeltaReportCategory::AlreadyExists,
+                    );
+                    continue;
+                }
+                object.merge_unserialized(None);
+                collection.push(object)?;
+            }
+            Change::Update(object) => {
+                let collection = T::collection_mut(collections);
+                match collection.get_idx(object.id()) {
+                    Some(idx) => {
+                        let mut current = collection.index_mut(idx);
+                        let previous = std::mem::replace(&mut *current, object);
+                        current.merge_unserialized(Some(previous));
+                    }
+                    None => report.add_error(
+                        format!(
+                            "{} {:?} cannot be updated: not found",
+                            T::OBJECT_NAME,
+                            object.id()
+                        ),
+                        DeltaReportCategory::ObjectNotFound,
+                    ),
+                }
+            }
+            Change::Delete(id) => {
+                let collection = T::collection_mut(collections);
+                if collection.contains_id(&id) {
+                    collection.retain(|object| object.id() != id);
+                } else {
+                    report.add_error(
+                        format!("{} {:?} cannot be deleted: not found", T::OBJECT_NAME, id),
+                        DeltaReportCategory::ObjectNotFound,
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn apply_calendar_date_changes(
+    collections: &mut Collections,
+    changes: Vec<CalendarDateChange>,
+    report: &mut Report<DeltaReportCategory>,
+) -> Result<()> {
+    for change in changes {
+        match change.action {
+            Action::Add => {
+                if !collections.calendars.contains_id(&change.service_id) {
+                    collections
+                        .calendars
+                        .push(Calendar::new(change.service_id.clone()))?;
+                }
+                let mut calendar = collections
+                    .calendars
+                    .get_mut(&change.service_id)
+                    .ok_or_else(|| anyhow!("calendar {:?} not found", change.service_id))?;
+                if !calendar.dates.insert(change.date) {
+                    report.add_error(
+                        format!(
+                            "date {} of calendar {:?} already exists",
+                            change.date, change.service_id
+                        ),
+                        DeltaReportCategory::AlreadyExists,
+                    );
+                }
+            }
+            Action::Delete => {
+                let removed = collections
+                    .calendars
+                    .get_mut(&change.service_id)
+                    .is_some_and(|mut calendar| calendar.dates.remove(&change.date));
+                if !removed {
+                    report.add_error(
+                        format!(
+                            "date {} of calendar {:?} cannot be deleted: not found",
+                            change.date, change.service_id
+                        ),
+                        DeltaReportCategory::ObjectNotFound,
+                    );
+                }
+            }
+            Action::Update => report.add_error(
+                format!(
+                    "date {} of calendar {:?} cannot be updated: only add and delete are supported",
+                    change.date, change.service_id
+                ),
+                DeltaReportCategory::UnsupportedAction,
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn apply_stop_time_changes(
+    collections: &mut Collections,
+    changes: Vec<StopTimeChange>,
+    report: &mut Report<DeltaReportCategory>,
+) {
+    let mut vj_idxs_to_sort = BTreeSet::new();
+    for change in changes {
+        let description = format!(
+            "stop time {} of trip {:?}",
+            change.stop_sequence, change.trip_id
+        );
+        let vj_idx = match collections.vehicle_journeys.get_idx(&change.trip_id) {
+            Some(vj_idx) => vj_idx,
+            None => {
+                report.add_error(
+                    format!("{} not applied: trip not found", description),
+                    DeltaReportCategory::MissingReference,
+                );
+                continue;
+            }
+        };
+        let stop_point_idx: Option<Idx<StopPoint>> = change
+            .stop_id
+            .as_ref()
+            .and_then(|stop_id| collections.stop_points.get_idx(stop_id));
+        let mut vj = collections.vehicle_journeys.index_mut(vj_idx);
+        let position = vj
+            .stop_times
+            .iter()
+            .position(|st| st.sequence == change.stop_sequence);
+        match (change.action, position, stop_point_idx) {
+            (Action::Add, Some(_), _) => report.add_error(
+                format!("{} already exists", description),
+                DeltaReportCategory::AlreadyExists,
+            ),
+            (Action::Update, None, _) | (Action::Delete, None, _) => report.add_error(
+                format!("{} cannot be {}d: not found", description, change.action),
+                DeltaReportCategory::ObjectNotFound,
+            ),
+            (Action::Add, None, None) | (Action::Update, Some(_), None) => report.add_error(
+                format!(
+                    "{} not applied: stop point {:?} not found",
+                    description,
+                    change.stop_id.unwrap_or_default()
+                ),
+                DeltaReportCategory::MissingReference,
+            ),
+            (Action::Add, None, Some(stop_point_idx)) => {
+                vj.stop_times.push(StopTime {
+                    stop_point_idx,
+                    sequence: change.stop_sequence,
+                    arrival_time: change.arrival_time,
+                    departure_time: change.departure_time,
+                    start_pickup_drop_off_window: None,
+                    end_pickup_drop_off_window: None,
+                    boarding_duration: change.boarding_duration,
+                    alighting_duration: change.alighting_duration,
+                    pickup_type: change.pickup_type,
+                    drop_off_type: change.drop_off_type,
+                    local_zone_id: None,
+                    precision: None,
+                    shape_dist_traveled: change.shape_dist_traveled,
+                    continuous_pickup: change.continuous_pickup,
+                    continuous_drop_off: change.continuous_drop_off,
+                });
+                vj_idxs_to_sort.insert(vj_idx);
+            }
+            (Action::Update, Some(position), Some(stop_point_idx)) => {
+                let stop_time = &mut vj.stop_times[position];
+                stop_time.stop_point_idx = stop_point_idx;
+                stop_time.arrival_time = change.arrival_time;
+                stop_time.departure_time = change.departure_time;
+                stop_time.boarding_duration = change.boarding_duration;
+                stop_time.alighting_duration = change.alighting_duration;
+                stop_time.pickup_type = change.pickup_type;
+                stop_time.drop_off_type = change.drop_off_type;
+                stop_time.shape_dist_traveled = change.shape_dist_traveled;
+                stop_time.continuous_pickup = change.continuous_pickup;
+                stop_time.continuous_drop_off = change.continuous_drop_off;
+            }
+            (Action::Delete, Some(position), _) => {
+                vj.stop_times.remove(position);
+            }
+        }
+    }
+    for vj_idx in vj_idxs_to_sort {
+        collections
+            .vehicle_journeys
+            .index_mut(vj_idx)
+            .stop_times
+            .sort_unstable_by_key(|st| st.sequence);
+    }
+}
+
+/// Removes the calendars without dates and the objects referencing a deleted
+/// object. The objects which are not referenced anymore are kept.
+fn remove_orphans(collections: &mut Collections, report: &mut Report<DeltaReportCategory>) {
+    fn log_orphan<'a, T, F>(
+        object_name: &'a str,
+        report: &'a mut Report<DeltaReportCategory>,
+        mut f: F,
+    ) -> impl 'a + FnMut(&T) -> bool
+    where
+        T: Id<T>,
+        F: 'a + FnMut(&T) -> bool,
+    {
+        move |object| {
+            if f(object) {
+                true
+            } else {
+                report.add_info(
+                    format!("{} {:?} removed", object_name, object.id()),
+                    DeltaReportCategory::OrphanRemoved,
+                );
+                false
+            }
+        }
+    }
+
+    let contributors = &collections.contributors;
+    collections.datasets.retain(log_orphan(
+        Dataset::OBJECT_NAME,
+        report,
+        |dataset: &Dataset| contributors.contains_id(&dataset.contributor_id),
+    ));
+    let networks = &collections.networks;
+    let commercial_modes = &collections.commercial_modes;
+    collections
+        .lines
+        .retain(log_orphan(Line::OBJECT_NAME, report, |line: &Line| {
+            networks.contains_id(&line.network_id)
+                && commercial_modes.contains_id(&line.commercial_mode_id)
+        }));
+    let lines = &collections.lines;
+    collections
+        .routes
+        .retain(log_orphan(Route::OBJECT_NAME, report, |route: &Route| {
+            lines.contains_id(&route.line_id)
+        }));
+    collections
+        .calendars
+        .retain(log_orphan("calendar", report, |calendar: &Calendar| {
+            !calendar.dates.is_empty()
+        }));
+    let routes = &collections.routes;
+    let physical_modes = &collections.physical_modes;
+    let datasets = &collections.datasets;
+    let companies = &collections.companies;
+    let calendars = &collections.calendars;
+    collections.vehicle_journeys.retain(log_orphan(
+        VehicleJourney::OBJECT_NAME,
+        report,
+        |vj: &VehicleJourney| {
+            routes.contains_id(&vj.route_id)
+                && physical_modes.contains_id(&vj.physical_mode_id)
+                && datasets.contains_id(&vj.dataset_id)
+                && companies.contains_id(&vj.company_id)
+                && calendars.contains_id(&vj.service_id)
+        },
+    ));
+
+    // Objects without identifier depending on a removed object
+    let vehicle_journeys = &collections.vehicle_journeys;
+    collections
+        .frequencies
+        .retain(|frequency| vehicle_journeys.contains_id(&frequency.vehicle_journey_id));
+    for values in [
+        &mut collections.stop_time_headsigns,
+        &mut collections.stop_time_ids,
+        &mut collections.stop_time_comments,
+    ] {
+        values.retain(|(vj_id, _), _| vehicle_journeys.contains_id(vj_id));
+    }
+    let lines = &collections.lines;
+    collections
+        .grid_rel_calendar_line
+        .retain(|rel| lines.contains_id(&rel.line_id));
+    collections
+        .occupancies
+        .retain(|occupancy| lines.contains_id(&occupancy.line_id));
+    let routes = &collections.routes;
+    collections
+        .journey_patterns
+        .retain(|journey_pattern| routes.contains_id(&journey_pattern.route_id));
+
+    let vj_idxs: Vec<_> = collections
+        .vehicle_journeys
+        .iter()
+        .filter(|(_, vj)| {
+            vj.trip_property_id
+                .as_ref()
+                .is_some_and(|id| !collections.trip_properties.contains_id(id))
+        })
+        .map(|(vj_idx, _)| vj_idx)
+        .collect();
+    for vj_idx in vj_idxs {
+        collections
+            .vehicle_journeys
+            .index_mut(vj_idx)
+            .trip_property_id = None;
+    }
+    let vj_idxs: Vec<_> = collections
+        .vehicle_journeys
+        .iter()
+        .filter(|(_, vj)| {
+            vj.journey_pattern_id
+                .as_ref()
+                .is_some_and(|id| !collections.journey_patterns.contains_id(id))
+        })
+        .map(|(vj_idx, _)| vj_idx)
+        .collect();
+    for vj_idx in vj_idxs {
+        collections
+            .vehicle_journeys
+            .index_mut(vj_idx)
+            .journey_pattern_id = None;
+    }
+    let stop_point_idxs: Vec<_> = collections
+        .stop_points
+        .iter()
+        .filter(|(_, stop_point)| {
+            stop_point
+                .equipment_id
+                .as_ref()
+                .is_some_and(|id| !collections.equipments.contains_id(id))
+        })
+        .map(|(stop_point_idx, _)| stop_point_idx)
+        .collect();
+    for stop_point_idx in stop_point_idxs {
+        collections
+            .stop_points
+            .index_mut(stop_point_idx)
+            .equipment_id = None;
+    }
+    let stop_area_idxs: Vec<_> = collections
+        .stop_areas
+        .iter()
+        .filter(|(_, stop_area)| {
+            stop_area
+                .equipment_id
+                .as_ref()
+                .is_some_and(|id| !collections.equipments.contains_id(id))
+        })
+        .map(|(stop_area_idx, _)| stop_area_idx)
+        .collect();
+    for stop_area_idx in stop_area_idxs {
+        collections.stop_areas.index_mut(stop_area_idx).equipment_id = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::Model, test_utils::*, ModelBuilder};
+    use pretty_assertions::assert_eq;
+
+    fn stop_times_of(collections: &Collections, vj_id: &str) -> Vec<(String, Time)> {
+        collections
+            .vehicle_journeys
+            .get(vj_id)
+            .unwrap()
+            .stop_times
+            .iter()
+            .map(|st| {
+                (
+                    collections.stop_points[st.stop_point_idx].id.clone(),
+                    st.departure_time.unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn ids<T: Id<T>>(collection: &CollectionWithId<T>) -> Vec<&str> {
+        let mut ids: Vec<_> = collection.values().map(|object| object.id()).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    fn old_model() -> Model {
+        ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.route("route1").st("A", "10:00:00").st("B", "11:00:00");
+            })
+            .vj("vj2", |vj| {
+                vj.route("route2").st("A", "12:00:00").st("C", "13:00:00");
+            })
+            .build()
+    }
+
+    fn new_model() -> Model {
+        ModelBuilder::default()
+            .route("route1", |r| {
+                r.name = "Route to B".to_owned();
+            })
+            .vj("vj1", |vj| {
+                vj.route("route1").st("A", "10:00:00").st("B", "11:30:00");
+            })
+            .vj("vj3", |vj| {
+                vj.route("route1").st("B", "14:00:00").st("C", "15:00:00");
+            })
+            .build()
+    }
+
+    #[test]
+    fn diff_then_apply_round_trips() {
+        let old = old_model().into_collections();
+        let new = new_model().into_collections();
+        let delta = diff(&old, &new).unwrap();
+        assert_eq!(
+            vec![Change::Delete("vj2".to_string())],
+            delta
+                .vehicle_journeys
+                .iter()
+                .filter(|change| matches!(change, Change::Delete(_)))
+                .cloned()
+                .collect::<Vec<_>>()
+        );
+
+        let mut collections = old;
+        let mut report = Report::default();
+        delta.apply(&mut collections, &mut report).unwrap();
+
+        assert_eq!(
+            ids(&new.vehicle_journeys),
+            ids(&collections.vehicle_journeys)
+        );
+        assert_eq!(ids(&new.routes), ids(&collections.routes));
+        assert_eq!(ids(&new.stop_points), ids(&collections.stop_points));
+        assert_eq!("Route to B", collections.routes.get("route1").unwrap().name);
+        for vj_id in &["vj1", "vj3"] {
+            assert_eq!(
+                stop_times_of(&new, vj_id),
+                stop_times_of(&collections, vj_id)
+            );
+        }
+        let report = serde_json::to_value(&report).unwrap();
+        assert!(report["errors"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn write_then_read_round_trips() {
+        test_in_tmp_dir(|path| {
+            let old = old_model().into_collections();
+            let mut new = new_model().into_collections();
+            new.vehicle_journeys.get_mut("vj3").unwrap().stop_times[0].continuous_pickup =
+                Some(ContinuousStopping::PhoneAgency);
+            diff(&old, &new).unwrap().write(path).unwrap();
+
+            let delta = Delta::read(path).unwrap();
+            let mut collections = old;
+            let mut report = Report::default();
+            delta.apply(&mut collections, &mut report).unwrap();
+
+            assert_eq!(
+                ids(&new.vehicle_journeys),
+                ids(&collections.vehicle_journeys)
+            );
+            assert_eq!("Route to B", collections.routes.get("route1").unwrap().name);
+            assert_eq!(
+                stop_times_of(&new, "vj1"),
+                stop_times_of(&collections, "vj1")
+            );
+            assert_eq!(
+                stop_times_of(&new, "vj3"),
+                stop_times_of(&collections, "vj3")
+            );
+            let vj3 = collections.vehicle_journeys.get("vj3").unwrap();
+            assert_eq!(
+                Some(ContinuousStopping::PhoneAgency),
+                vj3.stop_times[0].continuous_pickup
+            );
+        });
+    }
+
+    #[test]
+    fn reading_delete_needs_only_the_id() {
+        test_in_tmp_dir(|path| {
+            create_file_with_content(path, "routes.txt", "action,route_id\ndelete,route2");
+            let delta = Delta::read(path).unwrap();
+            assert_eq!(vec![Change::Delete("route2".to_string())], delta.routes);
+        });
+    }
+
+    #[test]
+    fn reading_out_of_scope_files_fails() {
+        test_in_tmp_dir(|path| {
+            create_file_with_content(path, "routes.txt", "action,route_id\ndelete,route2");
+            create_file_with_content(path, "stops.txt", "stop_id,stop_name\nC,C");
+            let error = Delta::read(path).unwrap_err();
+            assert_eq!(
+                "stops.txt can't be part of a delta, the whole dataset has to be reprocessed",
+                error.to_string()
+            );
+        });
+    }
+
+    #[test]
+    fn diff_fails_on_out_of_scope_changes() {
+        let old = old_model().into_collections();
+        let mut new = new_model().into_collections();
+        new.stop_points.get_mut("C").unwrap().name = "Renamed".to_string();
+        let error = diff(&old, &new).unwrap_err();
+        assert_eq!(
+            "stops.txt can't be changed by a delta, the whole dataset has to be reprocessed",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn deleting_a_route_removes_its_trips() {
+        let mut collections = old_model().into_collections();
+        let delta = Delta {
+            routes: vec![Change::Delete("route2".to_string())],
+            ..Default::default()
+        };
+        let mut report = Report::default();
+        delta.apply(&mut collections, &mut report).unwrap();
+
+        assert_eq!(vec!["vj1"], ids(&collections.vehicle_journeys));
+        assert_eq!(vec!["route1"], ids(&collections.routes));
+        // The stop points aren't sanitized
+        assert!(collections.stop_points.contains_id("C"));
+        let report = serde_json::to_value(&report).unwrap();
+        assert_eq!("OrphanRemoved", report["infos"][0]["category"]);
+    }
+
+    #[test]
+    fn unapplicable_changes_are_reported() {
+        let mut collections = old_model().into_collections();
+        let mut vj = collections.vehicle_journeys.get("vj1").unwrap().clone();
+        vj.id = "vj4".to_string();
+        vj.route_id = "unknown_route".to_string();
+        let delta = Delta {
+            vehicle_journeys: vec![Change::Add(vj), Change::Delete("unknown_vj".to_string())],
+            stop_times: vec![StopTimeChange {
+                action: Action::Add,
+                trip_id: "vj1".to_string(),
+                stop_sequence: 0,
+                stop_id: Some("C".to_string()),
+                arrival_time: Some(Time::new(9, 0, 0)),
+                departure_time: Some(Time::new(9, 0, 0)),
+                boarding_duration: 0,
+                alighting_duration: 0,
+                pickup_type: 0,
+                drop_off_type: 0,
+                shape_dist_traveled: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
+            }],
+            ..Default::default()
+        };
+        let mut report = Report::default();
+        delta.apply(&mut collections, &mut report).unwrap();
+
+        assert_eq!(vec!["vj1", "vj2"], ids(&collections.vehicle_journeys));
+        let report = serde_json::to_value(&report).unwrap();
+        let categories: BTreeSet<_> = report["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| error["category"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            vec!["AlreadyExists", "MissingReference", "ObjectNotFound"],
+            categories.into_iter().collect::<Vec<_>>()
+        );
+    }
+}
@@ -1,18 +1,25 @@
-use crate::model::Collections;
+use crate::{model::Collections, objects::StopTimeError};
 use tracing::warn;
 use typed_index_collection::CollectionWithId;
 
-pub fn check_stop_times_order(collections: &mut Collections) {
+/// Removes the vehicle journeys with incoherent stop times and returns the
+/// corresponding errors.
+pub fn check_stop_times_order(collections: &mut Collections) -> Vec<StopTimeError> {
     let vehicle_journeys = collections.vehicle_journeys.take();
     let mut filtered_vjs = Vec::new();
+    let mut errors = Vec::new();
     for mut vj in vehicle_journeys {
         match vj.sort_and_check_stop_times() {
             Ok(_) => filtered_vjs.push(vj),
-            Err(e) => warn!("{}", e),
+            Err(e) => {
+                warn!("{}", e);
+                errors.push(e);
+            }
         }
     }
     collections.vehicle_journeys = CollectionWithId::new(filtered_vjs)
         .expect("insert only vehicle journeys that were in a CollectionWithId before");
+    errors
 }
 
 #[cfg(test)]
@@ -45,6 +52,9 @@ mod tests {
                 drop_off_type: 0,
                 local_zone_id: None,
                 precision: None,
+                shape_dist_traveled: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
             },
             StopTime {
                 stop_point_idx,
@@ -59,6 +69,9 @@ mod tests {
                 drop_off_type: 0,
                 local_zone_id: None,
                 precision: None,
+                shape_dist_traveled: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
             },
         ];
         collections.vehicle_journeys = CollectionWithId::from(VehicleJourney {
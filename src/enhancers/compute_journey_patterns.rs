@@ -0,0 +1,176 @@
+use crate::{
+    model::Collections,
+    objects::{ContinuousStopping, JourneyPattern, LinksT, VehicleJourney},
+};
+use std::collections::BTreeMap;
+use typed_index_collection::{CollectionWithId, Idx};
+
+/// Stop point, pickup type, drop off type, local zone, continuous pickup and
+/// continuous drop off of each stop time, and booking rules of the vehicle
+/// journey
+type JourneyPatternKey = (
+    Vec<(
+        String,
+        u8,
+        u8,
+        Option<u16>,
+        Option<ContinuousStopping>,
+        Option<ContinuousStopping>,
+    )>,
+    LinksT,
+);
+type JourneyPatternsByRoute =
+    BTreeMap<String, BTreeMap<JourneyPatternKey, Vec<Idx<VehicleJourney>>>>;
+
+/// Group the vehicle journeys with the same route, the same stop times
+/// (stop point, pickup type, drop off type, local zone and continuous stopping)
+/// and the same
+/// booking rules into journey patterns, replacing the existing ones.
+///
+/// The identifier of a journey pattern is `journey_pattern:<route_id>:<n>`,
+/// `n` being the rank of the pattern among the patterns of the route sorted by
+/// their stop times and booking rules: it doesn't depend on the order of the
+/// vehicle journeys.
+pub fn compute_journey_patterns(collections: &mut Collections) {
+    let mut patterns_by_route = JourneyPatternsByRoute::new();
+    for (vj_idx, vj) in collections.vehicle_journeys.iter() {
+        let stop_times = vj
+            .stop_times
+            .iter()
+            .map(|stop_time| {
+                (
+                    collections.stop_points[stop_time.stop_point_idx].id.clone(),
+                    stop_time.pickup_type,
+                    stop_time.drop_off_type,
+                    stop_time.local_zone_id,
+                    stop_time.continuous_pickup,
+                    stop_time.continuous_drop_off,
+                )
+            })
+            .collect();
+        let key = (stop_times, vj.booking_rule_links.clone());
+        patterns_by_route
+            .entry(vj.route_id.clone())
+            .or_default()
+            .entry(key)
+            .or_default()
+            .push(vj_idx);
+    }
+
+    let mut journey_patterns = Vec::new();
+    for (route_id, patterns) in patterns_by_route {
+        for (rank, vj_idxs) in patterns.into_values().enumerate() {
+            let journey_pattern = JourneyPattern {
+                id: format!("journey_pattern:{}:{}", route_id, rank + 1),
+                route_id: route_id.clone(),
+            };
+            for vj_idx in vj_idxs {
+                collections
+                    .vehicle_journeys
+                    .index_mut(vj_idx)
+                    .journey_pattern_id = Some(journey_pattern.id.clone());
+            }
+            journey_patterns.push(journey_pattern);
+        }
+    }
+    collections.journey_patterns = CollectionWithId::new(journey_patterns)
+        .expect("journey pattern identifiers are unique by construction");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{objects::BookingRule, Model, ModelBuilder};
+    use pretty_assertions::assert_eq;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn group_vehicle_journeys_by_stop_times() {
+        let mut collections = ModelBuilder::default()
+            .vj("VJ3", |vj| {
+                vj.route("R1").st("SP1", "10:00:00").st("SP2", "10:10:00");
+            })
+            .vj("VJ1", |vj| {
+                vj.route("R1").st("SP1", "11:00:00").st("SP3", "11:10:00");
+            })
+            .vj("VJ2", |vj| {
+                vj.route("R1").st("SP1", "12:00:00").st("SP2", "12:15:00");
+            })
+            .vj("VJ4", |vj| {
+                vj.route("R2").st("SP1", "10:00:00").st("SP2", "10:10:00");
+            })
+            .vj("VJ5", |vj| {
+                vj.route("R1").st("SP1", "13:00:00").st("SP2", "13:10:00");
+            })
+            .vj("VJ6", |vj| {
+                vj.route("R1").st("SP1", "14:00:00").st("SP2", "14:10:00");
+            })
+            .vj("VJ7", |vj| {
+                vj.route("R1").st("SP1", "15:00:00").st("SP2", "15:10:00");
+            })
+            .build()
+            .into_collections();
+        collections
+            .vehicle_journeys
+            .get_mut("VJ5")
+            .unwrap()
+            .stop_times[1]
+            .drop_off_type = 1;
+        collections
+            .vehicle_journeys
+            .get_mut("VJ6")
+            .unwrap()
+            .booking_rule_links
+            .insert("BR1".to_string());
+        collections
+            .vehicle_journeys
+            .get_mut("VJ7")
+            .unwrap()
+            .stop_times[0]
+            .continuous_pickup = Some(ContinuousStopping::Continuous);
+        collections
+            .booking_rules
+            .push(BookingRule {
+                id: "BR1".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        compute_journey_patterns(&mut collections);
+
+        let journey_pattern_id = |vj_id: &str| {
+            collections
+                .vehicle_journeys
+                .get(vj_id)
+                .unwrap()
+                .journey_pattern_id
+                .clone()
+                .unwrap()
+        };
+        assert_eq!("journey_pattern:R1:1", journey_pattern_id("VJ3"));
+        assert_eq!("journey_pattern:R1:1", journey_pattern_id("VJ2"));
+        assert_eq!("journey_pattern:R1:2", journey_pattern_id("VJ6"));
+        assert_eq!("journey_pattern:R1:3", journey_pattern_id("VJ5"));
+        assert_eq!("journey_pattern:R1:4", journey_pattern_id("VJ1"));
+        assert_eq!("journey_pattern:R1:5", journey_pattern_id("VJ7"));
+        assert_eq!("journey_pattern:R2:1", journey_pattern_id("VJ4"));
+        assert_eq!(6, collections.journey_patterns.len());
+        let journey_pattern = collections
+            .journey_patterns
+            .get("journey_pattern:R2:1")
+            .unwrap();
+        assert_eq!("R2", journey_pattern.route_id);
+
+        let model = Model::new(collections).unwrap();
+        let journey_pattern_idx = model
+            .journey_patterns
+            .get_idx("journey_pattern:R1:1")
+            .unwrap();
+        let vj_ids: BTreeSet<&str> = model
+            .get_corresponding_from_idx::<JourneyPattern, VehicleJourney>(journey_pattern_idx)
+            .into_iter()
+            .map(|vj_idx| model.vehicle_journeys[vj_idx].id.as_str())
+            .collect();
+        assert_eq!(BTreeSet::from(["VJ2", "VJ3"]), vj_ids);
+    }
+}
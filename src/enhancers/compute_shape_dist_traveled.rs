@@ -0,0 +1,433 @@
+use crate::{
+    model::Collections,
+    objects::{Coord, VehicleJourney},
+};
+use geo::Geometry as GeoGeometry;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use std::collections::HashMap;
+
+/// Ratio (between 0 and 1) of the projection of `point` on the segment
+/// `[start, end]`, computed with an equirectangular projection.
+fn projection_ratio(point: &Coord, start: &Coord, end: &Coord) -> f64 {
+    let cos_lat = point.lat.to_radians().cos();
+    let (dx, dy) = ((end.lon - start.lon) * cos_lat, end.lat - start.lat);
+    let (px, py) = ((point.lon - start.lon) * cos_lat, point.lat - start.lat);
+    let sq_length = dx * dx + dy * dy;
+    if sq_length == 0. {
+        return 0.;
+    }
+    ((px * dx + py * dy) / sq_length).clamp(0., 1.)
+}
+
+/// Distances in meters from the start of `linestring` (at least 2 points) of
+/// the projections of `stops`. Each stop is projected on the closest segment
+/// which is not before the segment of the previous stop, so the distances
+/// never decrease.
+fn distances_along(linestring: &[Coord], stops: &[Coord]) -> Vec<f64> {
+    let mut cumulated_lengths = Vec::with_capacity(linestring.len());
+    let mut length = 0.;
+    for (i, point) in linestring.iter().enumerate() {
+        if i > 0 {
+            length += linestring[i - 1].distance_to(point);
+        }
+        cumulated_lengths.push(length);
+    }
+
+    let mut first_segment = 0;
+    let mut previous_distance: f64 = 0.;
+    stops
+        .iter()
+        .map(|stop| {
+            let approx = stop.approx();
+            let mut closest: Option<(f64, usize, f64)> = None;
+            for segment in first_segment..linestring.len() - 1 {
+                let (start, end) = (&linestring[segment], &linestring[segment + 1]);
+                let ratio = projection_ratio(stop, start, end);
+                let projection = Coord {
+                    lon: start.lon + ratio * (end.lon - start.lon),
+                    lat: start.lat + ratio * (end.lat - start.lat),
+                };
+                let sq_distance = approx.sq_distance_to(&projection);
+                if closest
+                    .is_none_or(|(closest_sq_distance, _, _)| sq_distance < closest_sq_distance)
+                {
+                    closest = Some((sq_distance, segment, ratio));
+                }
+            }
+            if let Some((_, segment, ratio)) = closest {
+                first_segment = segment;
+                let distance = cumulated_lengths[segment]
+                    + ratio * linestring[segment].distance_to(&linestring[segment + 1]);
+                previous_distance = previous_distance.max(distance);
+            }
+            previous_distance
+        })
+        .collect()
+}
+
+/// Distances in meters from the first stop, following straight lines between
+/// the stops.
+fn distances_between(stops: &[Coord]) -> Vec<f64> {
+    let mut distance = 0.;
+    stops
+        .iter()
+        .enumerate()
+        .map(|(i, stop)| {
+            if i > 0 {
+                distance += stops[i - 1].distance_to(stop);
+            }
+            distance
+        })
+        .collect()
+}
+
+/// Distances in meters of the stop times of a vehicle journey, from their
+/// `shape_dist_traveled` if they all have one, computed as in
+/// [`compute_shape_dist_traveled`] otherwise. `None` if a stop point has no
+/// coordinates.
+pub(crate) fn stop_time_distances(
+    collections: &Collections,
+    vj: &VehicleJourney,
+) -> Option<Vec<f64>> {
+    let known_distances: Option<Vec<f64>> = vj
+        .stop_times
+        .iter()
+        .map(|stop_time| stop_time.shape_dist_traveled.and_then(|d| d.to_f64()))
+        .collect();
+    if known_distances.is_some() {
+        return known_distances;
+    }
+    let stops = stop_coords(collections, vj)?;
+    let distances = match linestring(collections, vj) {
+        Some(linestring) => distances_along(&linestring, &stops),
+        None => distances_between(&stops),
+    };
+    Some(distances)
+}
+
+/// Coordinates of the stop points of a vehicle journey, `None` if a stop
+/// point has no coordinates.
+fn stop_coords(collections: &Collections, vj: &VehicleJourney) -> Option<Vec<Coord>> {
+    let stops: Vec<Coord> = vj
+        .stop_times
+        .iter()
+        .map(|stop_time| collections.stop_points[stop_time.stop_point_idx].coord)
+        .collect();
+    if stops.iter().any(|coord| *coord == Coord::default()) {
+        return None;
+    }
+    Some(stops)
+}
+
+/// Points of the geometry of a vehicle journey if it's a linestring of at
+/// least 2 points.
+fn linestring(collections: &Collections, vj: &VehicleJourney) -> Option<Vec<Coord>> {
+    vj.geometry_id
+        .as_ref()
+        .and_then(|geometry_id| collections.geometries.get(geometry_id))
+        .and_then(|geometry| match &geometry.geometry {
+            GeoGeometry::LineString(linestring) if linestring.0.len() > 1 => Some(
+                linestring
+                    .0
+                    .iter()
+                    .map(|point| Coord {
+                        lon: point.x,
+                        lat: point.y,
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        })
+}
+
+/// Fill the `shape_dist_traveled` of the stop times of the vehicle journeys
+/// where it's missing for all the stop times, rounded to the meter. The
+/// vehicle journeys with at least one `shape_dist_traveled` are kept as is.
+///
+/// The stop points are projected on the geometry of the vehicle journey if
+/// it's a linestring, the distances being measured from the start of the
+/// geometry. Otherwise, the distances follow straight lines between the stop
+/// points, from the first one. The vehicle journeys with a stop point without
+/// coordinates are ignored.
+pub fn compute_shape_dist_traveled(collections: &mut Collections) {
+    let vj_idxs: Vec<_> = collections.vehicle_journeys.indexes().collect();
+    for vj_idx in vj_idxs {
+        let vj = &collections.vehicle_journeys[vj_idx];
+        if vj
+            .stop_times
+            .iter()
+            .any(|stop_time| stop_time.shape_dist_traveled.is_some())
+        {
+            continue;
+        }
+        let distances = match stop_time_distances(collections, vj) {
+            Some(distances) => distances,
+            None => continue,
+        };
+
+        let mut vj = collections.vehicle_journeys.index_mut(vj_idx);
+        for (stop_time, distance) in vj.stop_times.iter_mut().zip(distances) {
+            stop_time.shape_dist_traveled = Some(Decimal::from(distance.round() as i64));
+        }
+    }
+}
+
+/// `shape_dist_traveled` of the points of each shape, in the unit of the
+/// input, with the distance in meters of the point from the start of the
+/// shape. The points are sorted along the shape.
+pub(crate) type ShapeDistances = HashMap<String, Vec<(f64, f64)>>;
+
+/// The `shape_dist_traveled` of a shape are considered as already in meters
+/// when its length in meters divided by its last `shape_dist_traveled` is
+/// between the inverse of this ratio and this ratio.
+const METERS_RATIO_TOLERANCE: f64 = 1.5;
+
+/// Distance in meters of a `shape_dist_traveled` in the unit of the shape,
+/// interpolated between the shape points around it (or scaled like the
+/// whole shape beyond the shape points).
+fn to_meters(points: &[(f64, f64)], distance: f64, meters_per_unit: f64) -> f64 {
+    match points.iter().position(|(unit, _)| *unit >= distance) {
+        Some(position) if position > 0 => {
+            let (start_unit, start_meters) = points[position - 1];
+            let (end_unit, end_meters) = points[position];
+            if end_unit == start_unit {
+                return start_meters;
+            }
+            let ratio = (distance - start_unit) / (end_unit - start_unit);
+            start_meters + ratio * (end_meters - start_meters)
+        }
+        _ => distance * meters_per_unit,
+    }
+}
+
+/// Convert the `shape_dist_traveled` of the stop times, read in the unit of
+/// the input (e.g. a GTFS may use kilometers), into meters, using the
+/// `shape_dist_traveled` of the points of the shape of the vehicle journey.
+///
+/// The `shape_dist_traveled` are kept as is when they are already in meters,
+/// or when their unit can't be found because the vehicle journey has no shape
+/// or its shape has no `shape_dist_traveled`.
+pub fn normalize_shape_dist_traveled(
+    collections: &mut Collections,
+    shape_distances: &ShapeDistances,
+) {
+    let vj_idxs: Vec<_> = collections.vehicle_journeys.indexes().collect();
+    for vj_idx in vj_idxs {
+        let vj = &collections.vehicle_journeys[vj_idx];
+        if vj
+            .stop_times
+            .iter()
+            .all(|stop_time| stop_time.shape_dist_traveled.is_none())
+        {
+            continue;
+        }
+        let points = match vj
+            .geometry_id
+            .as_ref()
+            .and_then(|geometry_id| shape_distances.get(geometry_id))
+        {
+            Some(points) => points,
+            None => continue,
+        };
+        let meters_per_unit = match points.last() {
+            Some((unit, meters)) if *unit > 0. => meters / unit,
+            _ => continue,
+        };
+        if (1. / METERS_RATIO_TOLERANCE..=METERS_RATIO_TOLERANCE).contains(&meters_per_unit) {
+            continue;
+        }
+
+        let mut vj = collections.vehicle_journeys.index_mut(vj_idx);
+        for stop_time in &mut vj.stop_times {
+            if let Some(distance) = stop_time.shape_dist_traveled.and_then(|d| d.to_f64()) {
+                let meters = to_meters(points, distance, meters_per_unit);
+                stop_time.shape_dist_traveled = Some(Decimal::from(meters.round() as i64));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{objects::Geometry, ModelBuilder};
+    use geo::line_string;
+    use pretty_assertions::assert_eq;
+
+    fn located(lon: f64, lat: f64) -> impl FnMut(&mut crate::objects::StopPoint) {
+        move |sp| {
+            sp.coord = Coord { lon, lat };
+            sp.stop_area_id = "SA".to_string();
+        }
+    }
+
+    fn collections(geometry_id: Option<&str>) -> Collections {
+        let mut collections = ModelBuilder::default()
+            .stop_area("SA", |_| {})
+            .stop_point("SP1", located(2.0, 48.0))
+            .stop_point("SP2", located(2.01, 48.0))
+            .stop_point("SP3", located(2.01, 48.01))
+            .vj("VJ", |vj| {
+                vj.st("SP1", "10:00:00")
+                    .st("SP2", "10:10:00")
+                    .st("SP3", "10:20:00");
+            })
+            .build()
+            .into_collections();
+        collections
+            .geometries
+            .push(Geometry {
+                id: "G".to_string(),
+                // Starts 0.005° of longitude before SP1 and goes up to SP3
+                // through SP2
+                geometry: line_string![
+                    (x: 1.995, y: 48.0),
+                    (x: 2.01, y: 48.0),
+                    (x: 2.01, y: 48.01),
+                ]
+                .into(),
+            })
+            .unwrap();
+        collections
+            .vehicle_journeys
+            .get_mut("VJ")
+            .unwrap()
+            .geometry_id = geometry_id.map(str::to_string);
+        collections
+    }
+
+    fn distances(collections: &Collections) -> Vec<Option<Decimal>> {
+        collections
+            .vehicle_journeys
+            .get("VJ")
+            .unwrap()
+            .stop_times
+            .iter()
+            .map(|stop_time| stop_time.shape_dist_traveled)
+            .collect()
+    }
+
+    #[test]
+    fn distances_along_the_geometry() {
+        let mut collections = collections(Some("G"));
+        compute_shape_dist_traveled(&mut collections);
+        // 0.005° of longitude at 48° of latitude is ~372m, 0.01° of latitude
+        // is ~1112m
+        assert_eq!(
+            vec![
+                Some(Decimal::from(372)),
+                Some(Decimal::from(1116)),
+                Some(Decimal::from(2228)),
+            ],
+            distances(&collections)
+        );
+    }
+
+    #[test]
+    fn distances_between_the_stops() {
+        let mut collections = collections(None);
+        compute_shape_dist_traveled(&mut collections);
+        assert_eq!(
+            vec![
+                Some(Decimal::from(0)),
+                Some(Decimal::from(744)),
+                Some(Decimal::from(1856)),
+            ],
+            distances(&collections)
+        );
+    }
+
+    #[test]
+    fn existing_distances_are_kept() {
+        let mut collections = collections(Some("G"));
+        for stop_time in &mut collections
+            .vehicle_journeys
+            .get_mut("VJ")
+            .unwrap()
+            .stop_times
+        {
+            stop_time.shape_dist_traveled = Some(Decimal::from(stop_time.sequence));
+        }
+        compute_shape_dist_traveled(&mut collections);
+        assert_eq!(
+            vec![
+                Some(Decimal::from(0)),
+                Some(Decimal::from(1)),
+                Some(Decimal::from(2)),
+            ],
+            distances(&collections)
+        );
+    }
+
+    #[test]
+    fn partial_distances_are_kept() {
+        let mut collections = collections(Some("G"));
+        collections
+            .vehicle_journeys
+            .get_mut("VJ")
+            .unwrap()
+            .stop_times[1]
+            .shape_dist_traveled = Some(Decimal::from(1200));
+        compute_shape_dist_traveled(&mut collections);
+        assert_eq!(
+            vec![None, Some(Decimal::from(1200)), None],
+            distances(&collections)
+        );
+    }
+
+    fn shape_distances(unit: f64) -> ShapeDistances {
+        // Lengths in meters of the geometry "G" up to each point
+        ShapeDistances::from([(
+            "G".to_string(),
+            vec![(0., 0.), (1116. * unit, 1116.), (2228. * unit, 2228.)],
+        )])
+    }
+
+    #[test]
+    fn normalize_along_the_geometry() {
+        let mut collections = collections(Some("G"));
+        // In kilometers, with a missing value
+        {
+            let mut vj = collections.vehicle_journeys.get_mut("VJ").unwrap();
+            vj.stop_times[0].shape_dist_traveled = Some(Decimal::new(4, 1));
+            vj.stop_times[1].shape_dist_traveled = Some(Decimal::new(1116, 3));
+        }
+        normalize_shape_dist_traveled(&mut collections, &shape_distances(0.001));
+        assert_eq!(
+            vec![Some(Decimal::from(400)), Some(Decimal::from(1116)), None],
+            distances(&collections)
+        );
+    }
+
+    #[test]
+    fn normalize_in_meters() {
+        let mut collections = collections(Some("G"));
+        collections
+            .vehicle_journeys
+            .get_mut("VJ")
+            .unwrap()
+            .stop_times[1]
+            .shape_dist_traveled = Some(Decimal::new(11205, 1));
+        normalize_shape_dist_traveled(&mut collections, &shape_distances(1.));
+        assert_eq!(
+            vec![None, Some(Decimal::new(11205, 1)), None],
+            distances(&collections)
+        );
+    }
+
+    #[test]
+    fn normalize_without_geometry() {
+        let mut collections = collections(None);
+        collections
+            .vehicle_journeys
+            .get_mut("VJ")
+            .unwrap()
+            .stop_times[1]
+            .shape_dist_traveled = Some(Decimal::new(7, 1));
+        normalize_shape_dist_traveled(&mut collections, &shape_distances(0.001));
+        assert_eq!(
+            vec![None, Some(Decimal::new(7, 1)), None],
+            distances(&collections)
+        );
+    }
+}
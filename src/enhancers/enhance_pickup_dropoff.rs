@@ -1,6 +1,6 @@
 use crate::{
     model::Collections,
-    objects::{StopTime, VehicleJourney},
+    objects::{ContinuousStopping, StopTime, VehicleJourney},
 };
 use std::collections::{HashMap, HashSet};
 use typed_index_collection::Idx;
@@ -76,6 +76,11 @@ use typed_index_collection::Idx;
 ///
 /// Example 4 is a valid use case of stay-in
 /// The pickup/dropoff will be possible between VJ:1 and VJ:2/VJ:3
+///
+/// The continuous stopping (of the stop times, or else of their route) is
+/// forbidden the same way: no continuous pickup from the last stop point of
+/// a vehicle journey, and no continuous drop off until its first stop point
+/// (e.g. along the route points before it).
 pub fn enhance_pickup_dropoff(collections: &mut Collections) {
     let mut allowed_last_pick_up_vj = HashSet::new();
     let mut allowed_first_drop_off_vj = HashSet::new();
@@ -138,22 +143,41 @@ pub fn enhance_pickup_dropoff(collections: &mut Collections) {
 
     let is_route_point =
         |stop_time: &StopTime| stop_time.pickup_type == 3 || stop_time.drop_off_type == 3;
+    // The continuous stopping of the stop time overrides the one of the route
+    let forbid_continuous_stopping =
+        |continuous_stopping: &mut Option<ContinuousStopping>,
+         route_continuous_stopping: Option<ContinuousStopping>| {
+            if continuous_stopping
+                .or(route_continuous_stopping)
+                .unwrap_or_default()
+                != ContinuousStopping::NotContinuous
+            {
+                *continuous_stopping = Some(ContinuousStopping::NotContinuous);
+            }
+        };
     for vj_idx in collections.vehicle_journeys.indexes() {
         let mut vj = collections.vehicle_journeys.index_mut(vj_idx);
+        let route = collections.routes.get(&vj.route_id);
+        let route_continuous_pickup = route.and_then(|route| route.continuous_pickup);
+        let route_continuous_drop_off = route.and_then(|route| route.continuous_drop_off);
 
         if !allowed_first_drop_off_vj.contains(&vj_idx) {
-            if let Some(st) = vj.stop_times.iter_mut().find(|st| !is_route_point(st)) {
-                st.drop_off_type = 1;
+            if let Some(position) = vj.stop_times.iter().position(|st| !is_route_point(st)) {
+                vj.stop_times[position].drop_off_type = 1;
+                for st in &mut vj.stop_times[..position] {
+                    forbid_continuous_stopping(
+                        &mut st.continuous_drop_off,
+                        route_continuous_drop_off,
+                    );
+                }
             }
         }
         if !allowed_last_pick_up_vj.contains(&vj_idx) {
-            if let Some(st) = vj
-                .stop_times
-                .iter_mut()
-                .rev()
-                .find(|st| !is_route_point(st))
-            {
-                st.pickup_type = 1;
+            if let Some(position) = vj.stop_times.iter().rposition(|st| !is_route_point(st)) {
+                vj.stop_times[position].pickup_type = 1;
+                for st in &mut vj.stop_times[position..] {
+                    forbid_continuous_stopping(&mut st.continuous_pickup, route_continuous_pickup);
+                }
             }
         }
     }
@@ -163,7 +187,7 @@ pub fn enhance_pickup_dropoff(collections: &mut Collections) {
 mod tests {
     use super::*;
     use crate::{
-        objects::{Calendar, Date, StopPoint, Time},
+        objects::{Calendar, Date, Route, StopPoint, Time},
         ModelBuilder,
     };
     use pretty_assertions::assert_eq;
@@ -211,6 +235,9 @@ mod tests {
             drop_off_type: 0,
             local_zone_id: None,
             precision: None,
+            shape_dist_traveled: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
         };
         // First vehicle journey, second stop time
         let stop_time_2 = StopTime {
@@ -226,6 +253,9 @@ mod tests {
             drop_off_type: 0,
             local_zone_id: None,
             precision: None,
+            shape_dist_traveled: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
         };
         // Second vehicle journey, first stop time
         let next_vj_config_time_1 = StopTime {
@@ -241,6 +271,9 @@ mod tests {
             drop_off_type: 0,
             local_zone_id: None,
             precision: None,
+            shape_dist_traveled: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
         };
         // Second vehicle journey, second stop time
         let next_vj_config_time_2 = StopTime {
@@ -256,6 +289,9 @@ mod tests {
             drop_off_type: 0,
             local_zone_id: None,
             precision: None,
+            shape_dist_traveled: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
         };
 
         let vj1 = VehicleJourney {
@@ -469,6 +505,9 @@ mod tests {
             drop_off_type: 3,
             local_zone_id: None,
             precision: None,
+            shape_dist_traveled: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
         });
         drop(vj_mut);
         let mut dates = BTreeSet::new();
@@ -498,6 +537,80 @@ mod tests {
         assert_eq!(0, stop_time.drop_off_type);
     }
 
+    #[test]
+    fn continuous_stopping_around_route_points() {
+        let mut collections = Collections::default();
+        let prev_vj_config = (
+            "block_id_1".to_string(),
+            1,
+            Time::new(10, 0, 0),
+            Time::new(11, 0, 0),
+        );
+        let next_vj_config = (
+            "block_id_2".to_string(),
+            2,
+            Time::new(12, 0, 0),
+            Time::new(13, 0, 0),
+        );
+        collections.vehicle_journeys = build_vehicle_journeys(prev_vj_config, next_vj_config);
+        let route_id = collections
+            .vehicle_journeys
+            .get("vj1")
+            .unwrap()
+            .route_id
+            .clone();
+        collections.routes = CollectionWithId::from(Route {
+            id: route_id,
+            continuous_pickup: Some(ContinuousStopping::Continuous),
+            ..Default::default()
+        });
+        let sp4_idx = collections
+            .stop_points
+            .push(StopPoint {
+                id: String::from("sp4"),
+                ..Default::default()
+            })
+            .unwrap();
+        let vj_idx = collections.vehicle_journeys.get_idx("vj1").unwrap();
+        let mut vj_mut = collections.vehicle_journeys.index_mut(vj_idx);
+        vj_mut.stop_times[0].continuous_drop_off = Some(ContinuousStopping::PhoneAgency);
+        vj_mut.stop_times.push(StopTime {
+            stop_point_idx: sp4_idx,
+            sequence: 2,
+            arrival_time: Some(Time::new(11, 30, 0)),
+            departure_time: Some(Time::new(11, 30, 0)),
+            start_pickup_drop_off_window: None,
+            end_pickup_drop_off_window: None,
+            boarding_duration: 0,
+            alighting_duration: 0,
+            pickup_type: 3,
+            drop_off_type: 3,
+            local_zone_id: None,
+            precision: None,
+            shape_dist_traveled: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
+        });
+        drop(vj_mut);
+        enhance_pickup_dropoff(&mut collections);
+        let vj1 = collections.vehicle_journeys.get("vj1").unwrap();
+        let continuous_stopping: Vec<_> = vj1
+            .stop_times
+            .iter()
+            .map(|st| (st.continuous_pickup, st.continuous_drop_off))
+            .collect();
+        // The continuous pickup of the route is forbidden from the last stop
+        // point, and the continuous drop off from the first stop point is kept
+        assert_eq!(
+            vec![
+                (None, Some(ContinuousStopping::PhoneAgency)),
+                (Some(ContinuousStopping::NotContinuous), None),
+                (Some(ContinuousStopping::NotContinuous), None),
+            ],
+            continuous_stopping
+        );
+    }
+
     #[test]
     fn forbidden_drop_off_should_be_kept() {
         // if restriction are explicitly set they should not be overriden
@@ -0,0 +1,398 @@
+use crate::{
+    model::Collections,
+    objects::{Date, GridCalendar, GridExceptionDate, GridPeriod, GridRelCalendarLine},
+    vptranslator,
+};
+use chrono::{Datelike, Days, Weekday};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// Calendars of a line with the same operating days
+struct DayType {
+    operating_days: Vec<Weekday>,
+    // Validity periods of the calendars
+    periods: Vec<(Date, Date)>,
+    dates: BTreeSet<Date>,
+}
+
+impl DayType {
+    /// Periods merged when they overlap or follow each other.
+    fn merged_periods(&self) -> Vec<(Date, Date)> {
+        let mut periods = self.periods.clone();
+        periods.sort();
+        let mut merged: Vec<(Date, Date)> = Vec::new();
+        for (start_date, end_date) in periods {
+            match merged.last_mut() {
+                Some((_, last_end_date)) if start_date <= *last_end_date + Days::new(1) => {
+                    *last_end_date = (*last_end_date).max(end_date)
+                }
+                _ => merged.push((start_date, end_date)),
+            }
+        }
+        merged
+    }
+
+    /// Exception dates of the merged periods (with `true` for an added date),
+    /// for the dates to be those of one of the calendars.
+    fn exception_dates(&self, periods: &[(Date, Date)]) -> Vec<(Date, bool)> {
+        let runs_on = |date: Date| self.operating_days.contains(&date.weekday());
+        let mut exceptions: Vec<(Date, bool)> = Vec::new();
+        for &(start_date, end_date) in periods {
+            exceptions.extend(
+                start_date
+                    .iter_days()
+                    .take_while(|&date| date <= end_date)
+                    .filter(|&date| runs_on(date) && !self.dates.contains(&date))
+                    .map(|date| (date, false)),
+            );
+        }
+        let in_periods = |date: Date| {
+            periods
+                .iter()
+                .any(|&(start_date, end_date)| start_date <= date && date <= end_date)
+        };
+        exceptions.extend(
+            self.dates
+                .iter()
+                .filter(|&&date| !in_periods(date) || !runs_on(date))
+                .map(|&date| (date, true)),
+        );
+        exceptions.sort();
+        exceptions
+    }
+}
+
+fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+/// Name of a grid calendar from its operating days, e.g. "Monday to Friday",
+/// "Saturday and Sunday" or "Monday to Friday and Sunday".
+fn grid_calendar_name(operating_days: &[Weekday]) -> String {
+    if operating_days.is_empty() {
+        return "Special dates".to_string();
+    }
+    if operating_days.len() == 7 {
+        return "Everyday".to_string();
+    }
+    // Runs of consecutive days
+    let mut runs: Vec<(Weekday, Weekday)> = Vec::new();
+    for &weekday in operating_days {
+        match runs.last_mut() {
+            Some((_, last)) if last.succ() == weekday => *last = weekday,
+            _ => runs.push((weekday, weekday)),
+        }
+    }
+    let mut parts: Vec<String> = Vec::new();
+    for (first, last) in runs {
+        let length = last.num_days_from_monday() - first.num_days_from_monday() + 1;
+        if length >= 3 {
+            parts.push(format!("{} to {}", weekday_name(first), weekday_name(last)));
+        } else {
+            parts.push(weekday_name(first).to_string());
+            if length == 2 {
+                parts.push(weekday_name(last).to_string());
+            }
+        }
+    }
+    match parts.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, others)) => format!("{} and {last}", others.join(", ")),
+        None => unreachable!("at least one operating day"),
+    }
+}
+
+/// Generate the grid calendars of the lines without grid calendars, to group
+/// their vehicle journeys by day type in line timetables.
+///
+/// The dates of each calendar of the vehicle journeys of a line are turned
+/// into operating days and a validity period (see [`vptranslator::translate`]).
+/// The calendars with the same operating days are grouped in a grid calendar,
+/// whose periods are their merged validity periods, with the exception dates
+/// needed for the grid calendar to run on the dates of the calendars. A grid
+/// calendar is identified by `grid_calendar:<line_id>:<n>`, `n` being its rank
+/// among the grid calendars of the line sorted by operating days, and named
+/// after its operating days (e.g. "Monday to Friday").
+pub fn generate_grid_calendars(collections: &mut Collections) {
+    let lines_with_grid_calendars: HashSet<String> = collections
+        .grid_rel_calendar_line
+        .values()
+        .map(|rel| rel.line_id.clone())
+        .collect();
+    let mut service_ids_by_line: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for vj in collections.vehicle_journeys.values() {
+        let line_id = match collections.routes.get(&vj.route_id) {
+            Some(route) => &route.line_id,
+            None => continue,
+        };
+        if !lines_with_grid_calendars.contains(line_id) {
+            service_ids_by_line
+                .entry(line_id.clone())
+                .or_default()
+                .insert(vj.service_id.clone());
+        }
+    }
+
+    for (line_id, service_ids) in service_ids_by_line {
+        // Day types by operating days (from Monday)
+        let mut day_types: BTreeMap<Vec<u32>, DayType> = BTreeMap::new();
+        for service_id in service_ids {
+            let calendar = match collections.calendars.get(&service_id) {
+                Some(calendar) => calendar,
+                None => continue,
+            };
+            let pattern = vptranslator::translate(&calendar.dates);
+            let validity_period = match pattern.validity_period {
+                Some(validity_period) => validity_period,
+                None => continue,
+            };
+            let key = pattern
+                .operating_days
+                .iter()
+                .map(Weekday::num_days_from_monday)
+                .collect();
+            let day_type = day_types.entry(key).or_insert_with(|| DayType {
+                operating_days: pattern.operating_days,
+                periods: Vec::new(),
+                dates: BTreeSet::new(),
+            });
+            day_type
+                .periods
+                .push((validity_period.start_date, validity_period.end_date));
+            day_type.dates.extend(calendar.dates.iter().copied());
+        }
+
+        for (rank, day_type) in day_types.into_values().enumerate() {
+            let grid_calendar_id = format!("grid_calendar:{line_id}:{}", rank + 1);
+            let runs_on = |weekday| day_type.operating_days.contains(&weekday);
+            let grid_calendar = GridCalendar {
+                id: grid_calendar_id.clone(),
+                name: grid_calendar_name(&day_type.operating_days),
+                monday: runs_on(Weekday::Mon),
+                tuesday: runs_on(Weekday::Tue),
+                wednesday: runs_on(Weekday::Wed),
+                thursday: runs_on(Weekday::Thu),
+                friday: runs_on(Weekday::Fri),
+                saturday: runs_on(Weekday::Sat),
+                sunday: runs_on(Weekday::Sun),
+            };
+            collections
+                .grid_calendars
+                .push(grid_calendar)
+                .expect("grid calendar identifiers are unique by construction");
+            let periods = day_type.merged_periods();
+            for (date, added) in day_type.exception_dates(&periods) {
+                collections.grid_exception_dates.push(GridExceptionDate {
+                    grid_calendar_id: grid_calendar_id.clone(),
+                    date,
+                    r#type: added,
+                });
+            }
+            for (start_date, end_date) in periods {
+                collections.grid_periods.push(GridPeriod {
+                    grid_calendar_id: grid_calendar_id.clone(),
+                    start_date,
+                    end_date,
+                });
+            }
+            collections
+                .grid_rel_calendar_line
+                .push(GridRelCalendarLine {
+                    grid_calendar_id,
+                    line_id: line_id.clone(),
+                    line_external_code: None,
+                });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ModelBuilder;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn names_of_grid_calendars() {
+        use Weekday::*;
+        assert_eq!(
+            "Everyday",
+            grid_calendar_name(&[Mon, Tue, Wed, Thu, Fri, Sat, Sun])
+        );
+        assert_eq!(
+            "Monday to Friday",
+            grid_calendar_name(&[Mon, Tue, Wed, Thu, Fri])
+        );
+        assert_eq!("Saturday", grid_calendar_name(&[Sat]));
+        assert_eq!("Saturday and Sunday", grid_calendar_name(&[Sat, Sun]));
+        assert_eq!(
+            "Monday, Wednesday and Friday",
+            grid_calendar_name(&[Mon, Wed, Fri])
+        );
+        assert_eq!(
+            "Monday to Friday and Sunday",
+            grid_calendar_name(&[Mon, Tue, Wed, Thu, Fri, Sun])
+        );
+    }
+
+    #[test]
+    fn grid_calendars_of_lines() {
+        // 2020-01-06 is a Monday, 2020-01-08 is a holiday
+        let mut collections = ModelBuilder::default()
+            .calendar(
+                "week",
+                &[
+                    "2020-01-06",
+                    "2020-01-07",
+                    "2020-01-09",
+                    "2020-01-10",
+                    "2020-01-13",
+                    "2020-01-14",
+                    "2020-01-15",
+                    "2020-01-16",
+                    "2020-01-17",
+                    "2020-01-20",
+                    "2020-01-21",
+                    "2020-01-22",
+                    "2020-01-23",
+                    "2020-01-24",
+                ],
+            )
+            .calendar("saturday", &["2020-01-11", "2020-01-18"])
+            .vj("VJ1", |vj| {
+                vj.route("R1")
+                    .calendar("week")
+                    .st("SP1", "10:00:00")
+                    .st("SP2", "10:10:00");
+            })
+            .vj("VJ2", |vj| {
+                vj.route("R1")
+                    .calendar("week")
+                    .st("SP1", "11:00:00")
+                    .st("SP2", "11:10:00");
+            })
+            .vj("VJ3", |vj| {
+                vj.route("R1")
+                    .calendar("saturday")
+                    .st("SP1", "10:00:00")
+                    .st("SP2", "10:10:00");
+            })
+            .build()
+            .into_collections();
+        generate_grid_calendars(&mut collections);
+
+        let line_id = collections.routes.get("R1").unwrap().line_id.clone();
+        let names: Vec<_> = collections
+            .grid_calendars
+            .values()
+            .map(|grid_calendar| (grid_calendar.id.clone(), grid_calendar.name.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    format!("grid_calendar:{line_id}:1"),
+                    "Monday to Friday".to_string()
+                ),
+                (format!("grid_calendar:{line_id}:2"), "Saturday".to_string()),
+            ],
+            names
+        );
+        assert_eq!(2, collections.grid_rel_calendar_line.len());
+        let periods: Vec<_> = collections
+            .grid_periods
+            .values()
+            .map(|period| (period.start_date.to_string(), period.end_date.to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("2020-01-06".to_string(), "2020-01-24".to_string()),
+                ("2020-01-11".to_string(), "2020-01-18".to_string()),
+            ],
+            periods
+        );
+        let exceptions: Vec<_> = collections
+            .grid_exception_dates
+            .values()
+            .map(|exception| (exception.date.to_string(), exception.r#type))
+            .collect();
+        assert_eq!(vec![("2020-01-08".to_string(), false)], exceptions);
+    }
+
+    // Dates from Monday to Friday between 2 dates, except some
+    fn weekdays(start_date: &str, end_date: &str, except: &[&str]) -> Vec<Date> {
+        let end_date: Date = end_date.parse().unwrap();
+        let except: Vec<Date> = except.iter().map(|date| date.parse().unwrap()).collect();
+        start_date
+            .parse::<Date>()
+            .unwrap()
+            .iter_days()
+            .take_while(|&date| date <= end_date)
+            .filter(|date| date.weekday().num_days_from_monday() < 5 && !except.contains(date))
+            .collect()
+    }
+
+    #[test]
+    fn calendars_grouped_by_day_type() {
+        let mut collections = ModelBuilder::default()
+            .calendar("first", &weekdays("2020-01-06", "2020-01-17", &[]))
+            .calendar("second", &weekdays("2020-01-13", "2020-01-24", &[]))
+            .calendar(
+                "march",
+                &weekdays("2020-03-02", "2020-03-20", &["2020-03-04"]),
+            )
+            .vj("VJ1", |vj| {
+                vj.route("R1")
+                    .calendar("first")
+                    .st("SP1", "10:00:00")
+                    .st("SP2", "10:10:00");
+            })
+            .vj("VJ2", |vj| {
+                vj.route("R1")
+                    .calendar("second")
+                    .st("SP1", "11:00:00")
+                    .st("SP2", "11:10:00");
+            })
+            .vj("VJ3", |vj| {
+                vj.route("R1")
+                    .calendar("march")
+                    .st("SP1", "12:00:00")
+                    .st("SP2", "12:10:00");
+            })
+            .build()
+            .into_collections();
+        generate_grid_calendars(&mut collections);
+
+        let names: Vec<_> = collections
+            .grid_calendars
+            .values()
+            .map(|grid_calendar| grid_calendar.name.clone())
+            .collect();
+        assert_eq!(vec!["Monday to Friday".to_string()], names);
+        // The overlapping periods of January are merged
+        let periods: Vec<_> = collections
+            .grid_periods
+            .values()
+            .map(|period| (period.start_date.to_string(), period.end_date.to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("2020-01-06".to_string(), "2020-01-24".to_string()),
+                ("2020-03-02".to_string(), "2020-03-20".to_string()),
+            ],
+            periods
+        );
+        let exceptions: Vec<_> = collections
+            .grid_exception_dates
+            .values()
+            .map(|exception| (exception.date.to_string(), exception.r#type))
+            .collect();
+        assert_eq!(vec![("2020-03-04".to_string(), false)], exceptions);
+    }
+}
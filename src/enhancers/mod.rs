@@ -2,13 +2,26 @@
 
 mod adjust_lines_names;
 mod check_stop_times_order;
+mod compute_journey_patterns;
+mod compute_shape_dist_traveled;
 mod enhance_pickup_dropoff;
 mod fill_co2;
+mod generate_grid_calendars;
 mod memory_shrink;
+mod normalize_timezones;
+mod restrict_time_window;
+mod split_vehicle_journeys;
 
 pub(crate) use adjust_lines_names::adjust_lines_names;
 pub(crate) use check_stop_times_order::check_stop_times_order;
+pub(crate) use compute_journey_patterns::compute_journey_patterns;
+pub(crate) use compute_shape_dist_traveled::{
+    compute_shape_dist_traveled, normalize_shape_dist_traveled, stop_time_distances, ShapeDistances,
+};
 pub(crate) use enhance_pickup_dropoff::enhance_pickup_dropoff;
 pub(crate) use fill_co2::fill_co2;
 pub(crate) use fill_co2::FALLBACK_PHYSICAL_MODES;
+pub(crate) use generate_grid_calendars::generate_grid_calendars;
 pub(crate) use memory_shrink::memory_shrink;
+pub(crate) use normalize_timezones::normalize_timezones;
+pub(crate) use restrict_time_window::restrict_time_window;
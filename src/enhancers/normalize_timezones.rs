@@ -0,0 +1,365 @@
+use super::split_vehicle_journeys::VehicleJourneySplitter;
+use crate::{
+    model::Collections,
+    objects::{Date, Frequency, Time, VehicleJourney},
+    Result,
+};
+use chrono::{Days, TimeZone};
+use chrono_tz::Tz;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tracing::warn;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Instant, in seconds since the epoch, of the start of a service day in a
+/// timezone: noon minus 12 hours, as in the GTFS, to be correct on the days of
+/// the DST changes.
+fn service_day_start(date: Date, timezone: Tz) -> Option<i64> {
+    let noon = date.and_hms_opt(12, 0, 0)?;
+    let noon = timezone.from_local_datetime(&noon).earliest()?;
+    Some(noon.timestamp() - SECONDS_PER_DAY / 2)
+}
+
+fn shift_time(time: Time, shift: i64) -> Time {
+    let seconds = i64::from(time.total_seconds()) + shift;
+    Time::new(0, 0, u32::try_from(seconds).unwrap_or_default())
+}
+
+fn shift_dates(dates: &BTreeSet<Date>, day_shift: i64) -> BTreeSet<Date> {
+    dates
+        .iter()
+        .filter_map(|&date| {
+            if day_shift >= 0 {
+                date.checked_add_days(Days::new(day_shift as u64))
+            } else {
+                date.checked_sub_days(Days::new(day_shift.unsigned_abs()))
+            }
+        })
+        .collect()
+}
+
+/// Shift of the times (in seconds) and of the dates (in days) of a vehicle
+/// journey running on `date` in `timezone` to express them in `reference`,
+/// the times keeping the same number of days after the start of the service
+/// day.
+fn shifts(date: Date, first_time: Time, timezone: Tz, reference: Tz) -> Option<(i64, i64)> {
+    let delta = service_day_start(date, timezone)? - service_day_start(date, reference)?;
+    let first_time = i64::from(first_time.total_seconds());
+    let day_shift =
+        (first_time + delta).div_euclid(SECONDS_PER_DAY) - first_time.div_euclid(SECONDS_PER_DAY);
+    Some((delta - day_shift * SECONDS_PER_DAY, day_shift))
+}
+
+/// Express the times of all the vehicle journeys in the `reference` timezone,
+/// and set it as the timezone of all the networks.
+///
+/// The times of a network without a timezone can't be shifted: its vehicle
+/// journeys are kept as is and its timezone is left unset, with a warning.
+///
+/// The stop times of the vehicle journeys of a network with another timezone
+/// are shifted by the difference between the timezones, and their dates are
+/// shifted by a day when their first stop time changes of day. As the
+/// difference between 2 timezones can change during the year (DST), a vehicle
+/// journey is split when the shift of its times isn't the same on all its
+/// dates, the new vehicle journeys being identified by `<id>:<n>`. The shifted
+/// dates are in new calendars, identified by `<service_id>:<n>`.
+///
+/// The frequencies of a vehicle journey are shifted like stop times, its
+/// dates being shifted according to its first frequency. Its stop times, which
+/// only give the durations between the stops, are unchanged.
+pub fn normalize_timezones(collections: &mut Collections, reference: Tz) -> Result<()> {
+    let network_timezones: HashMap<String, Tz> = collections
+        .networks
+        .values()
+        .filter_map(|network| Some((network.id.clone(), network.timezone?)))
+        .collect();
+    for network in collections.networks.values() {
+        if network.timezone.is_none() {
+            warn!(
+                "network {} has no timezone, the times of its vehicle journeys are not expressed in {}",
+                network.id, reference
+            );
+        }
+    }
+    let (routes, lines) = (&collections.routes, &collections.lines);
+    let timezone_of = |vj: &VehicleJourney| -> Option<Tz> {
+        let route = routes.get(&vj.route_id)?;
+        let line = lines.get(&route.line_id)?;
+        network_timezones.get(&line.network_id).copied()
+    };
+
+    let mut splitter = VehicleJourneySplitter::new(collections);
+    for vj in collections.vehicle_journeys.take() {
+        let timezone = match timezone_of(&vj) {
+            Some(timezone) if timezone != reference => timezone,
+            _ => {
+                splitter.keep(vj);
+                continue;
+            }
+        };
+        let frequencies = splitter.frequencies(&vj.id);
+        let first_time = if frequencies.is_empty() {
+            vj.stop_times
+                .iter()
+                .flat_map(|stop_time| {
+                    [
+                        stop_time.arrival_time,
+                        stop_time.departure_time,
+                        stop_time.start_pickup_drop_off_window,
+                        stop_time.end_pickup_drop_off_window,
+                    ]
+                })
+                .flatten()
+                .min()
+        } else {
+            frequencies
+                .iter()
+                .map(|frequency| frequency.start_time)
+                .min()
+        };
+        let (first_time, dates) = match (first_time, splitter.dates(&vj.service_id)) {
+            (Some(first_time), Some(dates)) => (first_time, dates),
+            _ => {
+                splitter.keep(vj);
+                continue;
+            }
+        };
+        let mut dates_by_shift: BTreeMap<i64, BTreeMap<i64, BTreeSet<Date>>> = BTreeMap::new();
+        for &date in dates {
+            if let Some((time_shift, day_shift)) = shifts(date, first_time, timezone, reference) {
+                dates_by_shift
+                    .entry(time_shift)
+                    .or_default()
+                    .entry(day_shift)
+                    .or_default()
+                    .insert(date);
+            }
+        }
+        let variants = dates_by_shift
+            .into_iter()
+            .map(|(time_shift, dates_by_day_shift)| {
+                let dates: BTreeSet<Date> = dates_by_day_shift
+                    .iter()
+                    .flat_map(|(&day_shift, dates)| shift_dates(dates, day_shift))
+                    .collect();
+                let mut stop_times = vj.stop_times.clone();
+                if frequencies.is_empty() {
+                    for stop_time in &mut stop_times {
+                        for time in [
+                            &mut stop_time.arrival_time,
+                            &mut stop_time.departure_time,
+                            &mut stop_time.start_pickup_drop_off_window,
+                            &mut stop_time.end_pickup_drop_off_window,
+                        ] {
+                            *time = time.map(|time| shift_time(time, time_shift));
+                        }
+                    }
+                }
+                let frequencies = frequencies
+                    .iter()
+                    .map(|frequency| Frequency {
+                        start_time: shift_time(frequency.start_time, time_shift),
+                        end_time: shift_time(frequency.end_time, time_shift),
+                        ..frequency.clone()
+                    })
+                    .collect();
+                (dates, stop_times, frequencies)
+            })
+            .collect();
+        splitter.split(vj, variants);
+    }
+    splitter.finish(collections)?;
+    for network_idx in collections.networks.indexes() {
+        let mut network = collections.networks.index_mut(network_idx);
+        if network.timezone.is_some() {
+            network.timezone = Some(reference);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ModelBuilder;
+    use chrono_tz::{America::New_York, Europe::London, Europe::Paris};
+    use pretty_assertions::assert_eq;
+
+    fn collections(timezone: Tz) -> Collections {
+        ModelBuilder::default()
+            .calendar("C", &["2020-03-07", "2020-03-09", "2020-03-30"])
+            .vj("VJ", |vj| {
+                vj.network("N")
+                    .timezone(timezone)
+                    .calendar("C")
+                    .st("SP1", "00:30:00")
+                    .st("SP2", "01:10:00");
+            })
+            .build()
+            .into_collections()
+    }
+
+    fn times_and_dates(collections: &Collections, vj_id: &str) -> (Vec<Time>, Vec<String>) {
+        let vj = collections.vehicle_journeys.get(vj_id).unwrap();
+        let times = vj
+            .stop_times
+            .iter()
+            .map(|stop_time| stop_time.departure_time.unwrap())
+            .collect();
+        let dates = collections
+            .calendars
+            .get(&vj.service_id)
+            .unwrap()
+            .dates
+            .iter()
+            .map(|date| date.to_string())
+            .collect();
+        (times, dates)
+    }
+
+    #[test]
+    fn shift_to_the_previous_day() {
+        let mut collections = collections(Paris);
+        normalize_timezones(&mut collections, London).unwrap();
+        assert_eq!(
+            (
+                vec![Time::new(23, 30, 0), Time::new(24, 10, 0)],
+                vec![
+                    "2020-03-06".to_string(),
+                    "2020-03-08".to_string(),
+                    "2020-03-29".to_string()
+                ]
+            ),
+            times_and_dates(&collections, "VJ")
+        );
+        assert_eq!(
+            Some(London),
+            collections.networks.get("N").unwrap().timezone
+        );
+    }
+
+    #[test]
+    fn split_on_dst_differences() {
+        // The US switch to summer time on 2020-03-08, Europe on 2020-03-29
+        let mut collections = collections(New_York);
+        normalize_timezones(&mut collections, Paris).unwrap();
+        // 6 hours of difference
+        assert_eq!(
+            (
+                vec![Time::new(6, 30, 0), Time::new(7, 10, 0)],
+                vec!["2020-03-07".to_string(), "2020-03-30".to_string()]
+            ),
+            times_and_dates(&collections, "VJ")
+        );
+        // 5 hours of difference
+        assert_eq!(
+            (
+                vec![Time::new(5, 30, 0), Time::new(6, 10, 0)],
+                vec!["2020-03-09".to_string()]
+            ),
+            times_and_dates(&collections, "VJ:1")
+        );
+        assert_eq!(2, collections.vehicle_journeys.len());
+    }
+
+    #[test]
+    fn same_timezone_unchanged() {
+        let mut collections = collections(Paris);
+        normalize_timezones(&mut collections, Paris).unwrap();
+        assert_eq!(
+            (
+                vec![Time::new(0, 30, 0), Time::new(1, 10, 0)],
+                vec![
+                    "2020-03-07".to_string(),
+                    "2020-03-09".to_string(),
+                    "2020-03-30".to_string()
+                ]
+            ),
+            times_and_dates(&collections, "VJ")
+        );
+        assert_eq!(
+            "C",
+            collections.vehicle_journeys.get("VJ").unwrap().service_id
+        );
+    }
+
+    #[test]
+    fn network_without_timezone_unchanged() {
+        let mut collections = ModelBuilder::default()
+            .calendar("C", &["2020-03-07"])
+            .vj("VJ1", |vj| {
+                vj.network("N1")
+                    .timezone(Paris)
+                    .calendar("C")
+                    .st("SP1", "10:00:00")
+                    .st("SP2", "10:10:00");
+            })
+            .vj("VJ2", |vj| {
+                vj.network("N2")
+                    .calendar("C")
+                    .st("SP1", "11:00:00")
+                    .st("SP2", "11:10:00");
+            })
+            .build()
+            .into_collections();
+        collections.networks.get_mut("N2").unwrap().timezone = None;
+        normalize_timezones(&mut collections, London).unwrap();
+        assert_eq!(
+            (
+                vec![Time::new(9, 0, 0), Time::new(9, 10, 0)],
+                vec!["2020-03-07".to_string()]
+            ),
+            times_and_dates(&collections, "VJ1")
+        );
+        assert_eq!(
+            (
+                vec![Time::new(11, 0, 0), Time::new(11, 10, 0)],
+                vec!["2020-03-07".to_string()]
+            ),
+            times_and_dates(&collections, "VJ2")
+        );
+        assert_eq!(
+            Some(London),
+            collections.networks.get("N1").unwrap().timezone
+        );
+        assert_eq!(None, collections.networks.get("N2").unwrap().timezone);
+    }
+
+    #[test]
+    fn shift_and_split_frequencies() {
+        let mut collections = collections(New_York);
+        collections.frequencies.push(Frequency {
+            vehicle_journey_id: "VJ".to_string(),
+            start_time: Time::new(0, 30, 0),
+            end_time: Time::new(2, 0, 0),
+            headway_secs: 1800,
+        });
+        normalize_timezones(&mut collections, Paris).unwrap();
+        let frequencies: Vec<(&str, Time, Time)> = collections
+            .frequencies
+            .values()
+            .map(|frequency| {
+                (
+                    frequency.vehicle_journey_id.as_str(),
+                    frequency.start_time,
+                    frequency.end_time,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("VJ", Time::new(6, 30, 0), Time::new(8, 0, 0)),
+                ("VJ:1", Time::new(5, 30, 0), Time::new(7, 0, 0)),
+            ],
+            frequencies
+        );
+        // The stop times only give the durations between the stops
+        assert_eq!(
+            (
+                vec![Time::new(0, 30, 0), Time::new(1, 10, 0)],
+                vec!["2020-03-09".to_string()]
+            ),
+            times_and_dates(&collections, "VJ:1")
+        );
+    }
+}
@@ -0,0 +1,372 @@
+use super::split_vehicle_journeys::VehicleJourneySplitter;
+use crate::{
+    model::Collections,
+    objects::{Date, Frequency, StopTime, Time, TimeWindow},
+    Result,
+};
+use chrono::Datelike;
+use std::collections::{BTreeMap, BTreeSet};
+
+fn times(stop_time: &StopTime) -> impl Iterator<Item = Time> {
+    [
+        stop_time.arrival_time,
+        stop_time.departure_time,
+        stop_time.start_pickup_drop_off_window,
+        stop_time.end_pickup_drop_off_window,
+    ]
+    .into_iter()
+    .flatten()
+}
+
+fn shift_time(time: Time, shift: i64) -> Option<Time> {
+    let seconds = u32::try_from(i64::from(time.total_seconds()) + shift).ok()?;
+    Some(Time::new(0, 0, seconds))
+}
+
+/// Indexes of the stop times kept on `date`, the stop times being shifted by
+/// `shift` seconds, or `None` if less than 2 are kept.
+fn kept_stop_times(
+    stop_times: &[StopTime],
+    window: &TimeWindow,
+    date: Date,
+    shift: i64,
+    truncate: bool,
+) -> Option<Vec<usize>> {
+    let indexes_in_window: Vec<usize> = stop_times
+        .iter()
+        .enumerate()
+        .filter(|(_, stop_time)| {
+            times(stop_time)
+                .filter_map(|time| shift_time(time, shift))
+                .any(|time| window.contains(date.weekday(), time))
+        })
+        .map(|(index, _)| index)
+        .collect();
+    let kept: Vec<usize> = if truncate {
+        indexes_in_window
+    } else if indexes_in_window.is_empty() {
+        return None;
+    } else {
+        (0..stop_times.len()).collect()
+    };
+    (kept.len() >= 2).then_some(kept)
+}
+
+// (start time, end time, headway)
+type Departures = (Time, Time, u32);
+
+/// Frequencies of a vehicle journey running on `date`, clipped to their
+/// departures in the time window, by indexes of the kept stop times. The times
+/// of the frequencies are expressed relatively to the first kept stop time.
+fn kept_frequencies(
+    stop_times: &[StopTime],
+    frequencies: &[Frequency],
+    window: &TimeWindow,
+    date: Date,
+    truncate: bool,
+) -> BTreeMap<Vec<usize>, Vec<Departures>> {
+    let first_time = |indexes: &[usize]| -> Option<i64> {
+        indexes
+            .iter()
+            .flat_map(|&index| times(&stop_times[index]))
+            .min()
+            .map(|time| i64::from(time.total_seconds()))
+    };
+    let mut kept_frequencies: BTreeMap<Vec<usize>, Vec<Departures>> = BTreeMap::new();
+    let all: Vec<usize> = (0..stop_times.len()).collect();
+    let Some(template_start) = first_time(&all) else {
+        return kept_frequencies;
+    };
+    for frequency in frequencies.iter().filter(|f| f.headway_secs > 0) {
+        let headway = Time::new(0, 0, frequency.headway_secs);
+        // Consecutive departures with the same kept stop times
+        let mut runs: Vec<(Vec<usize>, Time, Time)> = Vec::new();
+        let mut departure = frequency.start_time;
+        while departure < frequency.end_time {
+            let shift = i64::from(departure.total_seconds()) - template_start;
+            match (
+                kept_stop_times(stop_times, window, date, shift, truncate),
+                runs.last_mut(),
+            ) {
+                (Some(kept), Some((last_kept, _, last))) if *last_kept == kept => {
+                    if *last + headway == departure {
+                        *last = departure;
+                    } else {
+                        runs.push((kept, departure, departure));
+                    }
+                }
+                (Some(kept), _) => runs.push((kept, departure, departure)),
+                (None, _) => {}
+            }
+            departure = departure + headway;
+        }
+        for (kept, first, last) in runs {
+            let Some(shift) = first_time(&kept) else {
+                continue;
+            };
+            let shift = shift - template_start;
+            if let (Some(start_time), Some(end_time)) =
+                (shift_time(first, shift), shift_time(last + headway, shift))
+            {
+                kept_frequencies.entry(kept).or_default().push((
+                    start_time,
+                    end_time,
+                    frequency.headway_secs,
+                ));
+            }
+        }
+    }
+    kept_frequencies
+}
+
+/// Keep only the vehicle journeys running in the time window, on the dates
+/// when they run in it. The times after 24:00:00 are on the following day.
+///
+/// A vehicle journey is kept with all its stop times when one of them is in
+/// the time window, or only with its stop times in the time window when
+/// `truncate` is set (and at least 2 of them are). As the stop times in the
+/// window can depend on the weekday, a vehicle journey can be split, the new
+/// vehicle journeys being identified by `<id>:<n>`, with their dates in new
+/// calendars identified by `<service_id>:<n>`.
+///
+/// The frequencies of a vehicle journey are clipped to their departures whose
+/// stop times are kept, the same way.
+pub fn restrict_time_window(
+    collections: &mut Collections,
+    window: &TimeWindow,
+    truncate: bool,
+) -> Result<()> {
+    let mut splitter = VehicleJourneySplitter::new(collections);
+    for vj in collections.vehicle_journeys.take() {
+        let dates = match splitter.dates(&vj.service_id) {
+            Some(dates) => dates,
+            None => {
+                splitter.keep(vj);
+                continue;
+            }
+        };
+        let frequencies = splitter.frequencies(&vj.id);
+        let all_departures: Vec<Departures> = frequencies
+            .iter()
+            .map(|f| (f.start_time, f.end_time, f.headway_secs))
+            .collect();
+        // Indexes of the kept stop times and kept frequencies, with their dates
+        let mut dates_by_variant: BTreeMap<(Vec<usize>, Vec<Departures>), BTreeSet<Date>> =
+            BTreeMap::new();
+        for &date in dates {
+            if frequencies.is_empty() {
+                if let Some(kept) = kept_stop_times(&vj.stop_times, window, date, 0, truncate) {
+                    dates_by_variant
+                        .entry((kept, Vec::new()))
+                        .or_default()
+                        .insert(date);
+                }
+                continue;
+            }
+            for variant in kept_frequencies(&vj.stop_times, frequencies, window, date, truncate) {
+                dates_by_variant.entry(variant).or_default().insert(date);
+            }
+        }
+        let unchanged = match dates_by_variant.iter().next() {
+            Some(((kept, departures), kept_dates)) => {
+                dates_by_variant.len() == 1
+                    && kept.len() == vj.stop_times.len()
+                    && *departures == all_departures
+                    && kept_dates == dates
+            }
+            None => false,
+        };
+        if unchanged {
+            splitter.keep(vj);
+            continue;
+        }
+        let variants = dates_by_variant
+            .into_iter()
+            .map(|((kept, departures), dates)| {
+                let stop_times = kept
+                    .into_iter()
+                    .map(|index| vj.stop_times[index].clone())
+                    .collect();
+                let frequencies = departures
+                    .into_iter()
+                    .map(|(start_time, end_time, headway_secs)| Frequency {
+                        vehicle_journey_id: vj.id.clone(),
+                        start_time,
+                        end_time,
+                        headway_secs,
+                    })
+                    .collect();
+                (dates, stop_times, frequencies)
+            })
+            .collect();
+        splitter.split(vj, variants);
+    }
+    splitter.finish(collections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{objects::Time, ModelBuilder};
+    use pretty_assertions::assert_eq;
+
+    // 2020-03-06 is a Friday
+    fn collections() -> Collections {
+        ModelBuilder::default()
+            .calendar("C", &["2020-03-05", "2020-03-06", "2020-03-07"])
+            .vj("VJ", |vj| {
+                vj.calendar("C")
+                    .st("SP1", "21:00:00")
+                    .st("SP2", "23:00:00")
+                    .st("SP3", "25:00:00");
+            })
+            .vj("morning", |vj| {
+                vj.calendar("C").st("SP1", "07:00:00").st("SP2", "08:00:00");
+            })
+            .build()
+            .into_collections()
+    }
+
+    fn times_and_dates(collections: &Collections, vj_id: &str) -> (Vec<Time>, Vec<String>) {
+        let vj = collections.vehicle_journeys.get(vj_id).unwrap();
+        let times = vj
+            .stop_times
+            .iter()
+            .map(|stop_time| stop_time.departure_time.unwrap())
+            .collect();
+        let dates = collections
+            .calendars
+            .get(&vj.service_id)
+            .unwrap()
+            .dates
+            .iter()
+            .map(|date| date.to_string())
+            .collect();
+        (times, dates)
+    }
+
+    #[test]
+    fn keep_vehicle_journeys_in_window() {
+        let mut collections = collections();
+        let window = "Fri 22:00:00-Sat 06:00:00".parse().unwrap();
+        restrict_time_window(&mut collections, &window, false).unwrap();
+        assert_eq!(
+            (
+                vec![
+                    Time::new(21, 0, 0),
+                    Time::new(23, 0, 0),
+                    Time::new(25, 0, 0)
+                ],
+                vec!["2020-03-06".to_string()]
+            ),
+            times_and_dates(&collections, "VJ")
+        );
+        assert_eq!(1, collections.vehicle_journeys.len());
+    }
+
+    #[test]
+    fn truncate_vehicle_journeys_to_window() {
+        let mut collections = collections();
+        let window = "Fri 22:00:00-Sat 06:00:00".parse().unwrap();
+        restrict_time_window(&mut collections, &window, true).unwrap();
+        assert_eq!(
+            (
+                vec![Time::new(23, 0, 0), Time::new(25, 0, 0)],
+                vec!["2020-03-06".to_string()]
+            ),
+            times_and_dates(&collections, "VJ")
+        );
+        assert_eq!(1, collections.vehicle_journeys.len());
+    }
+
+    #[test]
+    fn split_vehicle_journeys_by_weekday() {
+        // The last stop time of the Thursday is on Friday 01:00:00
+        let mut collections = collections();
+        let window = "Thu 22:00:00-Fri 23:30:00".parse().unwrap();
+        restrict_time_window(&mut collections, &window, true).unwrap();
+        assert_eq!(
+            (
+                vec![Time::new(21, 0, 0), Time::new(23, 0, 0)],
+                vec!["2020-03-06".to_string()]
+            ),
+            times_and_dates(&collections, "VJ")
+        );
+        assert_eq!(
+            (
+                vec![Time::new(23, 0, 0), Time::new(25, 0, 0)],
+                vec!["2020-03-05".to_string()]
+            ),
+            times_and_dates(&collections, "VJ:1")
+        );
+        assert_eq!(
+            (
+                vec![Time::new(7, 0, 0), Time::new(8, 0, 0)],
+                vec!["2020-03-06".to_string()]
+            ),
+            times_and_dates(&collections, "morning")
+        );
+    }
+
+    fn frequencies(collections: &Collections, vj_id: &str) -> Vec<Departures> {
+        collections
+            .frequencies
+            .values()
+            .filter(|frequency| frequency.vehicle_journey_id == vj_id)
+            .map(|frequency| {
+                (
+                    frequency.start_time,
+                    frequency.end_time,
+                    frequency.headway_secs,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clip_frequencies_to_window() {
+        let mut collections = ModelBuilder::default()
+            .calendar("C", &["2020-03-05", "2020-03-06", "2020-03-07"])
+            .vj("VJ", |vj| {
+                vj.calendar("C")
+                    .st("SP1", "00:00:00")
+                    .st("SP2", "00:30:00")
+                    .st("SP3", "01:00:00");
+            })
+            .build()
+            .into_collections();
+        collections.frequencies.push(Frequency {
+            vehicle_journey_id: "VJ".to_string(),
+            start_time: Time::new(20, 0, 0),
+            end_time: Time::new(24, 0, 0),
+            headway_secs: 3600,
+        });
+        let window = "Fri 22:15:00-Sat 06:00:00".parse().unwrap();
+        restrict_time_window(&mut collections, &window, true).unwrap();
+        assert_eq!(
+            vec![(Time::new(23, 0, 0), Time::new(24, 0, 0), 3600)],
+            frequencies(&collections, "VJ")
+        );
+        assert_eq!(
+            (
+                vec![Time::new(0, 0, 0), Time::new(0, 30, 0), Time::new(1, 0, 0)],
+                vec!["2020-03-06".to_string()]
+            ),
+            times_and_dates(&collections, "VJ")
+        );
+        // Only the last 2 stop times of the departure of 22:00:00 are in the
+        // window
+        assert_eq!(
+            vec![(Time::new(22, 30, 0), Time::new(23, 30, 0), 3600)],
+            frequencies(&collections, "VJ:1")
+        );
+        assert_eq!(
+            (
+                vec![Time::new(0, 30, 0), Time::new(1, 0, 0)],
+                vec!["2020-03-06".to_string()]
+            ),
+            times_and_dates(&collections, "VJ:1")
+        );
+        assert_eq!(2, collections.frequencies.len());
+    }
+}
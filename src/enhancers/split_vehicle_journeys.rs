@@ -0,0 +1,193 @@
+use crate::{
+    model::Collections,
+    objects::{Calendar, Date, Frequency, StopTime, VehicleJourney},
+    Result,
+};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use typed_index_collection::{Collection, CollectionWithId};
+
+/// Replaces the vehicle journeys of a `Collections` by variants running on a
+/// part of their dates, with other stop times.
+///
+/// The variant with the most dates keeps the identifier of the vehicle
+/// journey, the others are identified by `<id>:<n>`. The dates of the variants
+/// are in the existing calendar with the same dates if any, or in new
+/// calendars identified by `<service_id>:<n>`. The frequencies of a vehicle
+/// journey are replaced by the frequencies of its variants.
+pub(crate) struct VehicleJourneySplitter {
+    calendars: Vec<Calendar>,
+    calendar_dates: HashMap<String, BTreeSet<Date>>,
+    calendar_ids: HashMap<BTreeSet<Date>, String>,
+    used_calendar_ids: HashSet<String>,
+    calendar_counters: HashMap<String, usize>,
+    frequencies: Vec<Frequency>,
+    frequencies_by_vj: HashMap<String, Vec<Frequency>>,
+    vehicle_journeys: Vec<VehicleJourney>,
+    variant_frequencies: Vec<Frequency>,
+    // (id of the vehicle journey, id of the variant)
+    variants: Vec<(String, String)>,
+}
+
+impl VehicleJourneySplitter {
+    /// Takes the calendars and the frequencies of the `Collections`.
+    pub(crate) fn new(collections: &mut Collections) -> Self {
+        let calendars = collections.calendars.take();
+        let calendar_dates = calendars
+            .iter()
+            .map(|calendar| (calendar.id.clone(), calendar.dates.clone()))
+            .collect();
+        let calendar_ids = calendars
+            .iter()
+            .map(|calendar| (calendar.dates.clone(), calendar.id.clone()))
+            .collect();
+        let used_calendar_ids = calendars
+            .iter()
+            .map(|calendar| calendar.id.clone())
+            .collect();
+        let frequencies = collections.frequencies.take();
+        let mut frequencies_by_vj: HashMap<String, Vec<Frequency>> = HashMap::new();
+        for frequency in &frequencies {
+            frequencies_by_vj
+                .entry(frequency.vehicle_journey_id.clone())
+                .or_default()
+                .push(frequency.clone());
+        }
+        VehicleJourneySplitter {
+            calendars,
+            calendar_dates,
+            calendar_ids,
+            used_calendar_ids,
+            calendar_counters: HashMap::new(),
+            frequencies,
+            frequencies_by_vj,
+            vehicle_journeys: Vec::new(),
+            variant_frequencies: Vec::new(),
+            variants: Vec::new(),
+        }
+    }
+
+    /// Dates of a calendar of the `Collections`.
+    pub(crate) fn dates(&self, service_id: &str) -> Option<&BTreeSet<Date>> {
+        self.calendar_dates.get(service_id)
+    }
+
+    /// Frequencies of a vehicle journey of the `Collections`, empty when its
+    /// departures are given by its stop times.
+    pub(crate) fn frequencies(&self, vj_id: &str) -> &[Frequency] {
+        self.frequencies_by_vj
+            .get(vj_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    fn calendar_id(&mut self, service_id: &str, dates: BTreeSet<Date>) -> String {
+        if let Some(calendar_id) = self.calendar_ids.get(&dates) {
+            return calendar_id.clone();
+        }
+        let counter = self
+            .calendar_counters
+            .entry(service_id.to_string())
+            .or_default();
+        let calendar_id = loop {
+            *counter += 1;
+            let calendar_id = format!("{service_id}:{counter}");
+            if self.used_calendar_ids.insert(calendar_id.clone()) {
+                break calendar_id;
+            }
+        };
+        self.calendars.push(Calendar {
+            id: calendar_id.clone(),
+            dates: dates.clone(),
+        });
+        self.calendar_ids.insert(dates, calendar_id.clone());
+        calendar_id
+    }
+
+    /// Keeps the vehicle journey unchanged.
+    pub(crate) fn keep(&mut self, vj: VehicleJourney) {
+        self.vehicle_journeys.push(vj);
+    }
+
+    /// Replaces the vehicle journey by its variants, each with its dates, its
+    /// stop times and its frequencies. The variants without dates are skipped,
+    /// as well as the variants without frequencies of a vehicle journey with
+    /// frequencies.
+    pub(crate) fn split(
+        &mut self,
+        vj: VehicleJourney,
+        mut variants: Vec<(BTreeSet<Date>, Vec<StopTime>, Vec<Frequency>)>,
+    ) {
+        let has_frequencies = self.frequencies_by_vj.contains_key(&vj.id);
+        variants.retain(|(dates, _, frequencies)| {
+            !dates.is_empty() && (!has_frequencies || !frequencies.is_empty())
+        });
+        variants.sort_by_key(|(dates, _, _)| std::cmp::Reverse(dates.len()));
+        for (rank, (dates, stop_times, frequencies)) in variants.into_iter().enumerate() {
+            let mut variant = vj.clone();
+            if rank > 0 {
+                variant.id = format!("{}:{}", vj.id, rank);
+            }
+            variant.service_id = self.calendar_id(&vj.service_id, dates);
+            variant.stop_times = stop_times;
+            self.variant_frequencies
+                .extend(frequencies.into_iter().map(|frequency| Frequency {
+                    vehicle_journey_id: variant.id.clone(),
+                    ..frequency
+                }));
+            self.variants.push((vj.id.clone(), variant.id.clone()));
+            self.vehicle_journeys.push(variant);
+        }
+    }
+
+    /// Puts the calendars, the vehicle journeys and the frequencies back in the
+    /// `Collections`, with the headsigns, comments and identifiers of the stop
+    /// times of the variants.
+    pub(crate) fn finish(self, collections: &mut Collections) -> Result<()> {
+        let vehicle_journeys = CollectionWithId::new(self.vehicle_journeys)?;
+        let sequences = |vj_id: &str| -> HashSet<u32> {
+            vehicle_journeys
+                .get(vj_id)
+                .map(|vj| vj.stop_times.iter().map(|st| st.sequence).collect())
+                .unwrap_or_default()
+        };
+        let mut variants_by_vj: HashMap<String, Vec<(String, HashSet<u32>)>> = HashMap::new();
+        for (vj_id, variant_id) in self.variants {
+            let variant_sequences = sequences(&variant_id);
+            variants_by_vj
+                .entry(vj_id)
+                .or_default()
+                .push((variant_id, variant_sequences));
+        }
+        let split_values = |values: &mut HashMap<(String, u32), String>, is_id: bool| {
+            let split: Vec<_> = values
+                .iter()
+                .filter(|((vj_id, _), _)| variants_by_vj.contains_key(vj_id))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            for ((vj_id, sequence), value) in split {
+                values.remove(&(vj_id.clone(), sequence));
+                for (variant_id, variant_sequences) in &variants_by_vj[&vj_id] {
+                    if !variant_sequences.contains(&sequence) {
+                        continue;
+                    }
+                    let value = if is_id && *variant_id != vj_id {
+                        format!("{variant_id}-{sequence}")
+                    } else {
+                        value.clone()
+                    };
+                    values.insert((variant_id.clone(), sequence), value);
+                }
+            }
+        };
+        split_values(&mut collections.stop_time_headsigns, false);
+        split_values(&mut collections.stop_time_comments, false);
+        split_values(&mut collections.stop_time_ids, true);
+        let mut frequencies = self.frequencies;
+        frequencies.retain(|frequency| !variants_by_vj.contains_key(&frequency.vehicle_journey_id));
+        frequencies.extend(self.variant_frequencies);
+        collections.frequencies = Collection::new(frequencies);
+        collections.calendars = CollectionWithId::new(self.calendars)?;
+        collections.vehicle_journeys = vehicle_journeys;
+        Ok(())
+    }
+}
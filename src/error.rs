@@ -0,0 +1,338 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Structured errors describing invalid input data.
+//!
+//! The functions of the crate return [`crate::Error`] (an [`anyhow::Error`]).
+//! The contract is the following: when the failure comes from the input data
+//! (for example with the readers or [`crate::Model::new`]), the error carries
+//! a [`DataError`], possibly wrapped in some context. It can be retrieved with
+//! [`DataError::find`], or with [`ResultExt::typed_error`] which turns the
+//! result into a [`TypedError`] to be matched without downcasting.
+//!
+//! ```
+//! use transit_model::{error::ResultExt, DataError, TypedError};
+//!
+//! match transit_model::ntfs::read("tests/fixtures/i_m_not_here").typed_error() {
+//!     Err(TypedError::Data(DataError::InvalidInput { format, .. })) => assert_eq!("ntfs", format),
+//!     result => panic!("unexpected result {:?}", result.map(|_| ())),
+//! }
+//! ```
+
+use crate::Error;
+use std::path::PathBuf;
+
+/// An error in the input data.
+#[derive(Debug, PartialEq, Eq, Clone, thiserror::Error)]
+#[non_exhaustive]
+pub enum DataError {
+    /// The input is neither a file nor a directory.
+    #[error("file {path:?} is neither a file nor a directory, cannot read a {format} from it")]
+    InvalidInput {
+        /// Path of the input.
+        path: PathBuf,
+        /// Format expected to be read from the input (`ntfs`, `gtfs`).
+        format: &'static str,
+    },
+    /// A mandatory file is missing.
+    #[error("file {file:?} not found")]
+    MissingFile {
+        /// Path of the missing file.
+        file: PathBuf,
+    },
+    /// A record of a file can't be read.
+    #[error("Error reading {file:?}{}{}: {message}", at_line(.line), in_column(.column))]
+    InvalidRecord {
+        /// Path of the file.
+        file: PathBuf,
+        /// Line of the record in the file, starting at 1.
+        line: Option<u64>,
+        /// Name of the invalid column.
+        column: Option<String>,
+        /// Description of the error.
+        message: String,
+    },
+    /// Two objects of the same type have the same identifier.
+    #[error("identifier {id} already exists ({object_type} in {file})")]
+    DuplicateIdentifier {
+        /// Name of the file of the objects.
+        file: String,
+        /// Type of the objects.
+        object_type: &'static str,
+        /// The duplicated identifier.
+        id: String,
+    },
+    /// An object of a file references an object which does not exist.
+    #[error(
+        "Invalid id in {file} line {line}: {}.{field}={value:?}{}",
+        snake_case(.object_type),
+        for_object(.object_type, .id)
+    )]
+    InvalidReference {
+        /// Name of the file of the object.
+        file: String,
+        /// Line of the object in the file, starting at 1.
+        line: u64,
+        /// Type of the object.
+        object_type: &'static str,
+        /// Identifier of the object, if the object has one.
+        id: Option<String>,
+        /// Field containing the reference.
+        field: &'static str,
+        /// Identifier of the referenced object.
+        value: String,
+    },
+    /// An object of the collections references an object which does not
+    /// exist. Contrary to [`DataError::InvalidReference`], the error is found
+    /// once the files are read (for example by [`crate::Model::new`]), so its
+    /// location in the input is unknown.
+    #[error(
+        "Invalid id: {}.{field}={value:?}{}",
+        snake_case(.object_type),
+        for_object(.object_type, .id)
+    )]
+    UnknownReference {
+        /// Type of the object.
+        object_type: &'static str,
+        /// Identifier of the object, if the object has one.
+        id: Option<String>,
+        /// Field containing the reference.
+        field: &'static str,
+        /// Identifier of the referenced object.
+        value: String,
+    },
+    /// A trip and its journey pattern are on different routes.
+    #[error(
+        "trip {trip_id:?} is on route {route_id:?} but its journey pattern \
+         {journey_pattern_id:?} is on route {journey_pattern_route_id:?}"
+    )]
+    InconsistentJourneyPattern {
+        /// Identifier of the trip.
+        trip_id: String,
+        /// Route of the trip.
+        route_id: String,
+        /// Identifier of the journey pattern.
+        journey_pattern_id: String,
+        /// Route of the journey pattern.
+        journey_pattern_route_id: String,
+    },
+    /// The stop times of a trip are not consistent, for example two stop
+    /// times with the same sequence or times going backwards.
+    #[error("{message}")]
+    InvalidStopTimes {
+        /// Identifier of the trip.
+        trip_id: String,
+        /// Description of the error.
+        message: String,
+    },
+    /// The geographic coordinates of a stop are not valid.
+    #[error("{object_type} {id:?} geographic coordinates are not valid")]
+    InvalidCoordinates {
+        /// Type of the stop.
+        object_type: &'static str,
+        /// Identifier of the stop.
+        id: String,
+    },
+    /// A trip can't be used, for example a trip without stop times.
+    #[error("trip {trip_id:?} is dropped: {reason}")]
+    DroppedTrip {
+        /// Identifier of the trip.
+        trip_id: String,
+        /// Why the trip can't be used.
+        reason: String,
+    },
+}
+
+fn at_line(line: &Option<u64>) -> String {
+    line.map(|line| format!(" at line {line}"))
+        .unwrap_or_default()
+}
+
+fn in_column(column: &Option<String>) -> String {
+    column
+        .as_ref()
+        .map(|column| format!(" in column {column:?}"))
+        .unwrap_or_default()
+}
+
+fn for_object(object_type: &str, id: &Option<String>) -> String {
+    id.as_ref()
+        .map(|id| format!(" for {object_type} {id:?}"))
+        .unwrap_or_default()
+}
+
+/// `StopPoint` becomes `stop_point`, as the objects are named in the files.
+fn snake_case(name: &str) -> String {
+    let mut snake_case = String::new();
+    for (i, c) in name.char_indices() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake_case.push('_');
+            }
+            snake_case.extend(c.to_lowercase());
+        } else {
+            snake_case.push(c);
+        }
+    }
+    snake_case
+}
+
+/// An error of the crate (returned by the readers, [`crate::Model::new`]...),
+/// separating the errors in the input data from the other failures.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum TypedError {
+    /// The input data is invalid.
+    #[error(transparent)]
+    Data(DataError),
+    /// Any other failure, for example an I/O error.
+    #[error(transparent)]
+    Other(Error),
+}
+
+impl From<Error> for TypedError {
+    fn from(error: Error) -> Self {
+        match DataError::find(&error) {
+            Some(data_error) => TypedError::Data(data_error.clone()),
+            None => TypedError::Other(error),
+        }
+    }
+}
+
+/// Extension of [`crate::Result`] to match the errors by type.
+pub trait ResultExt<T> {
+    /// Converts the error into a [`TypedError`].
+    fn typed_error(self) -> Result<T, TypedError>;
+}
+
+impl<T> ResultExt<T> for crate::Result<T> {
+    fn typed_error(self) -> Result<T, TypedError> {
+        self.map_err(TypedError::from)
+    }
+}
+
+impl DataError {
+    /// Returns the `DataError` at the origin of `error`, if any.
+    pub fn find(error: &Error) -> Option<&DataError> {
+        error
+            .downcast_ref::<DataError>()
+            .or_else(|| error.chain().find_map(|e| e.downcast_ref::<DataError>()))
+    }
+
+    pub(crate) fn from_csv(
+        file: PathBuf,
+        headers: Option<&csv::StringRecord>,
+        error: &csv::Error,
+    ) -> Self {
+        let line = error.position().map(csv::Position::line);
+        let (column, message) = match error.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => (
+                err.field()
+                    .and_then(|field| headers?.get(field as usize))
+                    .map(str::to_string),
+                err.kind().to_string(),
+            ),
+            _ => (None, error.to_string()),
+        };
+        DataError::InvalidRecord {
+            file,
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+/// Short name of the type `T`, used to describe objects in errors.
+pub(crate) fn object_type<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn find_through_context() {
+        let data_error = DataError::MissingFile {
+            file: PathBuf::from("stops.txt"),
+        };
+        let error = Err::<(), _>(data_error.clone())
+            .context("impossible to read ntfs")
+            .unwrap_err();
+        assert_eq!(Some(&data_error), DataError::find(&error));
+    }
+
+    #[test]
+    fn invalid_record_message() {
+        let error = DataError::InvalidRecord {
+            file: PathBuf::from("stops.txt"),
+            line: Some(42),
+            column: Some("stop_lat".into()),
+            message: "invalid float literal".into(),
+        };
+        assert_eq!(
+            "Error reading \"stops.txt\" at line 42 in column \"stop_lat\": invalid float literal",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn invalid_reference_message() {
+        let error = DataError::InvalidReference {
+            file: "transfers.txt".to_string(),
+            line: 42,
+            object_type: "Transfer",
+            id: None,
+            field: "from_stop_id",
+            value: "sp1".to_string(),
+        };
+        assert_eq!(
+            "Invalid id in transfers.txt line 42: transfer.from_stop_id=\"sp1\"",
+            error.to_string()
+        );
+        let error = DataError::UnknownReference {
+            object_type: "VehicleJourney",
+            id: Some("vj1".to_string()),
+            field: "route_id",
+            value: "r1".to_string(),
+        };
+        assert_eq!(
+            "Invalid id: vehicle_journey.route_id=\"r1\" for VehicleJourney \"vj1\"",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn typed_error() {
+        let data_error = DataError::MissingFile {
+            file: PathBuf::from("stops.txt"),
+        };
+        let result = Err::<(), _>(data_error.clone()).context("impossible to read ntfs");
+        match result.typed_error() {
+            Err(TypedError::Data(error)) => assert_eq!(data_error, error),
+            result => panic!("unexpected result {:?}", result),
+        }
+        let result: crate::Result<()> = Err(anyhow::anyhow!("network is down"));
+        assert!(matches!(result.typed_error(), Err(TypedError::Other(_))));
+    }
+
+    #[test]
+    fn short_object_type() {
+        assert_eq!("StopPoint", object_type::<crate::objects::StopPoint>());
+    }
+}
@@ -0,0 +1,706 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Computes the price of an itinerary with the fares of a [`Model`]
+//! (`tickets`, `ticket_uses`, `ticket_prices`, `ticket_use_perimeters` and
+//! `ticket_use_restrictions`).
+//!
+//! A ticket use can pay a sequence of consecutive legs of the itinerary when:
+//! * the line of every leg is in its perimeter (the line or its network is
+//!   included, and neither the line nor its network is excluded); a ticket
+//!   use with a perimeter on other objects (stop areas, routes...) is not
+//!   supported and never used
+//! * the number of transfers is not greater than `max_transfers`
+//! * every boarding happens at most `boarding_time_limit` seconds after the
+//!   first boarding
+//! * every alighting happens at most `alighting_time_limit` seconds after the
+//!   first boarding
+//! * one of its restrictions, if any, is satisfied: the origin and the
+//!   destination stop areas for an `OD` restriction; for a `zone`
+//!   restriction, the first boarding is in the origin zone, the last
+//!   alighting in the destination zone, and each leg boards and alights in
+//!   one of these 2 zones
+//! * its ticket has a price valid on the date of the first leg.
+//!
+//! ```
+//! # use transit_model::{fares::*, objects::Time};
+//! # fn run() -> transit_model::Result<()> {
+//! let model = transit_model::ntfs::read("tests/fixtures/ntfs/")?;
+//! let calculator = FareCalculator::new(&model);
+//! let legs = [Leg {
+//!     vehicle_journey_id: "RERAB1".into(),
+//!     boarding_stop_point_id: "GDLR".into(),
+//!     alighting_stop_point_id: "NATR".into(),
+//!     boarding_time: Time::new(9, 45, 0),
+//!     alighting_time: Time::new(9, 49, 0),
+//!     date: "2019-06-03".parse()?,
+//! }];
+//! for fare in calculator.cheapest_fares(&legs)? {
+//!     println!("{} {}", fare.price, fare.currency);
+//! }
+//! # Ok(())
+//! # }
+//! # run().unwrap()
+//! ```
+
+use crate::{
+    model::Model,
+    objects::{
+        Date, Line, ObjectType, PerimeterAction, RestrictionType, StopPoint, StopTime, TicketPrice,
+        TicketUse, TicketUseRestriction, Time, VehicleJourney,
+    },
+    Result,
+};
+use anyhow::{anyhow, bail};
+use chrono::Datelike;
+use rust_decimal::Decimal;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    ops::Range,
+};
+use tracing::warn;
+
+/// A leg of an itinerary: a vehicle journey used between 2 stop points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leg {
+    /// Identifier of the vehicle journey.
+    pub vehicle_journey_id: String,
+    /// Identifier of the stop point where the vehicle journey is boarded.
+    pub boarding_stop_point_id: String,
+    /// Identifier of the stop point where the vehicle journey is alighted.
+    pub alighting_stop_point_id: String,
+    /// Boarding time, relative to `date` (may be greater than 24:00:00).
+    pub boarding_time: Time,
+    /// Alighting time, relative to `date` (may be greater than 24:00:00).
+    pub alighting_time: Time,
+    /// Date of the vehicle journey.
+    pub date: Date,
+}
+
+/// A ticket bought to pay some legs of an itinerary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FareTicket {
+    /// Identifier of the ticket.
+    pub ticket_id: String,
+    /// Identifier of the ticket use applied.
+    pub ticket_use_id: String,
+    /// Price of the ticket.
+    pub price: Decimal,
+    /// Indexes of the legs paid with the ticket.
+    pub legs: Range<usize>,
+}
+
+/// The tickets to buy to pay a whole itinerary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fare {
+    /// ISO 4217 code of the currency of the prices.
+    pub currency: String,
+    /// Total price of the itinerary.
+    pub price: Decimal,
+    /// Tickets to buy, in the order of the legs.
+    pub tickets: Vec<FareTicket>,
+}
+
+#[derive(Default)]
+struct Perimeter<'a> {
+    included_networks: HashSet<&'a str>,
+    excluded_networks: HashSet<&'a str>,
+    included_lines: HashSet<&'a str>,
+    excluded_lines: HashSet<&'a str>,
+}
+
+impl Perimeter<'_> {
+    fn contains(&self, line: &Line) -> bool {
+        !self.excluded_lines.contains(line.id.as_str())
+            && !self.excluded_networks.contains(line.network_id.as_str())
+            && (self.included_lines.contains(line.id.as_str())
+                || self.included_networks.contains(line.network_id.as_str()))
+    }
+}
+
+/// A leg with the objects it references.
+struct ResolvedLeg<'a> {
+    line: &'a Line,
+    boarding: &'a StopPoint,
+    alighting: &'a StopPoint,
+    /// Boarding and alighting times in seconds since a fixed date.
+    boarding_time: i64,
+    alighting_time: i64,
+    date: Date,
+}
+
+/// Computes the cheapest tickets to pay an itinerary.
+pub struct FareCalculator<'a> {
+    model: &'a Model,
+    perimeters: HashMap<&'a str, Perimeter<'a>>,
+    restrictions: HashMap<&'a str, Vec<&'a TicketUseRestriction>>,
+    prices: HashMap<&'a str, Vec<&'a TicketPrice>>,
+}
+
+impl<'a> FareCalculator<'a> {
+    /// Indexes the fares of `model`.
+    pub fn new(model: &'a Model) -> Self {
+        let mut perimeters: HashMap<&str, Perimeter<'_>> = HashMap::new();
+        let mut unsupported_ticket_uses = HashSet::new();
+        for perimeter in model.ticket_use_perimeters.values() {
+            let entry = perimeters
+                .entry(perimeter.ticket_use_id.as_str())
+                .or_default();
+            let object_id = perimeter.object_id.as_str();
+            match (&perimeter.object_type, &perimeter.perimeter_action) {
+                (ObjectType::Network, PerimeterAction::Included) => {
+                    entry.included_networks.insert(object_id);
+                }
+                (ObjectType::Line, PerimeterAction::Included) => {
+                    entry.included_lines.insert(object_id);
+                }
+                (ObjectType::Network, PerimeterAction::Excluded) => {
+                    entry.excluded_networks.insert(object_id);
+                }
+                (ObjectType::Line, PerimeterAction::Excluded) => {
+                    entry.excluded_lines.insert(object_id);
+                }
+                (object_type, _) => {
+                    warn!(
+                        "ticket use {:?} is skipped, its perimeter on {} {:?} is not supported",
+                        perimeter.ticket_use_id,
+                        object_type.as_str(),
+                        object_id
+                    );
+                    unsupported_ticket_uses.insert(perimeter.ticket_use_id.as_str());
+                }
+            }
+        }
+        // Without a perimeter, a ticket use can't be used
+        perimeters.retain(|ticket_use_id, _| !unsupported_ticket_uses.contains(ticket_use_id));
+        let mut restrictions: HashMap<&str, Vec<_>> = HashMap::new();
+        for restriction in model.ticket_use_restrictions.values() {
+            restrictions
+                .entry(restriction.ticket_use_id.as_str())
+                .or_default()
+                .push(restriction);
+        }
+        let mut prices: HashMap<&str, Vec<_>> = HashMap::new();
+        for price in model.ticket_prices.values() {
+            prices
+                .entry(price.ticket_id.as_str())
+                .or_default()
+                .push(price);
+        }
+        FareCalculator {
+            model,
+            perimeters,
+            restrictions,
+            prices,
+        }
+    }
+
+    /// Returns, for each currency in which the whole itinerary can be paid,
+    /// the cheapest combination of tickets. Prices in different currencies
+    /// are never mixed, the result is empty if the itinerary can't be paid.
+    ///
+    /// Fails if a leg references an unknown object.
+    pub fn cheapest_fares(&self, legs: &[Leg]) -> Result<Vec<Fare>> {
+        if legs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let legs = legs
+            .iter()
+            .map(|leg| self.resolve(leg))
+            .collect::<Result<Vec<_>>>()?;
+        let currencies: BTreeSet<&str> = self
+            .model
+            .ticket_prices
+            .values()
+            .map(|price| price.currency.as_str())
+            .collect();
+        Ok(currencies
+            .into_iter()
+            .filter_map(|currency| self.cheapest_fare(&legs, currency))
+            .collect())
+    }
+
+    fn resolve(&self, leg: &Leg) -> Result<ResolvedLeg<'a>> {
+        let model = self.model;
+        let vj = model
+            .vehicle_journeys
+            .get(&leg.vehicle_journey_id)
+            .ok_or_else(|| anyhow!("trip {:?} not found", leg.vehicle_journey_id))?;
+        let line = model
+            .routes
+            .get(&vj.route_id)
+            .and_then(|route| model.lines.get(&route.line_id))
+            .ok_or_else(|| anyhow!("line of trip {:?} not found", vj.id))?;
+        let stop_point = |id: &str| {
+            model
+                .stop_points
+                .get(id)
+                .ok_or_else(|| anyhow!("stop point {:?} not found", id))
+        };
+        let boarding = stop_point(&leg.boarding_stop_point_id)?;
+        let alighting = stop_point(&leg.alighting_stop_point_id)?;
+        if !serves(model, vj, boarding, alighting) {
+            bail!(
+                "trip {:?} does not serve stop point {:?} then stop point {:?}",
+                vj.id,
+                boarding.id,
+                alighting.id
+            );
+        }
+        let day = i64::from(leg.date.num_days_from_ce()) * 24 * 60 * 60;
+        Ok(ResolvedLeg {
+            line,
+            boarding,
+            alighting,
+            boarding_time: day + i64::from(leg.boarding_time.total_seconds()),
+            alighting_time: day + i64::from(leg.alighting_time.total_seconds()),
+            date: leg.date,
+        })
+    }
+
+    /// Cheapest combination of tickets in `currency` (dynamic programming
+    /// on the index of the first leg not paid yet).
+    fn cheapest_fare(&self, legs: &[ResolvedLeg<'_>], currency: &str) -> Option<Fare> {
+        // best[i] is the cheapest way to pay legs[..i]: its price and the
+        // last ticket bought
+        let mut best: Vec<Option<(Decimal, FareTicket)>> = vec![None; legs.len() + 1];
+        for start in 0..legs.len() {
+            let paid = match (start, &best[start]) {
+                (0, _) => Decimal::ZERO,
+                (_, Some((price, _))) => *price,
+                (_, None) => continue,
+            };
+            for ticket_use in self.model.ticket_uses.values() {
+                let price = match self.price(ticket_use, currency, legs[start].date) {
+                    Some(price) => price,
+                    None => continue,
+                };
+                for end in start + 1..=legs.len() {
+                    let segment = &legs[start..end];
+                    if !self.is_allowed(ticket_use, segment) {
+                        // The constraints on the last leg only get stricter
+                        // with more legs, except for the restrictions on
+                        // the destination
+                        if !self.is_in_perimeter(ticket_use, &legs[end - 1])
+                            || !within_limits(ticket_use, segment)
+                        {
+                            break;
+                        }
+                        continue;
+                    }
+                    let total = paid + price;
+                    if best[end].as_ref().is_none_or(|(best, _)| total < *best) {
+                        best[end] = Some((
+                            total,
+                            FareTicket {
+                                ticket_id: ticket_use.ticket_id.clone(),
+                                ticket_use_id: ticket_use.id.clone(),
+                                price,
+                                legs: start..end,
+                            },
+                        ));
+                    }
+                }
+            }
+        }
+
+        let (price, _) = best[legs.len()].as_ref()?;
+        let mut tickets = Vec::new();
+        let mut end = legs.len();
+        while end > 0 {
+            let (_, ticket) = best[end].as_ref()?;
+            end = ticket.legs.start;
+            tickets.push(ticket.clone());
+        }
+        tickets.reverse();
+        Some(Fare {
+            currency: currency.to_string(),
+            price: *price,
+            tickets,
+        })
+    }
+
+    /// Cheapest price of the ticket of `ticket_use` valid on `date`.
+    fn price(&self, ticket_use: &TicketUse, currency: &str, date: Date) -> Option<Decimal> {
+        self.model.tickets.get(&ticket_use.ticket_id)?;
+        self.prices
+            .get(ticket_use.ticket_id.as_str())?
+            .iter()
+            .filter(|price| price.currency == currency)
+            .filter(|price| {
+                price.ticket_validity_start <= date && date <= price.ticket_validity_end
+            })
+            .map(|price| price.price)
+            .min()
+    }
+
+    fn is_in_perimeter(&self, ticket_use: &TicketUse, leg: &ResolvedLeg<'_>) -> bool {
+        self.perimeters
+            .get(ticket_use.id.as_str())
+            .is_some_and(|perimeter| perimeter.contains(leg.line))
+    }
+
+    fn is_allowed(&self, ticket_use: &TicketUse, legs: &[ResolvedLeg<'_>]) -> bool {
+        legs.iter().all(|leg| self.is_in_perimeter(ticket_use, leg))
+            && within_limits(ticket_use, legs)
+            && self
+                .restrictions
+                .get(ticket_use.id.as_str())
+                .is_none_or(|restrictions| {
+                    restrictions
+                        .iter()
+                        .any(|restriction| satisfies(restriction, legs))
+                })
+    }
+}
+
+/// Whether `vj` stops at `boarding`, then later at `alighting`.
+fn serves(model: &Model, vj: &VehicleJourney, boarding: &StopPoint, alighting: &StopPoint) -> bool {
+    let stops_at = |st: &StopTime, stop_point: &StopPoint| {
+        model.stop_points[st.stop_point_idx].id == stop_point.id
+    };
+    vj.stop_times
+        .iter()
+        .position(|st| stops_at(st, boarding))
+        .is_some_and(|i| {
+            vj.stop_times[i + 1..]
+                .iter()
+                .any(|st| stops_at(st, alighting))
+        })
+}
+
+/// Checks the transfers and time limits of `ticket_use`.
+fn within_limits(ticket_use: &TicketUse, legs: &[ResolvedLeg<'_>]) -> bool {
+    let first_boarding = legs[0].boarding_time;
+    let within = |limit: Option<u32>, time: i64| {
+        limit.is_none_or(|limit| time - first_boarding <= i64::from(limit))
+    };
+    ticket_use
+        .max_transfers
+        .is_none_or(|max| legs.len() - 1 <= max as usize)
+        && legs.iter().all(|leg| {
+            within(ticket_use.boarding_time_limit, leg.boarding_time)
+                && within(ticket_use.alighting_time_limit, leg.alighting_time)
+        })
+}
+
+fn satisfies(restriction: &TicketUseRestriction, legs: &[ResolvedLeg<'_>]) -> bool {
+    match restriction.restriction_type {
+        RestrictionType::OriginDestination => {
+            legs[0].boarding.stop_area_id == restriction.use_origin
+                && legs[legs.len() - 1].alighting.stop_area_id == restriction.use_destination
+        }
+        RestrictionType::Zone => {
+            let origin = restriction.use_origin.as_str();
+            let destination = restriction.use_destination.as_str();
+            let zone = |stop_point: &StopPoint| stop_point.fare_zone_id.as_deref();
+            let in_zones = |stop_point: &StopPoint| {
+                zone(stop_point).is_some_and(|zone| zone == origin || zone == destination)
+            };
+            zone(legs[0].boarding) == Some(origin)
+                && zone(legs[legs.len() - 1].alighting) == Some(destination)
+                && legs
+                    .iter()
+                    .all(|leg| in_zones(leg.boarding) && in_zones(leg.alighting))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::Collections,
+        objects::{Ticket, TicketUsePerimeter},
+        ModelBuilder,
+    };
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+
+    fn add_ticket(
+        collections: &mut Collections,
+        id: &str,
+        price: Decimal,
+        network_ids: &[&str],
+        configure: impl FnOnce(&mut TicketUse),
+    ) {
+        collections
+            .tickets
+            .push(Ticket {
+                id: id.to_string(),
+                name: id.to_string(),
+                comment: None,
+            })
+            .unwrap();
+        let mut ticket_use = TicketUse {
+            id: format!("use:{id}"),
+            ticket_id: id.to_string(),
+            max_transfers: None,
+            boarding_time_limit: None,
+            alighting_time_limit: None,
+        };
+        configure(&mut ticket_use);
+        for network_id in network_ids {
+            collections.ticket_use_perimeters.push(TicketUsePerimeter {
+                ticket_use_id: ticket_use.id.clone(),
+                object_type: ObjectType::Network,
+                object_id: network_id.to_string(),
+                perimeter_action: PerimeterAction::Included,
+            });
+        }
+        collections.ticket_uses.push(ticket_use).unwrap();
+        collections.ticket_prices.push(TicketPrice {
+            ticket_id: id.to_string(),
+            price,
+            currency: "EUR".to_string(),
+            ticket_validity_start: Date::from_ymd_opt(2020, 1, 1).unwrap(),
+            ticket_validity_end: Date::from_ymd_opt(2020, 12, 31).unwrap(),
+        });
+    }
+
+    fn model() -> Model {
+        let mut collections = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.line("L1")
+                    .network("N1")
+                    .st("A", "10:00:00")
+                    .st("B", "10:20:00");
+            })
+            .vj("vj2", |vj| {
+                vj.line("L2")
+                    .network("N2")
+                    .st("C", "10:30:00")
+                    .st("D", "10:50:00");
+            })
+            .vj("vj3", |vj| {
+                vj.line("L2")
+                    .network("N2")
+                    .st("C", "11:30:00")
+                    .st("D", "11:50:00");
+            })
+            .build()
+            .into_collections();
+        add_ticket(&mut collections, "n1", dec!(1.50), &["N1"], |_| {});
+        add_ticket(&mut collections, "n2", dec!(2.00), &["N2"], |_| {});
+        add_ticket(
+            &mut collections,
+            "all",
+            dec!(3.00),
+            &["N1", "N2"],
+            |ticket_use| {
+                ticket_use.max_transfers = Some(1);
+                ticket_use.alighting_time_limit = Some(3600);
+            },
+        );
+        Model::new(collections).unwrap()
+    }
+
+    fn leg(vj: &str, from: &str, to: &str, boarding: Time, alighting: Time) -> Leg {
+        Leg {
+            vehicle_journey_id: vj.to_string(),
+            boarding_stop_point_id: from.to_string(),
+            alighting_stop_point_id: to.to_string(),
+            boarding_time: boarding,
+            alighting_time: alighting,
+            date: Date::from_ymd_opt(2020, 6, 1).unwrap(),
+        }
+    }
+
+    #[test]
+    fn one_ticket_for_the_whole_itinerary() {
+        let model = model();
+        let legs = [
+            leg("vj1", "A", "B", Time::new(10, 0, 0), Time::new(10, 20, 0)),
+            leg("vj2", "C", "D", Time::new(10, 30, 0), Time::new(10, 50, 0)),
+        ];
+        let fares = FareCalculator::new(&model).cheapest_fares(&legs).unwrap();
+        assert_eq!(
+            vec![Fare {
+                currency: "EUR".to_string(),
+                price: dec!(3.00),
+                tickets: vec![FareTicket {
+                    ticket_id: "all".to_string(),
+                    ticket_use_id: "use:all".to_string(),
+                    price: dec!(3.00),
+                    legs: 0..2,
+                }],
+            }],
+            fares
+        );
+    }
+
+    #[test]
+    fn time_limit_exceeded() {
+        let model = model();
+        let legs = [
+            leg("vj1", "A", "B", Time::new(10, 0, 0), Time::new(10, 20, 0)),
+            leg("vj3", "C", "D", Time::new(11, 30, 0), Time::new(11, 50, 0)),
+        ];
+        let fares = FareCalculator::new(&model).cheapest_fares(&legs).unwrap();
+        assert_eq!(1, fares.len());
+        assert_eq!(dec!(3.50), fares[0].price);
+        let tickets: Vec<_> = fares[0]
+            .tickets
+            .iter()
+            .map(|ticket| (ticket.ticket_id.as_str(), ticket.legs.clone()))
+            .collect();
+        assert_eq!(vec![("n1", 0..1), ("n2", 1..2)], tickets);
+    }
+
+    #[test]
+    fn origin_destination_restriction() {
+        let mut collections = model().into_collections();
+        add_ticket(&mut collections, "od", dec!(1.00), &["N1"], |_| {});
+        collections
+            .ticket_use_restrictions
+            .push(TicketUseRestriction {
+                ticket_use_id: "use:od".to_string(),
+                restriction_type: RestrictionType::OriginDestination,
+                use_origin: "sa:B".to_string(),
+                use_destination: "sa:A".to_string(),
+            });
+        let model = Model::new(collections).unwrap();
+        let calculator = FareCalculator::new(&model);
+
+        let legs = [leg(
+            "vj1",
+            "A",
+            "B",
+            Time::new(10, 0, 0),
+            Time::new(10, 20, 0),
+        )];
+        let fares = calculator.cheapest_fares(&legs).unwrap();
+        assert_eq!("n1", fares[0].tickets[0].ticket_id);
+
+        let mut collections = model.into_collections();
+        collections
+            .ticket_use_restrictions
+            .values_mut()
+            .for_each(|restriction| {
+                restriction.use_origin = "sa:A".to_string();
+                restriction.use_destination = "sa:B".to_string();
+            });
+        let model = Model::new(collections).unwrap();
+        let fares = FareCalculator::new(&model).cheapest_fares(&legs).unwrap();
+        assert_eq!("od", fares[0].tickets[0].ticket_id);
+    }
+
+    #[test]
+    fn zone_restriction() {
+        let mut collections = model().into_collections();
+        add_ticket(&mut collections, "zone", dec!(1.00), &["N1", "N2"], |_| {});
+        collections
+            .ticket_use_restrictions
+            .push(TicketUseRestriction {
+                ticket_use_id: "use:zone".to_string(),
+                restriction_type: RestrictionType::Zone,
+                use_origin: "z1".to_string(),
+                use_destination: "z2".to_string(),
+            });
+        for (stop_point_id, zone) in [("A", "z1"), ("B", "z2"), ("C", "z2"), ("D", "z2")] {
+            collections
+                .stop_points
+                .get_mut(stop_point_id)
+                .unwrap()
+                .fare_zone_id = Some(zone.to_string());
+        }
+        let legs = [
+            leg("vj1", "A", "B", Time::new(10, 0, 0), Time::new(10, 20, 0)),
+            leg("vj2", "C", "D", Time::new(10, 30, 0), Time::new(10, 50, 0)),
+        ];
+        let model = Model::new(collections).unwrap();
+        let fares = FareCalculator::new(&model).cheapest_fares(&legs).unwrap();
+        assert_eq!("zone", fares[0].tickets[0].ticket_id);
+        assert_eq!(0..2, fares[0].tickets[0].legs);
+
+        // The itinerary ends outside of the zones of the restriction
+        let mut collections = model.into_collections();
+        collections.stop_points.get_mut("D").unwrap().fare_zone_id = Some("z3".to_string());
+        let model = Model::new(collections).unwrap();
+        let fares = FareCalculator::new(&model).cheapest_fares(&legs).unwrap();
+        assert_eq!("all", fares[0].tickets[0].ticket_id);
+    }
+
+    #[test]
+    fn unsupported_perimeter() {
+        let mut collections = model().into_collections();
+        add_ticket(&mut collections, "stop_area", dec!(0.50), &["N1"], |_| {});
+        collections.ticket_use_perimeters.push(TicketUsePerimeter {
+            ticket_use_id: "use:stop_area".to_string(),
+            object_type: ObjectType::StopArea,
+            object_id: "sa:A".to_string(),
+            perimeter_action: PerimeterAction::Included,
+        });
+        let model = Model::new(collections).unwrap();
+        let legs = [leg(
+            "vj1",
+            "A",
+            "B",
+            Time::new(10, 0, 0),
+            Time::new(10, 20, 0),
+        )];
+        let fares = FareCalculator::new(&model).cheapest_fares(&legs).unwrap();
+        assert_eq!("n1", fares[0].tickets[0].ticket_id);
+    }
+
+    #[test]
+    fn no_valid_price() {
+        let model = model();
+        let mut legs = [leg(
+            "vj1",
+            "A",
+            "B",
+            Time::new(10, 0, 0),
+            Time::new(10, 20, 0),
+        )];
+        legs[0].date = Date::from_ymd_opt(2021, 6, 1).unwrap();
+        let fares = FareCalculator::new(&model).cheapest_fares(&legs).unwrap();
+        assert!(fares.is_empty());
+    }
+
+    #[test]
+    fn unknown_vehicle_journey() {
+        let model = model();
+        let legs = [leg(
+            "vj9",
+            "A",
+            "B",
+            Time::new(10, 0, 0),
+            Time::new(10, 20, 0),
+        )];
+        let error = FareCalculator::new(&model)
+            .cheapest_fares(&legs)
+            .unwrap_err();
+        assert_eq!("trip \"vj9\" not found", error.to_string());
+    }
+
+    #[test]
+    fn alighting_before_boarding() {
+        let model = model();
+        let legs = [leg(
+            "vj1",
+            "B",
+            "A",
+            Time::new(10, 0, 0),
+            Time::new(10, 20, 0),
+        )];
+        let error = FareCalculator::new(&model)
+            .cheapest_fares(&legs)
+            .unwrap_err();
+        assert_eq!(
+            "trip \"vj1\" does not serve stop point \"B\" then stop point \"A\"",
+            error.to_string()
+        );
+    }
+}
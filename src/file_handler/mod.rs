@@ -1,12 +1,13 @@
 //! Provides an easy way to access directory or flat zip archive
-use crate::Result;
-use anyhow::{anyhow, Context};
+use crate::{error::DataError, Result};
+use anyhow::Context;
 use std::{
     collections::BTreeMap,
     fs::File,
     io::{Read, Seek},
     path::{Path, PathBuf},
 };
+use tracing::warn;
 
 /// Allows files in a directory or ZipArchive to be read either
 pub trait FileHandler
@@ -24,13 +25,24 @@ where
     fn get_file(self, name: &str) -> Result<(Self::Reader, PathBuf)> {
         let (reader, path) = self.get_file_if_exists(name)?;
         Ok((
-            reader.ok_or_else(|| anyhow!("file {:?} not found", path))?,
+            reader.ok_or_else(|| DataError::MissingFile { file: path.clone() })?,
             path,
         ))
     }
 
     /// Allows to have nicer error messages
     fn source_name(&self) -> &str;
+
+    /// Called when an object of a file is invalid. By default, the reading
+    /// fails; a lenient handler may skip the object instead.
+    fn on_invalid_object(self, error: DataError) -> Result<()> {
+        Err(error.into())
+    }
+
+    /// Called when an invalid object is skipped, whatever the handler.
+    fn on_skipped_object(self, error: DataError) {
+        warn!("{}", error);
+    }
 }
 
 /// PathFileHandler is used to read files for a directory
@@ -68,6 +80,54 @@ impl<P: AsRef<Path>> FileHandler for &mut PathFileHandler<P> {
     }
 }
 
+/// LenientFileHandler wraps a file handler to skip the invalid objects
+/// instead of failing, keeping track of all the errors found.
+pub struct LenientFileHandler<H> {
+    handler: H,
+    source_name: String,
+    errors: Vec<DataError>,
+}
+
+impl<H> LenientFileHandler<H>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    /// Constructs a new LenientFileHandler
+    pub fn new(mut handler: H) -> Self {
+        let source_name = (&mut handler).source_name().to_string();
+        LenientFileHandler {
+            handler,
+            source_name,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Consumes the handler, returning the errors found
+    pub fn into_errors(self) -> Vec<DataError> {
+        self.errors
+    }
+}
+
+impl<'a, H> FileHandler for &'a mut LenientFileHandler<H>
+where
+    &'a mut H: FileHandler,
+{
+    type Reader = <&'a mut H as FileHandler>::Reader;
+    fn get_file_if_exists(self, name: &str) -> Result<(Option<Self::Reader>, PathBuf)> {
+        (&mut self.handler).get_file_if_exists(name)
+    }
+    fn source_name(&self) -> &str {
+        &self.source_name
+    }
+    fn on_invalid_object(self, error: DataError) -> Result<()> {
+        self.errors.push(error);
+        Ok(())
+    }
+    fn on_skipped_object(self, error: DataError) {
+        self.errors.push(error);
+    }
+}
+
 /// ZipHandler is a wrapper around a ZipArchive
 /// It provides a way to access the archive's file by their names
 ///
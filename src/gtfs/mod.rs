@@ -19,17 +19,24 @@ mod write;
 
 use crate::{
     calendars::{manage_calendars, write_calendar_dates},
+    enhancers,
+    error::DataError,
     file_handler::{FileHandler, PathFileHandler, ZipHandler},
     model::{Collections, Model},
-    objects::{self, Availability, Contributor, Dataset, Network, StopType, Time},
+    objects::{
+        self, Availability, BookingType, ContinuousStopping, Contributor, Dataset, Network,
+        StopType, Time,
+    },
     parser::read_opt_collection,
+    report::{ReadReportCategory, Report},
     serde_utils::*,
     utils::*,
     validity_period, AddPrefix, PrefixConfiguration, Result,
 };
-use anyhow::{anyhow, Context};
+use anyhow::Context;
 use chrono_tz::Tz;
 use derivative::Derivative;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
@@ -44,9 +51,9 @@ use typed_index_collection::CollectionWithId;
 
 #[cfg(all(feature = "gtfs", feature = "parser"))]
 pub use read::{
-    apply_attribution_rules, manage_frequencies, manage_pathways, manage_shapes, manage_stop_times,
-    read_agency, read_attributions, read_location_groups, read_routes, read_stops, read_transfers,
-    EquipmentList,
+    apply_attribution_rules, manage_frequencies, manage_locations, manage_pathways, manage_shapes,
+    manage_stop_times, read_agency, read_attributions, read_location_groups, read_routes,
+    read_stops, read_transfers, EquipmentList,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -226,6 +233,12 @@ struct StopTime {
         skip_serializing
     )]
     location_group_id: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "de_option_without_slashes",
+        skip_serializing_if = "Option::is_none"
+    )]
+    location_id: Option<String>,
     stop_sequence: u32,
     #[serde(deserialize_with = "de_with_empty_default", default)]
     pickup_type: u8,
@@ -241,6 +254,20 @@ struct StopTime {
     timepoint: bool,
     pickup_booking_rule_id: Option<String>,
     drop_off_booking_rule_id: Option<String>,
+    #[serde(default, deserialize_with = "de_option_positive_decimal")]
+    shape_dist_traveled: Option<Decimal>,
+    #[serde(
+        default,
+        deserialize_with = "de_with_invalid_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    continuous_pickup: Option<ContinuousStopping>,
+    #[serde(
+        default,
+        deserialize_with = "de_with_invalid_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    continuous_drop_off: Option<ContinuousStopping>,
 }
 
 impl StopTime {
@@ -249,24 +276,19 @@ impl StopTime {
     }
 }
 
-#[derive(Derivative, Serialize, Deserialize)]
-#[derivative(Default)]
-enum BookingType {
-    #[derivative(Default)]
-    #[serde(rename = "0")]
-    RealTime,
-    #[serde(rename = "1")]
-    SameDayWithPriorNotice,
-    #[serde(rename = "2")]
-    UpToPreviousDays,
-}
-
 #[derive(Derivative, Serialize, Deserialize)]
 #[derivative(Default)]
 struct BookingRule {
     #[serde(rename = "booking_rule_id")]
     id: String,
     booking_type: BookingType,
+    prior_notice_duration_min: Option<u32>,
+    prior_notice_duration_max: Option<u32>,
+    prior_notice_last_day: Option<u32>,
+    prior_notice_last_time: Option<Time>,
+    prior_notice_start_day: Option<u32>,
+    prior_notice_start_time: Option<Time>,
+    prior_notice_service_id: Option<String>,
     message: Option<String>,
     phone_number: Option<String>,
     info_url: Option<String>,
@@ -277,11 +299,18 @@ impl From<&objects::BookingRule> for BookingRule {
     fn from(obj: &objects::BookingRule) -> BookingRule {
         BookingRule {
             id: obj.id.clone(),
+            booking_type: obj.booking_type.unwrap_or_default(),
+            prior_notice_duration_min: obj.prior_notice_duration_min,
+            prior_notice_duration_max: obj.prior_notice_duration_max,
+            prior_notice_last_day: obj.prior_notice_last_day,
+            prior_notice_last_time: obj.prior_notice_last_time,
+            prior_notice_start_day: obj.prior_notice_start_day,
+            prior_notice_start_time: obj.prior_notice_start_time,
+            prior_notice_service_id: obj.prior_notice_service_id.clone(),
             message: obj.message.clone(),
             phone_number: obj.phone.clone(),
             info_url: obj.info_url.clone(),
             booking_url: obj.booking_url.clone(),
-            ..Default::default()
         }
     }
 }
@@ -304,6 +333,14 @@ impl TryFrom<BookingRule> for objects::BookingRule {
             phone: obj.phone_number,
             info_url: obj.info_url,
             booking_url: obj.booking_url,
+            booking_type: Some(obj.booking_type),
+            prior_notice_duration_min: obj.prior_notice_duration_min,
+            prior_notice_duration_max: obj.prior_notice_duration_max,
+            prior_notice_last_day: obj.prior_notice_last_day,
+            prior_notice_last_time: obj.prior_notice_last_time,
+            prior_notice_start_day: obj.prior_notice_start_day,
+            prior_notice_start_time: obj.prior_notice_start_time,
+            prior_notice_service_id: obj.prior_notice_service_id,
             ..Default::default()
         })
     }
@@ -360,6 +397,8 @@ struct Shape {
     lon: f64,
     #[serde(rename = "shape_pt_sequence")]
     sequence: u32,
+    #[serde(default, deserialize_with = "de_option_positive_decimal")]
+    shape_dist_traveled: Option<Decimal>,
 }
 
 #[derive(Serialize, Debug)]
@@ -428,6 +467,81 @@ struct LocationGroupStop {
     stop_id: String,
 }
 
+/// GeoJSON `FeatureCollection` of the `locations.geojson` file (GTFS-Flex)
+#[derive(Debug, Serialize, Deserialize)]
+struct Locations {
+    #[serde(rename = "type")]
+    collection_type: String,
+    features: Vec<Location>,
+}
+
+/// Zone of `locations.geojson` in which the riders can request a pickup or a
+/// drop off
+#[derive(Debug, Serialize, Deserialize)]
+struct Location {
+    #[serde(rename = "type")]
+    feature_type: String,
+    id: String,
+    #[serde(default)]
+    properties: LocationProperties,
+    geometry: LocationGeometry,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LocationProperties {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_desc: Option<String>,
+}
+
+/// Only polygons are allowed as geometries of the locations
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "coordinates")]
+enum LocationGeometry {
+    Polygon(Vec<Vec<[f64; 2]>>),
+    MultiPolygon(Vec<Vec<Vec<[f64; 2]>>>),
+}
+
+fn rings_to_polygon(rings: Vec<Vec<[f64; 2]>>) -> geo::Polygon<f64> {
+    let mut rings = rings.into_iter().map(geo::LineString::from);
+    let exterior = rings.next().unwrap_or_else(|| geo::LineString::new(vec![]));
+    geo::Polygon::new(exterior, rings.collect())
+}
+
+fn polygon_to_rings(polygon: &geo::Polygon<f64>) -> Vec<Vec<[f64; 2]>> {
+    std::iter::once(polygon.exterior())
+        .chain(polygon.interiors())
+        .map(|ring| ring.coords().map(|coord| [coord.x, coord.y]).collect())
+        .collect()
+}
+
+impl From<LocationGeometry> for geo::Geometry<f64> {
+    fn from(geometry: LocationGeometry) -> Self {
+        match geometry {
+            LocationGeometry::Polygon(rings) => rings_to_polygon(rings).into(),
+            LocationGeometry::MultiPolygon(polygons) => {
+                geo::MultiPolygon::new(polygons.into_iter().map(rings_to_polygon).collect()).into()
+            }
+        }
+    }
+}
+
+impl TryFrom<&geo::Geometry<f64>> for LocationGeometry {
+    type Error = anyhow::Error;
+    fn try_from(geometry: &geo::Geometry<f64>) -> Result<Self> {
+        match geometry {
+            geo::Geometry::Polygon(polygon) => {
+                Ok(LocationGeometry::Polygon(polygon_to_rings(polygon)))
+            }
+            geo::Geometry::MultiPolygon(multi_polygon) => Ok(LocationGeometry::MultiPolygon(
+                multi_polygon.iter().map(polygon_to_rings).collect(),
+            )),
+            _ => anyhow::bail!("only POLYGON and MULTIPOLYGON geometries are allowed"),
+        }
+    }
+}
+
 fn read_file_handler<H>(file_handler: &mut H, configuration: Configuration) -> Result<Model>
 where
     for<'a> &'a mut H: FileHandler,
@@ -474,7 +588,7 @@ where
     collections.stop_points = stop_points;
     collections.stop_locations = stop_locations;
 
-    read::manage_shapes(&mut collections, file_handler)?;
+    let shape_distances = read::manage_shapes(&mut collections, file_handler)?;
 
     let mut attribution_rules = read::read_attributions(file_handler, "attributions.txt")?;
 
@@ -487,6 +601,7 @@ where
     )?;
     collections.equipments = CollectionWithId::new(equipments.into_equipments())?;
 
+    read::manage_locations(&mut collections, file_handler)?;
     let location_groups = read::read_location_groups(
         file_handler,
         &mut collections.stop_points,
@@ -500,6 +615,7 @@ where
         on_demand_transport_comment,
         &location_groups,
     )?;
+    enhancers::normalize_shape_dist_traveled(&mut collections, &shape_distances);
     read::manage_frequencies(&mut collections, file_handler)?;
     read::manage_pathways(&mut collections, file_handler)?;
     collections.levels = read_opt_collection(file_handler, "levels.txt")?;
@@ -590,12 +706,50 @@ impl Reader {
                 .parse_dir(p)
                 .with_context(|| format!("impossible to read gtfs directory from {p:?}"))?)
         } else {
-            Err(anyhow!(
-                "file {:?} is neither a file nor a directory, cannot read a gtfs from it",
-                p
-            ))
+            Err(DataError::InvalidInput {
+                path: p.to_path_buf(),
+                format: "gtfs",
+            }
+            .into())
         }
     }
+
+    /// Imports a `Model` from the
+    /// [GTFS](https://gtfs.org/reference/static)
+    /// like [Reader::parse], but the invalid objects are skipped instead of
+    /// failing. All the problems found in the data are recorded in the
+    /// returned report.
+    pub fn parse_with_report(
+        self,
+        path: impl AsRef<Path>,
+    ) -> Result<(Model, Report<ReadReportCategory>)> {
+        let p = path.as_ref();
+        let configuration = self.configuration;
+        if p.is_file() {
+            // if it's a file, we consider it to be a zip (and an error will be returned if it is not)
+            let reader = std::fs::File::open(p)?;
+            Ok(ZipHandler::new(reader, p)
+                .and_then(|file_handler| {
+                    read_leniently(file_handler, |file_handler| {
+                        read_file_handler_to_collections(file_handler, configuration)
+                    })
+                })
+                .with_context(|| format!("impossible to read zipped gtfs {p:?}"))?)
+        } else if p.is_dir() {
+            let file_handler = PathFileHandler::new(p.to_path_buf());
+            Ok(read_leniently(file_handler, |file_handler| {
+                read_file_handler_to_collections(file_handler, configuration)
+            })
+            .with_context(|| format!("impossible to read gtfs directory from {p:?}"))?)
+        } else {
+            Err(DataError::InvalidInput {
+                path: p.to_path_buf(),
+                format: "gtfs",
+            }
+            .into())
+        }
+    }
+
     /// Imports `Collections` from the
     /// [GTFS](https://gtfs.org/reference/static).
     /// files in the given directory.
@@ -612,10 +766,11 @@ impl Reader {
                 .parse_dir_collections(p)
                 .with_context(|| format!("impossible to read gtfs directory from {p:?}"))?)
         } else {
-            Err(anyhow!(
-                "file {:?} is neither a file nor a directory, cannot read a gtfs from it",
-                p
-            ))
+            Err(DataError::InvalidInput {
+                path: p.to_path_buf(),
+                format: "gtfs",
+            }
+            .into())
         }
     }
 
@@ -721,6 +876,18 @@ struct Route {
     text_color: Option<objects::Rgb>,
     #[serde(rename = "route_sort_order")]
     sort_order: Option<u32>,
+    #[serde(
+        default,
+        deserialize_with = "de_with_invalid_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    continuous_pickup: Option<ContinuousStopping>,
+    #[serde(
+        default,
+        deserialize_with = "de_with_invalid_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    continuous_drop_off: Option<ContinuousStopping>,
 }
 
 /// Use to serialize extended route type
@@ -830,14 +997,17 @@ pub fn write<P: AsRef<Path>>(model: Model, path: P, extend_route_type: bool) ->
     write::write_ticketing_deep_links(path, &ticketing_deep_links)?;
     write::write_agencies(path, &model.networks, &ticketing_deep_links)?;
     write_calendar_dates(path, &model.calendars)?;
-    write::write_stops(path, &model)?;
+    let locations = write::write_locations(path, &model)?;
+    write::write_stops(path, &model, &locations)?;
     let gtfs_trips = write::write_trips(path, &model)?;
     write::write_attributions(path, &model.companies, gtfs_trips)?;
     write::write_routes(path, &model, extend_route_type)?;
     write::write_stop_times(
         path,
         &model.vehicle_journeys,
+        &model.routes,
         &model.stop_points,
+        &locations,
         &model.stop_time_headsigns,
     )?;
     write::write_booking_rules(path, &model.booking_rules)?;
@@ -961,6 +1131,39 @@ mod tests {
         assert_eq!(obj.booking_url, Some("http://booking.url".to_string()));
     }
 
+    #[test]
+    fn test_booking_rule_prior_notice() {
+        let booking_rule = BookingRule {
+            id: "br7".to_string(),
+            booking_type: BookingType::UpToPreviousDays,
+            prior_notice_last_day: Some(2),
+            prior_notice_last_time: Some(Time::new(17, 0, 0)),
+            prior_notice_start_day: Some(30),
+            prior_notice_service_id: Some("weekdays".to_string()),
+            phone_number: Some("987654321".to_string()),
+            ..Default::default()
+        };
+        let obj = objects::BookingRule::try_from(booking_rule).unwrap();
+        assert_eq!(obj.booking_type, Some(BookingType::UpToPreviousDays));
+        assert_eq!(obj.prior_notice_last_day, Some(2));
+        assert_eq!(obj.prior_notice_last_time, Some(Time::new(17, 0, 0)));
+        assert_eq!(obj.prior_notice_start_day, Some(30));
+        assert_eq!(obj.prior_notice_start_time, None);
+        assert_eq!(obj.prior_notice_service_id, Some("weekdays".to_string()));
+
+        let booking_rule = BookingRule::from(&obj);
+        assert_eq!(booking_rule.booking_type, BookingType::UpToPreviousDays);
+        assert_eq!(booking_rule.prior_notice_last_day, Some(2));
+        assert_eq!(
+            booking_rule.prior_notice_last_time,
+            Some(Time::new(17, 0, 0))
+        );
+        assert_eq!(
+            booking_rule.prior_notice_service_id,
+            Some("weekdays".to_string())
+        );
+    }
+
     #[test]
     fn test_booking_rule_try_from_failure_all_none() {
         let booking_rule = BookingRule {
@@ -13,10 +13,12 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
 use super::{
-    Agency, Attribution, BookingRule, DirectionType, LocationGroupStop, Route, RouteType, Shape,
-    Stop, StopLocationType, StopTime, Transfer, TransferType, Trip,
+    Agency, Attribution, BookingRule, DirectionType, LocationGroupStop, Locations, Route,
+    RouteType, Shape, Stop, StopLocationType, StopTime, Transfer, TransferType, Trip,
 };
 use crate::{
+    enhancers,
+    error::{object_type, DataError},
     file_handler::FileHandler,
     model::Collections,
     objects::{
@@ -24,20 +26,25 @@ use crate::{
         Pathway, PropertiesMap, StopLocation, StopPoint, StopTimePrecision, StopType, Time,
         TransportType, VehicleJourney,
     },
-    parser::{read_collection, read_objects, read_objects_loose},
+    parser::{
+        read_collection, read_objects, read_objects_loose, read_objects_loose_with_lines,
+        read_objects_with_lines,
+    },
     serde_utils::de_with_empty_default,
     Result,
 };
-use anyhow::{anyhow, bail, Error};
+use anyhow::{anyhow, bail, Context, Error};
 use derivative::Derivative;
-use geo::{LineString, Point};
+use geo::{algorithm::centroid::Centroid, Geometry as GeoGeometry, LineString, Point};
+use rust_decimal::prelude::ToPrimitive;
 use serde::Deserialize;
-use skip_error::{skip_error_and_warn, SkipError};
+use skip_error::skip_error_and_warn;
 use std::convert::TryFrom;
 use std::{
     cmp,
     collections::{BTreeMap, BTreeSet, HashMap},
     hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
 };
 use tracing::{info, warn};
 use typed_index_collection::{impl_id, Collection, CollectionWithId, Idx};
@@ -225,16 +232,14 @@ impl TryFrom<Stop> for objects::StopLocation {
     }
 }
 
-impl TryFrom<Frequency> for objects::Frequency {
-    type Error = Error;
-    fn try_from(gtfs_frequency: Frequency) -> Result<Self> {
-        let ntm_frequency = objects::Frequency {
+impl From<Frequency> for objects::Frequency {
+    fn from(gtfs_frequency: Frequency) -> Self {
+        objects::Frequency {
             vehicle_journey_id: gtfs_frequency.trip_id,
             start_time: gtfs_frequency.start_time,
             end_time: gtfs_frequency.end_time,
             headway_secs: gtfs_frequency.headway_secs,
-        };
-        Ok(ntm_frequency)
+        }
     }
 }
 
@@ -364,7 +369,15 @@ impl Trip {
 }
 
 /// Reading rules for mapping vehicle travel paths, sometimes referred to as route alignments.
-pub fn manage_shapes<H>(collections: &mut Collections, file_handler: &mut H) -> Result<()>
+///
+/// The `shape_dist_traveled` of the shape points are returned with their
+/// distance in meters from the start of the shape, to convert the
+/// `shape_dist_traveled` of the stop times (see
+/// `enhancers::normalize_shape_dist_traveled`).
+pub fn manage_shapes<H>(
+    collections: &mut Collections,
+    file_handler: &mut H,
+) -> Result<enhancers::ShapeDistances>
 where
     for<'a> &'a mut H: FileHandler,
 {
@@ -372,10 +385,26 @@ where
     let mut shapes = read_objects_loose::<_, Shape>(file_handler, file, false)?;
     shapes.sort_unstable_by_key(|s| s.sequence);
     let mut map: HashMap<String, Vec<Point<f64>>> = HashMap::new();
+    let mut shape_distances = enhancers::ShapeDistances::new();
+    let mut shape_lengths: HashMap<String, (f64, Coord)> = HashMap::new();
     for s in &shapes {
-        map.entry(s.id.clone())
-            .or_default()
-            .push((s.lon, s.lat).into())
+        let points = map.entry(s.id.clone()).or_default();
+        let coord = Coord {
+            lon: s.lon,
+            lat: s.lat,
+        };
+        // Cumulated length in meters of the shape up to this point
+        let meters = shape_lengths.get(&s.id).map_or(0., |(length, previous)| {
+            length + previous.distance_to(&coord)
+        });
+        shape_lengths.insert(s.id.clone(), (meters, coord));
+        if let Some(distance) = s.shape_dist_traveled.and_then(|d| d.to_f64()) {
+            shape_distances
+                .entry(s.id.clone())
+                .or_default()
+                .push((distance, meters));
+        }
+        points.push((s.lon, s.lat).into());
     }
 
     collections.geometries = CollectionWithId::new(
@@ -391,7 +420,7 @@ where
             .collect(),
     )?;
 
-    Ok(())
+    Ok(shape_distances)
 }
 
 /// Reading times that a vehicle arrives at and departs from stops for each trip
@@ -408,38 +437,60 @@ where
     let file_name = "stop_times.txt";
     let mut headsigns = HashMap::new();
     let mut tmp_vjs = BTreeMap::new();
-    let stop_times = read_objects::<_, StopTime>(file_handler, file_name, true)?;
+    let stop_times = read_objects_with_lines::<_, StopTime>(file_handler, file_name, true)?;
 
-    for stop_time in stop_times {
+    for (line, stop_time) in stop_times {
         if let Some(vj_idx) = collections.vehicle_journeys.get_idx(&stop_time.trip_id) {
             tmp_vjs
                 .entry(vj_idx)
                 .or_insert_with(Vec::new)
                 .push(stop_time);
         } else {
-            warn!(
-                "Problem reading {:?}: trip_id={:?} not found. Skipping this stop_time",
-                file_name, stop_time.trip_id
-            )
+            (&mut *file_handler).on_skipped_object(DataError::InvalidReference {
+                file: file_name.to_string(),
+                line,
+                object_type: object_type::<StopTime>(),
+                id: None,
+                field: "trip_id",
+                value: stop_time.trip_id.clone(),
+            });
         }
     }
 
-    'vj_loop: for (vj_idx, mut stop_times) in tmp_vjs {
+    for (vj_idx, mut stop_times) in tmp_vjs {
         stop_times.sort_unstable_by_key(|st| st.stop_sequence);
         stop_times.dedup_by(|st2, st1| {
             let is_same_seq = st2.stop_sequence == st1.stop_sequence;
             if is_same_seq {
-                warn!(
-                    "remove duplicated stop_sequence '{}' of trip '{}'",
-                    st2.stop_sequence, st2.trip_id
-                );
+                (&mut *file_handler).on_skipped_object(DataError::InvalidStopTimes {
+                    trip_id: st2.trip_id.clone(),
+                    message: format!(
+                        "remove duplicated stop_sequence '{}' of trip '{}'",
+                        st2.stop_sequence, st2.trip_id
+                    ),
+                });
             }
             is_same_seq
         });
-        let (st_values, has_pickup_drop_off_windows) = interpolate_undefined_stop_times(
-            &collections.vehicle_journeys[vj_idx].id,
-            &stop_times,
-        )?;
+        // The trips which can't be used are skipped before any modification
+        let vj_id = &collections.vehicle_journeys[vj_idx].id;
+        let dropped_trip = |reason: String| DataError::DroppedTrip {
+            trip_id: vj_id.clone(),
+            reason,
+        };
+        let (st_values, has_pickup_drop_off_windows) = skip_error_and_report!(
+            file_handler,
+            interpolate_undefined_stop_times(vj_id, &stop_times)
+                .map_err(|error| dropped_trip(error.to_string()))
+        );
+        let stop_point_idxs = skip_error_and_report!(
+            file_handler,
+            stop_times
+                .iter()
+                .map(|stop_time| stop_point_idxs_of(collections, location_groups, stop_time))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|reason| dropped_trip(reason.to_string()))
+        );
         let company_idx = collections
             .companies
             .get_idx(&collections.vehicle_journeys[vj_idx].company_id);
@@ -447,34 +498,9 @@ where
         let mut booking_rule_found = false;
         let mut auto_generated_sequence = 0;
 
-        for (stop_time, st_values) in stop_times.iter().zip(st_values) {
-            let stop_point_idxs = if let Some(stop_id) = stop_time.stop_id.as_ref() {
-                collections
-                    .stop_points
-                    .get_idx(stop_id)
-                    .map(|idx| vec![idx])
-                    .unwrap_or_default()
-            } else if let Some(location_group_id) = stop_time.location_group_id.as_deref() {
-                location_groups
-                    .get(location_group_id)
-                    .cloned()
-                    .unwrap_or_default()
-            } else {
-                warn!(
-                    "stop_time with trip_id '{}' has no stop_id or location_group_id. Skipping this vehicle journey",
-                    collections.vehicle_journeys[vj_idx].id
-                );
-                continue 'vj_loop;
-            };
-
-            if stop_point_idxs.is_empty() {
-                println!(
-                    "stop_time with trip_id '{}' has no stop points. Skipping this vehicle journey",
-                    collections.vehicle_journeys[vj_idx].id
-                );
-                continue 'vj_loop;
-            }
-
+        for ((stop_time, st_values), stop_point_idxs) in
+            stop_times.iter().zip(st_values).zip(stop_point_idxs)
+        {
             let precision =
                 if on_demand_transport && st_values.precision == StopTimePrecision::Approximate {
                     Some(StopTimePrecision::Estimated)
@@ -558,6 +584,9 @@ where
                     drop_off_type,
                     local_zone_id: stop_time.local_zone_id,
                     precision,
+                    shape_dist_traveled: stop_time.shape_dist_traveled,
+                    continuous_pickup: stop_time.continuous_pickup,
+                    continuous_drop_off: stop_time.continuous_drop_off,
                 });
                 auto_generated_sequence += 1;
             }
@@ -569,6 +598,41 @@ where
     Ok(())
 }
 
+/// The stop points of a stop time: its stop, the stop points of its location
+/// group or its location. Fails with the reason why the stop time can't be
+/// used.
+fn stop_point_idxs_of(
+    collections: &Collections,
+    location_groups: &LocationGroups,
+    stop_time: &StopTime,
+) -> Result<Vec<Idx<StopPoint>>, &'static str> {
+    let stop_point_idxs = if let Some(stop_id) = stop_time.stop_id.as_ref() {
+        collections
+            .stop_points
+            .get_idx(stop_id)
+            .map(|idx| vec![idx])
+            .unwrap_or_default()
+    } else if let Some(location_group_id) = stop_time.location_group_id.as_deref() {
+        location_groups
+            .get(location_group_id)
+            .cloned()
+            .unwrap_or_default()
+    } else if let Some(location_id) = stop_time.location_id.as_ref() {
+        collections
+            .stop_points
+            .get_idx(location_id)
+            .map(|idx| vec![idx])
+            .unwrap_or_default()
+    } else {
+        return Err("a stop time has no stop_id, location_group_id or location_id");
+    };
+    if stop_point_idxs.is_empty() {
+        Err("a stop time has no stop points")
+    } else {
+        Ok(stop_point_idxs)
+    }
+}
+
 fn ventilate_stop_times(
     undefined_stop_times: &[&StopTime],
     before_departure_time: Time,
@@ -950,11 +1014,17 @@ where
 {
     let file = "stops.txt";
     info!(file_name = %file, "Reading");
-    let gtfs_stops = read_objects::<_, Stop>(file_handler, file, true)?;
+    let gtfs_stops = read_objects_with_lines::<_, Stop>(file_handler, file, true)?;
     let mut stop_areas = vec![];
     let mut stop_points = vec![];
     let mut stop_locations = vec![];
-    for stop in gtfs_stops {
+    for (line, stop) in gtfs_stops {
+        let invalid_stop = |error: Error| DataError::InvalidRecord {
+            file: PathBuf::from(file),
+            line,
+            column: None,
+            message: error.to_string(),
+        };
         let mut comment_links = LinksT::default();
         if let Some(comment) = generate_stop_comment(&stop) {
             comment_links.insert(comment.id.to_string());
@@ -965,8 +1035,10 @@ where
         let equipment_id = get_equipment_id_and_populate_equipments(equipments, &stop);
         match stop.location_type {
             StopLocationType::StopPoint => {
-                let mut stop_point =
-                    skip_error_and_warn!(objects::StopPoint::try_from(stop.clone()));
+                let mut stop_point = skip_error_and_report!(
+                    file_handler,
+                    objects::StopPoint::try_from(stop.clone()).map_err(invalid_stop)
+                );
                 if stop.parent_station.is_none() {
                     let stop_area = objects::StopArea::from(stop_point.clone());
                     stop_point.stop_area_id.clone_from(&stop_area.id);
@@ -977,13 +1049,19 @@ where
                 stop_points.push(stop_point);
             }
             StopLocationType::StopArea => {
-                let mut stop_area = skip_error_and_warn!(objects::StopArea::try_from(stop));
+                let mut stop_area = skip_error_and_report!(
+                    file_handler,
+                    objects::StopArea::try_from(stop).map_err(invalid_stop)
+                );
                 stop_area.comment_links = comment_links;
                 stop_area.equipment_id = equipment_id;
                 stop_areas.push(stop_area);
             }
             _ => {
-                let mut stop_location = skip_error_and_warn!(objects::StopLocation::try_from(stop));
+                let mut stop_location = skip_error_and_report!(
+                    file_handler,
+                    objects::StopLocation::try_from(stop).map_err(invalid_stop)
+                );
                 stop_location.comment_links = comment_links;
                 stop_location.equipment_id = equipment_id;
                 stop_locations.push(stop_location);
@@ -1003,40 +1081,37 @@ where
 {
     let file = "pathways.txt";
 
-    let gtfs_pathways = read_objects_loose::<_, Pathway>(file_handler, file, false)?;
+    let gtfs_pathways = read_objects_loose_with_lines::<_, Pathway>(file_handler, file, false)?;
     let mut pathways = vec![];
-    for mut pathway in gtfs_pathways {
-        pathway.from_stop_type = skip_error_and_warn!(collections
-            .stop_points
-            .get(&pathway.from_stop_id)
-            .map(|st| st.stop_type.clone())
-            .or_else(|| collections
-                .stop_locations
-                .get(&pathway.from_stop_id)
-                .map(|sl| sl.stop_type.clone()))
-            .ok_or_else(|| {
-                anyhow!(
-                    "Problem reading {:?}: from_stop_id={:?} not found",
-                    file,
-                    pathway.from_stop_id
-                )
-            }));
-
-        pathway.to_stop_type = skip_error_and_warn!(collections
-            .stop_points
-            .get(&pathway.to_stop_id)
-            .map(|st| st.stop_type.clone())
-            .or_else(|| collections
-                .stop_locations
-                .get(&pathway.to_stop_id)
-                .map(|sl| sl.stop_type.clone()))
-            .ok_or_else(|| {
-                anyhow!(
-                    "Problem reading {:?}: to_stop_id={:?} not found",
-                    file,
-                    pathway.to_stop_id
-                )
-            }));
+    for (line, mut pathway) in gtfs_pathways {
+        let stop_type = |field: &'static str, stop_id: &str| {
+            collections
+                .stop_points
+                .get(stop_id)
+                .map(|st| st.stop_type.clone())
+                .or_else(|| {
+                    collections
+                        .stop_locations
+                        .get(stop_id)
+                        .map(|sl| sl.stop_type.clone())
+                })
+                .ok_or_else(|| DataError::InvalidReference {
+                    file: file.to_string(),
+                    line,
+                    object_type: object_type::<Pathway>(),
+                    id: Some(pathway.id.clone()),
+                    field,
+                    value: stop_id.to_string(),
+                })
+        };
+        let from_stop_type = skip_error_and_report!(
+            file_handler,
+            stop_type("from_stop_id", &pathway.from_stop_id)
+        );
+        let to_stop_type =
+            skip_error_and_report!(file_handler, stop_type("to_stop_id", &pathway.to_stop_id));
+        pathway.from_stop_type = from_stop_type;
+        pathway.to_stop_type = to_stop_type;
         pathways.push(pathway);
     }
     collections.pathways = CollectionWithId::new(pathways)?;
@@ -1053,33 +1128,40 @@ where
     for<'a> &'a mut H: FileHandler,
 {
     let file = "transfers.txt";
-    let gtfs_transfers = read_objects_loose::<_, Transfer>(file_handler, file, false)?;
+    let gtfs_transfers = read_objects_loose_with_lines::<_, Transfer>(file_handler, file, false)?;
 
     let mut transfers = vec![];
-    for transfer in gtfs_transfers {
-        let expand_stop_area = |stop_id: &str| -> Result<Vec<&StopPoint>> {
-            if stop_areas.get(stop_id).is_some() {
-                let list_stop_points = stop_points
-                    .values()
-                    .filter(|stop_point| stop_point.stop_area_id == stop_id)
-                    .collect();
-                Ok(list_stop_points)
-            } else {
-                stop_points
-                    .get(stop_id)
-                    .ok_or_else(|| {
-                        anyhow!(
-                            "Problem reading {:?}: stop_id={:?} not found",
-                            file,
-                            stop_id
-                        )
-                    })
-                    .map(|stop_point| vec![stop_point])
-            }
-        };
-        let from_stop_points =
-            skip_error_and_warn!(expand_stop_area(transfer.from_stop_id.as_str()));
-        let to_stop_points = skip_error_and_warn!(expand_stop_area(transfer.to_stop_id.as_str()));
+    for (line, transfer) in gtfs_transfers {
+        let expand_stop_area =
+            |field: &'static str, stop_id: &str| -> Result<Vec<&StopPoint>, DataError> {
+                if stop_areas.get(stop_id).is_some() {
+                    let list_stop_points = stop_points
+                        .values()
+                        .filter(|stop_point| stop_point.stop_area_id == stop_id)
+                        .collect();
+                    Ok(list_stop_points)
+                } else {
+                    stop_points
+                        .get(stop_id)
+                        .ok_or_else(|| DataError::InvalidReference {
+                            file: file.to_string(),
+                            line,
+                            object_type: object_type::<Transfer>(),
+                            id: None,
+                            field,
+                            value: stop_id.to_string(),
+                        })
+                        .map(|stop_point| vec![stop_point])
+                }
+            };
+        let from_stop_points = skip_error_and_report!(
+            file_handler,
+            expand_stop_area("from_stop_id", &transfer.from_stop_id)
+        );
+        let to_stop_points = skip_error_and_report!(
+            file_handler,
+            expand_stop_area("to_stop_id", &transfer.to_stop_id)
+        );
         for from_stop_point in &from_stop_points {
             let approx = from_stop_point.coord.approx();
             for to_stop_point in &to_stop_points {
@@ -1299,6 +1381,8 @@ fn make_routes(
                     line_id: sr.id.clone(),
                     geometry_id: None,
                     destination_id: None,
+                    continuous_pickup: r.continuous_pickup,
+                    continuous_drop_off: r.continuous_drop_off,
                 });
             }
         }
@@ -1312,11 +1396,16 @@ fn make_ntfs_vehicle_journeys(
     datasets: &CollectionWithId<objects::Dataset>,
     networks: &CollectionWithId<objects::Network>,
     read_trip_short_name: bool,
-) -> (Vec<objects::VehicleJourney>, Vec<objects::TripProperty>) {
+) -> (
+    Vec<objects::VehicleJourney>,
+    Vec<objects::TripProperty>,
+    Vec<DataError>,
+) {
     // there always is one dataset from config or a default one
     let (_, dataset) = datasets.iter().next().unwrap();
     let mut vehicle_journeys: Vec<objects::VehicleJourney> = vec![];
     let mut trip_properties: Vec<objects::TripProperty> = vec![];
+    let mut dropped_trips = vec![];
     let mut map_tps_trips: BTreeMap<(Availability, Availability), Vec<&Trip>> = BTreeMap::new();
     let mut id_incr: u8 = 1;
     let mut property_id: Option<String>;
@@ -1348,22 +1437,24 @@ fn make_ntfs_vehicle_journeys(
             });
             id_incr += 1;
         }
-        trips
-            .iter()
-            .map(|t| {
-                t.to_ntfs_vehicle_journey(
-                    routes,
-                    dataset,
-                    &property_id,
-                    networks,
-                    read_trip_short_name,
-                )
-            })
-            .skip_error_and_warn()
-            .for_each(|vj| vehicle_journeys.push(vj));
+        for trip in trips {
+            match trip.to_ntfs_vehicle_journey(
+                routes,
+                dataset,
+                &property_id,
+                networks,
+                read_trip_short_name,
+            ) {
+                Ok(vj) => vehicle_journeys.push(vj),
+                Err(error) => dropped_trips.push(DataError::DroppedTrip {
+                    trip_id: trip.id.clone(),
+                    reason: error.to_string(),
+                }),
+            }
+        }
     }
 
-    (vehicle_journeys, trip_properties)
+    (vehicle_journeys, trip_properties, dropped_trips)
 }
 
 /// Reading transit routes. A route is a group of trips that are displayed to riders as a single service.
@@ -1410,13 +1501,16 @@ where
         };
     });
 
-    let (vehicle_journeys, trip_properties) = make_ntfs_vehicle_journeys(
+    let (vehicle_journeys, trip_properties, dropped_trips) = make_ntfs_vehicle_journeys(
         &gtfs_trips,
         &gtfs_routes_collection,
         &collections.datasets,
         &collections.networks,
         read_trip_short_name,
     );
+    for dropped_trip in dropped_trips {
+        (&mut *file_handler).on_skipped_object(dropped_trip);
+    }
     collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys)?;
     collections.trip_properties = CollectionWithId::new(trip_properties)?;
 
@@ -1450,9 +1544,8 @@ where
 {
     let file = "frequencies.txt";
     let frequencies = read_objects::<_, Frequency>(file_handler, file, false)?
-        .iter()
-        .map(|gtfs_frequency| objects::Frequency::try_from(gtfs_frequency.clone()))
-        .skip_error_and_warn()
+        .into_iter()
+        .map(objects::Frequency::from)
         .collect();
 
     collections.convert_frequencies_to_stoptimes(frequencies)
@@ -1685,6 +1778,81 @@ where
     Ok(location_groups)
 }
 
+/// Reading the zones of `locations.geojson` as stop points of type `Zone`,
+/// with their polygons as geometries
+pub fn manage_locations<H>(collections: &mut Collections, file_handler: &mut H) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let file = "locations.geojson";
+    let (reader, path) = file_handler.get_file_if_exists(file)?;
+    let Some(reader) = reader else {
+        info!(file_name = %file, "Skipping");
+        return Ok(());
+    };
+    info!(file_name = %file, "Reading");
+    let locations: Locations =
+        serde_json::from_reader(reader).with_context(|| format!("Error reading {path:?}"))?;
+
+    for location in locations.features {
+        if collections.stop_points.contains_id(&location.id) {
+            warn!(
+                "Problem reading {}: location id={} is already a stop id. Skipping this location",
+                file, location.id
+            );
+            continue;
+        }
+        let geometry: GeoGeometry<f64> = location.geometry.into();
+        let Some(centroid) = geometry.centroid() else {
+            warn!(
+                "Problem reading {}: location id={} has an empty geometry. Skipping this location",
+                file, location.id
+            );
+            continue;
+        };
+        let geometry_id = format!("location:{}", location.id);
+        collections.geometries.push(objects::Geometry {
+            id: geometry_id.clone(),
+            geometry,
+        })?;
+
+        let mut comment_links = LinksT::default();
+        if let Some(desc) = location.properties.stop_desc {
+            let comment = objects::Comment {
+                id: format!("stop:{}", location.id),
+                comment_type: objects::CommentType::Information,
+                label: None,
+                name: desc,
+                url: None,
+            };
+            comment_links.insert(comment.id.clone());
+            collections.comments.push(comment)?;
+        }
+        let mut codes: KeysValues = BTreeSet::new();
+        codes.insert(("source".to_string(), location.id.clone()));
+        let name = match location.properties.stop_name {
+            Some(stop_name) => stop_name,
+            None => location.id.clone(),
+        };
+        let mut stop_point = objects::StopPoint {
+            id: location.id,
+            name,
+            codes,
+            comment_links,
+            visible: true,
+            coord: Coord::from(centroid),
+            geometry_id: Some(geometry_id),
+            stop_type: StopType::Zone,
+            ..Default::default()
+        };
+        let stop_area = objects::StopArea::from(stop_point.clone());
+        stop_point.stop_area_id.clone_from(&stop_area.id);
+        collections.stop_areas.push(stop_area)?;
+        collections.stop_points.push(stop_point)?;
+    }
+    Ok(())
+}
+
 pub fn read_booking_rules<H>(file_handler: &mut H) -> Result<CollectionWithId<objects::BookingRule>>
 where
     for<'a> &'a mut H: FileHandler,
@@ -1705,7 +1873,7 @@ mod tests {
     use crate::{
         calendars,
         configuration::read_config,
-        file_handler::PathFileHandler,
+        file_handler::{LenientFileHandler, PathFileHandler},
         gtfs::{read::EquipmentList, StopTime as GtfsStopTime},
         model::Collections,
         objects::*,
@@ -1716,6 +1884,7 @@ mod tests {
     };
     use geo::line_string;
     use pretty_assertions::assert_eq;
+    use rust_decimal::Decimal;
     use typed_index_collection::Id;
 
     fn extract<'a, T, S: ::std::cmp::Ord>(f: fn(&'a T) -> S, c: &'a Collection<T>) -> Vec<S> {
@@ -2903,8 +3072,8 @@ mod tests {
              1,route_1,0,service_1,,";
 
         let stop_times_content = "trip_id,arrival_time,departure_time,stop_id,stop_sequence,stop_headsign,pickup_type,drop_off_type,shape_dist_traveled,timepoint\n\
-                                  1,06:00:00,06:00:00,sp:01,1,over there,,,,0\n\
-                                  1,06:06:27,06:06:27,sp:02,2,,2,1,,1\n\
+                                  1,06:00:00,06:00:00,sp:01,1,over there,,,0,0\n\
+                                  1,06:06:27,06:06:27,sp:02,2,,2,1,1200.5,1\n\
                                   1,06:06:27,06:06:27,sp:03,3,,2,1,,";
 
         test_in_tmp_dir(|path| {
@@ -2951,6 +3120,9 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Approximate),
+                        shape_dist_traveled: Some(Decimal::from(0)),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:02").unwrap(),
@@ -2965,6 +3137,9 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: Some(Decimal::new(12005, 1)),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:03").unwrap(),
@@ -2979,6 +3154,9 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                 ],
                 collections.vehicle_journeys.into_vec()[0].stop_times
@@ -2986,6 +3164,80 @@ mod tests {
         });
     }
 
+    #[test]
+    fn gtfs_continuous_stopping() {
+        let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type,continuous_pickup,continuous_drop_off\n\
+                              route_1,agency_1,1,My line 1,3,0,9";
+
+        let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type\n\
+                             sp:01,my stop point name 1,0.1,1.2,0\n\
+                             sp:02,my stop point name 2,0.2,1.5,0";
+
+        let trips_content = "trip_id,route_id,direction_id,service_id\n\
+                             1,route_1,0,service_1";
+
+        let stop_times_content = "trip_id,arrival_time,departure_time,stop_id,stop_sequence,continuous_pickup,continuous_drop_off\n\
+                                  1,06:00:00,06:00:00,sp:01,1,1,3\n\
+                                  1,06:06:27,06:06:27,sp:02,2,,";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "routes.txt", routes_content);
+            create_file_with_content(path, "trips.txt", trips_content);
+            create_file_with_content(path, "stop_times.txt", stop_times_content);
+            create_file_with_content(path, "stops.txt", stops_content);
+
+            let mut collections = Collections::default();
+            let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
+            collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
+            collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
+
+            let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
+            let mut equipments = EquipmentList::default();
+            let (_, stop_points, _) =
+                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            collections.stop_points = stop_points;
+
+            super::read_routes(&mut handler, &mut collections, false, false, &mut vec![]).unwrap();
+            let location_groups = HashMap::new();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                &location_groups,
+            )
+            .unwrap();
+
+            // The invalid continuous drop off of the route is ignored
+            let route = collections.routes.get("route_1").unwrap();
+            assert_eq!(
+                Some(ContinuousStopping::Continuous),
+                route.continuous_pickup
+            );
+            assert_eq!(None, route.continuous_drop_off);
+            let continuous_stopping: Vec<_> = collections
+                .vehicle_journeys
+                .values()
+                .next()
+                .unwrap()
+                .stop_times
+                .iter()
+                .map(|st| (st.continuous_pickup, st.continuous_drop_off))
+                .collect();
+            assert_eq!(
+                vec![
+                    (
+                        Some(ContinuousStopping::NotContinuous),
+                        Some(ContinuousStopping::CoordinateWithDriver)
+                    ),
+                    (None, None),
+                ],
+                continuous_stopping
+            );
+        });
+    }
+
     #[test]
     fn gtfs_stop_times_deduplicated() {
         let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type,route_color,route_text_color\n\
@@ -3057,6 +3309,9 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:02").unwrap(),
@@ -3071,6 +3326,9 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:03").unwrap(),
@@ -3085,6 +3343,9 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                 ],
                 collections.vehicle_journeys.into_vec()[0].stop_times
@@ -3154,6 +3415,9 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:02").unwrap(),
@@ -3168,6 +3432,9 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                 ],
                 collections.vehicle_journeys.into_vec()[0].stop_times
@@ -3624,7 +3891,7 @@ mod tests {
                                   ";
 
         test_in_tmp_dir(|path| {
-            let mut handler = PathFileHandler::new(path.to_path_buf());
+            let mut handler = LenientFileHandler::new(PathFileHandler::new(path.to_path_buf()));
             create_file_with_content(path, "routes.txt", routes_content);
             create_file_with_content(path, "trips.txt", trips_content);
             create_file_with_content(path, "stop_times.txt", stop_times_content);
@@ -3643,17 +3910,31 @@ mod tests {
 
             super::read_routes(&mut handler, &mut collections, false, false, &mut vec![]).unwrap();
             let location_groups = HashMap::new();
-            let val = super::manage_stop_times(
+            super::manage_stop_times(
                 &mut collections,
                 &mut handler,
                 false,
                 None,
                 &location_groups,
-            );
+            )
+            .unwrap();
 
-            // the first stop time of the vj has no departure/arrival, it's an error
-            let err = val.unwrap_err();
-            assert_eq!( "the first stop time of the vj '1' has no departure/arrival, the stop_times.txt file is not valid",format!("{}", err));
+            // the first stop time of the vj has no departure/arrival, the vj is skipped
+            assert!(collections
+                .vehicle_journeys
+                .get("1")
+                .unwrap()
+                .stop_times
+                .is_empty());
+            assert_eq!(
+                vec![DataError::DroppedTrip {
+                    trip_id: "1".to_string(),
+                    reason: "the first stop time of the vj '1' has no departure/arrival, \
+                             the stop_times.txt file is not valid"
+                        .to_string(),
+                }],
+                handler.into_errors()
+            );
         });
     }
     #[test]
@@ -3756,8 +4037,8 @@ mod tests {
              1,route_1,0,service_1,,";
 
         let stop_times_content = "trip_id,arrival_time,departure_time,stop_id,stop_sequence,stop_headsign,pickup_type,drop_off_type,shape_dist_traveled,timepoint\n\
-                                  1,06:00:00,06:00:00,sp:01,1,over there,,,,0\n\
-                                  1,06:06:27,06:06:27,sp:02,2,,2,1,,1\n\
+                                  1,06:00:00,06:00:00,sp:01,1,over there,,,0,0\n\
+                                  1,06:06:27,06:06:27,sp:02,2,,2,1,1200.5,1\n\
                                   1,06:06:27,06:06:27,sp:03,3,,2,1,,";
 
         test_in_tmp_dir(|path| {
@@ -3798,6 +4079,9 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Estimated),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:02").unwrap(),
@@ -3812,6 +4096,9 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:03").unwrap(),
@@ -3826,6 +4113,9 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                 ],
                 collections.vehicle_journeys.into_vec()[0].stop_times
@@ -13,8 +13,8 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
 use super::{
-    Agency, BookingRule, DirectionType, Route, RouteType, Shape, Stop, StopLocationType,
-    TicketingDeepLinks, Transfer, Trip,
+    Agency, BookingRule, DirectionType, Location, LocationGeometry, LocationProperties, Locations,
+    Route, RouteType, Shape, Stop, StopLocationType, TicketingDeepLinks, Transfer, Trip,
 };
 use crate::gtfs::{Attribution, ExtendedRoute, StopTime};
 use crate::model::{GetCorresponding, Model};
@@ -25,8 +25,10 @@ use crate::Result;
 use anyhow::Context;
 use geo::Geometry as GeoGeometry;
 use relational_types::IdxSet;
+use rust_decimal::Decimal;
 use serde::Serialize;
 use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::path;
 use tracing::{info, warn};
 use typed_index_collection::{Collection, CollectionWithId, Idx};
@@ -207,7 +209,64 @@ fn ntfs_stop_location_to_gtfs_stop(
     }
 }
 
-pub fn write_stops(path: &path::Path, model: &Model) -> Result<()> {
+/// Writes the zones with a polygon geometry to `locations.geojson`, returning
+/// the stop points written as locations rather than as stops
+pub fn write_locations(path: &path::Path, model: &Model) -> Result<HashSet<Idx<StopPoint>>> {
+    let mut locations = HashSet::new();
+    let mut features = vec![];
+    for (sp_idx, sp) in model.stop_points.iter() {
+        if sp.stop_type != StopType::Zone {
+            continue;
+        }
+        let Some(geometry) = sp
+            .geometry_id
+            .as_ref()
+            .and_then(|geometry_id| model.geometries.get(geometry_id))
+        else {
+            continue;
+        };
+        let geometry = match LocationGeometry::try_from(&geometry.geometry) {
+            Ok(geometry) => geometry,
+            Err(e) => {
+                warn!("Zone {} is not exported as a location: {}", sp.id, e);
+                continue;
+            }
+        };
+        features.push(Location {
+            feature_type: "Feature".to_string(),
+            id: sp.id.clone(),
+            properties: LocationProperties {
+                stop_name: Some(sp.name.clone()),
+                stop_desc: get_first_comment_name(sp, &model.comments),
+            },
+            geometry,
+        });
+        locations.insert(sp_idx);
+    }
+    if features.is_empty() {
+        return Ok(locations);
+    }
+
+    let file = "locations.geojson";
+    info!(file_name = %file, "Writing");
+    let path = path.join(file);
+    let writer = std::fs::File::create(&path).with_context(|| format!("Error writing {path:?}"))?;
+    serde_json::to_writer(
+        std::io::BufWriter::new(writer),
+        &Locations {
+            collection_type: "FeatureCollection".to_string(),
+            features,
+        },
+    )
+    .with_context(|| format!("Error writing {path:?}"))?;
+    Ok(locations)
+}
+
+pub fn write_stops(
+    path: &path::Path,
+    model: &Model,
+    locations: &HashSet<Idx<StopPoint>>,
+) -> Result<()> {
     let file = "stops.txt";
     info!(file_name = %file, "Writing");
     let path = path.join(file);
@@ -228,6 +287,9 @@ pub fn write_stops(path: &path::Path, model: &Model) -> Result<()> {
         // SL to SP
     }
     for (sp_idx, sp) in model.stop_points.iter() {
+        if locations.contains(&sp_idx) {
+            continue;
+        }
         // According to gtfs spec: value 1 (True) means directions should be generated
         // for access directly to the stop, independent of any entrances or pathways.
         // Basic case of roadside stops.
@@ -241,7 +303,22 @@ pub fn write_stops(path: &path::Path, model: &Model) -> Result<()> {
         .with_context(|| format!("Error reading {path:?}"))?;
     }
     info!("Writing {} from StopArea", file);
+    // the stop areas only grouping locations are not written
+    let stop_area_ids: HashSet<&str> = model
+        .stop_points
+        .iter()
+        .filter(|(sp_idx, _)| !locations.contains(sp_idx))
+        .map(|(_, sp)| sp.stop_area_id.as_str())
+        .collect();
+    let location_stop_area_ids: HashSet<&str> = locations
+        .iter()
+        .map(|sp_idx| model.stop_points[*sp_idx].stop_area_id.as_str())
+        .filter(|stop_area_id| !stop_area_ids.contains(stop_area_id))
+        .collect();
     for sa in model.stop_areas.values() {
+        if location_stop_area_ids.contains(sa.id.as_str()) {
+            continue;
+        }
         wtr.serialize(ntfs_stop_area_to_gtfs_stop(
             sa,
             &model.comments,
@@ -601,6 +678,8 @@ fn make_gtfs_route_from_ntfs_line(line: &objects::Line, pm: &PhysicalModeWithOrd
         color: line.color.clone(),
         text_color: line.text_color.clone(),
         sort_order: line.sort_order,
+        continuous_pickup: None,
+        continuous_drop_off: None,
     }
 }
 
@@ -632,7 +711,9 @@ pub fn write_routes(path: &path::Path, model: &Model, extend_route_type: bool) -
 pub fn write_stop_times(
     path: &path::Path,
     vehicle_journeys: &CollectionWithId<VehicleJourney>,
+    routes: &CollectionWithId<objects::Route>,
     stop_points: &CollectionWithId<StopPoint>,
+    locations: &HashSet<Idx<StopPoint>>,
     stop_times_headsigns: &HashMap<(String, u32), String>,
 ) -> Result<()> {
     let file = "stop_times.txt";
@@ -640,7 +721,20 @@ pub fn write_stop_times(
     let stop_times_path = path.join(file);
     let mut st_wtr = csv::Writer::from_path(&stop_times_path)
         .with_context(|| format!("Error reading {stop_times_path:?}"))?;
+    // The continuous stopping of the routes is written on the stop times, and
+    // the columns are only written if there is any continuous stopping, the
+    // number of serialized columns being the same for all stop times
+    // See struct StopTime -> skip_serializing_if on continuous_pickup
+    let has_continuous_stopping = routes
+        .values()
+        .any(|route| route.continuous_pickup.is_some() || route.continuous_drop_off.is_some())
+        || vehicle_journeys
+            .values()
+            .flat_map(|vj| &vj.stop_times)
+            .any(|st| st.continuous_pickup.is_some() || st.continuous_drop_off.is_some());
+    let default_route = objects::Route::default();
     for (vj_idx, vj) in vehicle_journeys {
+        let route = routes.get(&vj.route_id).unwrap_or(&default_route);
         for st in &vj.stop_times {
             // Notes :
             // 1 - In ntm, a vj can have n booking_rules. In gtfs it's only one. So we take the first one.
@@ -652,10 +746,22 @@ pub fn write_stop_times(
                 .first()
                 .filter(|_| st.pickup_type == 2u8 || st.drop_off_type == 2u8)
                 .cloned();
+            // The location_id column is only written if there are locations,
+            // the number of serialized columns being the same for all stop times
+            // See struct StopTime -> skip_serializing_if on location_id
+            let (stop_id, location_id) = if locations.contains(&st.stop_point_idx) {
+                (None, Some(stop_points[st.stop_point_idx].id.clone()))
+            } else {
+                (
+                    Some(stop_points[st.stop_point_idx].id.clone()),
+                    (!locations.is_empty()).then(String::new),
+                )
+            };
             st_wtr
                 .serialize(StopTime {
-                    stop_id: Some(stop_points[st.stop_point_idx].id.clone()),
+                    stop_id,
                     location_group_id: None, // arbitrary value, this field is not serialized in GTFS yet
+                    location_id,
                     trip_id: vj.id.clone(),
                     stop_sequence: st.sequence,
                     arrival_time: st.arrival_time,
@@ -671,6 +777,10 @@ pub fn write_stop_times(
                     timepoint: matches!(st.precision, None | Some(StopTimePrecision::Exact)),
                     pickup_booking_rule_id: booking_rule_id_opt.clone(),
                     drop_off_booking_rule_id: booking_rule_id_opt,
+                    shape_dist_traveled: st.shape_dist_traveled,
+                    continuous_pickup: has_continuous_stopping.then(|| st.continuous_pickup(route)),
+                    continuous_drop_off: has_continuous_stopping
+                        .then(|| st.continuous_drop_off(route)),
                 })
                 .with_context(|| format!("Error reading {st_wtr:?}"))?;
         }
@@ -714,11 +824,22 @@ fn ntfs_geometry_to_gtfs_shapes(g: &objects::Geometry) -> impl Iterator<Item = S
         }
     };
 
-    points.iter().enumerate().map(move |(i, p)| Shape {
-        id: g.id.clone(),
-        lat: p.y,
-        lon: p.x,
-        sequence: i as u32,
+    let mut distance = 0.;
+    points.iter().enumerate().map(move |(i, p)| {
+        if i > 0 {
+            let previous = Coord {
+                lon: points[i - 1].x,
+                lat: points[i - 1].y,
+            };
+            distance += previous.distance_to(&Coord { lon: p.x, lat: p.y });
+        }
+        Shape {
+            id: g.id.clone(),
+            lat: p.y,
+            lon: p.x,
+            sequence: i as u32,
+            shape_dist_traveled: Some(Decimal::from(distance.round() as i64)),
+        }
     })
 }
 
@@ -1175,6 +1296,9 @@ mod tests {
                     drop_off_type: 1,
                     local_zone_id: None,
                     precision: None,
+                    shape_dist_traveled: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
                 },
                 objects::StopTime {
                     stop_point_idx: collections.stop_points.get_idx("OIF:SP:36:2127").unwrap(),
@@ -1189,6 +1313,9 @@ mod tests {
                     drop_off_type: 0,
                     local_zone_id: None,
                     precision: None,
+                    shape_dist_traveled: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
                 },
             ],
             journey_pattern_id: Some(String::from("OIF:JP:1")),
@@ -1222,6 +1349,9 @@ mod tests {
                     drop_off_type: 1,
                     local_zone_id: None,
                     precision: None,
+                    shape_dist_traveled: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
                 },
                 objects::StopTime {
                     stop_point_idx: collections.stop_points.get_idx("OIF:SP:36:2127").unwrap(),
@@ -1236,6 +1366,9 @@ mod tests {
                     drop_off_type: 0,
                     local_zone_id: None,
                     precision: None,
+                    shape_dist_traveled: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
                 },
             ],
             journey_pattern_id: Some(String::from("OIF:JP:1")),
@@ -1276,12 +1409,14 @@ mod tests {
                 lon: 1.1,
                 lat: 2.2,
                 sequence: 0,
+                shape_dist_traveled: Some(Decimal::from(0)),
             },
             Shape {
                 id: "1".to_string(),
                 lon: 3.3,
                 lat: 4.4,
                 sequence: 1,
+                shape_dist_traveled: Some(Decimal::from(345_660)),
             },
         ];
 
@@ -1405,6 +1540,9 @@ mod tests {
                 drop_off_type: 0,
                 local_zone_id: None,
                 precision: None,
+                shape_dist_traveled: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
             },
             StopTime {
                 stop_point_idx: stop_points.get_idx("sp:01").unwrap(),
@@ -1419,6 +1557,9 @@ mod tests {
                 drop_off_type: 1,
                 local_zone_id: Some(3),
                 precision: Some(StopTimePrecision::Estimated),
+                shape_dist_traveled: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
             },
         ];
         let vehicle_journeys = CollectionWithId::from(VehicleJourney {
@@ -1446,7 +1587,9 @@ mod tests {
         write_stop_times(
             tmp_dir.path(),
             &vehicle_journeys,
+            &CollectionWithId::default(),
             &stop_points,
+            &HashSet::new(),
             &stop_times_headsigns,
         )
         .unwrap();
@@ -1456,9 +1599,71 @@ mod tests {
         let mut output_contents = String::new();
         output_file.read_to_string(&mut output_contents).unwrap();
         assert_eq!(
-            "trip_id,arrival_time,departure_time,start_pickup_drop_off_window,end_pickup_drop_off_window,stop_id,stop_sequence,pickup_type,drop_off_type,local_zone_id,stop_headsign,timepoint,pickup_booking_rule_id,drop_off_booking_rule_id\n\
-            vj:01,06:00:00,06:00:00,,,sp:01,1,0,0,,somewhere,1,,\n\
-            vj:01,06:06:27,06:06:27,,,sp:01,2,2,1,3,,0,,\n",
+            "trip_id,arrival_time,departure_time,start_pickup_drop_off_window,end_pickup_drop_off_window,stop_id,stop_sequence,pickup_type,drop_off_type,local_zone_id,stop_headsign,timepoint,pickup_booking_rule_id,drop_off_booking_rule_id,shape_dist_traveled\n\
+            vj:01,06:00:00,06:00:00,,,sp:01,1,0,0,,somewhere,1,,,\n\
+            vj:01,06:06:27,06:06:27,,,sp:01,2,2,1,3,,0,,,\n",
+            output_contents
+        );
+        tmp_dir.close().expect("delete temp dir");
+    }
+
+    #[test]
+    fn ntfs_continuous_stopping_to_stop_times() {
+        let stop_points = CollectionWithId::from(StopPoint {
+            id: "sp:01".to_string(),
+            name: "sp_name_1".to_string(),
+            stop_area_id: "sa_1".to_string(),
+            ..Default::default()
+        });
+        let stop_time = |sequence: u32, continuous_drop_off: Option<ContinuousStopping>| StopTime {
+            stop_point_idx: stop_points.get_idx("sp:01").unwrap(),
+            sequence,
+            arrival_time: Some(Time::new(6, sequence, 0)),
+            departure_time: Some(Time::new(6, sequence, 0)),
+            start_pickup_drop_off_window: None,
+            end_pickup_drop_off_window: None,
+            boarding_duration: 0,
+            alighting_duration: 0,
+            pickup_type: 0,
+            drop_off_type: 0,
+            local_zone_id: None,
+            precision: None,
+            shape_dist_traveled: None,
+            continuous_pickup: None,
+            continuous_drop_off,
+        };
+        let routes = CollectionWithId::from(objects::Route {
+            id: "r:01".to_string(),
+            continuous_pickup: Some(ContinuousStopping::Continuous),
+            ..Default::default()
+        });
+        let vehicle_journeys = CollectionWithId::from(VehicleJourney {
+            id: "vj:01".to_string(),
+            route_id: "r:01".to_string(),
+            stop_times: vec![
+                stop_time(1, None),
+                stop_time(2, Some(ContinuousStopping::CoordinateWithDriver)),
+            ],
+            ..Default::default()
+        });
+        let tmp_dir = tempdir().expect("create temp dir");
+        write_stop_times(
+            tmp_dir.path(),
+            &vehicle_journeys,
+            &routes,
+            &stop_points,
+            &HashSet::new(),
+            &HashMap::new(),
+        )
+        .unwrap();
+        let output_contents =
+            std::fs::read_to_string(tmp_dir.path().join("stop_times.txt")).unwrap();
+        // The continuous stopping of the route applies when the stop time
+        // doesn't specify it
+        assert_eq!(
+            "trip_id,arrival_time,departure_time,start_pickup_drop_off_window,end_pickup_drop_off_window,stop_id,stop_sequence,pickup_type,drop_off_type,local_zone_id,stop_headsign,timepoint,pickup_booking_rule_id,drop_off_booking_rule_id,shape_dist_traveled,continuous_pickup,continuous_drop_off\n\
+            vj:01,06:01:00,06:01:00,,,sp:01,1,0,0,,,1,,,,0,1\n\
+            vj:01,06:02:00,06:02:00,,,sp:01,2,0,0,,,1,,,,0,3\n",
             output_contents
         );
         tmp_dir.close().expect("delete temp dir");
@@ -1525,6 +1730,8 @@ mod tests {
             color: None,
             text_color: None,
             sort_order: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
         };
 
         assert_eq!(expected, make_gtfs_route_from_ntfs_line(&line, &pm));
@@ -1588,6 +1795,8 @@ mod tests {
                 blue: 45,
             }),
             sort_order: Some(1342),
+            continuous_pickup: None,
+            continuous_drop_off: None,
         };
 
         assert_eq!(expected, make_gtfs_route_from_ntfs_line(&line, &pm));
@@ -0,0 +1,533 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Reference calendar of public and school holidays, read from iCalendar
+//! (`.ics`) or CSV files, to tag the calendars with the holidays they run on
+//! and to generate their holiday service.
+
+use crate::{
+    model::Collections,
+    objects::{Date, Properties},
+    serde_utils::*,
+    vptranslator, Result,
+};
+use anyhow::{anyhow, Context};
+use chrono::{Days, Weekday};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+use tracing::{info, warn};
+
+/// Key of the `object_properties` of the vehicle journeys describing how they
+/// run on public holidays (see [`HolidayService`]).
+pub const PUBLIC_HOLIDAYS_PROPERTY: &str = "public_holidays";
+/// Key of the `object_properties` of the vehicle journeys describing how they
+/// run on school holidays (see [`HolidayService`]).
+pub const SCHOOL_HOLIDAYS_PROPERTY: &str = "school_holidays";
+/// Key of the `object_properties` of the vehicle journeys with the names of
+/// the holiday periods they run in, separated by `;`.
+pub const HOLIDAY_PERIODS_PROPERTY: &str = "holiday_periods";
+
+/// Kind of holidays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HolidayKind {
+    /// Public holidays, e.g. Christmas.
+    Public,
+    /// School holidays.
+    School,
+}
+
+/// A named period of holidays, e.g. "Christmas holidays".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HolidayPeriod {
+    /// Name of the period.
+    pub name: String,
+    /// Kind of holidays.
+    pub kind: HolidayKind,
+    /// First date of the period.
+    pub start_date: Date,
+    /// Last date of the period (included).
+    pub end_date: Date,
+}
+
+impl HolidayPeriod {
+    fn dates(&self) -> impl Iterator<Item = Date> + '_ {
+        self.start_date
+            .iter_days()
+            .take_while(move |date| *date <= self.end_date)
+    }
+}
+
+/// Holiday periods of reference.
+#[derive(Debug, Clone, Default)]
+pub struct ReferenceCalendar {
+    /// Holiday periods.
+    pub periods: Vec<HolidayPeriod>,
+}
+
+impl ReferenceCalendar {
+    /// Dates of the holidays of a kind.
+    pub fn dates(&self, kind: HolidayKind) -> BTreeSet<Date> {
+        self.periods
+            .iter()
+            .filter(|period| period.kind == kind)
+            .flat_map(HolidayPeriod::dates)
+            .collect()
+    }
+
+    /// Add the holiday periods of a file of a kind of holidays: an iCalendar
+    /// file (`.ics`) whose events are the holiday periods, or a CSV file with
+    /// the columns `name`, `start_date` and `end_date` (included, in the
+    /// `YYYYMMDD` format).
+    pub fn read<P: AsRef<Path>>(&mut self, path: P, kind: HolidayKind) -> Result<()> {
+        let path = path.as_ref();
+        info!("Reading holidays from {:?}", path);
+        let periods = if path.extension().is_some_and(|ext| ext == "ics") {
+            let content =
+                fs::read_to_string(path).with_context(|| format!("Error reading {path:?}"))?;
+            parse_ics(&content, kind).with_context(|| format!("Error reading {path:?}"))?
+        } else {
+            read_csv(path, kind)?
+        };
+        self.periods.extend(periods);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CsvHolidayPeriod {
+    name: String,
+    #[serde(deserialize_with = "de_from_date_string")]
+    start_date: Date,
+    #[serde(deserialize_with = "de_from_date_string")]
+    end_date: Date,
+}
+
+fn read_csv(path: &Path, kind: HolidayKind) -> Result<Vec<HolidayPeriod>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .with_context(|| format!("Error reading {path:?}"))?;
+    let mut periods = Vec::new();
+    for period in rdr.deserialize() {
+        let period: CsvHolidayPeriod = period.with_context(|| format!("Error reading {path:?}"))?;
+        periods.push(HolidayPeriod {
+            name: period.name,
+            kind,
+            start_date: period.start_date,
+            end_date: period.end_date,
+        });
+    }
+    Ok(periods)
+}
+
+/// Date of a `DTSTART` or `DTEND` value, a date (`20201225`) or a date-time
+/// (`20201225T000000Z`), and whether it's the start of the day (a date or a
+/// date-time at midnight).
+fn parse_ics_date(value: &str) -> Result<(Date, bool)> {
+    let date = value
+        .get(..8)
+        .ok_or_else(|| anyhow!("invalid date {value:?}"))?;
+    let date =
+        Date::parse_from_str(date, "%Y%m%d").with_context(|| format!("invalid date {value:?}"))?;
+    let time = value[8..].trim_end_matches('Z');
+    Ok((date, time.is_empty() || time == "T000000"))
+}
+
+/// Holiday periods of the events of an iCalendar content. The end of the
+/// events, when it's a date or a date-time at midnight, is excluded as
+/// specified by the RFC 5545. The recurrence rules (`RRULE`) aren't supported,
+/// only the first occurrence of a recurring event is read.
+fn parse_ics(content: &str, kind: HolidayKind) -> Result<Vec<HolidayPeriod>> {
+    // The long lines are folded, the following lines starting with a space
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(folded), Some(last)) => last.push_str(folded),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut periods = Vec::new();
+    let mut event: Option<BTreeMap<String, String>> = None;
+    for line in lines {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name, value.trim()),
+            None => continue,
+        };
+        // Ignore the parameters, e.g. `DTSTART;VALUE=DATE`
+        let name = name.split(';').next().unwrap_or_default().to_uppercase();
+        match (name.as_str(), value) {
+            ("BEGIN", "VEVENT") => event = Some(BTreeMap::new()),
+            ("END", "VEVENT") => {
+                let event = match event.take() {
+                    Some(event) => event,
+                    None => continue,
+                };
+                if event.contains_key("RRULE") {
+                    warn!(
+                        "event {:?} has a recurrence rule, only its first occurrence is read",
+                        event.get("SUMMARY").map(String::as_str).unwrap_or_default()
+                    );
+                }
+                let (start_date, _) = match event.get("DTSTART") {
+                    Some(dtstart) => parse_ics_date(dtstart)?,
+                    None => continue,
+                };
+                let end_date = match event.get("DTEND") {
+                    Some(dtend) => match parse_ics_date(dtend)? {
+                        (end_date, true) => (end_date - Days::new(1)).max(start_date),
+                        (end_date, false) => end_date,
+                    },
+                    None => start_date,
+                };
+                periods.push(HolidayPeriod {
+                    name: event.get("SUMMARY").cloned().unwrap_or_default(),
+                    kind,
+                    start_date,
+                    end_date,
+                });
+            }
+            _ => {
+                if let Some(event) = event.as_mut() {
+                    event.insert(name, value.replace("\\,", ","));
+                }
+            }
+        }
+    }
+    Ok(periods)
+}
+
+/// How a calendar runs on the holidays of a kind, during its validity period
+/// (from its first date to its last one).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HolidayService {
+    /// The calendar doesn't run on any holiday.
+    Excluded,
+    /// The calendar runs only on holidays.
+    Only,
+    /// The calendar runs on holidays and on other days.
+    Included,
+}
+
+impl HolidayService {
+    /// Value in the `object_properties`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HolidayService::Excluded => "excluded",
+            HolidayService::Only => "only",
+            HolidayService::Included => "included",
+        }
+    }
+
+    fn of(dates: &BTreeSet<Date>, holidays: &BTreeSet<Date>) -> Option<Self> {
+        let (first, last) = (dates.iter().next()?, dates.iter().next_back()?);
+        if holidays.range(first..=last).next().is_none() {
+            // No holidays during the validity period
+            return None;
+        }
+        let on_holidays = dates.iter().filter(|date| holidays.contains(date)).count();
+        Some(if on_holidays == 0 {
+            HolidayService::Excluded
+        } else if on_holidays == dates.len() {
+            HolidayService::Only
+        } else {
+            HolidayService::Included
+        })
+    }
+}
+
+/// Tags of a calendar describing how it runs on holidays.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalendarTags {
+    /// How the calendar runs on public holidays, `None` when there is none
+    /// during its validity period.
+    pub public_holidays: Option<HolidayService>,
+    /// How the calendar runs on school holidays, `None` when there is none
+    /// during its validity period.
+    pub school_holidays: Option<HolidayService>,
+    /// Names of the holiday periods the calendar runs in.
+    pub periods: BTreeSet<String>,
+}
+
+/// Tag the calendars with the holidays they run on, by calendar identifier.
+pub fn tag_calendars(
+    collections: &Collections,
+    reference: &ReferenceCalendar,
+) -> BTreeMap<String, CalendarTags> {
+    let public_holidays = reference.dates(HolidayKind::Public);
+    let school_holidays = reference.dates(HolidayKind::School);
+    collections
+        .calendars
+        .values()
+        .map(|calendar| {
+            let periods = reference
+                .periods
+                .iter()
+                .filter(|period| {
+                    calendar
+                        .dates
+                        .range(period.start_date..=period.end_date)
+                        .next()
+                        .is_some()
+                })
+                .map(|period| period.name.clone())
+                .collect();
+            let tags = CalendarTags {
+                public_holidays: HolidayService::of(&calendar.dates, &public_holidays),
+                school_holidays: HolidayService::of(&calendar.dates, &school_holidays),
+                periods,
+            };
+            (calendar.id.clone(), tags)
+        })
+        .collect()
+}
+
+/// Set the tags of their calendar in the `object_properties` of the vehicle
+/// journeys ([`PUBLIC_HOLIDAYS_PROPERTY`], [`SCHOOL_HOLIDAYS_PROPERTY`] and
+/// [`HOLIDAY_PERIODS_PROPERTY`]).
+pub fn set_calendar_tags(collections: &mut Collections, tags: &BTreeMap<String, CalendarTags>) {
+    for vj_idx in collections.vehicle_journeys.indexes() {
+        let mut vj = collections.vehicle_journeys.index_mut(vj_idx);
+        let calendar_tags = match tags.get(&vj.service_id) {
+            Some(calendar_tags) => calendar_tags,
+            None => continue,
+        };
+        let properties = vj.properties_mut();
+        for (key, service) in [
+            (PUBLIC_HOLIDAYS_PROPERTY, calendar_tags.public_holidays),
+            (SCHOOL_HOLIDAYS_PROPERTY, calendar_tags.school_holidays),
+        ] {
+            if let Some(service) = service {
+                properties.insert(key.to_string(), service.as_str().to_string());
+            }
+        }
+        if !calendar_tags.periods.is_empty() {
+            let periods: Vec<&str> = calendar_tags.periods.iter().map(String::as_str).collect();
+            properties.insert(HOLIDAY_PERIODS_PROPERTY.to_string(), periods.join(";"));
+        }
+    }
+}
+
+/// Run the service of a weekday on the holidays of a kind, e.g. the Sunday
+/// service on public holidays, then deduplicate the calendars (see
+/// [`Collections::calendar_deduplication`]).
+///
+/// During the validity period of each calendar, the calendar runs on the
+/// holidays if it usually runs on the weekday (see
+/// [`vptranslator::translate`]), and doesn't run on them otherwise.
+pub fn apply_holiday_service(
+    collections: &mut Collections,
+    reference: &ReferenceCalendar,
+    kind: HolidayKind,
+    weekday: Weekday,
+) {
+    let holidays = reference.dates(kind);
+    for calendar_idx in collections.calendars.indexes() {
+        let mut calendar = collections.calendars.index_mut(calendar_idx);
+        let (first, last) = match (
+            calendar.dates.iter().next(),
+            calendar.dates.iter().next_back(),
+        ) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => continue,
+        };
+        let runs_on_weekday = vptranslator::translate(&calendar.dates)
+            .operating_days
+            .contains(&weekday);
+        for &holiday in holidays.range(first..=last) {
+            if runs_on_weekday {
+                calendar.dates.insert(holiday);
+            } else {
+                calendar.dates.remove(&holiday);
+            }
+        }
+    }
+    collections.calendar_deduplication();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ModelBuilder;
+    use pretty_assertions::assert_eq;
+
+    fn date(date: &str) -> Date {
+        date.parse().unwrap()
+    }
+
+    fn reference() -> ReferenceCalendar {
+        ReferenceCalendar {
+            periods: vec![
+                HolidayPeriod {
+                    name: "Christmas".to_string(),
+                    kind: HolidayKind::Public,
+                    start_date: date("2020-12-25"),
+                    end_date: date("2020-12-25"),
+                },
+                HolidayPeriod {
+                    name: "Christmas holidays".to_string(),
+                    kind: HolidayKind::School,
+                    start_date: date("2020-12-19"),
+                    end_date: date("2021-01-03"),
+                },
+            ],
+        }
+    }
+
+    // From Monday 2020-12-14 to Sunday 2020-12-27
+    fn collections() -> Collections {
+        ModelBuilder::default()
+            .calendar(
+                "week",
+                &[
+                    "2020-12-14",
+                    "2020-12-15",
+                    "2020-12-16",
+                    "2020-12-17",
+                    "2020-12-18",
+                    "2020-12-21",
+                    "2020-12-22",
+                    "2020-12-23",
+                    "2020-12-24",
+                    "2020-12-25",
+                ],
+            )
+            .calendar("sunday", &["2020-12-20", "2020-12-27"])
+            .vj("VJ1", |vj| {
+                vj.calendar("week")
+                    .st("SP1", "10:00:00")
+                    .st("SP2", "10:10:00");
+            })
+            .vj("VJ2", |vj| {
+                vj.calendar("sunday")
+                    .st("SP1", "10:00:00")
+                    .st("SP2", "10:10:00");
+            })
+            .build()
+            .into_collections()
+    }
+
+    #[test]
+    fn parse_ics_events() {
+        let content = "BEGIN:VCALENDAR\r\n\
+                       BEGIN:VEVENT\r\n\
+                       DTSTART;VALUE=DATE:20201219\r\n\
+                       DTEND;VALUE=DATE:20210104\r\n\
+                       SUMMARY:Vacances de No\r\n \u{eb}l\r\n\
+                       END:VEVENT\r\n\
+                       BEGIN:VEVENT\r\n\
+                       DTSTART;VALUE=DATE:20201225\r\n\
+                       SUMMARY:Christmas\r\n\
+                       RRULE:FREQ=YEARLY\r\n\
+                       END:VEVENT\r\n\
+                       BEGIN:VEVENT\r\n\
+                       DTSTART:20201231T000000\r\n\
+                       DTEND:20210102T000000Z\r\n\
+                       SUMMARY:New Year\r\n\
+                       END:VEVENT\r\n\
+                       BEGIN:VEVENT\r\n\
+                       DTSTART:20210501T080000\r\n\
+                       DTEND:20210501T180000\r\n\
+                       SUMMARY:Labour Day\r\n\
+                       END:VEVENT\r\n\
+                       END:VCALENDAR\r\n";
+        let periods = parse_ics(content, HolidayKind::School).unwrap();
+        assert_eq!(
+            vec![
+                HolidayPeriod {
+                    name: "Vacances de No\u{eb}l".to_string(),
+                    kind: HolidayKind::School,
+                    start_date: date("2020-12-19"),
+                    end_date: date("2021-01-03"),
+                },
+                HolidayPeriod {
+                    name: "Christmas".to_string(),
+                    kind: HolidayKind::School,
+                    start_date: date("2020-12-25"),
+                    end_date: date("2020-12-25"),
+                },
+                // The end at midnight is excluded
+                HolidayPeriod {
+                    name: "New Year".to_string(),
+                    kind: HolidayKind::School,
+                    start_date: date("2020-12-31"),
+                    end_date: date("2021-01-01"),
+                },
+                HolidayPeriod {
+                    name: "Labour Day".to_string(),
+                    kind: HolidayKind::School,
+                    start_date: date("2021-05-01"),
+                    end_date: date("2021-05-01"),
+                },
+            ],
+            periods
+        );
+    }
+
+    #[test]
+    fn tag_calendars_with_holidays() {
+        let mut collections = collections();
+        let tags = tag_calendars(&collections, &reference());
+        assert_eq!(
+            CalendarTags {
+                public_holidays: Some(HolidayService::Included),
+                school_holidays: Some(HolidayService::Included),
+                periods: BTreeSet::from([
+                    "Christmas".to_string(),
+                    "Christmas holidays".to_string()
+                ]),
+            },
+            tags["week"]
+        );
+        assert_eq!(
+            CalendarTags {
+                public_holidays: Some(HolidayService::Excluded),
+                school_holidays: Some(HolidayService::Only),
+                periods: BTreeSet::from(["Christmas holidays".to_string()]),
+            },
+            tags["sunday"]
+        );
+
+        set_calendar_tags(&mut collections, &tags);
+        let properties = &collections
+            .vehicle_journeys
+            .get("VJ2")
+            .unwrap()
+            .object_properties;
+        assert_eq!("only", properties[SCHOOL_HOLIDAYS_PROPERTY]);
+        assert_eq!("excluded", properties[PUBLIC_HOLIDAYS_PROPERTY]);
+        assert_eq!("Christmas holidays", properties[HOLIDAY_PERIODS_PROPERTY]);
+    }
+
+    #[test]
+    fn sunday_service_on_public_holidays() {
+        let mut collections = collections();
+        apply_holiday_service(
+            &mut collections,
+            &reference(),
+            HolidayKind::Public,
+            Weekday::Sun,
+        );
+        let week = collections.calendars.get("week").unwrap();
+        assert!(!week.dates.contains(&date("2020-12-25")));
+        assert_eq!(9, week.dates.len());
+        let sunday = collections.calendars.get("sunday").unwrap();
+        assert!(sunday.dates.contains(&date("2020-12-25")));
+        assert_eq!(3, sunday.dates.len());
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! [HRDF](https://opentransportdata.swiss/en/cookbook/hafas-rohdaten-format-hrdf/)
+//! (HAFAS Raw Data Format) management, the format of the rail timetables of
+//! Switzerland and Germany.
+
+mod read;
+
+use crate::{
+    error::DataError,
+    file_handler::{FileHandler, PathFileHandler, ZipHandler},
+    model::{Collections, Model},
+    objects::{Contributor, Dataset},
+    validity_period, AddPrefix, PrefixConfiguration, Result,
+};
+use anyhow::{anyhow, Context};
+use std::{collections::BTreeMap, path::Path};
+use tracing::warn;
+use typed_index_collection::CollectionWithId;
+
+///parameters consolidation
+#[derive(Default)]
+pub struct Configuration {
+    /// The Contributor providing the Dataset
+    pub contributor: Contributor,
+    /// Describe the Dataset being parsed
+    pub dataset: Dataset,
+    /// Additional key-values for the 'feed_infos.txt'
+    pub feed_infos: BTreeMap<String, String>,
+    /// used to prefix objects
+    pub prefix_conf: Option<PrefixConfiguration>,
+}
+
+fn read_file_handler_to_collections<H>(
+    file_handler: &mut H,
+    configuration: Configuration,
+) -> Result<Collections>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let Configuration {
+        contributor,
+        mut dataset,
+        feed_infos,
+        prefix_conf,
+    } = configuration;
+
+    let period = read::read_lines(file_handler, "ECKDATEN")?
+        .ok_or_else(|| anyhow!("the file ECKDATEN is required"))?;
+    let period = read::read_period(&period)?;
+    let bitfields = read::read_lines(file_handler, "BITFELD")?
+        .map(|lines| read::read_bitfields(&lines, &period))
+        .unwrap_or_default();
+    let stations = read::Stations {
+        names: read::read_lines(file_handler, "BAHNHOF")?
+            .map(|lines| read::read_station_names(&lines))
+            .unwrap_or_default(),
+        coords: read::read_first_lines(file_handler, &["BFKOORD_WGS", "BFKOORD"])?
+            .map(|lines| read::read_station_coords(&lines))
+            .unwrap_or_default(),
+    };
+    let administration_names =
+        read::read_first_lines(file_handler, &["BETRIEB_DE", "BETRIEB_FR", "BETRIEB"])?
+            .map(|lines| read::read_administration_names(&lines))
+            .unwrap_or_default();
+    let product_classes = read::read_lines(file_handler, "ZUGART")?
+        .map(|lines| read::read_product_classes(&lines))
+        .unwrap_or_default();
+
+    let mut collections = Collections::default();
+    let timetable = read::read_lines(file_handler, "FPLAN")?
+        .ok_or_else(|| anyhow!("the file FPLAN is required"))?;
+    let referential = read::Referential {
+        period: &period,
+        bitfields: &bitfields,
+        stations: &stations,
+        administration_names: &administration_names,
+        product_classes: &product_classes,
+    };
+    read::read_timetable(&mut collections, &timetable, &referential, &dataset.id)?;
+    if let Some(lines) = read::read_lines(file_handler, "UMSTEIGB")? {
+        read::read_station_transfers(&mut collections, &lines);
+    }
+    if let Some(lines) = read::read_lines(file_handler, "METABHF")? {
+        read::read_station_group_transfers(&mut collections, &lines);
+    }
+    // NTFS transfers are between stop points, and can't hold the transfer
+    // times specific to a pair of lines or of journeys
+    for name in ["UMSTEIGL", "UMSTEIGZ"] {
+        if (&mut *file_handler).get_file_if_exists(name)?.0.is_some() {
+            warn!("the file {} is ignored", name);
+        }
+    }
+    validity_period::compute_dataset_validity_period(&mut dataset, &collections.calendars)?;
+
+    collections.contributors = CollectionWithId::from(contributor);
+    collections.datasets = CollectionWithId::from(dataset);
+    collections.feed_infos = feed_infos;
+
+    //add prefixes
+    if let Some(prefix_conf) = prefix_conf {
+        collections.prefix(&prefix_conf);
+    }
+
+    collections.calendar_deduplication();
+    Ok(collections)
+}
+
+/// Imports a `Model` from the
+/// [HRDF](https://opentransportdata.swiss/en/cookbook/hafas-rohdaten-format-hrdf/)
+/// files of a directory or of a zip archive.
+pub fn read<P: AsRef<Path>>(p: P) -> Result<Model> {
+    Reader::default().parse(p)
+}
+
+/// Structure to configure the HRDF reading
+#[derive(Default)]
+pub struct Reader {
+    configuration: Configuration,
+}
+
+impl Reader {
+    /// Build a Reader with a custom configuration
+    pub fn new(configuration: Configuration) -> Self {
+        Self { configuration }
+    }
+
+    /// Imports a `Model` from the
+    /// [HRDF](https://opentransportdata.swiss/en/cookbook/hafas-rohdaten-format-hrdf/)
+    /// files of a directory or of a zip archive.
+    ///
+    /// Each journey of `FPLAN` gives a `VehicleJourney` (one by repetition
+    /// of a cyclic journey), running on the days of its `BITFELD` bitfield.
+    /// The transfer times of `UMSTEIGB` and `METABHF` give `Transfer`s and
+    /// the attributes of the journeys their `TripProperty`.
+    pub fn parse(self, path: impl AsRef<Path>) -> Result<Model> {
+        let collections = self.parse_collections(path)?;
+        Model::new(collections)
+    }
+
+    /// Imports `Collections` from the
+    /// [HRDF](https://opentransportdata.swiss/en/cookbook/hafas-rohdaten-format-hrdf/)
+    /// files like [Reader::parse].
+    pub fn parse_collections(self, path: impl AsRef<Path>) -> Result<Collections> {
+        let p = path.as_ref();
+        if p.is_file() {
+            // if it's a file, we consider it to be a zip (and an error will be returned if it is not)
+            let reader = std::fs::File::open(p)?;
+            let mut file_handler = ZipHandler::new(reader, p)?;
+            read_file_handler_to_collections(&mut file_handler, self.configuration)
+                .with_context(|| format!("impossible to read zipped hrdf {p:?}"))
+        } else if p.is_dir() {
+            let mut file_handler = PathFileHandler::new(p.to_path_buf());
+            read_file_handler_to_collections(&mut file_handler, self.configuration)
+                .with_context(|| format!("impossible to read hrdf directory from {p:?}"))
+        } else {
+            Err(DataError::InvalidInput {
+                path: p.to_path_buf(),
+                format: "hrdf",
+            }
+            .into())
+        }
+    }
+}
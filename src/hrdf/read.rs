@@ -0,0 +1,1036 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use crate::{
+    file_handler::FileHandler,
+    model::Collections,
+    objects::{
+        Availability, Calendar, CommercialMode, Company, Coord, Date, KeysValues, Line, Network,
+        PhysicalMode, Route, StopArea, StopPoint, StopTime, StopType, Time, Transfer, TripProperty,
+        ValidityPeriod, VehicleJourney,
+    },
+    utils::required_stop_point_coord,
+    Result,
+};
+use anyhow::{anyhow, bail, Context};
+use skip_error::skip_error_and_warn;
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::Read,
+};
+use tracing::{info, warn};
+
+/// Stop number of the default transfer time in `UMSTEIGB`
+const DEFAULT_TRANSFER_STOP: &str = "9999999";
+/// Bitfield number of the journeys running every day of the timetable period
+const EVERY_DAY_BITFIELD: &str = "000000";
+
+/// Lines of a HRDF file, `None` if the file doesn't exist.
+///
+/// HRDF files are encoded in UTF-8 since the version 5.40, and in ISO-8859-1
+/// before.
+pub(super) fn read_lines<H>(file_handler: &mut H, name: &str) -> Result<Option<Vec<String>>>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let (reader, path) = (&mut *file_handler).get_file_if_exists(name)?;
+    let mut reader = match reader {
+        Some(reader) => reader,
+        None => return Ok(None),
+    };
+    info!("Reading {}", name);
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Error reading {path:?}"))?;
+    let content = match String::from_utf8(bytes) {
+        Ok(content) => content,
+        Err(error) => error.into_bytes().into_iter().map(char::from).collect(),
+    };
+    Ok(Some(
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect(),
+    ))
+}
+
+/// Lines of the first existing file among `names` (the name of some files
+/// depends on the version of the format or on the language), `None` if none
+/// of them exists.
+pub(super) fn read_first_lines<H>(
+    file_handler: &mut H,
+    names: &[&str],
+) -> Result<Option<Vec<String>>>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    for name in names {
+        if let Some(lines) = read_lines(file_handler, name)? {
+            return Ok(Some(lines));
+        }
+    }
+    Ok(None)
+}
+
+/// Trimmed content of the columns `start` to `end` (numbered from 1, both
+/// included) of a line, `None` if empty
+fn columns(line: &str, start: usize, end: usize) -> Option<&str> {
+    let byte_offset = |column: usize| {
+        line.char_indices()
+            .nth(column)
+            .map_or(line.len(), |(offset, _)| offset)
+    };
+    let field = line[byte_offset(start - 1)..byte_offset(end)].trim();
+    if field.is_empty() {
+        None
+    } else {
+        Some(field)
+    }
+}
+
+fn parse_date(date: &str) -> Result<Date> {
+    Date::parse_from_str(date, "%d.%m.%Y").with_context(|| format!("invalid date {date:?}"))
+}
+
+/// Timetable period of the `ECKDATEN` file, its first line being the start
+/// date and its second line the end date.
+pub(super) fn read_period(lines: &[String]) -> Result<ValidityPeriod> {
+    let date = |index: usize| {
+        let line = lines
+            .get(index)
+            .ok_or_else(|| anyhow!("missing line {} of ECKDATEN", index + 1))?;
+        let date = columns(line, 1, 10).ok_or_else(|| anyhow!("missing date in ECKDATEN"))?;
+        parse_date(date)
+    };
+    let period = ValidityPeriod {
+        start_date: date(0)?,
+        end_date: date(1)?,
+    };
+    if period.start_date > period.end_date {
+        bail!(
+            "the timetable period ends ({}) before it starts ({})",
+            period.end_date,
+            period.start_date
+        );
+    }
+    Ok(period)
+}
+
+/// Names of the stations of the `BAHNHOF` file, by station number.
+///
+/// The name can be followed by its variants (e.g. `Basel SBB$<1>$Basel$<2>`),
+/// only the first one is kept.
+pub(super) fn read_station_names(lines: &[String]) -> HashMap<String, String> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let number = columns(line, 1, 7)?;
+            let name = columns(line, 13, line.chars().count())?
+                .split('$')
+                .next()?
+                .trim();
+            Some((number.to_string(), name.to_string()))
+        })
+        .collect()
+}
+
+/// WGS84 coordinates of the stations of the `BFKOORD_WGS` file, by station
+/// number.
+pub(super) fn read_station_coords(lines: &[String]) -> HashMap<String, Coord> {
+    let mut coords = HashMap::new();
+    for line in lines {
+        let mut fields = line.split_whitespace();
+        let (number, lon, lat) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(number), Some(lon), Some(lat)) => (number, lon, lat),
+            _ => continue,
+        };
+        let coord = match (lon.parse(), lat.parse()) {
+            (Ok(lon), Ok(lat)) => Coord { lon, lat },
+            _ => {
+                warn!("station {}: invalid coordinates", number);
+                continue;
+            }
+        };
+        coords.insert(number.to_string(), coord);
+    }
+    coords
+}
+
+/// Names of the transport companies of the `BETRIEB` file, by
+/// administration number.
+///
+/// A company is described by a line with its names (the last one being its
+/// long name) followed by a line with its administration numbers, e.g.
+/// ```text
+/// 00001 K "SBB" L "SBB" V "Schweizerische Bundesbahnen SBB"
+/// 00001 : 000011
+/// ```
+pub(super) fn read_administration_names(lines: &[String]) -> HashMap<String, String> {
+    let mut company_names = HashMap::new();
+    let mut administration_names = HashMap::new();
+    for line in lines {
+        let company = match columns(line, 1, 5) {
+            Some(company) => company,
+            None => continue,
+        };
+        if columns(line, 7, 7) == Some(":") {
+            if let (Some(name), Some((_, administrations))) =
+                (company_names.get(company), line.split_once(':'))
+            {
+                for administration in administrations.split_whitespace() {
+                    administration_names.insert(administration.to_string(), name.clone());
+                }
+            }
+        } else if let Some(name) = line.split('"').skip(1).step_by(2).last() {
+            company_names.insert(company.to_string(), name.to_string());
+        }
+    }
+    administration_names
+}
+
+/// Product classes of the categories of the `ZUGART` file
+pub(super) fn read_product_classes(lines: &[String]) -> HashMap<String, u8> {
+    lines
+        .iter()
+        // The categories are followed by their texts, in sections starting
+        // with `<`
+        .take_while(|line| !line.starts_with('<'))
+        .filter_map(|line| {
+            let category = columns(line, 1, 3)?;
+            let class = columns(line, 5, 6)?.parse().ok()?;
+            Some((category.to_string(), class))
+        })
+        .collect()
+}
+
+/// Physical mode of a product class of HRDF
+fn physical_mode(product_class: Option<u8>) -> &'static str {
+    match product_class {
+        Some(0) | Some(1) | Some(2) => "LongDistanceTrain",
+        Some(3) => "LocalTrain",
+        Some(4) => "Ferry",
+        Some(5) => "RapidTransit",
+        Some(6) => "Bus",
+        Some(7) => "SuspendedCableCar",
+        Some(9) => "Tramway",
+        _ => "Train",
+    }
+}
+
+/// Dates of the days of the timetable period, from its start date
+fn period_dates(period: &ValidityPeriod) -> impl Iterator<Item = Date> {
+    let end_date = period.end_date;
+    period
+        .start_date
+        .iter_days()
+        .take_while(move |date| *date <= end_date)
+}
+
+/// Dates of an hexadecimal bitfield, each bit being a day of the timetable
+/// period from its start date.
+///
+/// The first 2 bits of the bitfield are padding and don't represent any day.
+fn bitfield_dates(bitfield: &str, period: &ValidityPeriod) -> Result<BTreeSet<Date>> {
+    let digits = bitfield
+        .chars()
+        .map(|digit| {
+            digit
+                .to_digit(16)
+                .ok_or_else(|| anyhow!("invalid bitfield {bitfield:?}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let bits = digits
+        .into_iter()
+        .flat_map(|digit| (0..4).rev().map(move |shift| (digit >> shift) & 1 == 1))
+        .skip(2);
+    Ok(bits
+        .zip(period_dates(period))
+        .filter(|(bit, _)| *bit)
+        .map(|(_, date)| date)
+        .collect())
+}
+
+/// Dates of the bitfields of the `BITFELD` file, by bitfield number.
+pub(super) fn read_bitfields(
+    lines: &[String],
+    period: &ValidityPeriod,
+) -> HashMap<String, BTreeSet<Date>> {
+    let mut bitfields = HashMap::new();
+    for line in lines {
+        let (number, bitfield) = match (columns(line, 1, 6), columns(line, 8, 103)) {
+            (Some(number), Some(bitfield)) => (number, bitfield),
+            _ => continue,
+        };
+        let dates = skip_error_and_warn!(bitfield_dates(bitfield, period)
+            .with_context(|| format!("bitfield {number}: invalid days")));
+        bitfields.insert(number.to_string(), dates);
+    }
+    bitfields
+}
+
+/// Stop of a journey of the `FPLAN` file
+#[derive(Debug)]
+struct JourneyStop {
+    stop_id: String,
+    name: Option<String>,
+    arrival_time: Option<Time>,
+    departure_time: Option<Time>,
+    alighting: bool,
+    boarding: bool,
+}
+
+/// Journey of the `FPLAN` file, from its `*Z` line to the next one
+#[derive(Debug, Default)]
+struct Journey {
+    number: String,
+    administration: String,
+    /// Number of repetitions of the journey after its first run
+    cycles: u32,
+    /// Time between two repetitions of the journey, in minutes
+    cycle_time: u32,
+    category: Option<String>,
+    line: Option<String>,
+    backward: bool,
+    bitfield: Option<String>,
+    attributes: BTreeSet<String>,
+    stops: Vec<JourneyStop>,
+}
+
+/// Time of a stop of a journey (`HHHMM`, the hours can exceed 24), and
+/// whether the travellers can alight (arrival) or board (departure), a
+/// negative time forbidding it
+fn parse_stop_time(time: Option<&str>) -> Result<(Option<Time>, bool)> {
+    let time = match time {
+        Some(time) => time,
+        None => return Ok((None, true)),
+    };
+    let (time, allowed) = match time.strip_prefix('-') {
+        Some(time) => (time, false),
+        None => (time, true),
+    };
+    let time: u32 = time
+        .parse()
+        .with_context(|| format!("invalid time {time:?}"))?;
+    Ok((Some(Time::new(time / 100, time % 100, 0)), allowed))
+}
+
+fn parse_optional_number(line: &str, start: usize, end: usize) -> Result<u32> {
+    columns(line, start, end)
+        .map(|number| {
+            number
+                .parse()
+                .with_context(|| format!("invalid number {number:?}"))
+        })
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+fn read_journey_line(journey: &mut Journey, line: &str) -> Result<()> {
+    match line.get(..2) {
+        Some("*G") => journey.category = columns(line, 4, 6).map(str::to_string),
+        Some("*L") => journey.line = columns(line, 4, 11).map(str::to_string),
+        Some("*R") => journey.backward = columns(line, 4, 4) == Some("R"),
+        Some("*A") => match columns(line, 4, 5) {
+            // The first service days of the journey apply to all its stops
+            Some("VE") => {
+                if journey.bitfield.is_none() {
+                    journey.bitfield = columns(line, 23, 28).map(str::to_string);
+                }
+            }
+            Some(attribute) => {
+                journey.attributes.insert(attribute.to_string());
+            }
+            None => {}
+        },
+        Some(tag) if tag.starts_with('*') => {}
+        _ => {
+            let stop_id = columns(line, 1, 7).ok_or_else(|| anyhow!("missing stop number"))?;
+            let (arrival_time, alighting) = parse_stop_time(columns(line, 30, 35))?;
+            let (departure_time, boarding) = parse_stop_time(columns(line, 37, 42))?;
+            journey.stops.push(JourneyStop {
+                stop_id: stop_id.to_string(),
+                name: columns(line, 9, 29).map(str::to_string),
+                arrival_time,
+                departure_time,
+                alighting,
+                boarding,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Journeys of the `FPLAN` file
+fn read_journeys(lines: &[String]) -> Vec<Journey> {
+    let mut journeys = Vec::new();
+    let mut journey: Option<Journey> = None;
+    let mut invalid = false;
+    for line in lines {
+        // `*T` introduces a journey defined by its travel time, which is not
+        // supported
+        if line.starts_with("*Z") || line.starts_with("*T") {
+            journeys.extend(journey.take().filter(|_| !invalid));
+            invalid = false;
+            if line.starts_with("*T") {
+                warn!("journeys defined by their travel time (*T) are not supported");
+                invalid = true;
+                continue;
+            }
+            let number = columns(line, 4, 9).unwrap_or_default();
+            match parse_optional_number(line, 22, 24)
+                .and_then(|cycles| Ok((cycles, parse_optional_number(line, 26, 28)?)))
+            {
+                Ok((cycles, cycle_time)) => {
+                    journey = Some(Journey {
+                        number: number.to_string(),
+                        administration: columns(line, 11, 16).unwrap_or_default().to_string(),
+                        cycles,
+                        cycle_time,
+                        ..Default::default()
+                    })
+                }
+                Err(error) => {
+                    warn!("journey {}: invalid cycle: {}", number, error);
+                    invalid = true;
+                }
+            }
+        } else if let Some(current) = journey.as_mut().filter(|_| !invalid) {
+            if let Err(error) = read_journey_line(current, line) {
+                warn!("journey {}: {}", current.number, error);
+                invalid = true;
+            }
+        }
+    }
+    journeys.extend(journey.filter(|_| !invalid));
+    journeys
+}
+
+/// Stations of the `BAHNHOF` and `BFKOORD_WGS` files
+#[derive(Debug, Default)]
+pub(super) struct Stations {
+    pub(super) names: HashMap<String, String>,
+    pub(super) coords: HashMap<String, Coord>,
+}
+
+fn push_stop_point(
+    collections: &mut Collections,
+    stop: &JourneyStop,
+    stations: &Stations,
+) -> Result<()> {
+    let name = stations
+        .names
+        .get(&stop.stop_id)
+        .or(stop.name.as_ref())
+        .unwrap_or(&stop.stop_id);
+    let coord =
+        required_stop_point_coord(&stop.stop_id, stations.coords.get(&stop.stop_id).copied())?;
+    let mut codes = KeysValues::default();
+    codes.insert(("source".to_string(), stop.stop_id.clone()));
+    let mut stop_point = StopPoint {
+        id: stop.stop_id.clone(),
+        name: name.clone(),
+        codes,
+        coord,
+        visible: true,
+        stop_type: StopType::Point,
+        ..Default::default()
+    };
+    let stop_area = StopArea::from(stop_point.clone());
+    stop_point.stop_area_id.clone_from(&stop_area.id);
+    if !collections.stop_areas.contains_id(&stop_area.id) {
+        collections.stop_areas.push(stop_area)?;
+    }
+    collections.stop_points.push(stop_point)?;
+    Ok(())
+}
+
+/// Company and network of an administration
+fn push_administration(
+    collections: &mut Collections,
+    administration: &str,
+    administration_names: &HashMap<String, String>,
+) -> Result<()> {
+    let name = administration_names
+        .get(administration)
+        .map_or(administration, String::as_str);
+    if !collections.companies.contains_id(administration) {
+        collections.companies.push(Company {
+            id: administration.to_string(),
+            name: name.to_string(),
+            ..Default::default()
+        })?;
+    }
+    if !collections.networks.contains_id(administration) {
+        collections.networks.push(Network {
+            id: administration.to_string(),
+            name: name.to_string(),
+            timezone: Some(chrono_tz::Europe::Zurich),
+            ..Default::default()
+        })?;
+    }
+    Ok(())
+}
+
+/// Identifiers of the line and of the route of a journey
+fn push_line_and_route(
+    collections: &mut Collections,
+    journey: &Journey,
+    category: &str,
+    product_classes: &HashMap<String, u8>,
+) -> Result<(String, String)> {
+    if !collections.commercial_modes.contains_id(category) {
+        collections.commercial_modes.push(CommercialMode {
+            id: category.to_string(),
+            name: category.to_string(),
+        })?;
+    }
+    let physical_mode = physical_mode(product_classes.get(category).copied());
+    if !collections.physical_modes.contains_id(physical_mode) {
+        collections.physical_modes.push(PhysicalMode {
+            id: physical_mode.to_string(),
+            name: physical_mode.to_string(),
+            co2_emission: None,
+        })?;
+    }
+    let (line_id, line_name) = match &journey.line {
+        Some(line) => (
+            format!("{}:{category}:{line}", journey.administration),
+            format!("{category} {line}"),
+        ),
+        None => (
+            format!("{}:{category}", journey.administration),
+            category.to_string(),
+        ),
+    };
+    if !collections.lines.contains_id(&line_id) {
+        collections.lines.push(Line {
+            id: line_id.clone(),
+            code: journey.line.clone(),
+            name: line_name.clone(),
+            commercial_mode_id: category.to_string(),
+            network_id: journey.administration.clone(),
+            ..Default::default()
+        })?;
+    }
+    let direction = if journey.backward {
+        "backward"
+    } else {
+        "forward"
+    };
+    let route_id = format!("{line_id}:{direction}");
+    if !collections.routes.contains_id(&route_id) {
+        collections.routes.push(Route {
+            id: route_id.clone(),
+            name: line_name,
+            direction_type: Some(direction.to_string()),
+            line_id: line_id.clone(),
+            ..Default::default()
+        })?;
+    }
+    Ok((line_id, route_id))
+}
+
+/// Wheelchair accessibility, bike acceptance and air conditioning of a
+/// journey, from its attributes
+fn trip_property_availabilities(
+    attributes: &BTreeSet<String>,
+) -> (Availability, Availability, Availability) {
+    let availability = |attribute: &str| {
+        if attributes.contains(attribute) {
+            Availability::Available
+        } else {
+            Availability::InformationNotAvailable
+        }
+    };
+    // NF: low-floor access, VR: bikes with reservation, KL: air-conditioned
+    (availability("NF"), availability("VR"), availability("KL"))
+}
+
+/// Identifier of the trip property of a journey, `None` if its attributes
+/// don't give any information
+fn push_trip_property(
+    collections: &mut Collections,
+    journey: &Journey,
+    trip_property_ids: &mut HashMap<(Availability, Availability, Availability), String>,
+) -> Result<Option<String>> {
+    let availabilities = trip_property_availabilities(&journey.attributes);
+    let (wheelchair_accessible, bike_accepted, air_conditioned) = availabilities;
+    if [wheelchair_accessible, bike_accepted, air_conditioned]
+        .iter()
+        .all(|availability| *availability == Availability::InformationNotAvailable)
+    {
+        return Ok(None);
+    }
+    if let Some(id) = trip_property_ids.get(&availabilities) {
+        return Ok(Some(id.clone()));
+    }
+    let id = (trip_property_ids.len() + 1).to_string();
+    collections.trip_properties.push(TripProperty {
+        id: id.clone(),
+        wheelchair_accessible,
+        bike_accepted,
+        air_conditioned,
+        ..Default::default()
+    })?;
+    trip_property_ids.insert(availabilities, id.clone());
+    Ok(Some(id))
+}
+
+/// Stop times of a journey
+fn stop_times(collections: &Collections, journey: &Journey) -> Result<Vec<StopTime>> {
+    let mut stop_times = Vec::with_capacity(journey.stops.len());
+    // The stops without time are passed through
+    for stop in journey
+        .stops
+        .iter()
+        .filter(|stop| stop.arrival_time.is_some() || stop.departure_time.is_some())
+    {
+        let stop_point_idx = collections
+            .stop_points
+            .get_idx(&stop.stop_id)
+            .ok_or_else(|| anyhow!("unknown stop point {}", stop.stop_id))?;
+        let arrival_time = stop.arrival_time.or(stop.departure_time);
+        let departure_time = stop.departure_time.or(stop.arrival_time);
+        stop_times.push(StopTime {
+            stop_point_idx,
+            sequence: stop_times.len() as u32,
+            arrival_time,
+            departure_time,
+            start_pickup_drop_off_window: None,
+            end_pickup_drop_off_window: None,
+            boarding_duration: 0,
+            alighting_duration: 0,
+            pickup_type: if stop.departure_time.is_some() && stop.boarding {
+                0
+            } else {
+                1
+            },
+            drop_off_type: if stop.arrival_time.is_some() && stop.alighting {
+                0
+            } else {
+                1
+            },
+            local_zone_id: None,
+            precision: None,
+            shape_dist_traveled: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
+        });
+    }
+    if stop_times.len() < 2 {
+        bail!("less than 2 stops with a time");
+    }
+    Ok(stop_times)
+}
+
+/// Referential data of a HRDF dataset used to read its journeys
+pub(super) struct Referential<'a> {
+    pub(super) period: &'a ValidityPeriod,
+    pub(super) bitfields: &'a HashMap<String, BTreeSet<Date>>,
+    pub(super) stations: &'a Stations,
+    pub(super) administration_names: &'a HashMap<String, String>,
+    pub(super) product_classes: &'a HashMap<String, u8>,
+}
+
+/// Identifier of a repetition of a cyclic journey, the first run keeping the
+/// identifier of the journey
+fn cycle_id(id: &str, cycle: u32) -> String {
+    if cycle == 0 {
+        id.to_string()
+    } else {
+        format!("{id}:{cycle}")
+    }
+}
+
+/// Identifier of a journey, `<id>:<n>` when the same journey number is used
+/// by several journeys of an administration. The identifiers of all the
+/// repetitions of the journey must be unused.
+fn unique_journey_id(collections: &Collections, id: &str, cycles: u32) -> String {
+    let is_used = |id: &str| {
+        (0..=cycles).any(|cycle| {
+            collections
+                .vehicle_journeys
+                .contains_id(&cycle_id(id, cycle))
+        })
+    };
+    if !is_used(id) {
+        return id.to_string();
+    }
+    let unique_id = (2..)
+        .map(|n| format!("{id}:{n}"))
+        .find(|unique_id| !is_used(unique_id))
+        .expect("an unused identifier");
+    warn!(
+        "journey {} already exists, the duplicate is identified by {}",
+        id, unique_id
+    );
+    unique_id
+}
+
+fn push_vehicle_journeys(
+    collections: &mut Collections,
+    journey: &Journey,
+    referential: &Referential,
+    trip_property_ids: &mut HashMap<(Availability, Availability, Availability), String>,
+    dataset_id: &str,
+) -> Result<()> {
+    let id = format!("{}:{}", journey.administration, journey.number);
+    let category = journey
+        .category
+        .as_deref()
+        .ok_or_else(|| anyhow!("journey {id}: missing category (*G)"))?;
+    let bitfield = journey.bitfield.as_deref().unwrap_or(EVERY_DAY_BITFIELD);
+    if !collections.calendars.contains_id(bitfield) {
+        let dates = if bitfield == EVERY_DAY_BITFIELD {
+            period_dates(referential.period).collect()
+        } else {
+            referential
+                .bitfields
+                .get(bitfield)
+                .cloned()
+                .ok_or_else(|| anyhow!("journey {id}: unknown bitfield {bitfield}"))?
+        };
+        if dates.is_empty() {
+            bail!("journey {id}: no date of operation");
+        }
+        collections.calendars.push(Calendar {
+            id: bitfield.to_string(),
+            dates,
+        })?;
+    }
+
+    // The stops without time are passed through and not needed
+    for stop in journey
+        .stops
+        .iter()
+        .filter(|stop| stop.arrival_time.is_some() || stop.departure_time.is_some())
+    {
+        if !collections.stop_points.contains_id(&stop.stop_id) {
+            push_stop_point(collections, stop, referential.stations)?;
+        }
+    }
+    let base_stop_times =
+        stop_times(collections, journey).with_context(|| format!("journey {id}: invalid stops"))?;
+    push_administration(
+        collections,
+        &journey.administration,
+        referential.administration_names,
+    )?;
+    let (_, route_id) =
+        push_line_and_route(collections, journey, category, referential.product_classes)?;
+    let trip_property_id = push_trip_property(collections, journey, trip_property_ids)?;
+
+    let mut codes = KeysValues::default();
+    codes.insert(("source".to_string(), journey.number.clone()));
+    let short_name = journey
+        .number
+        .parse::<u32>()
+        .map_or_else(|_| journey.number.clone(), |number| number.to_string());
+    let headsign = journey
+        .stops
+        .last()
+        .and_then(|stop| collections.stop_points.get(&stop.stop_id))
+        .map(|stop_point| stop_point.name.clone());
+    let unique_id = unique_journey_id(collections, &id, journey.cycles);
+    // A cyclic journey is repeated every `cycle_time` minutes
+    for cycle in 0..=journey.cycles {
+        let vehicle_journey_id = cycle_id(&unique_id, cycle);
+        let offset = Time::new(0, cycle * journey.cycle_time, 0);
+        let mut stop_times = base_stop_times.clone();
+        for stop_time in &mut stop_times {
+            stop_time.arrival_time = stop_time.arrival_time.map(|time| time + offset);
+            stop_time.departure_time = stop_time.departure_time.map(|time| time + offset);
+        }
+        collections.vehicle_journeys.push(VehicleJourney {
+            id: vehicle_journey_id,
+            codes: codes.clone(),
+            route_id: route_id.clone(),
+            physical_mode_id: physical_mode(referential.product_classes.get(category).copied())
+                .to_string(),
+            dataset_id: dataset_id.to_string(),
+            service_id: bitfield.to_string(),
+            headsign: headsign.clone(),
+            short_name: Some(short_name.clone()),
+            company_id: journey.administration.clone(),
+            trip_property_id: trip_property_id.clone(),
+            stop_times,
+            ..Default::default()
+        })?;
+    }
+    Ok(())
+}
+
+/// Read the journeys of the `FPLAN` file into the collections.
+pub(super) fn read_timetable(
+    collections: &mut Collections,
+    lines: &[String],
+    referential: &Referential,
+    dataset_id: &str,
+) -> Result<()> {
+    let mut trip_property_ids = HashMap::new();
+    for journey in read_journeys(lines) {
+        skip_error_and_warn!(push_vehicle_journeys(
+            collections,
+            &journey,
+            referential,
+            &mut trip_property_ids,
+            dataset_id,
+        ));
+    }
+    Ok(())
+}
+
+fn push_transfer(collections: &mut Collections, from_stop_id: &str, to_stop_id: &str, time: u32) {
+    collections.transfers.push(Transfer {
+        from_stop_id: from_stop_id.to_string(),
+        to_stop_id: to_stop_id.to_string(),
+        min_transfer_time: Some(time),
+        real_min_transfer_time: Some(time),
+        equipment_id: None,
+    });
+}
+
+/// Transfer times within the stations of the `UMSTEIGB` file (in minutes),
+/// the station `9999999` giving the default transfer time of the other
+/// stations.
+pub(super) fn read_station_transfers(collections: &mut Collections, lines: &[String]) {
+    let mut default_time = None;
+    let mut times = HashMap::new();
+    for line in lines {
+        let mut fields = line.split_whitespace();
+        let (stop_id, minutes) = match (fields.next(), fields.next()) {
+            (Some(stop_id), Some(minutes)) => (stop_id, minutes),
+            _ => continue,
+        };
+        let minutes: u32 = skip_error_and_warn!(minutes
+            .parse()
+            .with_context(|| format!("station {stop_id}: invalid transfer time {minutes:?}")));
+        let time = minutes * 60;
+        if stop_id == DEFAULT_TRANSFER_STOP {
+            default_time = Some(time);
+        } else {
+            times.insert(stop_id, time);
+        }
+    }
+    let stop_ids: Vec<String> = collections
+        .stop_points
+        .values()
+        .map(|stop_point| stop_point.id.clone())
+        .collect();
+    for stop_id in stop_ids {
+        if let Some(time) = times.get(stop_id.as_str()).copied().or(default_time) {
+            push_transfer(collections, &stop_id, &stop_id, time);
+        }
+    }
+}
+
+/// Transfer times between stations of the `METABHF` file (in minutes), e.g.
+/// `8500010 8500090 005`
+pub(super) fn read_station_group_transfers(collections: &mut Collections, lines: &[String]) {
+    let is_stop_number =
+        |number: &str| number.len() == 7 && number.bytes().all(|b| b.is_ascii_digit());
+    for line in lines {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (from_stop_id, to_stop_id, minutes) = match fields.as_slice() {
+            [from, to, minutes, ..] if is_stop_number(from) && is_stop_number(to) => {
+                (*from, *to, *minutes)
+            }
+            _ => continue,
+        };
+        let minutes: u32 = match minutes.parse() {
+            Ok(minutes) => minutes,
+            Err(_) => continue,
+        };
+        if collections.stop_points.contains_id(from_stop_id)
+            && collections.stop_points.contains_id(to_stop_id)
+        {
+            push_transfer(collections, from_stop_id, to_stop_id, minutes * 60);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn period() -> ValidityPeriod {
+        ValidityPeriod {
+            start_date: Date::from_ymd_opt(2023, 12, 10).unwrap(),
+            end_date: Date::from_ymd_opt(2023, 12, 20).unwrap(),
+        }
+    }
+
+    #[test]
+    fn fixed_width_columns() {
+        let line = "8503000 Zürich HB                    00602";
+        assert_eq!(Some("8503000"), columns(line, 1, 7));
+        assert_eq!(Some("Zürich HB"), columns(line, 9, 29));
+        assert_eq!(None, columns(line, 30, 35));
+        assert_eq!(Some("00602"), columns(line, 37, 42));
+        assert_eq!(None, columns(line, 44, 48));
+    }
+
+    #[test]
+    fn bitfield() {
+        // 0x3 = 0011: 2 bits of padding then the first 2 days,
+        // 0x5 = 0101: the 4th and the 6th days
+        let dates: Vec<String> = bitfield_dates("350", &period())
+            .unwrap()
+            .iter()
+            .map(|date| date.to_string())
+            .collect();
+        assert_eq!(
+            vec!["2023-12-10", "2023-12-11", "2023-12-13", "2023-12-15"],
+            dates
+        );
+        assert!(bitfield_dates("3G", &period()).is_err());
+    }
+
+    #[test]
+    fn stop_times_with_restrictions() {
+        assert_eq!(
+            (Some(Time::new(24, 15, 0)), true),
+            parse_stop_time(Some("02415")).unwrap()
+        );
+        assert_eq!(
+            (Some(Time::new(6, 2, 0)), false),
+            parse_stop_time(Some("-00602")).unwrap()
+        );
+        assert_eq!((None, true), parse_stop_time(None).unwrap());
+    }
+
+    #[test]
+    fn journeys() {
+        let lines: Vec<String> = [
+            "*Z 002359 000011     002 030",
+            "*G IR  8503000 8500010",
+            "*A VE 8503000 8500010 000001",
+            "*A NF 8503000 8500010",
+            "*L 37      8503000 8500010",
+            "*R R",
+            "8503000 Zürich HB                    00602",
+            "8503006 Zürich Oerlikon       00607  00608",
+            "8500010 Basel SBB            -00710",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        let journeys = read_journeys(&lines);
+        assert_eq!(1, journeys.len());
+        let journey = &journeys[0];
+        assert_eq!("002359", journey.number);
+        assert_eq!("000011", journey.administration);
+        assert_eq!((2, 30), (journey.cycles, journey.cycle_time));
+        assert_eq!(Some("IR"), journey.category.as_deref());
+        assert_eq!(Some("37"), journey.line.as_deref());
+        assert!(journey.backward);
+        assert_eq!(Some("000001"), journey.bitfield.as_deref());
+        assert!(journey.attributes.contains("NF"));
+        let stops: Vec<_> = journey
+            .stops
+            .iter()
+            .map(|stop| {
+                (
+                    stop.stop_id.as_str(),
+                    stop.arrival_time,
+                    stop.departure_time,
+                    stop.alighting,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("8503000", None, Some(Time::new(6, 2, 0)), true),
+                (
+                    "8503006",
+                    Some(Time::new(6, 7, 0)),
+                    Some(Time::new(6, 8, 0)),
+                    true
+                ),
+                ("8500010", Some(Time::new(7, 10, 0)), None, false),
+            ],
+            stops
+        );
+    }
+
+    #[test]
+    fn journeys_sharing_a_number() {
+        let lines: Vec<String> = [
+            "*Z 002359 000011     002 030",
+            "*G IR  8503000 8500010",
+            "8503000 Zürich HB                    00602",
+            "8500010 Basel SBB             00710",
+            "*Z 002359 000011     001 030",
+            "*G IR  8503000 8500010",
+            "8503000 Zürich HB                    00802",
+            "8500010 Basel SBB             00910",
+            "*Z 002359 000011",
+            "*G IR  8503000 8500010",
+            "8503000 Zürich HB                    00902",
+            "8500010 Basel SBB             01010",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        let stations = Stations {
+            names: HashMap::new(),
+            coords: [
+                (
+                    "8503000".to_string(),
+                    Coord {
+                        lon: 8.54,
+                        lat: 47.38,
+                    },
+                ),
+                (
+                    "8500010".to_string(),
+                    Coord {
+                        lon: 7.59,
+                        lat: 47.55,
+                    },
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let referential = Referential {
+            period: &period(),
+            bitfields: &HashMap::new(),
+            stations: &stations,
+            administration_names: &HashMap::new(),
+            product_classes: &HashMap::new(),
+        };
+        let mut collections = Collections::default();
+        read_timetable(&mut collections, &lines, &referential, "dataset").unwrap();
+        let vehicle_journeys: Vec<_> = collections
+            .vehicle_journeys
+            .values()
+            .map(|vj| (vj.id.as_str(), vj.stop_times[0].departure_time.unwrap()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("000011:002359", Time::new(6, 2, 0)),
+                ("000011:002359:1", Time::new(6, 32, 0)),
+                ("000011:002359:2", Time::new(7, 2, 0)),
+                ("000011:002359:3", Time::new(8, 2, 0)),
+                ("000011:002359:3:1", Time::new(8, 32, 0)),
+                ("000011:002359:4", Time::new(9, 2, 0)),
+            ],
+            vehicle_journeys
+        );
+    }
+}
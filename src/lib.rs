@@ -18,7 +18,7 @@
 //! [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md).
 //!
 //! # Features
-//! `transit_model` has 2 possible features: `proj` and `xmllint`.
+//! `transit_model` has several optional features, described below.
 //!
 //! ## `proj`
 //! `proj` feature is used for geolocation conversion (see
@@ -41,6 +41,22 @@
 //! Some utilities to turn csv files into vector of objects or CollectionWithId (See
 //! https://github.com/hove-io/typed_index_collection/)
 //!
+//! ## `transxchange`
+//! Import of the
+//! [TransXChange](https://www.gov.uk/government/collections/transxchange)
+//! format, used for the bus timetables of the United Kingdom (see
+//! [`transxchange`](crate::transxchange)).
+//!
+//! ## `netex`
+//! Import of the [NeTEx](https://netex-cen.eu/) format, whatever its
+//! national profile (see [`netex`](crate::netex)).
+//!
+//! ## `osm`
+//! Generation of the geometries of the vehicle journeys without a shape by
+//! matching their stops on the roads or the rails of an
+//! [OpenStreetMap](https://www.openstreetmap.org/) extract (see
+//! `map_matching`).
+//!
 //! [`CONTRIBUTING.md`]: https://github.com/hove-io/transit_model/blob/master/CONTRIBUTING.md
 
 #![deny(missing_docs)]
@@ -51,17 +67,27 @@ mod add_prefix;
 pub mod serde_utils;
 pub use add_prefix::{AddPrefix, PrefixConfiguration};
 pub mod calendars;
+pub mod co2;
 #[macro_use]
 pub mod objects;
 pub mod configuration;
+pub mod delta;
 mod enhancers;
+pub mod error;
+pub mod fares;
 #[cfg(not(feature = "parser"))]
 pub(crate) mod file_handler;
 #[cfg(feature = "parser")]
 pub mod file_handler;
 pub mod gtfs;
+pub mod holidays;
+pub mod hrdf;
+#[cfg(feature = "osm")]
+pub mod map_matching;
 pub mod model;
 pub mod model_builder;
+#[cfg(feature = "netex")]
+pub mod netex;
 #[cfg(feature = "proj")]
 pub mod netex_france;
 pub mod ntfs;
@@ -71,14 +97,23 @@ pub(crate) mod parser;
 pub mod parser;
 pub mod physical_modes_utils;
 pub mod report;
+pub mod stop_area_clustering;
+pub mod stop_matching;
 #[doc(hidden)]
 pub mod test_utils;
 pub mod transfers;
+pub mod transposition;
+#[cfg(feature = "transxchange")]
+pub mod transxchange;
+pub mod validator;
 pub mod validity_period;
+pub mod vdv452;
 mod version_utils;
 pub mod vptranslator;
 #[cfg(feature = "proj")]
 mod xml_builder;
+#[cfg(any(feature = "netex", feature = "transxchange"))]
+mod xml_reader;
 
 // Good average size for initialization of the `StopTime` collection in `VehicleJourney`
 // Note: they are shrinked down in `Model::new()` to fit the real size
@@ -106,12 +141,14 @@ lazy_static::lazy_static! {
     pub static ref CURRENT_DATETIME: String = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
 }
 
-/// The error type used by the crate.
+/// The error type used by the crate. When the input data is invalid, it
+/// carries a [`DataError`], see [`error`].
 pub type Error = anyhow::Error;
 
 /// The corresponding result type used by the crate.
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+pub use crate::error::{DataError, TypedError};
 pub use crate::model::Model;
 pub use crate::model_builder::ModelBuilder;
 
@@ -0,0 +1,679 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Generation of the geometries of the vehicle journeys by routing between
+//! their stop points on a road or rail network read from OpenStreetMap.
+//!
+//! ```no_run
+//! use transit_model::map_matching::{self, MapMatchingConfiguration};
+//!
+//! let mut collections = transit_model::ntfs::read_collections("path/to/ntfs/").unwrap();
+//! let networks = map_matching::read_osm("path/to/file.osm.pbf").unwrap();
+//! map_matching::generate_geometries(
+//!     &mut collections,
+//!     &networks,
+//!     &MapMatchingConfiguration::default(),
+//! )
+//! .unwrap();
+//! ```
+
+use crate::{
+    model::{
+        Collections, BUS_PHYSICAL_MODE, BUS_RAPID_TRANSIT_PHYSICAL_MODE, COACH_PHYSICAL_MODE,
+        FUNICULAR_PHYSICAL_MODE, LOCAL_TRAIN_PHYSICAL_MODE, LONG_DISTANCE_TRAIN_PHYSICAL_MODE,
+        METRO_PHYSICAL_MODE, RAIL_SHUTTLE_PHYSICAL_MODE, RAPID_TRANSIT_PHYSICAL_MODE,
+        SHUTTLE_PHYSICAL_MODE, TAXI_PHYSICAL_MODE, TRAIN_PHYSICAL_MODE, TRAMWAY_PHYSICAL_MODE,
+    },
+    objects::{Coord, Geometry, StopPoint, VehicleJourney},
+    Result,
+};
+use anyhow::Context;
+use geo::{Coord as GeoCoord, LineString};
+use osmpbfreader::{OsmObj, OsmPbfReader};
+use rstar::{RTree, RTreeObject, AABB};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BinaryHeap, HashMap, HashSet},
+    fs::File,
+    path::Path,
+};
+use tracing::info;
+use typed_index_collection::Idx;
+
+/// Values of the `highway` tag of the OSM ways used by road vehicles.
+const ROAD_HIGHWAYS: &[&str] = &[
+    "motorway",
+    "motorway_link",
+    "trunk",
+    "trunk_link",
+    "primary",
+    "primary_link",
+    "secondary",
+    "secondary_link",
+    "tertiary",
+    "tertiary_link",
+    "unclassified",
+    "residential",
+    "living_street",
+    "service",
+    "road",
+    "busway",
+    "bus_guideway",
+];
+
+/// Values of the `railway` tag of the OSM ways used by rail vehicles.
+const RAILWAYS: &[&str] = &[
+    "rail",
+    "light_rail",
+    "narrow_gauge",
+    "subway",
+    "tram",
+    "monorail",
+    "funicular",
+];
+
+/// Kind of network followed by a vehicle journey.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NetworkKind {
+    /// Roads, for buses and coaches.
+    Road,
+    /// Rails, for trains, tramways and metros.
+    Rail,
+}
+
+impl NetworkKind {
+    /// Kind of network of the vehicle journeys of a physical mode, `None` if
+    /// the physical mode doesn't follow a road or a rail (boat, air, etc.).
+    pub fn from_physical_mode(physical_mode_id: &str) -> Option<Self> {
+        match physical_mode_id {
+            BUS_PHYSICAL_MODE
+            | BUS_RAPID_TRANSIT_PHYSICAL_MODE
+            | COACH_PHYSICAL_MODE
+            | SHUTTLE_PHYSICAL_MODE
+            | TAXI_PHYSICAL_MODE => Some(NetworkKind::Road),
+            TRAIN_PHYSICAL_MODE
+            | LOCAL_TRAIN_PHYSICAL_MODE
+            | LONG_DISTANCE_TRAIN_PHYSICAL_MODE
+            | RAPID_TRANSIT_PHYSICAL_MODE
+            | RAIL_SHUTTLE_PHYSICAL_MODE
+            | TRAMWAY_PHYSICAL_MODE
+            | METRO_PHYSICAL_MODE
+            | FUNICULAR_PHYSICAL_MODE => Some(NetworkKind::Rail),
+            _ => None,
+        }
+    }
+}
+
+/// Node of a network with its position in the network, for use in R-tree
+#[derive(Debug, Clone)]
+struct NetworkNode {
+    position: usize,
+    coord: Coord,
+}
+
+impl RTreeObject for NetworkNode {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point([self.coord.lon, self.coord.lat])
+    }
+}
+
+/// Routable graph of a road or rail network.
+#[derive(Debug, Default)]
+pub struct Network {
+    nodes: Vec<Coord>,
+    node_positions: HashMap<i64, usize>,
+    /// Outgoing edges of each node, with their length in meters
+    edges: Vec<Vec<(usize, f64)>>,
+    rtree: RTree<NetworkNode>,
+}
+
+impl Network {
+    /// Number of nodes of the network.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if the network has no node.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    fn node_position(&mut self, id: i64, coord: Coord) -> usize {
+        if let Some(&position) = self.node_positions.get(&id) {
+            return position;
+        }
+        let position = self.nodes.len();
+        self.nodes.push(coord);
+        self.edges.push(Vec::new());
+        self.node_positions.insert(id, position);
+        self.rtree.insert(NetworkNode { position, coord });
+        position
+    }
+
+    /// Add a way, given by its nodes (identifier and coordinates), which can
+    /// only be followed in the order of the nodes if `oneway` is `true`.
+    pub fn add_way(&mut self, nodes: &[(i64, Coord)], oneway: bool) {
+        for window in nodes.windows(2) {
+            let (from_id, from_coord) = window[0];
+            let (to_id, to_coord) = window[1];
+            let from = self.node_position(from_id, from_coord);
+            let to = self.node_position(to_id, to_coord);
+            let length = from_coord.distance_to(&to_coord);
+            self.edges[from].push((to, length));
+            if !oneway {
+                self.edges[to].push((from, length));
+            }
+        }
+    }
+
+    /// Closest node of `coord` within `max_distance` meters.
+    fn closest_node(&self, coord: &Coord, max_distance: f64) -> Option<usize> {
+        // See `generate_missing_transfers_from_sp` for the conversion of the
+        // distance into degrees
+        let approx = coord.approx();
+        let search_distance_lat = max_distance / 111_000.0;
+        let search_distance_lon = max_distance / (111_000.0 * approx.cos_lat());
+        let search_box = AABB::from_corners(
+            [
+                coord.lon - search_distance_lon,
+                coord.lat - search_distance_lat,
+            ],
+            [
+                coord.lon + search_distance_lon,
+                coord.lat + search_distance_lat,
+            ],
+        );
+        self.rtree
+            .locate_in_envelope(&search_box)
+            .map(|node| (approx.sq_distance_to(&node.coord), node.position))
+            .filter(|&(sq_distance, _)| sq_distance <= max_distance.powi(2))
+            .min_by(|(d1, p1), (d2, p2)| d1.total_cmp(d2).then(p1.cmp(p2)))
+            .map(|(_, position)| position)
+    }
+
+    /// Shortest path from `from` to `to` (both included), `None` if there is
+    /// no path shorter than `max_length` meters.
+    fn shortest_path(&self, from: usize, to: usize, max_length: f64) -> Option<Vec<usize>> {
+        let mut lengths: HashMap<usize, f64> = HashMap::from([(from, 0.)]);
+        let mut predecessors: HashMap<usize, usize> = HashMap::new();
+        let mut queue = BinaryHeap::from([QueueItem {
+            length: 0.,
+            position: from,
+        }]);
+        while let Some(QueueItem { length, position }) = queue.pop() {
+            if position == to {
+                let mut path = vec![to];
+                let mut current = to;
+                while let Some(&predecessor) = predecessors.get(&current) {
+                    path.push(predecessor);
+                    current = predecessor;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if lengths.get(&position).is_some_and(|&l| length > l) {
+                continue;
+            }
+            for &(next, edge_length) in &self.edges[position] {
+                let next_length = length + edge_length;
+                if next_length > max_length || lengths.get(&next).is_some_and(|&l| next_length >= l)
+                {
+                    continue;
+                }
+                lengths.insert(next, next_length);
+                predecessors.insert(next, position);
+                queue.push(QueueItem {
+                    length: next_length,
+                    position: next,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// Item of the priority queue of the shortest path computation, the shortest
+/// length being the greatest item.
+#[derive(Debug, PartialEq)]
+struct QueueItem {
+    length: f64,
+    position: usize,
+}
+
+impl Eq for QueueItem {}
+
+impl Ord for QueueItem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .length
+            .total_cmp(&self.length)
+            .then_with(|| self.position.cmp(&other.position))
+    }
+}
+
+impl PartialOrd for QueueItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Road and rail networks.
+#[derive(Debug, Default)]
+pub struct Networks {
+    /// Network followed by buses and coaches.
+    pub road: Network,
+    /// Network followed by trains, tramways and metros.
+    pub rail: Network,
+}
+
+impl Networks {
+    /// Network of a kind.
+    pub fn get(&self, kind: NetworkKind) -> &Network {
+        match kind {
+            NetworkKind::Road => &self.road,
+            NetworkKind::Rail => &self.rail,
+        }
+    }
+
+    /// Mutable network of a kind.
+    pub fn get_mut(&mut self, kind: NetworkKind) -> &mut Network {
+        match kind {
+            NetworkKind::Road => &mut self.road,
+            NetworkKind::Rail => &mut self.rail,
+        }
+    }
+}
+
+/// Read the road and rail networks of an OpenStreetMap `.osm.pbf` file.
+///
+/// The roads are the ways with a `highway` tag usable by buses (`primary`,
+/// `residential`, `busway`, etc.), the `oneway` tags being taken into account.
+/// The rails are the ways with a `railway` tag `rail`, `tram`, `subway`, etc.
+pub fn read_osm<P: AsRef<Path>>(path: P) -> Result<Networks> {
+    let path = path.as_ref();
+    info!("Reading OSM networks from {:?}", path);
+    let file = File::open(path).with_context(|| format!("Error reading {path:?}"))?;
+    let mut reader = OsmPbfReader::new(file);
+
+    let mut ways = Vec::new();
+    let mut node_ids = HashSet::new();
+    for object in reader.iter() {
+        let way = match object.with_context(|| format!("Error reading {path:?}"))? {
+            OsmObj::Way(way) => way,
+            _ => continue,
+        };
+        let tag = |key: &str| way.tags.get(key).map(|value| value.as_str());
+        let (kind, oneway, reversed) = if tag("highway").is_some_and(|h| ROAD_HIGHWAYS.contains(&h))
+        {
+            let oneway = tag("oneway");
+            (
+                NetworkKind::Road,
+                matches!(oneway, Some("yes") | Some("true") | Some("1") | Some("-1"))
+                    || tag("junction") == Some("roundabout"),
+                oneway == Some("-1"),
+            )
+        } else if tag("railway").is_some_and(|r| RAILWAYS.contains(&r)) {
+            (NetworkKind::Rail, false, false)
+        } else {
+            continue;
+        };
+        let mut nodes: Vec<i64> = way.nodes.iter().map(|node_id| node_id.0).collect();
+        if reversed {
+            nodes.reverse();
+        }
+        node_ids.extend(nodes.iter().copied());
+        ways.push((kind, nodes, oneway));
+    }
+
+    reader
+        .rewind()
+        .with_context(|| format!("Error reading {path:?}"))?;
+    let mut coords = HashMap::new();
+    for object in reader.iter() {
+        if let OsmObj::Node(node) = object.with_context(|| format!("Error reading {path:?}"))? {
+            if node_ids.contains(&node.id.0) {
+                let coord = Coord {
+                    lon: node.lon(),
+                    lat: node.lat(),
+                };
+                coords.insert(node.id.0, coord);
+            }
+        }
+    }
+
+    let mut networks = Networks::default();
+    for (kind, nodes, oneway) in ways {
+        // The nodes outside of the extract are missing
+        let nodes: Vec<(i64, Coord)> = nodes
+            .into_iter()
+            .filter_map(|id| coords.get(&id).map(|coord| (id, *coord)))
+            .collect();
+        networks.get_mut(kind).add_way(&nodes, oneway);
+    }
+    info!(
+        "{} road nodes and {} rail nodes read",
+        networks.road.len(),
+        networks.rail.len()
+    );
+    Ok(networks)
+}
+
+/// Configuration of the generation of the geometries.
+#[derive(Debug, Clone)]
+pub struct MapMatchingConfiguration {
+    /// Maximum distance in meters between a stop point and the closest node
+    /// of the network.
+    pub max_snap_distance: f64,
+    /// Maximum ratio between the length of the path between 2 stop points and
+    /// their distance. Above it, or if there is no path, the stop points are
+    /// linked by a straight line.
+    pub max_detour_factor: f64,
+}
+
+impl Default for MapMatchingConfiguration {
+    fn default() -> Self {
+        Self {
+            max_snap_distance: 100.0,
+            max_detour_factor: 3.0,
+        }
+    }
+}
+
+/// Line string going through the stop points, following the network between
+/// them when possible.
+fn match_stop_points(
+    network: &Network,
+    stop_points: &[&StopPoint],
+    config: &MapMatchingConfiguration,
+) -> Vec<Coord> {
+    let nodes: Vec<Option<usize>> = stop_points
+        .iter()
+        .map(|sp| network.closest_node(&sp.coord, config.max_snap_distance))
+        .collect();
+    let mut points: Vec<Coord> = Vec::new();
+    for (i, sp) in stop_points.iter().enumerate() {
+        if i > 0 {
+            if let (Some(from), Some(to)) = (nodes[i - 1], nodes[i]) {
+                let distance = stop_points[i - 1].coord.distance_to(&sp.coord);
+                let max_length =
+                    distance * config.max_detour_factor + 2. * config.max_snap_distance;
+                if let Some(path) = network.shortest_path(from, to, max_length) {
+                    points.extend(path.into_iter().map(|position| network.nodes[position]));
+                }
+            }
+        }
+        points.push(sp.coord);
+    }
+    points.dedup();
+    points
+}
+
+/// Generate the geometries of the vehicle journeys without one, by routing
+/// between their stop points on the network of their physical mode (see
+/// [`NetworkKind::from_physical_mode`]).
+///
+/// The vehicle journeys with the same stop points and the same kind of network
+/// share the same geometry, identified by `geometry:<id>`, `<id>` being the
+/// journey pattern of the first of them if any, or its identifier otherwise.
+/// The lines without a geometry get the generated geometry used by most of
+/// their vehicle journeys.
+///
+/// The vehicle journeys with stop points without coordinates are ignored.
+pub fn generate_geometries(
+    collections: &mut Collections,
+    networks: &Networks,
+    config: &MapMatchingConfiguration,
+) -> Result<()> {
+    type Key = (NetworkKind, Vec<Idx<StopPoint>>);
+    let mut groups: BTreeMap<Key, Vec<Idx<VehicleJourney>>> = BTreeMap::new();
+    for (vj_idx, vj) in collections.vehicle_journeys.iter() {
+        if vj.geometry_id.is_some() || vj.stop_times.len() < 2 {
+            continue;
+        }
+        let kind = match NetworkKind::from_physical_mode(&vj.physical_mode_id) {
+            Some(kind) if !networks.get(kind).is_empty() => kind,
+            _ => continue,
+        };
+        let stop_point_idxs: Vec<_> = vj
+            .stop_times
+            .iter()
+            .map(|stop_time| stop_time.stop_point_idx)
+            .collect();
+        if stop_point_idxs
+            .iter()
+            .any(|&sp_idx| collections.stop_points[sp_idx].coord == Coord::default())
+        {
+            continue;
+        }
+        groups
+            .entry((kind, stop_point_idxs))
+            .or_default()
+            .push(vj_idx);
+    }
+
+    let mut geometries_by_line: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+    for ((kind, stop_point_idxs), vj_idxs) in groups {
+        let first_vj = &collections.vehicle_journeys[vj_idxs[0]];
+        let mut geometry_id = format!(
+            "geometry:{}",
+            first_vj.journey_pattern_id.as_ref().unwrap_or(&first_vj.id)
+        );
+        if collections.geometries.contains_id(&geometry_id) {
+            geometry_id = format!("geometry:{}", first_vj.id);
+        }
+        let stop_points: Vec<&StopPoint> = stop_point_idxs
+            .iter()
+            .map(|&sp_idx| &collections.stop_points[sp_idx])
+            .collect();
+        let points = match_stop_points(networks.get(kind), &stop_points, config);
+        let linestring: LineString = points
+            .into_iter()
+            .map(|coord| GeoCoord {
+                x: coord.lon,
+                y: coord.lat,
+            })
+            .collect();
+        collections.geometries.push(Geometry {
+            id: geometry_id.clone(),
+            geometry: linestring.into(),
+        })?;
+        for vj_idx in vj_idxs {
+            let mut vj = collections.vehicle_journeys.index_mut(vj_idx);
+            vj.geometry_id = Some(geometry_id.clone());
+            if let Some(route) = collections.routes.get(&vj.route_id) {
+                *geometries_by_line
+                    .entry(route.line_id.clone())
+                    .or_default()
+                    .entry(geometry_id.clone())
+                    .or_default() += 1;
+            }
+        }
+    }
+
+    for (line_id, geometries) in geometries_by_line {
+        let geometry_id = geometries
+            .into_iter()
+            .max_by(|(id1, count1), (id2, count2)| count1.cmp(count2).then(id2.cmp(id1)))
+            .map(|(geometry_id, _)| geometry_id);
+        if let Some(mut line) = collections.lines.get_mut(&line_id) {
+            if line.geometry_id.is_none() {
+                line.geometry_id = geometry_id;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ModelBuilder;
+    use geo::Geometry as GeoGeometry;
+    use pretty_assertions::assert_eq;
+
+    fn coord(lon: f64, lat: f64) -> Coord {
+        Coord { lon, lat }
+    }
+
+    // A loop of roads, the top road being only usable from west to east and
+    // the bottom road making a detour to the south
+    //
+    //  3 ----> 4 ----> 5
+    //  |               |
+    //  0               2
+    //    \           /
+    //      --- 1 ---
+    //
+    // The positions of the nodes in the network are their identifiers.
+    fn road_network() -> Network {
+        let mut network = Network::default();
+        network.add_way(
+            &[
+                (0, coord(2.0, 48.0)),
+                (1, coord(2.01, 47.995)),
+                (2, coord(2.02, 48.0)),
+            ],
+            false,
+        );
+        network.add_way(&[(0, coord(2.0, 48.0)), (3, coord(2.0, 48.01))], false);
+        network.add_way(
+            &[
+                (3, coord(2.0, 48.01)),
+                (4, coord(2.01, 48.01)),
+                (5, coord(2.02, 48.01)),
+            ],
+            true,
+        );
+        network.add_way(&[(2, coord(2.02, 48.0)), (5, coord(2.02, 48.01))], false);
+        network
+    }
+
+    #[test]
+    fn shortest_path_follows_oneways() {
+        let network = road_network();
+        assert_eq!(
+            Some(vec![0, 3, 4, 5]),
+            network.shortest_path(0, 5, f64::MAX)
+        );
+        assert_eq!(
+            Some(vec![5, 2, 1, 0, 3]),
+            network.shortest_path(5, 3, f64::MAX)
+        );
+        assert_eq!(None, network.shortest_path(5, 3, 1000.));
+    }
+
+    #[test]
+    fn generate_shared_geometries() {
+        let mut collections = ModelBuilder::default()
+            .stop_area("SA", |_| {})
+            .stop_point("SP1", |sp| {
+                sp.coord = coord(2.0, 48.0001);
+                sp.stop_area_id = "SA".to_string();
+            })
+            .stop_point("SP2", |sp| {
+                sp.coord = coord(2.02, 48.0099);
+                sp.stop_area_id = "SA".to_string();
+            })
+            .vj("VJ1", |vj| {
+                vj.route("R1")
+                    .physical_mode("Bus")
+                    .st("SP1", "10:00:00")
+                    .st("SP2", "10:10:00");
+            })
+            .vj("VJ2", |vj| {
+                vj.route("R1")
+                    .physical_mode("Bus")
+                    .st("SP1", "11:00:00")
+                    .st("SP2", "11:10:00");
+            })
+            .vj("VJ3", |vj| {
+                vj.route("R1")
+                    .physical_mode("Boat")
+                    .st("SP1", "12:00:00")
+                    .st("SP2", "12:10:00");
+            })
+            .build()
+            .into_collections();
+        let networks = Networks {
+            road: road_network(),
+            rail: Network::default(),
+        };
+
+        generate_geometries(
+            &mut collections,
+            &networks,
+            &MapMatchingConfiguration::default(),
+        )
+        .unwrap();
+
+        let geometry_id = |vj_id: &str| {
+            collections
+                .vehicle_journeys
+                .get(vj_id)
+                .unwrap()
+                .geometry_id
+                .clone()
+        };
+        assert_eq!(Some("geometry:VJ1".to_string()), geometry_id("VJ1"));
+        assert_eq!(Some("geometry:VJ1".to_string()), geometry_id("VJ2"));
+        assert_eq!(None, geometry_id("VJ3"));
+        assert_eq!(1, collections.geometries.len());
+        let line = collections.lines.values().next().unwrap();
+        assert_eq!(Some("geometry:VJ1".to_string()), line.geometry_id);
+
+        let geometry = collections.geometries.get("geometry:VJ1").unwrap();
+        let points: Vec<(f64, f64)> = match &geometry.geometry {
+            GeoGeometry::LineString(linestring) => linestring
+                .0
+                .iter()
+                .map(|point| (point.x, point.y))
+                .collect(),
+            _ => panic!("geometry must be a linestring"),
+        };
+        // Through the oneway road, shorter than the detour to the south
+        assert_eq!(
+            vec![
+                (2.0, 48.0001),
+                (2.0, 48.0),
+                (2.0, 48.01),
+                (2.01, 48.01),
+                (2.02, 48.01),
+                (2.02, 48.0099),
+            ],
+            points
+        );
+    }
+
+    #[test]
+    fn straight_line_without_close_node() {
+        let stop_points = [
+            StopPoint {
+                coord: coord(2.0, 48.0),
+                ..Default::default()
+            },
+            StopPoint {
+                coord: coord(3.0, 49.0),
+                ..Default::default()
+            },
+        ];
+        let points = match_stop_points(
+            &road_network(),
+            &[&stop_points[0], &stop_points[1]],
+            &MapMatchingConfiguration::default(),
+        );
+        assert_eq!(vec![coord(2.0, 48.0), coord(3.0, 49.0)], points);
+    }
+}
@@ -14,9 +14,16 @@
 
 //! Definition of the navitia transit model.
 
-use crate::{enhancers, objects::*, Error, Result};
+use crate::{
+    enhancers,
+    error::{object_type, DataError},
+    objects::*,
+    transposition::{self, Transposition},
+    Error, Result,
+};
 use anyhow::{anyhow, bail};
 use chrono::NaiveDate;
+use chrono_tz::Tz;
 use derivative::Derivative;
 use geo::algorithm::centroid::Centroid;
 use geo::MultiPoint;
@@ -24,6 +31,7 @@ use relational_types::{GetCorresponding, IdxSet, ManyToMany, OneToMany, Relation
 use serde::{Deserialize, Serialize};
 use skip_error::skip_error_and_warn;
 use std::{
+    any::TypeId,
     cmp::{self, Ordering, Reverse},
     collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashMap, HashSet},
     convert::TryFrom,
@@ -86,6 +94,7 @@ pub struct Collections {
     pub lines: CollectionWithId<Line>,
     pub routes: CollectionWithId<Route>,
     pub vehicle_journeys: CollectionWithId<VehicleJourney>,
+    pub journey_patterns: CollectionWithId<JourneyPattern>,
     pub frequencies: Collection<Frequency>,
     pub physical_modes: CollectionWithId<PhysicalMode>,
     pub stop_areas: CollectionWithId<StopArea>,
@@ -178,6 +187,21 @@ impl Collections {
         Ok(())
     }
 
+    /// Transpose the dates of the current `Collections` from a period onto
+    /// another one, e.g. to reuse the timetable of the last year (see
+    /// [`crate::transposition`]).
+    pub fn transpose_calendars(&mut self, transposition: &Transposition) {
+        transposition::transpose(self, transposition)
+    }
+
+    /// Restrict the current `Collections` to the vehicle journeys running in
+    /// a weekly time window (e.g. from Friday 22:00:00 to Saturday 06:00:00),
+    /// on the dates when they run in it. With `truncate`, only their stop
+    /// times in the time window are kept.
+    pub fn restrict_time_window(&mut self, window: &TimeWindow, truncate: bool) -> Result<()> {
+        enhancers::restrict_time_window(self, window, truncate)
+    }
+
     /// Filters vehicle_journeys in collection based on their schedule types
     /// Multiple choice possible (through Vec<>). See VehicleJourneyScheduleType list.
     pub fn filter_by_vj_schedule_types(
@@ -189,6 +213,213 @@ impl Collections {
         Ok(())
     }
 
+    /// Removes the objects which would make [`Model::new`] fail, such as
+    /// objects referencing unknown objects, and returns the problems found
+    /// in the data. The problems fixed by [`Model::new`] (incoherent stop
+    /// times, invalid coordinates, trips without stop times or active dates)
+    /// are returned too.
+    pub fn remove_invalid_objects(&mut self) -> Vec<DataError> {
+        fn remove_invalid_references<O, M>(
+            one: &CollectionWithId<O>,
+            many: &mut CollectionWithId<M>,
+            field: &'static str,
+            errors: &mut Vec<DataError>,
+        ) where
+            O: Id<O>,
+            M: Id<M> + Id<O>,
+        {
+            many.retain(|object| {
+                let value = <M as Id<O>>::id(object);
+                if one.contains_id(value) {
+                    true
+                } else {
+                    errors.push(DataError::UnknownReference {
+                        object_type: object_type::<M>(),
+                        id: Some(<M as Id<M>>::id(object).to_string()),
+                        field,
+                        value: value.to_string(),
+                    });
+                    false
+                }
+            });
+        }
+
+        let mut errors: Vec<DataError> = enhancers::check_stop_times_order(self)
+            .into_iter()
+            .map(|error| {
+                let trip_id = match &error {
+                    StopTimeError::DuplicateStopSequence { vj_id, .. }
+                    | StopTimeError::IncoherentStopTimes { vj_id, .. } => vj_id.clone(),
+                };
+                DataError::InvalidStopTimes {
+                    trip_id,
+                    message: error.to_string(),
+                }
+            })
+            .collect();
+
+        let invalid_coordinates = self
+            .stop_areas
+            .values()
+            .filter(|sa| !sa.coord.is_valid())
+            .map(|sa| (object_type::<StopArea>(), &sa.id))
+            .chain(
+                self.stop_points
+                    .values()
+                    .filter(|sp| !sp.coord.is_valid())
+                    .map(|sp| (object_type::<StopPoint>(), &sp.id)),
+            )
+            .chain(
+                self.stop_locations
+                    .values()
+                    .filter(|sl| sl.stop_type == StopType::StopEntrance && !sl.coord.is_valid())
+                    .map(|sl| (object_type::<StopLocation>(), &sl.id)),
+            )
+            .map(|(object_type, id)| DataError::InvalidCoordinates {
+                object_type,
+                id: id.clone(),
+            });
+        errors.extend(invalid_coordinates);
+
+        remove_invalid_references(
+            &self.contributors,
+            &mut self.datasets,
+            "contributor_id",
+            &mut errors,
+        );
+        remove_invalid_references(&self.networks, &mut self.lines, "network_id", &mut errors);
+        remove_invalid_references(
+            &self.commercial_modes,
+            &mut self.lines,
+            "commercial_mode_id",
+            &mut errors,
+        );
+        remove_invalid_references(&self.lines, &mut self.routes, "line_id", &mut errors);
+        remove_invalid_references(
+            &self.routes,
+            &mut self.journey_patterns,
+            "route_id",
+            &mut errors,
+        );
+
+        // Stop points are referenced by index in the stop times, the trips
+        // using them must be removed first
+        let invalid_stop_points: HashSet<String> = self
+            .stop_points
+            .values()
+            .filter(|sp| !self.stop_areas.contains_id(&sp.stop_area_id))
+            .map(|sp| {
+                errors.push(DataError::UnknownReference {
+                    object_type: object_type::<StopPoint>(),
+                    id: Some(sp.id.clone()),
+                    field: "stop_area_id",
+                    value: sp.stop_area_id.clone(),
+                });
+                sp.id.clone()
+            })
+            .collect();
+
+        let mut invalid_trips = HashSet::new();
+        for vj in self.vehicle_journeys.values() {
+            let references = [
+                (
+                    "route_id",
+                    &vj.route_id,
+                    self.routes.contains_id(&vj.route_id),
+                ),
+                (
+                    "physical_mode_id",
+                    &vj.physical_mode_id,
+                    self.physical_modes.contains_id(&vj.physical_mode_id),
+                ),
+                (
+                    "dataset_id",
+                    &vj.dataset_id,
+                    self.datasets.contains_id(&vj.dataset_id),
+                ),
+                (
+                    "company_id",
+                    &vj.company_id,
+                    self.companies.contains_id(&vj.company_id),
+                ),
+                (
+                    "service_id",
+                    &vj.service_id,
+                    self.calendars.contains_id(&vj.service_id),
+                ),
+            ];
+            let invalid_stop_point = vj
+                .stop_times
+                .iter()
+                .map(|st| &self.stop_points[st.stop_point_idx].id)
+                .find(|sp_id| invalid_stop_points.contains(*sp_id))
+                .map(|sp_id| ("stop_id", sp_id, false));
+            for &(field, value, valid) in references.iter().chain(&invalid_stop_point) {
+                if !valid {
+                    errors.push(DataError::UnknownReference {
+                        object_type: object_type::<VehicleJourney>(),
+                        id: Some(vj.id.clone()),
+                        field,
+                        value: value.clone(),
+                    });
+                    invalid_trips.insert(vj.id.clone());
+                }
+            }
+        }
+        VehicleJourney::remove_all(self, &invalid_trips);
+        StopPoint::remove_all(self, &invalid_stop_points);
+
+        // The journey pattern of a trip is optional, an invalid one is unset
+        let vj_idxs: Vec<Idx<VehicleJourney>> = self.vehicle_journeys.indexes().collect();
+        for vj_idx in vj_idxs {
+            if let Err(error) =
+                journey_pattern_of(&self.journey_patterns, &self.vehicle_journeys[vj_idx])
+            {
+                errors.push(error);
+                self.vehicle_journeys.index_mut(vj_idx).journey_pattern_id = None;
+            }
+        }
+
+        let stop_points = &self.stop_points;
+        self.transfers.retain(|transfer| {
+            let references = [
+                ("from_stop_id", &transfer.from_stop_id),
+                ("to_stop_id", &transfer.to_stop_id),
+            ];
+            let mut valid = true;
+            for &(field, value) in references.iter() {
+                if !stop_points.contains_id(value) {
+                    errors.push(DataError::UnknownReference {
+                        object_type: object_type::<Transfer>(),
+                        id: None,
+                        field,
+                        value: value.clone(),
+                    });
+                    valid = false;
+                }
+            }
+            valid
+        });
+
+        for vj in self.vehicle_journeys.values() {
+            let reason = if vj.stop_times.is_empty() {
+                Some("no stop times".to_string())
+            } else {
+                self.calendars
+                    .get(&vj.service_id)
+                    .filter(|calendar| calendar.dates.is_empty())
+                    .map(|calendar| format!("calendar {:?} has no active date", calendar.id))
+            };
+            if let Some(reason) = reason {
+                errors.push(DataError::DroppedTrip {
+                    trip_id: vj.id.clone(),
+                    reason,
+                });
+            }
+        }
+        errors
+    }
+
     /// Keep the collections consistent for the new model by purging unreferenced data by
     /// calendars
     pub fn sanitize(&mut self) -> Result<()> {
@@ -235,6 +466,7 @@ impl Collections {
         let mut datasets_ids_used = HashSet::<String>::new();
         let mut equipments_ids_used = HashSet::<String>::new();
         let mut geometries_ids_used = HashSet::<String>::new();
+        let mut journey_patterns_ids_used = HashSet::<String>::new();
         let mut levels_ids_used = HashSet::<String>::new();
         let mut lines_ids_used = HashSet::<String>::new();
         let mut networks_ids_used = HashSet::<String>::new();
@@ -295,6 +527,9 @@ impl Collections {
                 if let Some(prop_id) = &vj.trip_property_id {
                     trip_properties_ids_used.insert(prop_id.clone());
                 }
+                if let Some(journey_pattern_id) = &vj.journey_pattern_id {
+                    journey_patterns_ids_used.insert(journey_pattern_id.clone());
+                }
                 companies_ids_used.insert(vj.company_id.clone());
                 routes_ids_used.insert(vj.route_id.clone());
                 for stop_time in &vj.stop_times {
@@ -531,6 +766,11 @@ impl Collections {
             "BookingRule",
             |booking_rule: &BookingRule| booking_rules_ids_used.contains(&booking_rule.id),
         ));
+        calendars_ids_used.extend(
+            self.booking_rules
+                .values()
+                .filter_map(|booking_rule| booking_rule.prior_notice_service_id.clone()),
+        );
 
         self.lines = CollectionWithId::new(lines)?;
         self.stop_points = CollectionWithId::new(stop_points)?;
@@ -622,6 +862,12 @@ impl Collections {
             .retain(|frequency| vehicle_journeys_ids_used.contains(&frequency.vehicle_journey_id));
         self.levels
             .retain(|level| levels_ids_used.contains(&level.id));
+        self.journey_patterns.retain(log_predicate(
+            "Journey Pattern",
+            |journey_pattern: &JourneyPattern| {
+                journey_patterns_ids_used.contains(&journey_pattern.id)
+            },
+        ));
         self.calendars
             .retain(|c| calendars_ids_used.contains(&c.id));
         self.addresses
@@ -993,9 +1239,58 @@ impl Collections {
                 }
             }
         }
+        let mut booking_rules = self.booking_rules.take();
+        for booking_rule in &mut booking_rules {
+            if let Some(calendar) = booking_rule
+                .prior_notice_service_id
+                .as_ref()
+                .and_then(|service_id| self.calendars.get(service_id))
+            {
+                if let Some(dup_calendar) =
+                    calendars_used.iter().find(|c| c.dates == calendar.dates)
+                {
+                    booking_rule.prior_notice_service_id = Some(dup_calendar.id.clone());
+                } else {
+                    calendars_used.push(calendar.clone());
+                }
+            }
+        }
         self.calendars
             .retain(|calendar| calendars_used.contains(calendar));
         self.vehicle_journeys = CollectionWithId::new(vehicle_journeys).unwrap();
+        self.booking_rules = CollectionWithId::new(booking_rules).unwrap();
+    }
+
+    /// Group the vehicle journeys with the same route, the same stop times
+    /// (stop point, pickup type, drop off type, local zone and continuous
+    /// stopping) and the same booking rules into journey patterns, and set
+    /// their `journey_pattern_id`. The existing journey patterns are replaced.
+    pub fn compute_journey_patterns(&mut self) {
+        enhancers::compute_journey_patterns(self);
+    }
+
+    /// Fill the `shape_dist_traveled` of the stop times of the vehicle
+    /// journeys without any, in meters, along the geometry of the vehicle journey if any or following straight
+    /// lines between the stop points otherwise.
+    pub fn compute_shape_dist_traveled(&mut self) {
+        enhancers::compute_shape_dist_traveled(self);
+    }
+
+    /// Generate the grid calendars of the lines without grid calendars, from
+    /// the dates of their vehicle journeys, to group them by day type (e.g.
+    /// "Monday to Friday") in line timetables.
+    pub fn generate_grid_calendars(&mut self) {
+        enhancers::generate_grid_calendars(self);
+    }
+
+    /// Express the times of all the vehicle journeys in the given timezone,
+    /// which becomes the timezone of all the networks. The dates of the
+    /// vehicle journeys are shifted by a day when needed, and a vehicle journey
+    /// is split when the difference between the timezones isn't the same on
+    /// all its dates (DST). The networks without a timezone are left
+    /// unchanged.
+    pub fn normalize_timezones(&mut self, timezone: Tz) -> Result<()> {
+        enhancers::normalize_timezones(self, timezone)
     }
 
     /// Some comments are identical and can be deduplicated
@@ -1491,6 +1786,9 @@ impl Collections {
                             drop_off_type: stop_time.drop_off_type,
                             local_zone_id: stop_time.local_zone_id,
                             precision: stop_time.precision,
+                            shape_dist_traveled: stop_time.shape_dist_traveled,
+                            continuous_pickup: stop_time.continuous_pickup,
+                            continuous_drop_off: stop_time.continuous_drop_off,
                         }
                     })
                     .collect();
@@ -1574,6 +1872,7 @@ pub struct Model {
     vehicle_journeys_to_stop_points: ManyToMany<VehicleJourney, StopPoint>,
     transfers_to_stop_points: ManyToMany<Transfer, StopPoint>,
     calendars_to_vehicle_journeys: OneToMany<Calendar, VehicleJourney>,
+    journey_patterns_to_vehicle_journeys: OptionalOneToMany<JourneyPattern, VehicleJourney>,
 
     // shortcuts
     #[get_corresponding(weight = "1.9")]
@@ -1594,6 +1893,9 @@ impl Model {
     /// Constructs a model from the given `Collections`.  Fails in
     /// case of incoherence, as invalid external references.
     ///
+    /// When the failure comes from the data, the error carries a
+    /// [`DataError`] (see [`crate::error`]).
+    ///
     /// # Examples
     ///
     /// ```
@@ -1627,41 +1929,26 @@ impl Model {
         c.clean_comments();
         c.sanitize()?;
 
-        let forward_vj_to_sp = c
-            .vehicle_journeys
-            .iter()
-            .map(|(idx, vj)| {
-                let sps = vj.stop_times.iter().map(|st| st.stop_point_idx).collect();
-                (idx, sps)
-            })
-            .collect();
-
-        let forward_tr_to_sp = c
-            .transfers
-            .iter()
-            .map(|(idx, tr)| {
-                let mut stop_points = IdxSet::default();
-                stop_points.insert(c.stop_points.get_idx(&tr.from_stop_id).ok_or_else(|| {
-                    anyhow!("Invalid id: transfer.from_stop_id={:?}", tr.from_stop_id)
-                })?);
-                stop_points.insert(c.stop_points.get_idx(&tr.to_stop_id).ok_or_else(|| {
-                    anyhow!("Invalid id: transfer.to_stop_id={:?}", tr.to_stop_id)
-                })?);
-                Ok((idx, stop_points))
-            })
-            .collect::<Result<BTreeMap<_, _>, Error>>()?;
+        let forward_vj_to_sp = vehicle_journeys_to_stop_points_forward(&c);
+        let forward_tr_to_sp = transfers_to_stop_points_forward(&c)?;
         let vehicle_journeys_to_stop_points = ManyToMany::from_forward(forward_vj_to_sp);
-        let routes_to_vehicle_journeys =
-            OneToMany::new(&c.routes, &c.vehicle_journeys, "routes_to_vehicle_journeys")?;
-        let physical_modes_to_vehicle_journeys = OneToMany::new(
+        let routes_to_vehicle_journeys = one_to_many(
+            &c.routes,
+            &c.vehicle_journeys,
+            "routes_to_vehicle_journeys",
+            "route_id",
+        )?;
+        let physical_modes_to_vehicle_journeys = one_to_many(
             &c.physical_modes,
             &c.vehicle_journeys,
             "physical_modes_to_vehicle_journeys",
+            "physical_mode_id",
         )?;
-        let datasets_to_vehicle_journeys = OneToMany::new(
+        let datasets_to_vehicle_journeys = one_to_many(
             &c.datasets,
             &c.vehicle_journeys,
             "datasets_to_vehicle_journeys",
+            "dataset_id",
         )?;
         let routes_to_stop_points = ManyToMany::from_relations_chain(
             &routes_to_vehicle_journeys,
@@ -1688,23 +1975,39 @@ impl Model {
             &physical_modes_to_vehicle_journeys,
         );
         let transfers_to_stop_points = ManyToMany::from_forward(forward_tr_to_sp);
-        let networks_to_lines = OneToMany::new(&c.networks, &c.lines, "networks_to_lines")?;
-        let commercial_modes_to_lines =
-            OneToMany::new(&c.commercial_modes, &c.lines, "commercial_modes_to_lines")?;
-        let lines_to_routes = OneToMany::new(&c.lines, &c.routes, "lines_to_routes")?;
-        let stop_areas_to_stop_points =
-            OneToMany::new(&c.stop_areas, &c.stop_points, "stop_areas_to_stop_points")?;
-        let contributors_to_datasets =
-            OneToMany::new(&c.contributors, &c.datasets, "contributors_to_datasets")?;
-        let companies_to_vehicle_journeys = OneToMany::new(
+        let journey_patterns_to_vehicle_journeys = journey_patterns_to_vehicle_journeys(&c)?;
+        let networks_to_lines =
+            one_to_many(&c.networks, &c.lines, "networks_to_lines", "network_id")?;
+        let commercial_modes_to_lines = one_to_many(
+            &c.commercial_modes,
+            &c.lines,
+            "commercial_modes_to_lines",
+            "commercial_mode_id",
+        )?;
+        let lines_to_routes = one_to_many(&c.lines, &c.routes, "lines_to_routes", "line_id")?;
+        let stop_areas_to_stop_points = one_to_many(
+            &c.stop_areas,
+            &c.stop_points,
+            "stop_areas_to_stop_points",
+            "stop_area_id",
+        )?;
+        let contributors_to_datasets = one_to_many(
+            &c.contributors,
+            &c.datasets,
+            "contributors_to_datasets",
+            "contributor_id",
+        )?;
+        let companies_to_vehicle_journeys = one_to_many(
             &c.companies,
             &c.vehicle_journeys,
             "companies_to_vehicle_journeys",
+            "company_id",
         )?;
-        let calendars_to_vehicle_journeys = OneToMany::new(
+        let calendars_to_vehicle_journeys = one_to_many(
             &c.calendars,
             &c.vehicle_journeys,
             "calendars_to_vehicle_journeys",
+            "service_id",
         )?;
 
         c.update_stop_area_coords();
@@ -1738,6 +2041,7 @@ impl Model {
             contributors_to_datasets,
             companies_to_vehicle_journeys,
             calendars_to_vehicle_journeys,
+            journey_patterns_to_vehicle_journeys,
             collections: c,
         })
     }
@@ -1767,6 +2071,334 @@ impl Model {
     pub fn into_collections(self) -> Collections {
         self.collections
     }
+
+    /// Modifies the model in place. The modifications done by `f` are
+    /// committed if `f` succeeds and if all the references between objects
+    /// are valid, in which case only the relations involving the types of the
+    /// modified objects are rebuilt. Otherwise, all the modifications are rolled back
+    /// and the error is returned.
+    ///
+    /// Contrary to [`Model::new`], the enhancers are not applied and unused
+    /// objects are kept; use [`Model::sanitize`] to remove them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use transit_model::{model::*, objects::Route};
+    /// # fn run() -> transit_model::Result<()> {
+    /// let mut model = transit_model::ModelBuilder::default()
+    ///     .vj("vj1", |vj| {
+    ///         vj.route("route1")
+    ///             .st("SP1", "10:00:00")
+    ///             .st("SP2", "11:00:00");
+    ///     })
+    ///     .build();
+    /// model.transaction(|tx| {
+    ///     tx.update("route1", |route: &mut Route| route.name = "Express".into())
+    /// })?;
+    /// assert_eq!("Express", model.routes.get("route1").unwrap().name);
+    ///
+    /// // The route is still used by "vj1", the removal is rolled back
+    /// assert!(model.transaction(|tx| tx.remove::<Route>("route1")).is_err());
+    /// assert!(model.routes.contains_id("route1"));
+    /// # Ok(())
+    /// # }
+    /// # run().unwrap()
+    /// ```
+    pub fn transaction<F>(&mut self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Transaction<'_>) -> Result<()>,
+    {
+        let (touched, undo_log) = {
+            let mut transaction = Transaction::new(&mut self.collections);
+            match f(&mut transaction).and_then(|_| transaction.commit()) {
+                Ok(touched) => (touched, std::mem::take(&mut transaction.undo_log)),
+                Err(e) => {
+                    transaction.rollback();
+                    return Err(e);
+                }
+            }
+        };
+        if let Err(e) = self.rebuild_relations(&touched) {
+            // Some relations may already be updated, they are all rebuilt
+            // from the restored collections
+            for undo in undo_log.into_iter().rev() {
+                undo(&mut self.collections);
+            }
+            self.rebuild_relations(&Touched::all())?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Removes the objects not used anymore, for example after a
+    /// [`Model::transaction`], and rebuilds all the relations.
+    pub fn sanitize(&mut self) -> Result<()> {
+        self.collections.sanitize()?;
+        self.rebuild_relations(&Touched::all())
+    }
+
+    fn rebuild_relations(&mut self, touched: &Touched) -> Result<()> {
+        // Objects linked to the modified vehicle journeys before the update
+        let vjs = &touched.vehicle_journeys;
+        let old_routes = self
+            .routes_to_vehicle_journeys
+            .get_corresponding_backward(vjs);
+        let old_physical_modes = self
+            .physical_modes_to_vehicle_journeys
+            .get_corresponding_backward(vjs);
+        let old_datasets = self
+            .datasets_to_vehicle_journeys
+            .get_corresponding_backward(vjs);
+
+        let c = &self.collections;
+        if touched.contains::<Network>() || touched.contains::<Line>() {
+            self.networks_to_lines =
+                one_to_many(&c.networks, &c.lines, "networks_to_lines", "network_id")?;
+        }
+        if touched.contains::<CommercialMode>() || touched.contains::<Line>() {
+            self.commercial_modes_to_lines = one_to_many(
+                &c.commercial_modes,
+                &c.lines,
+                "commercial_modes_to_lines",
+                "commercial_mode_id",
+            )?;
+        }
+        if touched.contains::<Line>() || touched.contains::<Route>() {
+            self.lines_to_routes = one_to_many(&c.lines, &c.routes, "lines_to_routes", "line_id")?;
+        }
+        if touched.contains::<StopArea>() || touched.contains::<StopPoint>() {
+            self.stop_areas_to_stop_points = one_to_many(
+                &c.stop_areas,
+                &c.stop_points,
+                "stop_areas_to_stop_points",
+                "stop_area_id",
+            )?;
+        }
+        if touched.contains::<Contributor>() || touched.contains::<Dataset>() {
+            self.contributors_to_datasets = one_to_many(
+                &c.contributors,
+                &c.datasets,
+                "contributors_to_datasets",
+                "contributor_id",
+            )?;
+        }
+        if touched.contains::<Company>() || touched.contains::<VehicleJourney>() {
+            self.companies_to_vehicle_journeys = one_to_many(
+                &c.companies,
+                &c.vehicle_journeys,
+                "companies_to_vehicle_journeys",
+                "company_id",
+            )?;
+        }
+        if touched.contains::<Calendar>() || touched.contains::<VehicleJourney>() {
+            self.calendars_to_vehicle_journeys = one_to_many(
+                &c.calendars,
+                &c.vehicle_journeys,
+                "calendars_to_vehicle_journeys",
+                "service_id",
+            )?;
+        }
+        if touched.contains::<StopPoint>() {
+            self.transfers_to_stop_points =
+                ManyToMany::from_forward(transfers_to_stop_points_forward(c)?);
+        }
+        if touched.contains::<VehicleJourney>() || touched.contains::<JourneyPattern>() {
+            self.journey_patterns_to_vehicle_journeys = journey_patterns_to_vehicle_journeys(c)?;
+        }
+
+        let routes_to_vjs = touched.contains::<Route>() || touched.contains::<VehicleJourney>();
+        let physical_modes_to_vjs =
+            touched.contains::<PhysicalMode>() || touched.contains::<VehicleJourney>();
+        let datasets_to_vjs = touched.contains::<Dataset>() || touched.contains::<VehicleJourney>();
+        if routes_to_vjs {
+            self.routes_to_vehicle_journeys = one_to_many(
+                &c.routes,
+                &c.vehicle_journeys,
+                "routes_to_vehicle_journeys",
+                "route_id",
+            )?;
+        }
+        if physical_modes_to_vjs {
+            self.physical_modes_to_vehicle_journeys = one_to_many(
+                &c.physical_modes,
+                &c.vehicle_journeys,
+                "physical_modes_to_vehicle_journeys",
+                "physical_mode_id",
+            )?;
+        }
+        if datasets_to_vjs {
+            self.datasets_to_vehicle_journeys = one_to_many(
+                &c.datasets,
+                &c.vehicle_journeys,
+                "datasets_to_vehicle_journeys",
+                "dataset_id",
+            )?;
+        }
+
+        // The relations from the vehicle journeys and the shortcuts are
+        // rebuilt reusing the related objects of the unaffected vehicle
+        // journeys, unless a removal changed the indexes
+        let same_vjs_and_sps =
+            touched.same_indexes::<VehicleJourney>() && touched.same_indexes::<StopPoint>();
+        if !same_vjs_and_sps {
+            self.vehicle_journeys_to_stop_points =
+                ManyToMany::from_forward(vehicle_journeys_to_stop_points_forward(c));
+        } else if !vjs.is_empty() {
+            self.vehicle_journeys_to_stop_points = update_many_to_many(
+                &self.vehicle_journeys_to_stop_points,
+                c.vehicle_journeys.indexes(),
+                vjs,
+                |vj_idx| {
+                    c.vehicle_journeys[vj_idx]
+                        .stop_times
+                        .iter()
+                        .map(|st| st.stop_point_idx)
+                        .collect()
+                },
+            );
+        }
+
+        // shortcuts
+        let mut routes = old_routes;
+        routes.extend(
+            self.routes_to_vehicle_journeys
+                .get_corresponding_backward(vjs),
+        );
+        let mut physical_modes = old_physical_modes;
+        physical_modes.extend(
+            self.physical_modes_to_vehicle_journeys
+                .get_corresponding_backward(vjs),
+        );
+        let mut datasets = old_datasets;
+        datasets.extend(
+            self.datasets_to_vehicle_journeys
+                .get_corresponding_backward(vjs),
+        );
+        let same_routes = touched.same_indexes::<Route>();
+        let same_physical_modes = touched.same_indexes::<PhysicalMode>();
+        let same_datasets = touched.same_indexes::<Dataset>();
+        if !(same_routes && same_vjs_and_sps) {
+            self.routes_to_stop_points = ManyToMany::from_relations_chain(
+                &self.routes_to_vehicle_journeys,
+                &self.vehicle_journeys_to_stop_points,
+            );
+        } else if !vjs.is_empty() {
+            self.routes_to_stop_points = update_many_to_many(
+                &self.routes_to_stop_points,
+                c.routes.indexes(),
+                &routes,
+                |idx| {
+                    self.vehicle_journeys_to_stop_points
+                        .get_corresponding_forward(
+                            &self
+                                .routes_to_vehicle_journeys
+                                .get_corresponding_forward(&idx_set(idx)),
+                        )
+                },
+            );
+        }
+        if !(same_physical_modes && same_vjs_and_sps) {
+            self.physical_modes_to_stop_points = ManyToMany::from_relations_chain(
+                &self.physical_modes_to_vehicle_journeys,
+                &self.vehicle_journeys_to_stop_points,
+            );
+        } else if !vjs.is_empty() {
+            self.physical_modes_to_stop_points = update_many_to_many(
+                &self.physical_modes_to_stop_points,
+                c.physical_modes.indexes(),
+                &physical_modes,
+                |idx| {
+                    self.vehicle_journeys_to_stop_points
+                        .get_corresponding_forward(
+                            &self
+                                .physical_modes_to_vehicle_journeys
+                                .get_corresponding_forward(&idx_set(idx)),
+                        )
+                },
+            );
+        }
+        let same_vjs = touched.same_indexes::<VehicleJourney>();
+        if !(same_physical_modes && same_routes && same_vjs) {
+            self.physical_modes_to_routes = ManyToMany::from_relations_sink(
+                &self.physical_modes_to_vehicle_journeys,
+                &self.routes_to_vehicle_journeys,
+            );
+        } else if !vjs.is_empty() {
+            self.physical_modes_to_routes = update_many_to_many(
+                &self.physical_modes_to_routes,
+                c.physical_modes.indexes(),
+                &physical_modes,
+                |idx| {
+                    self.routes_to_vehicle_journeys.get_corresponding_backward(
+                        &self
+                            .physical_modes_to_vehicle_journeys
+                            .get_corresponding_forward(&idx_set(idx)),
+                    )
+                },
+            );
+        }
+        if !(same_datasets && same_vjs_and_sps) {
+            self.datasets_to_stop_points = ManyToMany::from_relations_chain(
+                &self.datasets_to_vehicle_journeys,
+                &self.vehicle_journeys_to_stop_points,
+            );
+        } else if !vjs.is_empty() {
+            self.datasets_to_stop_points = update_many_to_many(
+                &self.datasets_to_stop_points,
+                c.datasets.indexes(),
+                &datasets,
+                |idx| {
+                    self.vehicle_journeys_to_stop_points
+                        .get_corresponding_forward(
+                            &self
+                                .datasets_to_vehicle_journeys
+                                .get_corresponding_forward(&idx_set(idx)),
+                        )
+                },
+            );
+        }
+        if !(same_datasets && same_routes && same_vjs) {
+            self.datasets_to_routes = ManyToMany::from_relations_sink(
+                &self.datasets_to_vehicle_journeys,
+                &self.routes_to_vehicle_journeys,
+            );
+        } else if !vjs.is_empty() {
+            self.datasets_to_routes = update_many_to_many(
+                &self.datasets_to_routes,
+                c.datasets.indexes(),
+                &datasets,
+                |idx| {
+                    self.routes_to_vehicle_journeys.get_corresponding_backward(
+                        &self
+                            .datasets_to_vehicle_journeys
+                            .get_corresponding_forward(&idx_set(idx)),
+                    )
+                },
+            );
+        }
+        if !(same_datasets && same_physical_modes && same_vjs) {
+            self.datasets_to_physical_modes = ManyToMany::from_relations_sink(
+                &self.datasets_to_vehicle_journeys,
+                &self.physical_modes_to_vehicle_journeys,
+            );
+        } else if !vjs.is_empty() {
+            self.datasets_to_physical_modes = update_many_to_many(
+                &self.datasets_to_physical_modes,
+                c.datasets.indexes(),
+                &datasets,
+                |idx| {
+                    self.physical_modes_to_vehicle_journeys
+                        .get_corresponding_backward(
+                            &self
+                                .datasets_to_vehicle_journeys
+                                .get_corresponding_forward(&idx_set(idx)),
+                        )
+                },
+            );
+        }
+        Ok(())
+    }
 }
 
 impl ::serde::Serialize for Model {
@@ -1794,78 +2426,980 @@ impl ops::Deref for Model {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Builds a `OneToMany` relation. On failure, the error describes the first
+/// object of `many` referencing an unknown object of `one` through `field`.
+fn one_to_many<O, M>(
+    one: &CollectionWithId<O>,
+    many: &CollectionWithId<M>,
+    name: &str,
+    field: &'static str,
+) -> Result<OneToMany<O, M>>
+where
+    O: Id<O>,
+    M: Id<M> + Id<O>,
+{
+    OneToMany::new(one, many, name).map_err(|e| {
+        many.values()
+            .find(|object| !one.contains_id(<M as Id<O>>::id(object)))
+            .map(|object| {
+                Error::from(DataError::UnknownReference {
+                    object_type: object_type::<M>(),
+                    id: Some(<M as Id<M>>::id(object).to_string()),
+                    field,
+                    value: <M as Id<O>>::id(object).to_string(),
+                })
+            })
+            .unwrap_or_else(|| Error::from(e))
+    })
+}
 
-    mod enhance_trip_headsign {
-        use super::*;
-        use pretty_assertions::assert_eq;
+fn vehicle_journeys_to_stop_points_forward(
+    c: &Collections,
+) -> BTreeMap<Idx<VehicleJourney>, IdxSet<StopPoint>> {
+    c.vehicle_journeys
+        .iter()
+        .map(|(idx, vj)| {
+            let sps = vj.stop_times.iter().map(|st| st.stop_point_idx).collect();
+            (idx, sps)
+        })
+        .collect()
+}
 
-        fn collections(trip_headsign: Option<String>) -> Collections {
-            let mut collections = Collections::default();
-            collections
-                .stop_points
-                .push(StopPoint {
-                    id: String::from("stop_point_id"),
-                    name: String::from("Stop Name"),
-                    ..Default::default()
-                })
-                .unwrap();
-            let stop_time = StopTime {
-                stop_point_idx: collections.stop_points.get_idx("stop_point_id").unwrap(),
-                sequence: 0,
-                arrival_time: Some(Time::new(0, 0, 0)),
-                departure_time: Some(Time::new(0, 0, 0)),
-                start_pickup_drop_off_window: None,
-                end_pickup_drop_off_window: None,
-                boarding_duration: 0,
-                alighting_duration: 0,
-                pickup_type: 0,
-                drop_off_type: 0,
-                local_zone_id: Some(0),
-                precision: None,
-            };
-            collections
-                .vehicle_journeys
-                .push(VehicleJourney {
-                    id: String::from("vehicle_journey_id_1"),
-                    stop_times: vec![stop_time],
-                    headsign: trip_headsign,
-                    ..Default::default()
-                })
-                .unwrap();
-            collections
-                .vehicle_journeys
-                .push(VehicleJourney {
-                    id: String::from("vehicle_journey_id_2"),
-                    headsign: Some(String::from("Headsign")),
-                    ..Default::default()
-                })
-                .unwrap();
-            collections
+/// Returns the journey pattern of a vehicle journey, which must be on the
+/// same route. `journey_pattern_id` is kept as is, without journey pattern,
+/// when there are no journey patterns (e.g. no `journey_patterns.txt`).
+fn journey_pattern_of(
+    journey_patterns: &CollectionWithId<JourneyPattern>,
+    vj: &VehicleJourney,
+) -> Result<Option<Idx<JourneyPattern>>, DataError> {
+    let journey_pattern_id = match &vj.journey_pattern_id {
+        Some(journey_pattern_id) if !journey_patterns.is_empty() => journey_pattern_id,
+        _ => return Ok(None),
+    };
+    let journey_pattern_idx = journey_patterns
+        .get_idx(journey_pattern_id)
+        .ok_or_else(|| DataError::UnknownReference {
+            object_type: object_type::<VehicleJourney>(),
+            id: Some(vj.id.clone()),
+            field: "journey_pattern_id",
+            value: journey_pattern_id.clone(),
+        })?;
+    let journey_pattern = &journey_patterns[journey_pattern_idx];
+    if journey_pattern.route_id != vj.route_id {
+        return Err(DataError::InconsistentJourneyPattern {
+            trip_id: vj.id.clone(),
+            route_id: vj.route_id.clone(),
+            journey_pattern_id: journey_pattern.id.clone(),
+            journey_pattern_route_id: journey_pattern.route_id.clone(),
+        });
+    }
+    Ok(Some(journey_pattern_idx))
+}
+
+fn journey_patterns_to_vehicle_journeys(
+    c: &Collections,
+) -> Result<OptionalOneToMany<JourneyPattern, VehicleJourney>> {
+    let mut backward = BTreeMap::new();
+    for (vj_idx, vj) in c.vehicle_journeys.iter() {
+        if let Some(journey_pattern_idx) = journey_pattern_of(&c.journey_patterns, vj)? {
+            backward.insert(vj_idx, journey_pattern_idx);
         }
+    }
+    Ok(OptionalOneToMany::from_backward(backward))
+}
 
-        #[test]
-        fn enhance() {
-            let mut collections = collections(None);
-            collections.enhance_trip_headsign();
-            let vehicle_journey = collections
-                .vehicle_journeys
-                .get("vehicle_journey_id_1")
-                .unwrap();
-            assert_eq!("Stop Name", vehicle_journey.headsign.as_ref().unwrap());
-            let vehicle_journey = collections
-                .vehicle_journeys
-                .get("vehicle_journey_id_2")
-                .unwrap();
-            assert_eq!("Headsign", vehicle_journey.headsign.as_ref().unwrap());
+/// A one to many relation in which an object of `U` can have no
+/// corresponding `T`, unlike `OneToMany` which needs all the objects of `U`
+/// to reference an existing `T`.
+#[derive(Debug)]
+struct OptionalOneToMany<T, U> {
+    one_to_many: BTreeMap<Idx<T>, IdxSet<U>>,
+    many_to_one: BTreeMap<Idx<U>, Idx<T>>,
+}
+
+impl<T, U> OptionalOneToMany<T, U> {
+    fn from_backward(many_to_one: BTreeMap<Idx<U>, Idx<T>>) -> Self {
+        let mut one_to_many: BTreeMap<Idx<T>, IdxSet<U>> = BTreeMap::new();
+        for (&many_idx, &one_idx) in &many_to_one {
+            one_to_many.entry(one_idx).or_default().insert(many_idx);
+        }
+        OptionalOneToMany {
+            one_to_many,
+            many_to_one,
         }
+    }
+}
 
-        #[test]
-        fn enhance_when_string_empty() {
-            let mut collections = collections(Some(String::new()));
-            collections.enhance_trip_headsign();
+impl<T, U> Relation for OptionalOneToMany<T, U> {
+    type From = T;
+    type To = U;
+
+    fn get_from(&self) -> IdxSet<T> {
+        self.one_to_many.keys().copied().collect()
+    }
+
+    fn get_corresponding_forward(&self, from: &IdxSet<T>) -> IdxSet<U> {
+        from.iter()
+            .filter_map(|idx| self.one_to_many.get(idx))
+            .flat_map(|idxs| idxs.iter().copied())
+            .collect()
+    }
+
+    fn get_corresponding_backward(&self, from: &IdxSet<U>) -> IdxSet<T> {
+        from.iter()
+            .filter_map(|idx| self.many_to_one.get(idx))
+            .copied()
+            .collect()
+    }
+}
+
+fn transfers_to_stop_points_forward(
+    c: &Collections,
+) -> Result<BTreeMap<Idx<Transfer>, IdxSet<StopPoint>>> {
+    c.transfers
+        .iter()
+        .map(|(idx, tr)| {
+            let mut stop_points = IdxSet::default();
+            let invalid_reference = |field, value: &str| DataError::UnknownReference {
+                object_type: object_type::<Transfer>(),
+                id: None,
+                field,
+                value: value.to_string(),
+            };
+            stop_points.insert(
+                c.stop_points
+                    .get_idx(&tr.from_stop_id)
+                    .ok_or_else(|| invalid_reference("from_stop_id", &tr.from_stop_id))?,
+            );
+            stop_points.insert(
+                c.stop_points
+                    .get_idx(&tr.to_stop_id)
+                    .ok_or_else(|| invalid_reference("to_stop_id", &tr.to_stop_id))?,
+            );
+            Ok((idx, stop_points))
+        })
+        .collect::<Result<BTreeMap<_, _>, Error>>()
+}
+
+fn idx_set<T>(idx: Idx<T>) -> IdxSet<T> {
+    std::iter::once(idx).collect()
+}
+
+/// Rebuilds a `ManyToMany` relation from the objects `from`. Only the objects
+/// related to the `affected` ones are computed with `f`, the others are copied
+/// from `relation`; the whole relation is still rebuilt.
+fn update_many_to_many<T, U, F>(
+    relation: &ManyToMany<T, U>,
+    from: impl Iterator<Item = Idx<T>>,
+    affected: &IdxSet<T>,
+    f: F,
+) -> ManyToMany<T, U>
+where
+    F: Fn(Idx<T>) -> IdxSet<U>,
+{
+    let forward = from
+        .map(|idx| {
+            let to = if affected.contains(&idx) {
+                f(idx)
+            } else {
+                relation.get_corresponding_forward(&idx_set(idx))
+            };
+            (idx, to)
+        })
+        .collect();
+    ManyToMany::from_forward(forward)
+}
+
+/// Objects modified by a `Transaction`, to know which relations of the
+/// `Model` must be updated.
+#[derive(Default)]
+struct Touched {
+    all: bool,
+    /// Types of the objects inserted, removed or with modified references
+    types: HashSet<TypeId>,
+    /// Types of the removed objects, the indexes of their collection changed
+    removed: HashSet<TypeId>,
+    /// Vehicle journeys inserted, or with modified references or stop times
+    vehicle_journeys: IdxSet<VehicleJourney>,
+}
+
+impl Touched {
+    fn all() -> Self {
+        Touched {
+            all: true,
+            ..Default::default()
+        }
+    }
+
+    fn insert<T: 'static>(&mut self) {
+        self.types.insert(TypeId::of::<T>());
+    }
+
+    fn insert_object<T: TransactionObject>(&mut self, collections: &Collections, id: &str) {
+        self.insert::<T>();
+        if TypeId::of::<T>() == TypeId::of::<VehicleJourney>() {
+            self.vehicle_journeys
+                .extend(collections.vehicle_journeys.get_idx(id));
+        }
+    }
+
+    fn contains<T: 'static>(&self) -> bool {
+        self.all || self.types.contains(&TypeId::of::<T>())
+    }
+
+    /// Returns `true` if the indexes of the objects of type `T` didn't
+    /// change, so the relations involving them can be updated incrementally.
+    fn same_indexes<T: 'static>(&self) -> bool {
+        !self.all && !self.removed.contains(&TypeId::of::<T>())
+    }
+}
+
+type Check = fn(&Transaction<'_>, &str) -> Vec<String>;
+/// Restores the collections modified by an operation of a `Transaction`.
+pub type Undo = Box<dyn FnOnce(&mut Collections)>;
+
+/// A set of modifications of a [`Model`], see [`Model::transaction`].
+///
+/// Insertions and updates are applied immediately, removals are applied
+/// when the transaction is committed.
+pub struct Transaction<'a> {
+    collections: &'a mut Collections,
+    undo_log: Vec<Undo>,
+    checks: Vec<(Check, String)>,
+    removal_checks: Vec<(Check, String)>,
+    removed: HashMap<TypeId, HashSet<String>>,
+    removers: HashMap<TypeId, fn(&mut Collections, &HashSet<String>) -> Undo>,
+    touched: Touched,
+}
+
+impl<'a> Transaction<'a> {
+    fn new(collections: &'a mut Collections) -> Self {
+        Transaction {
+            collections,
+            undo_log: Vec::new(),
+            checks: Vec::new(),
+            removal_checks: Vec::new(),
+            removed: HashMap::new(),
+            removers: HashMap::new(),
+            touched: Touched::default(),
+        }
+    }
+
+    /// The collections, including the modifications of the transaction
+    /// except the removals.
+    pub fn collections(&self) -> &Collections {
+        self.collections
+    }
+
+    /// Returns `true` if the object exists and is not removed by the
+    /// transaction.
+    pub fn contains<T: TransactionObject>(&self, id: &str) -> bool {
+        T::collection(self.collections).contains_id(id) && !self.is_removed::<T>(id)
+    }
+
+    /// Returns the object if it exists and is not removed by the transaction.
+    pub fn get<T: TransactionObject>(&self, id: &str) -> Option<&T> {
+        if self.is_removed::<T>(id) {
+            None
+        } else {
+            T::collection(self.collections).get(id)
+        }
+    }
+
+    /// Returns `true` if the object is removed by the transaction.
+    pub fn is_removed<T: TransactionObject>(&self, id: &str) -> bool {
+        self.removed
+            .get(&TypeId::of::<T>())
+            .is_some_and(|ids| ids.contains(id))
+    }
+
+    /// Inserts a new object. Fails if an object with the same identifier
+    /// already exists.
+    pub fn insert<T: TransactionObject>(&mut self, object: T) -> Result<()> {
+        let id = object.id().to_string();
+        T::collection_mut(self.collections).push(object)?;
+        let undo_id = id.clone();
+        self.undo_log.push(Box::new(move |c: &mut Collections| {
+            T::collection_mut(c).retain(|object| object.id() != undo_id)
+        }));
+        self.touched.insert_object::<T>(self.collections, &id);
+        self.checks.push((check_references::<T>, id));
+        Ok(())
+    }
+
+    /// Updates an existing object. The identifier of the object can't be
+    /// modified.
+    pub fn update<T, F>(&mut self, id: &str, f: F) -> Result<()>
+    where
+        T: TransactionObject,
+        F: FnOnce(&mut T),
+    {
+        let collection = T::collection_mut(self.collections);
+        let idx = collection
+            .get_idx(id)
+            .ok_or_else(|| anyhow!("{} {:?} not found", T::OBJECT_NAME, id))?;
+        let previous = collection[idx].clone();
+        let mut object = previous.clone();
+        f(&mut object);
+        if object.id() != id {
+            bail!(
+                "identifier of {} {:?} can't be modified",
+                T::OBJECT_NAME,
+                id
+            );
+        }
+        let same_references = object.same_references(&previous);
+        *collection.index_mut(idx) = object;
+        if !same_references {
+            self.touched.insert_object::<T>(self.collections, id);
+        }
+        self.undo_log.push(Box::new(move |c: &mut Collections| {
+            *T::collection_mut(c).index_mut(idx) = previous
+        }));
+        self.checks.push((check_references::<T>, id.to_string()));
+        Ok(())
+    }
+
+    /// Edits the stop times of an existing vehicle journey. The stop times
+    /// are sorted by sequence afterwards. Fails if a stop time references
+    /// an index which is not in the stop points of the model.
+    pub fn update_stop_times<F>(&mut self, vehicle_journey_id: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Vec<StopTime>),
+    {
+        let vj_idx = self
+            .collections
+            .vehicle_journeys
+            .get_idx(vehicle_journey_id)
+            .ok_or_else(|| anyhow!("trip {:?} not found", vehicle_journey_id))?;
+        let last_stop_point_idx = self.collections.stop_points.indexes().last();
+        let previous = {
+            let mut vj = self.collections.vehicle_journeys.index_mut(vj_idx);
+            let previous = vj.stop_times.clone();
+            f(&mut vj.stop_times);
+            if vj
+                .stop_times
+                .iter()
+                .any(|st| Some(st.stop_point_idx) > last_stop_point_idx)
+            {
+                vj.stop_times = previous;
+                bail!(
+                    "trip {:?} references an unknown stop point",
+                    vehicle_journey_id
+                );
+            }
+            vj.stop_times.sort_unstable();
+            previous
+        };
+        self.undo_log.push(Box::new(move |c: &mut Collections| {
+            c.vehicle_journeys.index_mut(vj_idx).stop_times = previous
+        }));
+        self.checks.push((
+            check_references::<VehicleJourney>,
+            vehicle_journey_id.to_string(),
+        ));
+        self.touched.vehicle_journeys.insert(vj_idx);
+        Ok(())
+    }
+
+    /// Removes an existing object. The removal is applied when the
+    /// transaction is committed, and fails if the object is still
+    /// referenced by an object which is not removed.
+    pub fn remove<T: TransactionObject>(&mut self, id: &str) -> Result<()> {
+        if !self.contains::<T>(id) {
+            bail!("{} {:?} not found", T::OBJECT_NAME, id);
+        }
+        self.removed
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .insert(id.to_string());
+        self.removers.insert(TypeId::of::<T>(), T::remove_all);
+        self.removal_checks
+            .push((check_removal::<T>, id.to_string()));
+        self.touched.insert::<T>();
+        self.touched.removed.insert(TypeId::of::<T>());
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<Touched> {
+        let mut errors = Vec::new();
+        for (check, id) in self.checks.iter().chain(&self.removal_checks) {
+            errors.extend(check(&*self, id));
+        }
+        if !errors.is_empty() {
+            bail!("Transaction aborted: {}", errors.join("; "));
+        }
+        // Vehicle journeys are removed first as the removal of stop points
+        // updates the stop times of the remaining vehicle journeys
+        let vj_type_id = TypeId::of::<VehicleJourney>();
+        if let Some(ids) = self.removed.get(&vj_type_id) {
+            let undo = VehicleJourney::remove_all(self.collections, ids);
+            self.undo_log.push(undo);
+        }
+        for (type_id, remove_all) in &self.removers {
+            if *type_id != vj_type_id {
+                let undo = remove_all(self.collections, &self.removed[type_id]);
+                self.undo_log.push(undo);
+            }
+        }
+        Ok(std::mem::take(&mut self.touched))
+    }
+
+    fn rollback(&mut self) {
+        while let Some(undo) = self.undo_log.pop() {
+            undo(self.collections);
+        }
+    }
+}
+
+fn check_references<T: TransactionObject>(transaction: &Transaction<'_>, id: &str) -> Vec<String> {
+    transaction
+        .get::<T>(id)
+        .map(|object| {
+            object
+                .missing_references(transaction)
+                .into_iter()
+                .map(|missing| {
+                    format!("{} {:?} references unknown {}", T::OBJECT_NAME, id, missing)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn check_removal<T: TransactionObject>(transaction: &Transaction<'_>, id: &str) -> Vec<String> {
+    let referencing = T::referenced_by(id, transaction);
+    if referencing.is_empty() {
+        vec![]
+    } else {
+        vec![format!(
+            "{} {:?} can't be removed, it is referenced by {}",
+            T::OBJECT_NAME,
+            id,
+            referencing.join(", ")
+        )]
+    }
+}
+
+/// Describes the reference to `id` if the object is not in the transaction.
+fn missing<T: TransactionObject>(transaction: &Transaction, id: &str) -> Option<String> {
+    if transaction.contains::<T>(id) {
+        None
+    } else {
+        Some(format!("{} {:?}", T::OBJECT_NAME, id))
+    }
+}
+
+/// Describes the objects of type `T` matching `f` and not removed.
+fn referencing<T, F>(transaction: &Transaction, f: F) -> Vec<String>
+where
+    T: TransactionObject,
+    F: Fn(&T) -> bool,
+{
+    T::collection(transaction.collections())
+        .values()
+        .filter(|object| f(object) && !transaction.is_removed::<T>(object.id()))
+        .map(|object| format!("{} {:?}", T::OBJECT_NAME, object.id()))
+        .collect()
+}
+
+/// Objects of a [`Model`] which can be modified in a [`Transaction`].
+pub trait TransactionObject: Id<Self> + Clone + Sized + 'static {
+    /// Name of the type of object, used in error messages.
+    const OBJECT_NAME: &'static str;
+
+    /// The collection of the objects.
+    fn collection(collections: &Collections) -> &CollectionWithId<Self>;
+
+    /// The mutable collection of the objects.
+    fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self>;
+
+    /// Describes each reference of the object to an object missing in the
+    /// transaction.
+    fn missing_references(&self, _transaction: &Transaction) -> Vec<String> {
+        vec![]
+    }
+
+    /// Describes the objects of the transaction referencing the object `id`.
+    fn referenced_by(_id: &str, _transaction: &Transaction) -> Vec<String> {
+        vec![]
+    }
+
+    /// Returns `true` if both objects have the same references, in which
+    /// case the relations don't need to be rebuilt after an update.
+    fn same_references(&self, _other: &Self) -> bool {
+        true
+    }
+
+    /// Removes the objects with the given identifiers from the collections,
+    /// and returns how to restore them.
+    fn remove_all(collections: &mut Collections, ids: &HashSet<String>) -> Undo {
+        let removed = remove_objects(Self::collection_mut(collections), |object| {
+            ids.contains(object.id())
+        });
+        Box::new(move |c: &mut Collections| restore_objects(Self::collection_mut(c), removed))
+    }
+}
+
+fn split_off<T, F>(objects: Vec<T>, f: F) -> (Vec<T>, Vec<(usize, T)>)
+where
+    F: Fn(&T) -> bool,
+{
+    let mut kept = Vec::with_capacity(objects.len());
+    let mut removed = Vec::new();
+    for (position, object) in objects.into_iter().enumerate() {
+        if f(&object) {
+            removed.push((position, object));
+        } else {
+            kept.push(object);
+        }
+    }
+    (kept, removed)
+}
+
+fn merge_back<T>(mut objects: Vec<T>, removed: Vec<(usize, T)>) -> Vec<T> {
+    for (position, object) in removed {
+        objects.insert(position, object);
+    }
+    objects
+}
+
+/// Removes the objects matching `f`, and returns them with their positions
+/// to restore them with `restore_objects`.
+fn remove_objects<T, F>(collection: &mut CollectionWithId<T>, f: F) -> Vec<(usize, T)>
+where
+    T: Id<T>,
+    F: Fn(&T) -> bool,
+{
+    let (kept, removed) = split_off(collection.take(), f);
+    *collection = CollectionWithId::new(kept).unwrap();
+    removed
+}
+
+/// Puts back the removed objects at their positions, so the indexes of all
+/// the objects are the same as before the removal.
+fn restore_objects<T: Id<T>>(collection: &mut CollectionWithId<T>, removed: Vec<(usize, T)>) {
+    let objects = merge_back(collection.take(), removed);
+    *collection = CollectionWithId::new(objects).unwrap();
+}
+
+fn remove_values<T, F>(collection: &mut Collection<T>, f: F) -> Vec<(usize, T)>
+where
+    F: Fn(&T) -> bool,
+{
+    let (kept, removed) = split_off(collection.take(), f);
+    *collection = Collection::new(kept);
+    removed
+}
+
+fn restore_values<T>(collection: &mut Collection<T>, removed: Vec<(usize, T)>) {
+    *collection = Collection::new(merge_back(collection.take(), removed));
+}
+
+fn remove_stop_time_entries(
+    map: &mut HashMap<(String, u32), String>,
+    ids: &HashSet<String>,
+) -> Vec<((String, u32), String)> {
+    let keys: Vec<(String, u32)> = map
+        .keys()
+        .filter(|(vj_id, _)| ids.contains(vj_id))
+        .cloned()
+        .collect();
+    keys.iter()
+        .filter_map(|key| map.remove_entry(key))
+        .collect()
+}
+
+macro_rules! impl_transaction_object {
+    ($ty:ty, $collection:ident, $name:expr, $child:ty, $child_field:ident) => {
+        impl TransactionObject for $ty {
+            const OBJECT_NAME: &'static str = $name;
+
+            fn collection(collections: &Collections) -> &CollectionWithId<Self> {
+                &collections.$collection
+            }
+
+            fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self> {
+                &mut collections.$collection
+            }
+
+            fn referenced_by(id: &str, transaction: &Transaction) -> Vec<String> {
+                referencing(transaction, |child: &$child| child.$child_field == id)
+            }
+        }
+    };
+}
+
+impl_transaction_object!(Network, networks, "network", Line, network_id);
+impl_transaction_object!(
+    CommercialMode,
+    commercial_modes,
+    "commercial mode",
+    Line,
+    commercial_mode_id
+);
+impl_transaction_object!(
+    PhysicalMode,
+    physical_modes,
+    "physical mode",
+    VehicleJourney,
+    physical_mode_id
+);
+impl_transaction_object!(
+    Contributor,
+    contributors,
+    "contributor",
+    Dataset,
+    contributor_id
+);
+impl_transaction_object!(Company, companies, "company", VehicleJourney, company_id);
+impl_transaction_object!(Calendar, calendars, "calendar", VehicleJourney, service_id);
+impl_transaction_object!(StopArea, stop_areas, "stop area", StopPoint, stop_area_id);
+
+impl TransactionObject for Dataset {
+    const OBJECT_NAME: &'static str = "dataset";
+
+    fn collection(collections: &Collections) -> &CollectionWithId<Self> {
+        &collections.datasets
+    }
+
+    fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self> {
+        &mut collections.datasets
+    }
+
+    fn missing_references(&self, transaction: &Transaction) -> Vec<String> {
+        missing::<Contributor>(transaction, &self.contributor_id)
+            .into_iter()
+            .collect()
+    }
+
+    fn referenced_by(id: &str, transaction: &Transaction) -> Vec<String> {
+        referencing(transaction, |vj: &VehicleJourney| vj.dataset_id == id)
+    }
+
+    fn same_references(&self, other: &Self) -> bool {
+        self.contributor_id == other.contributor_id
+    }
+}
+
+impl TransactionObject for Line {
+    const OBJECT_NAME: &'static str = "line";
+
+    fn collection(collections: &Collections) -> &CollectionWithId<Self> {
+        &collections.lines
+    }
+
+    fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self> {
+        &mut collections.lines
+    }
+
+    fn missing_references(&self, transaction: &Transaction) -> Vec<String> {
+        missing::<Network>(transaction, &self.network_id)
+            .into_iter()
+            .chain(missing::<CommercialMode>(
+                transaction,
+                &self.commercial_mode_id,
+            ))
+            .collect()
+    }
+
+    fn referenced_by(id: &str, transaction: &Transaction) -> Vec<String> {
+        referencing(transaction, |route: &Route| route.line_id == id)
+    }
+
+    fn same_references(&self, other: &Self) -> bool {
+        self.network_id == other.network_id && self.commercial_mode_id == other.commercial_mode_id
+    }
+}
+
+impl TransactionObject for Route {
+    const OBJECT_NAME: &'static str = "route";
+
+    fn collection(collections: &Collections) -> &CollectionWithId<Self> {
+        &collections.routes
+    }
+
+    fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self> {
+        &mut collections.routes
+    }
+
+    fn missing_references(&self, transaction: &Transaction) -> Vec<String> {
+        missing::<Line>(transaction, &self.line_id)
+            .into_iter()
+            .collect()
+    }
+
+    fn referenced_by(id: &str, transaction: &Transaction) -> Vec<String> {
+        let mut referencing_objects =
+            referencing(transaction, |vj: &VehicleJourney| vj.route_id == id);
+        referencing_objects.extend(referencing(
+            transaction,
+            |journey_pattern: &JourneyPattern| journey_pattern.route_id == id,
+        ));
+        referencing_objects
+    }
+
+    fn same_references(&self, other: &Self) -> bool {
+        self.line_id == other.line_id
+    }
+}
+
+impl TransactionObject for JourneyPattern {
+    const OBJECT_NAME: &'static str = "journey pattern";
+
+    fn collection(collections: &Collections) -> &CollectionWithId<Self> {
+        &collections.journey_patterns
+    }
+
+    fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self> {
+        &mut collections.journey_patterns
+    }
+
+    fn missing_references(&self, transaction: &Transaction) -> Vec<String> {
+        missing::<Route>(transaction, &self.route_id)
+            .into_iter()
+            .collect()
+    }
+
+    fn referenced_by(id: &str, transaction: &Transaction) -> Vec<String> {
+        referencing(transaction, |vj: &VehicleJourney| {
+            vj.journey_pattern_id.as_deref() == Some(id)
+        })
+    }
+
+    fn same_references(&self, other: &Self) -> bool {
+        self.route_id == other.route_id
+    }
+}
+
+impl TransactionObject for StopPoint {
+    const OBJECT_NAME: &'static str = "stop point";
+
+    fn collection(collections: &Collections) -> &CollectionWithId<Self> {
+        &collections.stop_points
+    }
+
+    fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self> {
+        &mut collections.stop_points
+    }
+
+    fn missing_references(&self, transaction: &Transaction) -> Vec<String> {
+        missing::<StopArea>(transaction, &self.stop_area_id)
+            .into_iter()
+            .collect()
+    }
+
+    fn referenced_by(id: &str, transaction: &Transaction) -> Vec<String> {
+        match transaction.collections().stop_points.get_idx(id) {
+            Some(stop_point_idx) => referencing(transaction, |vj: &VehicleJourney| {
+                vj.stop_times
+                    .iter()
+                    .any(|st| st.stop_point_idx == stop_point_idx)
+            }),
+            None => vec![],
+        }
+    }
+
+    fn same_references(&self, other: &Self) -> bool {
+        self.stop_area_id == other.stop_area_id
+    }
+
+    fn remove_all(collections: &mut Collections, ids: &HashSet<String>) -> Undo {
+        fn update_stop_point_idxs(
+            collections: &mut Collections,
+            stop_point_idxs: &HashMap<Idx<StopPoint>, Idx<StopPoint>>,
+        ) {
+            for vj_idx in collections.vehicle_journeys.indexes() {
+                let mut vj = collections.vehicle_journeys.index_mut(vj_idx);
+                for st in vj.stop_times.iter_mut() {
+                    st.stop_point_idx = stop_point_idxs[&st.stop_point_idx];
+                }
+            }
+        }
+        let stop_point_id_to_old_idx = collections.stop_points.get_id_to_idx().clone();
+        let stop_points = remove_objects(&mut collections.stop_points, |stop_point| {
+            ids.contains(&stop_point.id)
+        });
+        let stop_point_old_idx_to_new_idx: HashMap<Idx<StopPoint>, Idx<StopPoint>> = collections
+            .stop_points
+            .iter()
+            .map(|(new_idx, stop_point)| (stop_point_id_to_old_idx[&stop_point.id], new_idx))
+            .collect();
+        update_stop_point_idxs(collections, &stop_point_old_idx_to_new_idx);
+        let transfers = remove_values(&mut collections.transfers, |transfer| {
+            ids.contains(&transfer.from_stop_id) || ids.contains(&transfer.to_stop_id)
+        });
+        let pathways = remove_objects(&mut collections.pathways, |pathway| {
+            ids.contains(&pathway.from_stop_id) || ids.contains(&pathway.to_stop_id)
+        });
+        Box::new(move |c: &mut Collections| {
+            restore_objects(&mut c.pathways, pathways);
+            restore_values(&mut c.transfers, transfers);
+            restore_objects(&mut c.stop_points, stop_points);
+            let stop_point_new_idx_to_old_idx: HashMap<_, _> = stop_point_old_idx_to_new_idx
+                .into_iter()
+                .map(|(old_idx, new_idx)| (new_idx, old_idx))
+                .collect();
+            update_stop_point_idxs(c, &stop_point_new_idx_to_old_idx);
+        })
+    }
+}
+
+impl TransactionObject for VehicleJourney {
+    const OBJECT_NAME: &'static str = "trip";
+
+    fn collection(collections: &Collections) -> &CollectionWithId<Self> {
+        &collections.vehicle_journeys
+    }
+
+    fn collection_mut(collections: &mut Collections) -> &mut CollectionWithId<Self> {
+        &mut collections.vehicle_journeys
+    }
+
+    fn missing_references(&self, transaction: &Transaction) -> Vec<String> {
+        let stop_points = &transaction.collections().stop_points;
+        // The stop times may have been modified by `Transaction::update` with
+        // an index which is not in the stop points
+        let last_stop_point_idx = stop_points.indexes().last();
+        let missing_stop_points = self.stop_times.iter().filter_map(|st| {
+            if Some(st.stop_point_idx) > last_stop_point_idx {
+                return Some(format!(
+                    "{} at sequence {}",
+                    StopPoint::OBJECT_NAME,
+                    st.sequence
+                ));
+            }
+            let stop_point_id = &stop_points[st.stop_point_idx].id;
+            transaction
+                .is_removed::<StopPoint>(stop_point_id)
+                .then(|| format!("{} {:?}", StopPoint::OBJECT_NAME, stop_point_id))
+        });
+        let journey_patterns = &transaction.collections().journey_patterns;
+        let missing_journey_pattern = self
+            .journey_pattern_id
+            .as_ref()
+            .filter(|_| !journey_patterns.is_empty())
+            .and_then(|id| missing::<JourneyPattern>(transaction, id));
+        missing::<Route>(transaction, &self.route_id)
+            .into_iter()
+            .chain(missing::<PhysicalMode>(transaction, &self.physical_mode_id))
+            .chain(missing::<Dataset>(transaction, &self.dataset_id))
+            .chain(missing::<Company>(transaction, &self.company_id))
+            .chain(missing::<Calendar>(transaction, &self.service_id))
+            .chain(missing_journey_pattern)
+            .chain(missing_stop_points)
+            .collect()
+    }
+
+    fn same_references(&self, other: &Self) -> bool {
+        self.route_id == other.route_id
+            && self.physical_mode_id == other.physical_mode_id
+            && self.dataset_id == other.dataset_id
+            && self.company_id == other.company_id
+            && self.service_id == other.service_id
+            && self.journey_pattern_id == other.journey_pattern_id
+            && self
+                .stop_times
+                .iter()
+                .map(|st| st.stop_point_idx)
+                .eq(other.stop_times.iter().map(|st| st.stop_point_idx))
+    }
+
+    fn remove_all(collections: &mut Collections, ids: &HashSet<String>) -> Undo {
+        let vehicle_journeys =
+            remove_objects(&mut collections.vehicle_journeys, |vj| ids.contains(&vj.id));
+        let frequencies = remove_values(&mut collections.frequencies, |frequency| {
+            ids.contains(&frequency.vehicle_journey_id)
+        });
+        let headsigns = remove_stop_time_entries(&mut collections.stop_time_headsigns, ids);
+        let stop_time_ids = remove_stop_time_entries(&mut collections.stop_time_ids, ids);
+        let comments = remove_stop_time_entries(&mut collections.stop_time_comments, ids);
+        Box::new(move |c: &mut Collections| {
+            restore_objects(&mut c.vehicle_journeys, vehicle_journeys);
+            restore_values(&mut c.frequencies, frequencies);
+            c.stop_time_headsigns.extend(headsigns);
+            c.stop_time_ids.extend(stop_time_ids);
+            c.stop_time_comments.extend(comments);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ModelBuilder;
+
+    mod enhance_trip_headsign {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        fn collections(trip_headsign: Option<String>) -> Collections {
+            let mut collections = Collections::default();
+            collections
+                .stop_points
+                .push(StopPoint {
+                    id: String::from("stop_point_id"),
+                    name: String::from("Stop Name"),
+                    ..Default::default()
+                })
+                .unwrap();
+            let stop_time = StopTime {
+                stop_point_idx: collections.stop_points.get_idx("stop_point_id").unwrap(),
+                sequence: 0,
+                arrival_time: Some(Time::new(0, 0, 0)),
+                departure_time: Some(Time::new(0, 0, 0)),
+                start_pickup_drop_off_window: None,
+                end_pickup_drop_off_window: None,
+                boarding_duration: 0,
+                alighting_duration: 0,
+                pickup_type: 0,
+                drop_off_type: 0,
+                local_zone_id: Some(0),
+                precision: None,
+                shape_dist_traveled: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
+            };
+            collections
+                .vehicle_journeys
+                .push(VehicleJourney {
+                    id: String::from("vehicle_journey_id_1"),
+                    stop_times: vec![stop_time],
+                    headsign: trip_headsign,
+                    ..Default::default()
+                })
+                .unwrap();
+            collections
+                .vehicle_journeys
+                .push(VehicleJourney {
+                    id: String::from("vehicle_journey_id_2"),
+                    headsign: Some(String::from("Headsign")),
+                    ..Default::default()
+                })
+                .unwrap();
+            collections
+        }
+
+        #[test]
+        fn enhance() {
+            let mut collections = collections(None);
+            collections.enhance_trip_headsign();
+            let vehicle_journey = collections
+                .vehicle_journeys
+                .get("vehicle_journey_id_1")
+                .unwrap();
+            assert_eq!("Stop Name", vehicle_journey.headsign.as_ref().unwrap());
+            let vehicle_journey = collections
+                .vehicle_journeys
+                .get("vehicle_journey_id_2")
+                .unwrap();
+            assert_eq!("Headsign", vehicle_journey.headsign.as_ref().unwrap());
+        }
+
+        #[test]
+        fn enhance_when_string_empty() {
+            let mut collections = collections(Some(String::new()));
+            collections.enhance_trip_headsign();
             let vehicle_journey = collections
                 .vehicle_journeys
                 .get("vehicle_journey_id_1")
@@ -2167,6 +3701,9 @@ mod tests {
                 drop_off_type: 0,
                 local_zone_id: None,
                 precision: None,
+                shape_dist_traveled: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
             };
             let stop_times: Vec<_> = stop_point_ids.into_iter().map(stop_time_at).collect();
             VehicleJourney {
@@ -2549,6 +4086,50 @@ mod tests {
         }
     }
 
+    mod convert_frequencies_to_stoptimes {
+        use crate::{
+            objects::{ContinuousStopping, Frequency, Time},
+            ModelBuilder,
+        };
+        use pretty_assertions::assert_eq;
+
+        #[test]
+        fn keep_continuous_stopping() {
+            let mut collections = ModelBuilder::default()
+                .vj("vj1", |vj| {
+                    vj.st("SP1", "10:00:00").st("SP2", "10:10:00");
+                })
+                .build()
+                .into_collections();
+            {
+                let mut vj = collections.vehicle_journeys.get_mut("vj1").unwrap();
+                vj.stop_times[0].continuous_pickup = Some(ContinuousStopping::Continuous);
+                vj.stop_times[0].continuous_drop_off = Some(ContinuousStopping::PhoneAgency);
+            }
+
+            collections
+                .convert_frequencies_to_stoptimes(vec![Frequency {
+                    vehicle_journey_id: "vj1".to_string(),
+                    start_time: Time::new(12, 0, 0),
+                    end_time: Time::new(12, 30, 0),
+                    headway_secs: 1800,
+                }])
+                .unwrap();
+
+            let vj = collections.vehicle_journeys.get("vj1-0").unwrap();
+            let stop_time = &vj.stop_times[0];
+            assert_eq!(Some(Time::new(12, 0, 0)), stop_time.departure_time);
+            assert_eq!(
+                Some(ContinuousStopping::Continuous),
+                stop_time.continuous_pickup
+            );
+            assert_eq!(
+                Some(ContinuousStopping::PhoneAgency),
+                stop_time.continuous_drop_off
+            );
+        }
+    }
+
     mod check_coord_integrity {
         use crate::objects::Coord;
 
@@ -2644,6 +4225,9 @@ mod tests {
                 drop_off_type: 0,
                 local_zone_id: None,
                 precision: None,
+                shape_dist_traveled: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
             }
         }
 
@@ -2758,4 +4342,543 @@ mod tests {
             assert_eq!(Some(Time::new(26, 30, 0)), line.closing_time); // 25:30 + 1h trip duration
         }
     }
+
+    #[test]
+    fn invalid_reference() {
+        let mut collections = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("SP1", "10:00:00").st("SP2", "11:00:00");
+            })
+            .build()
+            .into_collections();
+        collections
+            .vehicle_journeys
+            .get_mut("vj1")
+            .unwrap()
+            .route_id = "unknown".to_string();
+        let error = Model::new(collections).unwrap_err();
+        assert_eq!(
+            Some(&DataError::UnknownReference {
+                object_type: "VehicleJourney",
+                id: Some("vj1".to_string()),
+                field: "route_id",
+                value: "unknown".to_string(),
+            }),
+            DataError::find(&error)
+        );
+    }
+
+    #[test]
+    fn remove_invalid_objects() {
+        let mut collections = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("SP1", "10:00:00").st("SP2", "11:00:00");
+            })
+            .vj("vj2", |vj| {
+                vj.st("SP1", "10:00:00").st("SP3", "11:00:00");
+            })
+            .build()
+            .into_collections();
+        collections
+            .vehicle_journeys
+            .get_mut("vj1")
+            .unwrap()
+            .route_id = "unknown".to_string();
+        let errors = collections.remove_invalid_objects();
+        assert_eq!(
+            vec![DataError::UnknownReference {
+                object_type: "VehicleJourney",
+                id: Some("vj1".to_string()),
+                field: "route_id",
+                value: "unknown".to_string(),
+            }],
+            errors
+        );
+        assert!(!collections.vehicle_journeys.contains_id("vj1"));
+        let model = Model::new(collections).unwrap();
+        assert!(model.vehicle_journeys.contains_id("vj2"));
+    }
+
+    #[test]
+    fn invalid_journey_patterns() {
+        let collections = || {
+            let mut collections = ModelBuilder::default()
+                .vj("vj1", |vj| {
+                    vj.route("route1")
+                        .st("SP1", "10:00:00")
+                        .st("SP2", "11:00:00");
+                })
+                .vj("vj2", |vj| {
+                    vj.route("route2")
+                        .st("SP1", "10:00:00")
+                        .st("SP2", "11:00:00");
+                })
+                .vj("vj3", |vj| {
+                    vj.route("route1")
+                        .st("SP1", "10:00:00")
+                        .st("SP2", "11:00:00");
+                })
+                .build()
+                .into_collections();
+            collections
+                .journey_patterns
+                .push(JourneyPattern {
+                    id: "jp1".to_string(),
+                    route_id: "route1".to_string(),
+                })
+                .unwrap();
+            for (vj_id, journey_pattern_id) in [("vj1", "jp1"), ("vj2", "jp1"), ("vj3", "jp2")] {
+                collections
+                    .vehicle_journeys
+                    .get_mut(vj_id)
+                    .unwrap()
+                    .journey_pattern_id = Some(journey_pattern_id.to_string());
+            }
+            collections
+        };
+        let error = Model::new(collections()).unwrap_err();
+        assert_eq!(
+            Some(&DataError::InconsistentJourneyPattern {
+                trip_id: "vj2".to_string(),
+                route_id: "route2".to_string(),
+                journey_pattern_id: "jp1".to_string(),
+                journey_pattern_route_id: "route1".to_string(),
+            }),
+            DataError::find(&error)
+        );
+
+        let mut collections = collections();
+        let errors = collections.remove_invalid_objects();
+        assert_eq!(
+            vec![
+                DataError::InconsistentJourneyPattern {
+                    trip_id: "vj2".to_string(),
+                    route_id: "route2".to_string(),
+                    journey_pattern_id: "jp1".to_string(),
+                    journey_pattern_route_id: "route1".to_string(),
+                },
+                DataError::UnknownReference {
+                    object_type: "VehicleJourney",
+                    id: Some("vj3".to_string()),
+                    field: "journey_pattern_id",
+                    value: "jp2".to_string(),
+                }
+            ],
+            errors
+        );
+        let model = Model::new(collections).unwrap();
+        let journey_pattern_idx = model.journey_patterns.get_idx("jp1").unwrap();
+        let vj_idxs: IdxSet<VehicleJourney> = model.get_corresponding_from_idx(journey_pattern_idx);
+        assert_eq!(
+            vec!["vj1"],
+            vj_idxs
+                .iter()
+                .map(|idx| model.vehicle_journeys[*idx].id.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            None,
+            model
+                .vehicle_journeys
+                .get("vj2")
+                .unwrap()
+                .journey_pattern_id
+        );
+    }
+
+    #[test]
+    fn journey_pattern_ids_without_journey_patterns() {
+        let mut collections = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("SP1", "10:00:00").st("SP2", "11:00:00");
+            })
+            .build()
+            .into_collections();
+        collections
+            .vehicle_journeys
+            .get_mut("vj1")
+            .unwrap()
+            .journey_pattern_id = Some("jp1".to_string());
+        assert!(collections.remove_invalid_objects().is_empty());
+        let model = Model::new(collections).unwrap();
+        assert_eq!(
+            Some("jp1"),
+            model
+                .vehicle_journeys
+                .get("vj1")
+                .unwrap()
+                .journey_pattern_id
+                .as_deref()
+        );
+    }
+
+    mod transaction {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        fn model() -> Model {
+            ModelBuilder::default()
+                .vj("vj1", |vj| {
+                    vj.route("route1")
+                        .st("SP1", "10:00:00")
+                        .st("SP2", "11:00:00")
+                        .st("SP3", "12:00:00");
+                })
+                .vj("vj2", |vj| {
+                    vj.route("route2")
+                        .st("SP4", "10:00:00")
+                        .st("SP5", "11:00:00");
+                })
+                .build()
+        }
+
+        fn route_stop_points(model: &Model, route_id: &str) -> BTreeSet<String> {
+            let route_idx = model.routes.get_idx(route_id).unwrap();
+            let stop_points: IdxSet<StopPoint> = model.get_corresponding_from_idx(route_idx);
+            stop_points
+                .into_iter()
+                .map(|idx| model.stop_points[idx].id.clone())
+                .collect()
+        }
+
+        fn ids(ids: &[&str]) -> BTreeSet<String> {
+            ids.iter().map(|id| id.to_string()).collect()
+        }
+
+        #[test]
+        fn update_object() {
+            let mut model = model();
+            model
+                .transaction(|tx| {
+                    tx.update("route1", |route: &mut Route| route.name = "Express".into())
+                })
+                .unwrap();
+            assert_eq!("Express", model.routes.get("route1").unwrap().name);
+        }
+
+        #[test]
+        fn update_identifier_fails() {
+            let mut model = model();
+            let error = model
+                .transaction(|tx| {
+                    tx.update("route1", |route: &mut Route| route.id = "route3".into())
+                })
+                .unwrap_err();
+            assert_eq!(
+                "identifier of route \"route1\" can't be modified",
+                error.to_string()
+            );
+            assert!(model.routes.contains_id("route1"));
+        }
+
+        #[test]
+        fn insert_vehicle_journey_updates_relations() {
+            let mut model = model();
+            model
+                .transaction(|tx| {
+                    let mut vj = tx.get::<VehicleJourney>("vj1").unwrap().clone();
+                    vj.id = "vj3".into();
+                    vj.route_id = "route2".into();
+                    tx.insert(vj)
+                })
+                .unwrap();
+            assert_eq!(
+                ids(&["SP1", "SP2", "SP3", "SP4", "SP5"]),
+                route_stop_points(&model, "route2")
+            );
+        }
+
+        #[test]
+        fn update_stop_times_updates_relations() {
+            let mut model = model();
+            model
+                .transaction(|tx| tx.update_stop_times("vj1", |stop_times| stop_times.truncate(2)))
+                .unwrap();
+            assert_eq!(ids(&["SP1", "SP2"]), route_stop_points(&model, "route1"));
+        }
+
+        #[test]
+        fn remove_referenced_object_rolls_back() {
+            let mut model = model();
+            let error = model
+                .transaction(|tx| {
+                    let mut route = tx.get::<Route>("route1").unwrap().clone();
+                    route.id = "route3".into();
+                    tx.insert(route)?;
+                    tx.remove::<Route>("route1")
+                })
+                .unwrap_err();
+            assert_eq!(
+                "Transaction aborted: route \"route1\" can't be removed, \
+                 it is referenced by trip \"vj1\"",
+                error.to_string()
+            );
+            assert!(model.routes.contains_id("route1"));
+            assert!(!model.routes.contains_id("route3"));
+        }
+
+        #[test]
+        fn insert_with_unknown_reference_rolls_back() {
+            let mut model = model();
+            let error = model
+                .transaction(|tx| {
+                    let mut vj = tx.get::<VehicleJourney>("vj1").unwrap().clone();
+                    vj.id = "vj3".into();
+                    vj.route_id = "unknown".into();
+                    tx.insert(vj)
+                })
+                .unwrap_err();
+            assert_eq!(
+                "Transaction aborted: trip \"vj3\" references unknown route \"unknown\"",
+                error.to_string()
+            );
+            assert!(!model.vehicle_journeys.contains_id("vj3"));
+        }
+
+        #[test]
+        fn remove_objects() {
+            let mut model = model();
+            model
+                .transaction(|tx| {
+                    tx.remove::<VehicleJourney>("vj1")?;
+                    tx.remove::<Route>("route1")?;
+                    tx.remove::<StopPoint>("SP1")
+                })
+                .unwrap();
+            assert!(!model.vehicle_journeys.contains_id("vj1"));
+            assert!(!model.routes.contains_id("route1"));
+            assert!(!model.stop_points.contains_id("SP1"));
+            // unused objects are only removed by `sanitize`
+            assert!(model.stop_points.contains_id("SP2"));
+            assert_eq!(ids(&["SP4", "SP5"]), route_stop_points(&model, "route2"));
+
+            model.sanitize().unwrap();
+            assert!(!model.stop_points.contains_id("SP2"));
+            assert_eq!(ids(&["SP4", "SP5"]), route_stop_points(&model, "route2"));
+        }
+
+        #[test]
+        fn update_vehicle_journey_route_updates_relations() {
+            let mut model = model();
+            model
+                .transaction(|tx| {
+                    tx.update("vj1", |vj: &mut VehicleJourney| {
+                        vj.route_id = "route2".into()
+                    })
+                })
+                .unwrap();
+            assert_eq!(ids(&[]), route_stop_points(&model, "route1"));
+            assert_eq!(
+                ids(&["SP1", "SP2", "SP3", "SP4", "SP5"]),
+                route_stop_points(&model, "route2")
+            );
+        }
+
+        #[test]
+        fn update_stop_times_with_unknown_stop_point_fails() {
+            let mut model = model();
+            let other_stop_points = CollectionWithId::new(
+                (0..10)
+                    .map(|i| StopPoint {
+                        id: format!("other{i}"),
+                        ..Default::default()
+                    })
+                    .collect(),
+            )
+            .unwrap();
+            let unknown_idx = other_stop_points.get_idx("other9").unwrap();
+            let error = model
+                .transaction(|tx| {
+                    tx.update_stop_times("vj1", |stop_times| {
+                        stop_times[0].stop_point_idx = unknown_idx
+                    })
+                })
+                .unwrap_err();
+            assert_eq!(
+                "trip \"vj1\" references an unknown stop point",
+                error.to_string()
+            );
+            assert_eq!(
+                ids(&["SP1", "SP2", "SP3"]),
+                route_stop_points(&model, "route1")
+            );
+        }
+
+        #[test]
+        fn update_with_unknown_stop_point_rolls_back() {
+            let mut model = model();
+            let other_stop_points = CollectionWithId::new(
+                (0..10)
+                    .map(|i| StopPoint {
+                        id: format!("other{i}"),
+                        ..Default::default()
+                    })
+                    .collect(),
+            )
+            .unwrap();
+            let unknown_idx = other_stop_points.get_idx("other9").unwrap();
+            let error = model
+                .transaction(|tx| {
+                    tx.update("vj1", |vj: &mut VehicleJourney| {
+                        vj.stop_times[0].stop_point_idx = unknown_idx
+                    })
+                })
+                .unwrap_err();
+            assert_eq!(
+                "Transaction aborted: trip \"vj1\" references unknown stop point at sequence 0",
+                error.to_string()
+            );
+            assert_eq!(
+                ids(&["SP1", "SP2", "SP3"]),
+                route_stop_points(&model, "route1")
+            );
+        }
+
+        #[test]
+        fn journey_patterns() {
+            let mut model = model();
+            model
+                .transaction(|tx| {
+                    tx.insert(JourneyPattern {
+                        id: "jp1".into(),
+                        route_id: "route1".into(),
+                    })?;
+                    tx.update("vj1", |vj: &mut VehicleJourney| {
+                        vj.journey_pattern_id = Some("jp1".into())
+                    })
+                })
+                .unwrap();
+            assert_eq!(
+                Some("jp1"),
+                model
+                    .vehicle_journeys
+                    .get("vj1")
+                    .unwrap()
+                    .journey_pattern_id
+                    .as_deref()
+            );
+
+            let error = model
+                .transaction(|tx| tx.remove::<JourneyPattern>("jp1"))
+                .unwrap_err();
+            assert_eq!(
+                "Transaction aborted: journey pattern \"jp1\" can't be removed, \
+                 it is referenced by trip \"vj1\"",
+                error.to_string()
+            );
+
+            let error = model
+                .transaction(|tx| tx.remove::<Route>("route1"))
+                .unwrap_err();
+            assert_eq!(
+                "Transaction aborted: route \"route1\" can't be removed, \
+                 it is referenced by trip \"vj1\", journey pattern \"jp1\"",
+                error.to_string()
+            );
+
+            model
+                .transaction(|tx| {
+                    tx.update("vj1", |vj: &mut VehicleJourney| {
+                        vj.journey_pattern_id = None
+                    })?;
+                    tx.remove::<JourneyPattern>("jp1")
+                })
+                .unwrap();
+            assert!(model.journey_patterns.is_empty());
+        }
+
+        #[test]
+        fn remove_stop_point_removes_transfers_and_pathways() {
+            let mut model = ModelBuilder::default()
+                .vj("vj1", |vj| {
+                    vj.st("SP1", "10:00:00").st("SP2", "11:00:00");
+                })
+                .vj("vj2", |vj| {
+                    vj.st("SP3", "10:00:00").st("SP4", "11:00:00");
+                })
+                .add_transfer("SP1", "SP3", "00:02:00")
+                .add_transfer("SP2", "SP4", "00:02:00")
+                .pathway(
+                    "SP1",
+                    StopType::Point,
+                    "SP3",
+                    StopType::Point,
+                    50,
+                    60,
+                    |_| {},
+                )
+                .pathway(
+                    "SP2",
+                    StopType::Point,
+                    "SP4",
+                    StopType::Point,
+                    50,
+                    60,
+                    |_| {},
+                )
+                .build();
+            model
+                .transaction(|tx| {
+                    tx.update_stop_times("vj1", |stop_times| {
+                        stop_times.remove(0);
+                    })?;
+                    tx.remove::<StopPoint>("SP1")
+                })
+                .unwrap();
+            let transfers: Vec<_> = model
+                .transfers
+                .values()
+                .map(|transfer| (transfer.from_stop_id.as_str(), transfer.to_stop_id.as_str()))
+                .collect();
+            assert_eq!(vec![("SP2", "SP4")], transfers);
+            assert!(!model.pathways.contains_id("SP1:SP3"));
+            assert!(model.pathways.contains_id("SP2:SP4"));
+        }
+
+        #[test]
+        fn removals_can_be_undone() {
+            let mut collections = ModelBuilder::default()
+                .vj("vj1", |vj| {
+                    vj.st("SP1", "10:00:00").st("SP2", "11:00:00");
+                })
+                .vj("vj2", |vj| {
+                    vj.st("SP3", "10:00:00").st("SP4", "11:00:00");
+                })
+                .add_transfer("SP1", "SP3", "00:02:00")
+                .build()
+                .into_collections();
+            let stop_point_ids = |c: &Collections| -> Vec<String> {
+                c.vehicle_journeys
+                    .values()
+                    .flat_map(|vj| &vj.stop_times)
+                    .map(|st| c.stop_points[st.stop_point_idx].id.clone())
+                    .collect()
+            };
+            let expected = stop_point_ids(&collections);
+            let expected_order: Vec<String> = collections
+                .stop_points
+                .values()
+                .map(|sp| sp.id.clone())
+                .collect();
+            let undo_vjs =
+                VehicleJourney::remove_all(&mut collections, &ids(&["vj1"]).into_iter().collect());
+            let undo_stop_points = StopPoint::remove_all(
+                &mut collections,
+                &ids(&["SP1", "SP2"]).into_iter().collect(),
+            );
+            assert_eq!(vec!["SP3", "SP4"], stop_point_ids(&collections));
+            assert!(collections.transfers.is_empty());
+
+            undo_stop_points(&mut collections);
+            undo_vjs(&mut collections);
+            assert_eq!(expected, stop_point_ids(&collections));
+            let order: Vec<String> = collections
+                .stop_points
+                .values()
+                .map(|sp| sp.id.clone())
+                .collect();
+            assert_eq!(expected_order, order);
+            assert_eq!(1, collections.transfers.len());
+        }
+    }
 }
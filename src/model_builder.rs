@@ -897,6 +897,9 @@ impl VehicleJourneyBuilder<'_> {
                 drop_off_type,
                 local_zone_id,
                 precision: None,
+                shape_dist_traveled: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
             };
             st_muter(&mut stop_time);
 
@@ -1001,6 +1004,9 @@ impl VehicleJourneyBuilder<'_> {
                 drop_off_type,
                 local_zone_id,
                 precision,
+                shape_dist_traveled: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
             };
             st_muter(&mut stop_time);
 
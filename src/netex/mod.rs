@@ -0,0 +1,121 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! [NeTEx](https://netex-cen.eu/) format management, independently of the
+//! national profiles (e.g. the Nordic or the Italian ones), the NeTEx France
+//! profile being written by the `netex_france` module.
+
+mod read;
+
+use crate::{
+    model::{Collections, Model},
+    objects::{Contributor, Dataset},
+    validity_period,
+    xml_reader::read_xml_files,
+    AddPrefix, PrefixConfiguration, Result,
+};
+use anyhow::Context;
+use roxmltree::Document;
+use std::{collections::BTreeMap, path::Path};
+use tracing::info;
+use typed_index_collection::CollectionWithId;
+
+///parameters consolidation
+#[derive(Default)]
+pub struct Configuration {
+    /// The Contributor providing the Dataset
+    pub contributor: Contributor,
+    /// Describe the Dataset being parsed
+    pub dataset: Dataset,
+    /// Additional key-values for the 'feed_infos.txt'
+    pub feed_infos: BTreeMap<String, String>,
+    /// used to prefix objects
+    pub prefix_conf: Option<PrefixConfiguration>,
+}
+
+fn read_to_collections(path: &Path, configuration: Configuration) -> Result<Collections> {
+    let Configuration {
+        contributor,
+        mut dataset,
+        feed_infos,
+        prefix_conf,
+    } = configuration;
+
+    let files = read_xml_files(path, "netex")?;
+    let documents = files
+        .iter()
+        .map(|(file_path, content)| {
+            info!("Reading {:?}", file_path);
+            Document::parse(content).with_context(|| format!("Error reading {file_path:?}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut collections = Collections::default();
+    read::read_documents(&mut collections, &documents, &dataset.id)?;
+    validity_period::compute_dataset_validity_period(&mut dataset, &collections.calendars)?;
+
+    collections.contributors = CollectionWithId::from(contributor);
+    collections.datasets = CollectionWithId::from(dataset);
+    collections.feed_infos = feed_infos;
+
+    //add prefixes
+    if let Some(prefix_conf) = prefix_conf {
+        collections.prefix(&prefix_conf);
+    }
+
+    collections.calendar_deduplication();
+    Ok(collections)
+}
+
+/// Imports a `Model` from the [NeTEx](https://netex-cen.eu/) files of a
+/// directory, of a zip archive or from a single XML file.
+pub fn read<P: AsRef<Path>>(p: P) -> Result<Model> {
+    Reader::default().parse(p)
+}
+
+/// Structure to configure the NeTEx reading
+#[derive(Default)]
+pub struct Reader {
+    configuration: Configuration,
+}
+
+impl Reader {
+    /// Build a Reader with a custom configuration
+    pub fn new(configuration: Configuration) -> Self {
+        Self { configuration }
+    }
+
+    /// Imports a `Model` from the [NeTEx](https://netex-cen.eu/) files of a
+    /// directory (with its subdirectories), of a zip archive or from a single
+    /// XML file.
+    ///
+    /// The references are resolved across the frames and the files, so the
+    /// objects can be split in any way between them. Each `StopPlace` gives a
+    /// `StopArea` and each `Quay` a `StopPoint`, each `Line` and `Route` a
+    /// `Line` and a `Route`, and each `ServiceJourney` a `VehicleJourney`
+    /// with the stop points of its journey pattern and its `passingTimes`,
+    /// running on the dates of its day types (from their operating days and
+    /// operating periods). The `ServiceJourneyInterchange` give `Transfer`.
+    pub fn parse(self, path: impl AsRef<Path>) -> Result<Model> {
+        let collections = self.parse_collections(path)?;
+        Model::new(collections)
+    }
+
+    /// Imports `Collections` from the [NeTEx](https://netex-cen.eu/) files
+    /// like [Reader::parse].
+    pub fn parse_collections(self, path: impl AsRef<Path>) -> Result<Collections> {
+        let p = path.as_ref();
+        read_to_collections(p, self.configuration)
+            .with_context(|| format!("impossible to read netex from {p:?}"))
+    }
+}
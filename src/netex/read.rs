@@ -0,0 +1,869 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use crate::{
+    model::Collections,
+    objects::{
+        Calendar, CommercialMode, Company, Coord, Date, Line, Network, PhysicalMode, Route,
+        StopArea, StopPoint, StopTime, StopType, Time, Transfer, VehicleJourney,
+    },
+    utils::required_stop_point_coord,
+    xml_reader::{child, children, elements, optional_duration, required_text, text},
+    Result,
+};
+use anyhow::{anyhow, bail, Context};
+use chrono::{Datelike, Days, Weekday};
+use chrono_tz::Tz;
+use roxmltree::{Document, Node};
+use skip_error::skip_error_and_warn;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use tracing::warn;
+use typed_index_collection::Idx;
+
+const LINES: [&str; 2] = ["Line", "FlexibleLine"];
+const JOURNEY_PATTERNS: [&str; 2] = ["ServiceJourneyPattern", "JourneyPattern"];
+const WEEK: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// NeTEx objects of all the documents, by element name and identifier, to
+/// resolve the references whatever the frame or the file they are defined in
+struct Index<'a, 'input> {
+    documents: &'a [Document<'input>],
+    objects: HashMap<&'a str, BTreeMap<&'a str, Node<'a, 'input>>>,
+    timezone: Option<Tz>,
+}
+
+impl<'a, 'input> Index<'a, 'input> {
+    fn new(documents: &'a [Document<'input>]) -> Self {
+        let mut objects: HashMap<&str, BTreeMap<&str, Node>> = HashMap::new();
+        let mut timezone = None;
+        for node in documents
+            .iter()
+            .flat_map(|document| document.descendants())
+            .filter(Node::is_element)
+        {
+            if let Some(id) = node.attribute("id") {
+                // The first version of an object is kept
+                objects
+                    .entry(node.tag_name().name())
+                    .or_default()
+                    .entry(id)
+                    .or_insert(node);
+            } else if node.has_tag_name("DefaultLocale") && timezone.is_none() {
+                timezone = text(node, &["TimeZone"]).and_then(|timezone| timezone.parse().ok());
+            }
+        }
+        Index {
+            documents,
+            objects,
+            timezone,
+        }
+    }
+
+    /// Object of one of the element names
+    fn get(&self, names: &[&str], id: &str) -> Option<Node<'a, 'input>> {
+        names
+            .iter()
+            .find_map(|name| self.objects.get(name)?.get(id).copied())
+    }
+
+    /// Identifiers and objects of an element name, sorted by identifier
+    fn objects(&self, name: &str) -> impl Iterator<Item = (&'a str, Node<'a, 'input>)> + '_ {
+        self.objects
+            .get(name)
+            .into_iter()
+            .flat_map(|objects| objects.iter().map(|(&id, &node)| (id, node)))
+    }
+
+    /// Elements of a name, with or without identifier (e.g. the assignments)
+    fn elements(&self, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+        self.documents
+            .iter()
+            .flat_map(|document| document.descendants())
+            .filter(move |node| node.has_tag_name(name))
+    }
+}
+
+/// Reference of a child element, e.g. `<LineRef ref="..."/>`
+fn reference<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name)?.attribute("ref")
+}
+
+/// Dates are `xsd:date` or `xsd:dateTime`
+fn parse_date(date: &str) -> Result<Date> {
+    date.split('T')
+        .next()
+        .unwrap_or(date)
+        .parse()
+        .with_context(|| format!("invalid date {date:?}"))
+}
+
+fn coord(node: Node) -> Option<Coord> {
+    let location = child(child(node, "Centroid")?, "Location")?;
+    Some(Coord {
+        lon: text(location, &["Longitude"])?.parse().ok()?,
+        lat: text(location, &["Latitude"])?.parse().ok()?,
+    })
+}
+
+/// Stop areas of the `StopPlace`, and stop points of the `Quay`
+fn read_stops(collections: &mut Collections, index: &Index) -> Result<()> {
+    for (id, stop_place) in index.objects("StopPlace") {
+        collections.stop_areas.push(StopArea {
+            id: id.to_string(),
+            name: text(stop_place, &["Name"]).unwrap_or(id).to_string(),
+            // Without coordinates, the centroid of the stop points is computed
+            coord: coord(stop_place).unwrap_or_default(),
+            visible: true,
+            ..Default::default()
+        })?;
+    }
+    for (id, quay) in index.objects("Quay") {
+        let stop_place = quay
+            .ancestors()
+            .skip(1)
+            .find(|ancestor| ancestor.has_tag_name("StopPlace"));
+        let coord = skip_error_and_warn!(required_stop_point_coord(
+            id,
+            coord(quay).or_else(|| stop_place.and_then(coord))
+        ));
+        let mut stop_point = StopPoint {
+            id: id.to_string(),
+            name: text(quay, &["Name"])
+                .or_else(|| stop_place.and_then(|stop_place| text(stop_place, &["Name"])))
+                .unwrap_or(id)
+                .to_string(),
+            code: text(quay, &["PublicCode"]).map(str::to_string),
+            coord,
+            visible: true,
+            stop_type: StopType::Point,
+            ..Default::default()
+        };
+        match stop_place.and_then(|stop_place| stop_place.attribute("id")) {
+            Some(stop_area_id) => stop_point.stop_area_id = stop_area_id.to_string(),
+            None => {
+                let stop_area = StopArea::from(stop_point.clone());
+                stop_point.stop_area_id.clone_from(&stop_area.id);
+                collections.stop_areas.push(stop_area)?;
+            }
+        }
+        collections.stop_points.push(stop_point)?;
+    }
+    Ok(())
+}
+
+/// Stop points (quays) of the scheduled stop points, from the
+/// `PassengerStopAssignment`
+fn read_stop_assignments<'a>(index: &Index<'a, '_>) -> HashMap<&'a str, &'a str> {
+    let mut assignments = HashMap::new();
+    for assignment in index.elements("PassengerStopAssignment") {
+        if let (Some(scheduled_stop_point), Some(quay)) = (
+            reference(assignment, "ScheduledStopPointRef"),
+            reference(assignment, "QuayRef"),
+        ) {
+            assignments.entry(scheduled_stop_point).or_insert(quay);
+        }
+    }
+    assignments
+}
+
+fn read_companies(collections: &mut Collections, index: &Index) -> Result<()> {
+    for (id, operator) in index.objects("Operator") {
+        collections.companies.push(Company {
+            id: id.to_string(),
+            name: text(operator, &["Name"])
+                .or_else(|| text(operator, &["ShortName"]))
+                .unwrap_or(id)
+                .to_string(),
+            url: text(operator, &["ContactDetails", "Url"]).map(str::to_string),
+            mail: text(operator, &["ContactDetails", "Email"]).map(str::to_string),
+            phone: text(operator, &["ContactDetails", "Phone"]).map(str::to_string),
+            ..Default::default()
+        })?;
+    }
+    Ok(())
+}
+
+fn read_networks(collections: &mut Collections, index: &Index) -> Result<()> {
+    for (id, network) in index.objects("Network") {
+        collections.networks.push(Network {
+            id: id.to_string(),
+            name: text(network, &["Name"]).unwrap_or(id).to_string(),
+            timezone: index.timezone,
+            ..Default::default()
+        })?;
+    }
+    Ok(())
+}
+
+/// Network of a line, referenced directly or through one of its groups of
+/// lines, the default network being created if needed
+fn network_id(collections: &mut Collections, index: &Index, line: Node) -> Result<String> {
+    let network = reference(line, "RepresentedByGroupRef").and_then(|group_ref| {
+        index.get(&["Network"], group_ref).or_else(|| {
+            index
+                .get(&["GroupOfLines"], group_ref)?
+                .ancestors()
+                .find(|ancestor| ancestor.has_tag_name("Network"))
+        })
+    });
+    if let Some(network_id) = network.and_then(|network| network.attribute("id")) {
+        return Ok(network_id.to_string());
+    }
+    let default_network = Network {
+        timezone: index.timezone,
+        ..Default::default()
+    };
+    if !collections.networks.contains_id(&default_network.id) {
+        collections.networks.push(default_network.clone())?;
+    }
+    Ok(default_network.id)
+}
+
+/// Physical and commercial mode of a `TransportMode` of NeTEx
+fn mode(mode: Option<&str>) -> &'static str {
+    match mode {
+        Some("coach") => "Coach",
+        Some("tram") => "Tramway",
+        Some("metro") => "Metro",
+        Some("rail") => "Train",
+        Some("water") | Some("ferry") => "Ferry",
+        Some("cableway") | Some("telecabin") => "SuspendedCableCar",
+        Some("funicular") => "Funicular",
+        Some("air") => "Air",
+        Some("taxi") => "Taxi",
+        _ => "Bus",
+    }
+}
+
+fn push_modes(collections: &mut Collections, mode: &str) -> Result<()> {
+    if !collections.commercial_modes.contains_id(mode) {
+        collections.commercial_modes.push(CommercialMode {
+            id: mode.to_string(),
+            name: mode.to_string(),
+        })?;
+    }
+    if !collections.physical_modes.contains_id(mode) {
+        collections.physical_modes.push(PhysicalMode {
+            id: mode.to_string(),
+            name: mode.to_string(),
+            co2_emission: None,
+        })?;
+    }
+    Ok(())
+}
+
+fn read_lines(collections: &mut Collections, index: &Index) -> Result<()> {
+    for (id, line) in LINES.iter().flat_map(|name| index.objects(name)) {
+        let mode = mode(text(line, &["TransportMode"]));
+        push_modes(collections, mode)?;
+        let network_id = network_id(collections, index, line)?;
+        let code = text(line, &["PublicCode"]).or_else(|| text(line, &["ShortName"]));
+        let color =
+            |name: &str| text(line, &["Presentation", name]).and_then(|color| color.parse().ok());
+        collections.lines.push(Line {
+            id: id.to_string(),
+            code: code.map(str::to_string),
+            name: text(line, &["Name"]).or(code).unwrap_or(id).to_string(),
+            color: color("Colour"),
+            text_color: color("TextColour"),
+            network_id,
+            commercial_mode_id: mode.to_string(),
+            ..Default::default()
+        })?;
+    }
+    Ok(())
+}
+
+fn line_ref<'a>(node: Node<'a, '_>) -> Option<&'a str> {
+    reference(node, "LineRef").or_else(|| reference(node, "FlexibleLineRef"))
+}
+
+fn read_routes(collections: &mut Collections, index: &Index) -> Result<()> {
+    for (id, route) in index.objects("Route") {
+        let line = skip_error_and_warn!(line_ref(route)
+            .and_then(|line_ref| collections.lines.get(line_ref))
+            .ok_or_else(|| anyhow!("route {id}: unknown line")));
+        let direction_type = match text(route, &["DirectionType"]) {
+            Some("outbound") | Some("clockwise") => Some("forward"),
+            Some("inbound") | Some("anticlockwise") => Some("backward"),
+            _ => None,
+        };
+        let route = Route {
+            id: id.to_string(),
+            name: text(route, &["Name"]).unwrap_or(&line.name).to_string(),
+            direction_type: direction_type.map(str::to_string),
+            line_id: line.id.clone(),
+            ..Default::default()
+        };
+        collections.routes.push(route)?;
+    }
+    Ok(())
+}
+
+/// Days of week of the properties of a day type, every day if not specified
+fn days_of_week(day_type: Node) -> HashSet<Weekday> {
+    let mut weekdays = HashSet::new();
+    for days in child(day_type, "properties")
+        .into_iter()
+        .flat_map(|properties| children(properties, "PropertyOfDay"))
+        .filter_map(|property| text(property, &["DaysOfWeek"]))
+    {
+        for day in days.split_whitespace() {
+            match day {
+                "Weekdays" => weekdays.extend(&WEEK[..5]),
+                "Weekend" => weekdays.extend(&WEEK[5..]),
+                "Everyday" => weekdays.extend(&WEEK),
+                _ => match day.parse::<Weekday>() {
+                    Ok(weekday) => {
+                        weekdays.insert(weekday);
+                    }
+                    Err(_) => warn!("unknown day of week {}", day),
+                },
+            }
+        }
+    }
+    if weekdays.is_empty() {
+        weekdays.extend(&WEEK);
+    }
+    weekdays
+}
+
+fn operating_day(index: &Index, operating_day_ref: &str) -> Result<Date> {
+    let operating_day = index
+        .get(&["OperatingDay"], operating_day_ref)
+        .ok_or_else(|| anyhow!("unknown operating day {operating_day_ref}"))?;
+    parse_date(required_text(operating_day, &["CalendarDate"])?)
+}
+
+/// Bound of an operating period, as a date or as an operating day
+fn period_bound(index: &Index, period: Node, date: &str, operating_day_ref: &str) -> Result<Date> {
+    match (text(period, &[date]), reference(period, operating_day_ref)) {
+        (Some(date), _) => parse_date(date),
+        (None, Some(operating_day_ref)) => operating_day(index, operating_day_ref),
+        (None, None) => bail!("missing {date} in operating period"),
+    }
+}
+
+/// Dates of an operating period on some days of week, or on the days of its
+/// `ValidDayBits` for a `UicOperatingPeriod`
+fn period_dates(
+    index: &Index,
+    operating_period_ref: &str,
+    weekdays: &HashSet<Weekday>,
+) -> Result<Vec<Date>> {
+    let period = index
+        .get(
+            &["OperatingPeriod", "UicOperatingPeriod"],
+            operating_period_ref,
+        )
+        .ok_or_else(|| anyhow!("unknown operating period {operating_period_ref}"))?;
+    let start_date = period_bound(index, period, "FromDate", "FromOperatingDayRef")?;
+    if let Some(valid_day_bits) = text(period, &["ValidDayBits"]) {
+        return Ok(valid_day_bits
+            .chars()
+            .enumerate()
+            .filter(|&(_, bit)| bit == '1')
+            .filter_map(|(day, _)| start_date.checked_add_days(Days::new(day as u64)))
+            .collect());
+    }
+    let end_date = period_bound(index, period, "ToDate", "ToOperatingDayRef")?;
+    Ok(start_date
+        .iter_days()
+        .take_while(|&date| date <= end_date)
+        .filter(|date| weekdays.contains(&date.weekday()))
+        .collect())
+}
+
+/// Dates of the day types, from their `DayTypeAssignment` to dates,
+/// operating days and operating periods, the assignments not available
+/// removing their dates
+fn read_day_types<'a>(index: &Index<'a, '_>) -> HashMap<&'a str, BTreeSet<Date>> {
+    let mut available: HashMap<&str, BTreeSet<Date>> = HashMap::new();
+    let mut unavailable: HashMap<&str, BTreeSet<Date>> = HashMap::new();
+    for assignment in index.elements("DayTypeAssignment") {
+        let day_type_ref = skip_error_and_warn!(reference(assignment, "DayTypeRef")
+            .ok_or_else(|| anyhow!("missing DayTypeRef in day type assignment")));
+        let dates = if let Some(date) = text(assignment, &["Date"]) {
+            skip_error_and_warn!(parse_date(date).map(|date| vec![date]))
+        } else if let Some(operating_day_ref) = reference(assignment, "OperatingDayRef") {
+            skip_error_and_warn!(operating_day(index, operating_day_ref).map(|date| vec![date]))
+        } else if let Some(operating_period_ref) = reference(assignment, "OperatingPeriodRef") {
+            let weekdays = index
+                .get(&["DayType"], day_type_ref)
+                .map(days_of_week)
+                .unwrap_or_else(|| WEEK.iter().copied().collect());
+            skip_error_and_warn!(period_dates(index, operating_period_ref, &weekdays))
+        } else {
+            warn!("day type {}: assignment without date", day_type_ref);
+            continue;
+        };
+        let day_types = match text(assignment, &["isAvailable"]) {
+            Some("false") => &mut unavailable,
+            _ => &mut available,
+        };
+        day_types.entry(day_type_ref).or_default().extend(dates);
+    }
+    for (day_type_ref, dates) in unavailable {
+        if let Some(available_dates) = available.get_mut(day_type_ref) {
+            available_dates.retain(|date| !dates.contains(date));
+        }
+    }
+    available
+}
+
+/// Dates of the `DatedServiceJourney` of each service journey
+fn read_dated_service_journeys<'a>(index: &Index<'a, '_>) -> HashMap<&'a str, BTreeSet<Date>> {
+    let mut dates: HashMap<&str, BTreeSet<Date>> = HashMap::new();
+    for (id, dated_journey) in index.objects("DatedServiceJourney") {
+        let service_journey_ref =
+            skip_error_and_warn!(reference(dated_journey, "ServiceJourneyRef")
+                .ok_or_else(|| anyhow!("dated service journey {id}: missing ServiceJourneyRef")));
+        let operating_day_ref = skip_error_and_warn!(reference(dated_journey, "OperatingDayRef")
+            .ok_or_else(|| anyhow!("dated service journey {id}: missing OperatingDayRef")));
+        let date = skip_error_and_warn!(operating_day(index, operating_day_ref));
+        dates.entry(service_journey_ref).or_default().insert(date);
+    }
+    dates
+}
+
+/// Point of a journey pattern, with the stop point of its scheduled stop
+/// point
+struct JourneyPatternPoint<'a> {
+    stop_point_idx: Idx<StopPoint>,
+    for_boarding: bool,
+    for_alighting: bool,
+    destination_display_ref: Option<&'a str>,
+}
+
+/// Points of a journey pattern, in their order, by identifier
+fn journey_pattern_points<'a>(
+    collections: &Collections,
+    journey_pattern: Node<'a, '_>,
+    assignments: &HashMap<&str, &str>,
+) -> Result<Vec<(Option<&'a str>, JourneyPatternPoint<'a>)>> {
+    let mut points = Vec::new();
+    for (position, point) in child(journey_pattern, "pointsInSequence")
+        .into_iter()
+        .flat_map(elements)
+        .enumerate()
+    {
+        let scheduled_stop_point_ref = match reference(point, "ScheduledStopPointRef") {
+            Some(scheduled_stop_point_ref) => scheduled_stop_point_ref,
+            // e.g. a `TimingPointInJourneyPattern` without stop
+            None => continue,
+        };
+        // A scheduled stop point is sometimes the quay itself
+        let stop_point_id = assignments
+            .get(scheduled_stop_point_ref)
+            .copied()
+            .unwrap_or(scheduled_stop_point_ref);
+        let stop_point_idx = collections
+            .stop_points
+            .get_idx(stop_point_id)
+            .ok_or_else(|| anyhow!("no stop point for {scheduled_stop_point_ref}"))?;
+        let order = point
+            .attribute("order")
+            .and_then(|order| order.parse::<usize>().ok())
+            .unwrap_or(position);
+        points.push((
+            order,
+            point.attribute("id"),
+            JourneyPatternPoint {
+                stop_point_idx,
+                for_boarding: text(point, &["ForBoarding"]) != Some("false"),
+                for_alighting: text(point, &["ForAlighting"]) != Some("false"),
+                destination_display_ref: reference(point, "DestinationDisplayRef"),
+            },
+        ));
+    }
+    points.sort_by_key(|(order, _, _)| *order);
+    Ok(points
+        .into_iter()
+        .map(|(_, id, point)| (id, point))
+        .collect())
+}
+
+/// Time of a passing time with its day offset
+fn passing_time(passing_time: Node, time: &str, day_offset: &str) -> Result<Option<Time>> {
+    let time = match text(passing_time, &[time]) {
+        Some(time) => time,
+        None => return Ok(None),
+    };
+    let mut time: Time = time
+        .parse()
+        .with_context(|| format!("invalid time {time:?}"))?;
+    if let Some(day_offset) = text(passing_time, &[day_offset]) {
+        let day_offset: u32 = day_offset
+            .parse()
+            .with_context(|| format!("invalid day offset {day_offset:?}"))?;
+        time = time + Time::new(24 * day_offset, 0, 0);
+    }
+    Ok(Some(time))
+}
+
+/// Stop times of the `TimetabledPassingTime` of a service journey, at the
+/// points of its journey pattern they reference (or in the same order)
+fn stop_times(
+    service_journey: Node,
+    points: &[(Option<&str>, JourneyPatternPoint)],
+) -> Result<Vec<StopTime>> {
+    let positions: HashMap<&str, usize> = points
+        .iter()
+        .enumerate()
+        .filter_map(|(position, (id, _))| Some(((*id)?, position)))
+        .collect();
+    let mut times = Vec::new();
+    for (index, timetabled_passing_time) in child(service_journey, "passingTimes")
+        .into_iter()
+        .flat_map(|passing_times| children(passing_times, "TimetabledPassingTime"))
+        .enumerate()
+    {
+        let position = match reference(timetabled_passing_time, "StopPointInJourneyPatternRef")
+            .or_else(|| reference(timetabled_passing_time, "PointInJourneyPatternRef"))
+        {
+            Some(point_ref) => *positions
+                .get(point_ref)
+                .ok_or_else(|| anyhow!("unknown point in journey pattern {point_ref}"))?,
+            None => index,
+        };
+        let arrival_time =
+            passing_time(timetabled_passing_time, "ArrivalTime", "ArrivalDayOffset")?;
+        let departure_time = passing_time(
+            timetabled_passing_time,
+            "DepartureTime",
+            "DepartureDayOffset",
+        )?;
+        let (arrival_time, departure_time) = match (arrival_time, departure_time) {
+            (Some(arrival_time), Some(departure_time)) => (arrival_time, departure_time),
+            (Some(time), None) | (None, Some(time)) => (time, time),
+            (None, None) => bail!("no time at position {}", position + 1),
+        };
+        times.push((position, arrival_time, departure_time));
+    }
+    times.sort_by_key(|&(position, _, _)| position);
+    if times.len() < 2 {
+        bail!("at least 2 passing times are required");
+    }
+
+    let mut stop_times = Vec::with_capacity(times.len());
+    for (sequence, (position, arrival_time, departure_time)) in times.into_iter().enumerate() {
+        let (_, point) = points
+            .get(position)
+            .ok_or_else(|| anyhow!("no point in journey pattern at position {}", position + 1))?;
+        stop_times.push(StopTime {
+            stop_point_idx: point.stop_point_idx,
+            sequence: sequence as u32,
+            arrival_time: Some(arrival_time),
+            departure_time: Some(departure_time),
+            start_pickup_drop_off_window: None,
+            end_pickup_drop_off_window: None,
+            boarding_duration: 0,
+            alighting_duration: 0,
+            pickup_type: if point.for_boarding { 0 } else { 1 },
+            drop_off_type: if point.for_alighting { 0 } else { 1 },
+            local_zone_id: None,
+            precision: None,
+            shape_dist_traveled: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
+        });
+    }
+    Ok(stop_times)
+}
+
+/// Route of a journey pattern, or a route of the line if the journey
+/// pattern has no known route
+fn route_id(
+    collections: &mut Collections,
+    journey_pattern: Node,
+    line_id: Option<&str>,
+) -> Result<String> {
+    if let Some(route_ref) = reference(journey_pattern, "RouteRef") {
+        if collections.routes.contains_id(route_ref) {
+            return Ok(route_ref.to_string());
+        }
+    }
+    let line_id = line_id.ok_or_else(|| anyhow!("no route and no line"))?;
+    let line = collections
+        .lines
+        .get(line_id)
+        .ok_or_else(|| anyhow!("unknown line {line_id}"))?;
+    let route_id = format!("{line_id}:route");
+    if !collections.routes.contains_id(&route_id) {
+        let route = Route {
+            id: route_id.clone(),
+            name: line.name.clone(),
+            line_id: line_id.to_string(),
+            ..Default::default()
+        };
+        collections.routes.push(route)?;
+    }
+    Ok(route_id)
+}
+
+/// Company of an operator, or the default company if needed
+fn company_id(collections: &mut Collections, operator_ref: Option<&str>) -> Result<String> {
+    if let Some(operator_ref) = operator_ref {
+        if collections.companies.contains_id(operator_ref) {
+            return Ok(operator_ref.to_string());
+        }
+    }
+    let default_company = Company::default();
+    if !collections.companies.contains_id(&default_company.id) {
+        collections.companies.push(default_company.clone())?;
+    }
+    Ok(default_company.id)
+}
+
+struct Calendars<'a> {
+    day_types: HashMap<&'a str, BTreeSet<Date>>,
+    dated_journeys: HashMap<&'a str, BTreeSet<Date>>,
+}
+
+fn read_service_journey(
+    collections: &mut Collections,
+    index: &Index,
+    id: &str,
+    service_journey: Node,
+    assignments: &HashMap<&str, &str>,
+    calendars: &Calendars,
+    dataset_id: &str,
+) -> Result<()> {
+    let journey_pattern_ref = reference(service_journey, "ServiceJourneyPatternRef")
+        .or_else(|| reference(service_journey, "JourneyPatternRef"))
+        .ok_or_else(|| anyhow!("missing JourneyPatternRef"))?;
+    let journey_pattern = index
+        .get(&JOURNEY_PATTERNS, journey_pattern_ref)
+        .ok_or_else(|| anyhow!("unknown journey pattern {journey_pattern_ref}"))?;
+    let route = reference(journey_pattern, "RouteRef")
+        .and_then(|route_ref| index.get(&["Route"], route_ref));
+    let line_id = line_ref(service_journey).or_else(|| route.and_then(line_ref));
+    let line = line_id.and_then(|line_id| index.get(&LINES, line_id));
+
+    let mut dates = BTreeSet::new();
+    for day_type_ref in child(service_journey, "dayTypes")
+        .into_iter()
+        .flat_map(|day_types| children(day_types, "DayTypeRef"))
+        .filter_map(|day_type_ref| day_type_ref.attribute("ref"))
+    {
+        if let Some(day_type_dates) = calendars.day_types.get(day_type_ref) {
+            dates.extend(day_type_dates);
+        }
+    }
+    if let Some(dated_journey_dates) = calendars.dated_journeys.get(id) {
+        dates.extend(dated_journey_dates);
+    }
+    if dates.is_empty() {
+        warn!("service journey {}: no date of operation", id);
+        return Ok(());
+    }
+
+    let points = journey_pattern_points(collections, journey_pattern, assignments)?;
+    let stop_times = stop_times(service_journey, &points)?;
+    let headsign = points
+        .iter()
+        .find_map(|(_, point)| point.destination_display_ref)
+        .and_then(|destination_display_ref| {
+            index.get(&["DestinationDisplay"], destination_display_ref)
+        })
+        .and_then(|destination_display| text(destination_display, &["FrontText"]));
+
+    let route_id = route_id(collections, journey_pattern, line_id)?;
+    let operator_ref = reference(service_journey, "OperatorRef")
+        .or_else(|| line.and_then(|line| reference(line, "OperatorRef")));
+    let company_id = company_id(collections, operator_ref)?;
+    let mode = mode(
+        text(service_journey, &["TransportMode"])
+            .or_else(|| line.and_then(|line| text(line, &["TransportMode"]))),
+    );
+    push_modes(collections, mode)?;
+
+    collections.calendars.push(Calendar {
+        id: id.to_string(),
+        dates,
+    })?;
+    collections.vehicle_journeys.push(VehicleJourney {
+        id: id.to_string(),
+        route_id,
+        physical_mode_id: mode.to_string(),
+        dataset_id: dataset_id.to_string(),
+        service_id: id.to_string(),
+        headsign: headsign.map(str::to_string),
+        short_name: text(service_journey, &["PublicCode"])
+            .or_else(|| text(service_journey, &["PrivateCode"]))
+            .map(str::to_string),
+        company_id,
+        stop_times,
+        journey_pattern_id: Some(journey_pattern_ref.to_string()),
+        ..Default::default()
+    })?;
+    Ok(())
+}
+
+/// Transfers between the stop points of the `ServiceJourneyInterchange`.
+///
+/// NTFS transfers being between stop points, the service journeys of the
+/// interchanges (and their guarantee) are not kept.
+fn read_interchanges(
+    collections: &mut Collections,
+    index: &Index,
+    assignments: &HashMap<&str, &str>,
+) -> Result<()> {
+    let mut transfers = HashSet::new();
+    for interchange in index.elements("ServiceJourneyInterchange") {
+        let stop_point_id = |point_ref: &str| -> Result<String> {
+            let scheduled_stop_point_ref = reference(interchange, point_ref)
+                .ok_or_else(|| anyhow!("interchange: missing {point_ref}"))?;
+            let stop_point_id = assignments
+                .get(scheduled_stop_point_ref)
+                .copied()
+                .unwrap_or(scheduled_stop_point_ref);
+            if !collections.stop_points.contains_id(stop_point_id) {
+                bail!("interchange: no stop point for {scheduled_stop_point_ref}");
+            }
+            Ok(stop_point_id.to_string())
+        };
+        let from_stop_id = skip_error_and_warn!(stop_point_id("FromPointRef"));
+        let to_stop_id = skip_error_and_warn!(stop_point_id("ToPointRef"));
+        let min_transfer_time =
+            skip_error_and_warn!(optional_duration(interchange, &["MinimumTransferTime"]));
+        if transfers.insert((from_stop_id.clone(), to_stop_id.clone())) {
+            collections.transfers.push(Transfer {
+                from_stop_id,
+                to_stop_id,
+                min_transfer_time,
+                real_min_transfer_time: min_transfer_time,
+                equipment_id: None,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Read the NeTEx documents into the collections.
+///
+/// The objects are looked up in all the frames of all the documents,
+/// whatever their layout (e.g. a `CompositeFrame` in a single file, or the
+/// shared data in a separate file).
+pub(super) fn read_documents(
+    collections: &mut Collections,
+    documents: &[Document],
+    dataset_id: &str,
+) -> Result<()> {
+    let index = Index::new(documents);
+    read_stops(collections, &index)?;
+    let assignments = read_stop_assignments(&index);
+    read_companies(collections, &index)?;
+    read_networks(collections, &index)?;
+    read_lines(collections, &index)?;
+    read_routes(collections, &index)?;
+
+    let calendars = Calendars {
+        day_types: read_day_types(&index),
+        dated_journeys: read_dated_service_journeys(&index),
+    };
+    for (id, service_journey) in index.objects("ServiceJourney") {
+        skip_error_and_warn!(read_service_journey(
+            collections,
+            &index,
+            id,
+            service_journey,
+            &assignments,
+            &calendars,
+            dataset_id,
+        )
+        .with_context(|| format!("service journey {id}")));
+    }
+    read_interchanges(collections, &index, &assignments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn day_type_dates(calendar: &str) -> Vec<String> {
+        let documents = vec![Document::parse(calendar).unwrap()];
+        let index = Index::new(&documents);
+        read_day_types(&index)
+            .remove("DT")
+            .unwrap_or_default()
+            .iter()
+            .map(|date| date.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn operating_period_on_days_of_week() {
+        // The 2023-12-11 is a Monday
+        let calendar = r#"<ServiceCalendarFrame>
+            <dayTypes>
+                <DayType id="DT"><properties><PropertyOfDay>
+                    <DaysOfWeek>Saturday Sunday</DaysOfWeek>
+                </PropertyOfDay></properties></DayType>
+            </dayTypes>
+            <operatingPeriods>
+                <OperatingPeriod id="OP">
+                    <FromDate>2023-12-11T00:00:00</FromDate>
+                    <ToDate>2023-12-24T00:00:00</ToDate>
+                </OperatingPeriod>
+            </operatingPeriods>
+            <dayTypeAssignments>
+                <DayTypeAssignment order="1">
+                    <OperatingPeriodRef ref="OP"/><DayTypeRef ref="DT"/>
+                </DayTypeAssignment>
+                <DayTypeAssignment order="2">
+                    <Date>2023-12-23</Date><DayTypeRef ref="DT"/>
+                    <isAvailable>false</isAvailable>
+                </DayTypeAssignment>
+            </dayTypeAssignments>
+        </ServiceCalendarFrame>"#;
+        assert_eq!(
+            vec!["2023-12-16", "2023-12-17", "2023-12-24"],
+            day_type_dates(calendar)
+        );
+    }
+
+    #[test]
+    fn uic_operating_period_and_operating_days() {
+        let calendar = r#"<ServiceCalendarFrame>
+            <operatingDays>
+                <OperatingDay id="OD"><CalendarDate>2023-12-20</CalendarDate></OperatingDay>
+            </operatingDays>
+            <operatingPeriods>
+                <UicOperatingPeriod id="OP">
+                    <FromDate>2023-12-11T00:00:00</FromDate>
+                    <ToDate>2023-12-15T00:00:00</ToDate>
+                    <ValidDayBits>10011</ValidDayBits>
+                </UicOperatingPeriod>
+            </operatingPeriods>
+            <dayTypeAssignments>
+                <DayTypeAssignment><OperatingPeriodRef ref="OP"/><DayTypeRef ref="DT"/></DayTypeAssignment>
+                <DayTypeAssignment><OperatingDayRef ref="OD"/><DayTypeRef ref="DT"/></DayTypeAssignment>
+            </dayTypeAssignments>
+        </ServiceCalendarFrame>"#;
+        assert_eq!(
+            vec!["2023-12-11", "2023-12-14", "2023-12-15", "2023-12-20"],
+            day_type_dates(calendar)
+        );
+    }
+}
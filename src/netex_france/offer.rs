@@ -19,7 +19,9 @@ use crate::{
         exporter::{Exporter, ObjectType},
         LineExporter, LineModes, NetexMode, StopExporter,
     },
-    objects::{Coord, Line, Route, StopPoint, StopTime, Time, VehicleJourney},
+    objects::{
+        BookingRule, BookingType, Coord, Line, Route, StopPoint, StopTime, Time, VehicleJourney,
+    },
     Model, Result,
 };
 use anyhow::anyhow;
@@ -30,7 +32,8 @@ use tracing::warn;
 use typed_index_collection::Idx;
 
 // A journey pattern is the sequence of stops of a particular trip.
-// Modelization of JourneyPattern by a VehicleJourney is sufficient for now.
+// Modelization of JourneyPattern by a VehicleJourney is sufficient for now:
+// the first Vehicle Journey of each journey pattern represents it.
 type JourneyPattern = VehicleJourney;
 
 pub struct OfferExporter<'a> {
@@ -236,22 +239,20 @@ impl<'a> OfferExporter<'a> {
         vehicle_journey
             .stop_times
             .iter()
-            .map(|stop_time| {
-                self.export_stop_point_in_journey_pattern(&vehicle_journey.id, stop_time)
-            })
+            .map(|stop_time| self.export_stop_point_in_journey_pattern(vehicle_journey, stop_time))
             .collect()
     }
 
     fn export_stop_point_in_journey_pattern(
         &self,
-        vehicle_journey_id: &'a str,
+        vehicle_journey: &'a VehicleJourney,
         stop_time: &'a StopTime,
     ) -> Element {
-        Element::builder(ObjectType::StopPointInJourneyPattern.to_string())
+        let element_builder = Element::builder(ObjectType::StopPointInJourneyPattern.to_string())
             .attr(
                 "id",
                 Self::generate_stop_sequence_id(
-                    vehicle_journey_id,
+                    &vehicle_journey.id,
                     stop_time.sequence,
                     ObjectType::StopPointInJourneyPattern,
                 ),
@@ -259,12 +260,37 @@ impl<'a> OfferExporter<'a> {
             .attr("order", (stop_time.sequence + 1).to_string())
             .attr("version", "any")
             .append(Self::generate_scheduled_stop_point_ref(
-                vehicle_journey_id,
+                &vehicle_journey.id,
                 stop_time.sequence,
             ))
             .append(Self::generate_for_alighting(stop_time.drop_off_type))
-            .append(Self::generate_for_boarding(stop_time.pickup_type))
-            .build()
+            .append(Self::generate_for_boarding(stop_time.pickup_type));
+        // Like in GTFS, the booking rule only applies to the stops where the
+        // pickup or the drop off must be booked
+        let booking_rule = self
+            .booking_rule(vehicle_journey)
+            .filter(|_| stop_time.pickup_type == 2 || stop_time.drop_off_type == 2);
+        let element_builder = if let Some(booking_rule) = booking_rule {
+            element_builder.append(Self::generate_booking_arrangements(booking_rule))
+        } else {
+            element_builder
+        };
+        element_builder.build()
+    }
+
+    // The first booking rule of the vehicle journey, or else of its line
+    fn booking_rule(&self, vehicle_journey: &'a VehicleJourney) -> Option<&'a BookingRule> {
+        let line_booking_rule_links = self
+            .model
+            .routes
+            .get(&vehicle_journey.route_id)
+            .and_then(|route| self.model.lines.get(&route.line_id))
+            .map(|line| &line.booking_rule_links);
+        vehicle_journey
+            .booking_rule_links
+            .iter()
+            .chain(line_booking_rule_links.into_iter().flatten())
+            .find_map(|booking_rule_id| self.model.booking_rules.get(booking_rule_id))
     }
 
     fn export_scheduled_stop_points(
@@ -645,6 +671,59 @@ impl<'a> OfferExporter<'a> {
             .build()
     }
 
+    fn generate_booking_arrangements(booking_rule: &'a BookingRule) -> Element {
+        fn text_element(name: &str, text: String) -> Element {
+            Element::builder(name).append(Node::Text(text)).build()
+        }
+        let mut elements = Vec::new();
+        let contact_elements: Vec<Element> = [
+            ("Phone", booking_rule.phone.as_ref()),
+            ("Url", booking_rule.info_url.as_ref()),
+        ]
+        .iter()
+        .filter_map(|(name, text)| text.map(|text| text_element(name, text.to_owned())))
+        .collect();
+        if !contact_elements.is_empty() {
+            elements.push(
+                Element::builder("BookingContact")
+                    .append_all(contact_elements)
+                    .build(),
+            );
+        }
+        if let Some(booking_type) = booking_rule.booking_type {
+            let book_when = match booking_type {
+                BookingType::RealTime => "timeOfTravelOnly",
+                BookingType::SameDayWithPriorNotice => "advanceAndDayOfTravel",
+                BookingType::UpToPreviousDays => "untilPreviousDay",
+            };
+            elements.push(text_element("BookWhen", book_when.to_owned()));
+        }
+        if let Some(last_time) = booking_rule.prior_notice_last_time {
+            elements.push(text_element("LatestBookingTime", last_time.to_string()));
+        }
+        let minimum_booking_period = match booking_rule.booking_type {
+            Some(BookingType::SameDayWithPriorNotice) => booking_rule
+                .prior_notice_duration_min
+                .map(|minutes| format!("PT{minutes}M")),
+            Some(BookingType::UpToPreviousDays) => booking_rule
+                .prior_notice_last_day
+                .map(|days| format!("P{days}D")),
+            _ => None,
+        };
+        if let Some(minimum_booking_period) = minimum_booking_period {
+            elements.push(text_element("MinimumBookingPeriod", minimum_booking_period));
+        }
+        if let Some(booking_url) = &booking_rule.booking_url {
+            elements.push(text_element("BookingUrl", booking_url.to_owned()));
+        }
+        if let Some(message) = &booking_rule.message {
+            elements.push(text_element("BookingNote", message.to_owned()));
+        }
+        Element::builder("BookingArrangements")
+            .append_all(elements)
+            .build()
+    }
+
     fn generate_arrival_time(arrival_time: Time) -> Element {
         Element::builder("ArrivalTime")
             .append(Node::Text(arrival_time.to_string()))
@@ -687,12 +766,42 @@ impl<'a> OfferExporter<'a> {
         vehicle_journey_indexes.sort_unstable_by_key(|vehicle_journey_idx| {
             &self.model.vehicle_journeys[*vehicle_journey_idx].id
         });
+        // If the journey patterns of the model are known for all the Vehicle
+        // Journeys of the route (see `Collections::compute_journey_patterns`),
+        // they are used as is
+        let journey_pattern_ids: Option<Vec<&str>> = vehicle_journey_indexes
+            .iter()
+            .map(|vehicle_journey_idx| {
+                self.model.vehicle_journeys[*vehicle_journey_idx]
+                    .journey_pattern_id
+                    .as_deref()
+                    .filter(|id| self.model.journey_patterns.contains_id(id))
+            })
+            .collect();
+        if let Some(journey_pattern_ids) = journey_pattern_ids {
+            let mut journey_patterns: Vec<(Idx<JourneyPattern>, Vec<Idx<VehicleJourney>>)> =
+                Vec::new();
+            let mut positions: BTreeMap<&str, usize> = BTreeMap::new();
+            for (vehicle_journey_idx, journey_pattern_id) in
+                vehicle_journey_indexes.into_iter().zip(journey_pattern_ids)
+            {
+                match positions.get(journey_pattern_id) {
+                    Some(position) => journey_patterns[*position].1.push(vehicle_journey_idx),
+                    None => {
+                        positions.insert(journey_pattern_id, journey_patterns.len());
+                        journey_patterns.push((vehicle_journey_idx, vec![vehicle_journey_idx]));
+                    }
+                }
+            }
+            return journey_patterns;
+        }
         let mut journey_patterns: Vec<(Idx<JourneyPattern>, Vec<Idx<VehicleJourney>>)> = Vec::new();
         for vehicle_journey_idx in vehicle_journey_indexes {
             let vehicle_journey = &self.model.vehicle_journeys[vehicle_journey_idx];
             let is_same_journey_pattern = |journey_pattern_idx: Idx<VehicleJourney>| {
                 let journey_pattern_vj = &self.model.vehicle_journeys[journey_pattern_idx];
                 vehicle_journey.stop_times.len() == journey_pattern_vj.stop_times.len()
+                    && vehicle_journey.booking_rule_links == journey_pattern_vj.booking_rule_links
                     && vehicle_journey
                         .stop_times
                         .iter()
@@ -820,6 +929,9 @@ mod tests {
                     drop_off_type: 0,
                     local_zone_id: Some(1),
                     precision: Some(StopTimePrecision::Exact),
+                    shape_dist_traveled: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
                 },
                 StopTime {
                     stop_point_idx: collections.stop_points.get_idx("sp_id_2").unwrap(),
@@ -834,6 +946,9 @@ mod tests {
                     drop_off_type: 1,
                     local_zone_id: Some(1),
                     precision: Some(StopTimePrecision::Exact),
+                    shape_dist_traveled: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
                 },
             ],
             ..Default::default()
@@ -867,6 +982,9 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: Some(1),
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp_id_2").unwrap(),
@@ -881,6 +999,9 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: Some(1),
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                 ],
                 ..Default::default()
@@ -925,6 +1046,9 @@ mod tests {
                     drop_off_type: 0,
                     local_zone_id: Some(1),
                     precision: Some(StopTimePrecision::Exact),
+                    shape_dist_traveled: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
                 }],
                 ..Default::default()
             })
@@ -975,6 +1099,9 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: Some(1),
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp_id_2").unwrap(),
@@ -989,6 +1116,9 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: Some(1),
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                 ],
                 ..Default::default()
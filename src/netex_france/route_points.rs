@@ -120,6 +120,9 @@ mod tests {
                 drop_off_type: 0,
                 local_zone_id: None,
                 precision: None,
+                shape_dist_traveled: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
             }
         }
 
@@ -20,17 +20,20 @@ mod write;
 
 use crate::{
     calendars::{manage_calendars, write_calendar_dates},
-    file_handler::{FileHandler, PathFileHandler, ZipHandler},
+    error::DataError,
+    file_handler::{FileHandler, LenientFileHandler, PathFileHandler, ZipHandler},
     model::{Collections, Model},
     objects::*,
+    report::{ReadReportCategory, Report},
     serde_utils::*,
     utils::*,
     Result,
 };
-use anyhow::{anyhow, Context};
+use anyhow::Context;
 use chrono::{DateTime, FixedOffset};
 use chrono_tz::Tz;
 use derivative::Derivative;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::path;
 use tempfile::tempdir;
@@ -60,6 +63,32 @@ struct StopTime {
     stop_time_id: Option<String>,
     #[serde(rename = "stop_time_precision")]
     precision: Option<StopTimePrecision>,
+    // The column is only written if any stop time has a distance
+    #[serde(
+        default,
+        deserialize_with = "de_shape_dist_traveled",
+        skip_serializing_if = "Option::is_none"
+    )]
+    shape_dist_traveled: Option<Option<Decimal>>,
+    #[serde(
+        default,
+        deserialize_with = "de_with_invalid_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    continuous_pickup: Option<ContinuousStopping>,
+    #[serde(
+        default,
+        deserialize_with = "de_with_invalid_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    continuous_drop_off: Option<ContinuousStopping>,
+}
+
+fn de_shape_dist_traveled<'de, D>(deserializer: D) -> Result<Option<Option<Decimal>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    de_option_positive_decimal(deserializer).map(Some)
 }
 
 #[derive(Derivative, Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -253,10 +282,53 @@ pub fn read<P: AsRef<path::Path>>(path: P) -> Result<Model> {
     } else if p.is_dir() {
         Ok(from_dir(p).with_context(|| format!("impossible to read ntfs directory from {p:?}"))?)
     } else {
-        Err(anyhow!(
-            "file {:?} is neither a file nor a directory, cannot read a ntfs from it",
-            p
-        ))
+        Err(DataError::InvalidInput {
+            path: p.to_path_buf(),
+            format: "ntfs",
+        }
+        .into())
+    }
+}
+
+/// Imports a `Model` from the
+/// [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md)
+/// like [read], but the invalid objects are skipped instead of failing. All
+/// the problems found in the data are recorded in the returned report.
+///
+/// ```
+/// # fn run() -> transit_model::Result<()> {
+/// let (model, report) = transit_model::ntfs::read_with_report("tests/fixtures/minimal_ntfs/")?;
+/// println!("{}", serde_json::to_string_pretty(&report)?);
+/// # Ok(())
+/// # }
+/// # run().unwrap()
+/// ```
+pub fn read_with_report<P: AsRef<path::Path>>(
+    path: P,
+) -> Result<(Model, Report<ReadReportCategory>)> {
+    let p = path.as_ref();
+    if p.is_file() {
+        // if it's a file, we consider it to be a zip (and an error will be returned if it is not)
+        let reader = std::fs::File::open(p)?;
+        Ok(ZipHandler::new(reader, p)
+            .and_then(|file_handler| {
+                read_leniently(file_handler, |file_handler| {
+                    read_collections_file_handler(file_handler)
+                })
+            })
+            .with_context(|| format!("impossible to read zipped ntfs {p:?}"))?)
+    } else if p.is_dir() {
+        let file_handler = PathFileHandler::new(p.to_path_buf());
+        Ok(read_leniently(file_handler, |file_handler| {
+            read_collections_file_handler(file_handler)
+        })
+        .with_context(|| format!("impossible to read ntfs directory from {p:?}"))?)
+    } else {
+        Err(DataError::InvalidInput {
+            path: p.to_path_buf(),
+            format: "ntfs",
+        }
+        .into())
     }
 }
 
@@ -276,10 +348,47 @@ pub fn read_collections<P: AsRef<path::Path>>(path: P) -> Result<Collections> {
         Ok(collections_from_dir(p)
             .with_context(|| format!("impossible to read ntfs directory from {p:?}"))?)
     } else {
-        Err(anyhow!(
-            "file {:?} is neither a file nor a directory, cannot read a ntfs from it",
-            p
-        ))
+        Err(DataError::InvalidInput {
+            path: p.to_path_buf(),
+            format: "ntfs",
+        }
+        .into())
+    }
+}
+
+/// Imports `Collections` from the
+/// [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md)
+/// like [read_collections], but the invalid objects of the files are skipped
+/// instead of failing, and returned with the collections. The references
+/// between the objects are not checked (see
+/// [`Collections::remove_invalid_objects`]).
+pub fn read_collections_with_errors<P: AsRef<path::Path>>(
+    path: P,
+) -> Result<(Collections, Vec<DataError>)> {
+    fn read_leniently<H>(file_handler: H) -> Result<(Collections, Vec<DataError>)>
+    where
+        for<'a> &'a mut H: FileHandler,
+    {
+        let mut file_handler = LenientFileHandler::new(file_handler);
+        let collections = read_collections_file_handler(&mut file_handler)?;
+        Ok((collections, file_handler.into_errors()))
+    }
+    let p = path.as_ref();
+    if p.is_file() {
+        // if it's a file, we consider it to be a zip (and an error will be returned if it is not)
+        let reader = std::fs::File::open(p)?;
+        Ok(ZipHandler::new(reader, p)
+            .and_then(read_leniently)
+            .with_context(|| format!("impossible to read zipped ntfs {p:?}"))?)
+    } else if p.is_dir() {
+        Ok(read_leniently(PathFileHandler::new(p.to_path_buf()))
+            .with_context(|| format!("impossible to read ntfs directory from {p:?}"))?)
+    } else {
+        Err(DataError::InvalidInput {
+            path: p.to_path_buf(),
+            format: "ntfs",
+        }
+        .into())
     }
 }
 
@@ -307,6 +416,7 @@ where
         lines: make_collection_with_id(file_handler, "lines.txt")?,
         routes: make_collection_with_id(file_handler, "routes.txt")?,
         vehicle_journeys: make_collection_with_id(file_handler, "trips.txt")?,
+        journey_patterns: make_opt_collection_with_id(file_handler, "journey_patterns.txt")?,
         frequencies: make_opt_collection(file_handler, "frequencies.txt")?,
         physical_modes: make_collection_with_id(file_handler, "physical_modes.txt")?,
         companies: make_collection_with_id(file_handler, "companies.txt")?,
@@ -369,7 +479,7 @@ pub fn write<P: AsRef<path::Path>>(
     write_collection_with_id(path, "lines.txt", &collections.lines)?;
     write_collection_with_id(path, "physical_modes.txt", &collections.physical_modes)?;
     write_collection_with_id(path, "equipments.txt", &collections.equipments)?;
-    write_collection_with_id(path, "routes.txt", &collections.routes)?;
+    write::write_routes(path, &collections.routes)?;
     write_collection_with_id(path, "trip_properties.txt", &collections.trip_properties)?;
     write_collection_with_id(path, "geometries.txt", &collections.geometries)?;
     write_collection(path, "transfers.txt", &collections.transfers)?;
@@ -402,10 +512,12 @@ pub fn write<P: AsRef<path::Path>>(
     write::write_vehicle_journeys_and_stop_times(
         path,
         &collections.vehicle_journeys,
+        &collections.routes,
         &collections.stop_points,
         &collections.stop_time_headsigns,
         &collections.stop_time_ids,
     )?;
+    write_collection_with_id(path, "journey_patterns.txt", &collections.journey_patterns)?;
     write_collection(path, "frequencies.txt", &collections.frequencies)?;
     write_calendar_dates(path, &collections.calendars)?;
     write::write_stops(
@@ -703,6 +815,8 @@ mod tests {
                 line_id: "OIF:002002002:BDEOIF829".to_string(),
                 geometry_id: Some("Geometry:Line:Relation:6883353".to_string()),
                 destination_id: Some("OIF,OIF:SA:4:126".to_string()),
+                continuous_pickup: None,
+                continuous_drop_off: None,
             },
             Route {
                 id: "OIF:002002002:CEN".to_string(),
@@ -714,6 +828,8 @@ mod tests {
                 line_id: "OIF:002002002:BDEOIF829".to_string(),
                 geometry_id: None,
                 destination_id: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
             },
         ]);
     }
@@ -781,6 +897,9 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: Some(Decimal::from(0)),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                     objects::StopTime {
                         stop_point_idx: stop_points.get_idx("OIF:SP:36:2127").unwrap(),
@@ -795,6 +914,9 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: Some(Decimal::new(1695, 1)),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                 ],
                 journey_pattern_id: Some(String::from("OIF:JP:1")),
@@ -836,6 +958,7 @@ mod tests {
             write::write_vehicle_journeys_and_stop_times(
                 path,
                 &vehicle_journeys,
+                &CollectionWithId::default(),
                 &stop_points,
                 &headsigns,
                 &stop_time_ids,
@@ -856,6 +979,20 @@ mod tests {
         });
     }
 
+    #[test]
+    fn journey_patterns_serialization_deserialization() {
+        test_serialize_deserialize_collection_with_id(vec![
+            JourneyPattern {
+                id: "OIF:JP:1".to_string(),
+                route_id: "OIF:R1".to_string(),
+            },
+            JourneyPattern {
+                id: "OIF:JP:2".to_string(),
+                route_id: "OIF:R1".to_string(),
+            },
+        ]);
+    }
+
     #[test]
     fn contributors_serialization_deserialization() {
         test_serialize_deserialize_collection_with_id(vec![
@@ -1116,6 +1253,7 @@ mod tests {
                 phone: Some("01 02 03 04 01".to_string()),
                 message: Some("lundi au vendredi de 9h à 18h".to_string()),
                 booking_url: Some("https://deeplink1".to_string()),
+                ..Default::default()
             },
             BookingRule {
                 id: "odt:2".to_string(),
@@ -1124,6 +1262,11 @@ mod tests {
                 phone: Some("01 02 03 04 02".to_string()),
                 message: Some("lundi au samedi de 8h à 15h".to_string()),
                 booking_url: Some("https://deeplink2".to_string()),
+                booking_type: Some(BookingType::UpToPreviousDays),
+                prior_notice_last_day: Some(1),
+                prior_notice_last_time: Some(Time::new(17, 0, 0)),
+                prior_notice_service_id: Some("service_1".to_string()),
+                ..Default::default()
             },
             BookingRule {
                 id: "odt:3".to_string(),
@@ -1132,6 +1275,10 @@ mod tests {
                 phone: Some("01 02 03 04 03".to_string()),
                 message: Some("lundi au mardi de 9h à 10h".to_string()),
                 booking_url: Some("https://deeplink3".to_string()),
+                booking_type: Some(BookingType::SameDayWithPriorNotice),
+                prior_notice_duration_min: Some(60),
+                prior_notice_duration_max: Some(1440),
+                ..Default::default()
             },
         ])
         .unwrap();
@@ -1226,6 +1373,8 @@ mod tests {
             line_id: "OIF:002002002:BDEOIF829".to_string(),
             geometry_id: None,
             destination_id: None,
+            continuous_pickup: Some(ContinuousStopping::Continuous),
+            continuous_drop_off: Some(ContinuousStopping::NotContinuous),
         });
 
         let vehicle_journeys = CollectionWithId::from(VehicleJourney {
@@ -1263,6 +1412,9 @@ mod tests {
                 drop_off_type: 2,
                 local_zone_id: None,
                 precision: None,
+                shape_dist_traveled: None,
+                continuous_pickup: Some(ContinuousStopping::Continuous),
+                continuous_drop_off: Some(ContinuousStopping::PhoneAgency),
             }],
             journey_pattern_id: None,
         });
@@ -1306,11 +1458,12 @@ mod tests {
                 &ser_collections.stop_locations,
             )
             .unwrap();
-            write_collection_with_id(path, "routes.txt", &ser_collections.routes).unwrap();
+            write::write_routes(path, &ser_collections.routes).unwrap();
             write_collection_with_id(path, "networks.txt", &ser_collections.networks).unwrap();
             write::write_vehicle_journeys_and_stop_times(
                 path,
                 &ser_collections.vehicle_journeys,
+                &ser_collections.routes,
                 &ser_collections.stop_points,
                 &ser_collections.stop_time_headsigns,
                 &ser_collections.stop_time_ids,
@@ -13,21 +13,29 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
 use super::{BookingRuleLink, Code, CommentLink, ObjectProperty, Stop, StopLocationType, StopTime};
+use crate::error::{object_type, DataError};
 use crate::file_handler::FileHandler;
 use crate::model::Collections;
 use crate::ntfs::has_fares_v2;
 use crate::objects::*;
-use crate::parser::{read_objects, read_objects_loose};
-use crate::utils::make_opt_collection_with_id;
+use crate::parser::{
+    read_objects, read_objects_loose, read_objects_loose_with_lines, read_objects_with_lines,
+};
+use crate::utils::{make_opt_collection_with_id, push_object};
 use crate::Result;
-use anyhow::{anyhow, bail, ensure, Context};
+use anyhow::{bail, ensure, Context};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use skip_error::skip_error_and_warn;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::convert::TryFrom;
+use std::path::PathBuf;
 use tracing::{error, info, warn};
 use typed_index_collection::{Collection, CollectionWithId, Id, Idx};
 
+const COMMENT_LINKS: &str = "comment_links.txt";
+const BOOKING_RULE_LINKS: &str = "booking_rule_links.txt";
+
 impl TryFrom<Stop> for StopArea {
     type Error = anyhow::Error;
     fn try_from(stop: Stop) -> Result<Self> {
@@ -159,28 +167,56 @@ pub(crate) fn manage_stops<H>(collections: &mut Collections, file_handler: &mut
 where
     for<'a> &'a mut H: FileHandler,
 {
-    let stops = read_objects::<_, Stop>(file_handler, "stops.txt", true)?;
+    let file = "stops.txt";
+    let stops = read_objects_with_lines::<_, Stop>(file_handler, file, true)?;
     let mut stop_areas: CollectionWithId<StopArea> = CollectionWithId::default();
     let mut stop_points: CollectionWithId<StopPoint> = CollectionWithId::default();
     let mut stop_locations: CollectionWithId<StopLocation> = CollectionWithId::default();
-    for stop in stops {
+    for (line, stop) in stops {
+        let invalid_stop = |error: anyhow::Error| DataError::InvalidRecord {
+            file: PathBuf::from(file),
+            line,
+            column: None,
+            message: error.to_string(),
+        };
         match stop.location_type {
             StopLocationType::StopPoint | StopLocationType::GeographicArea => {
-                let mut stop_point = skip_error_and_warn!(StopPoint::try_from(stop.clone()));
+                let mut stop_point = skip_error_and_report!(
+                    file_handler,
+                    StopPoint::try_from(stop.clone()).map_err(invalid_stop)
+                );
                 if stop.parent_station.is_none() {
                     let mut stop_area = StopArea::from(stop_point.clone());
                     stop_point.stop_area_id.clone_from(&stop_area.id);
                     stop_area.visible = stop.location_type == StopLocationType::StopPoint;
-                    skip_error_and_warn!(stop_areas.push(stop_area));
+                    skip_error_and_report!(
+                        file_handler,
+                        push_object(&mut stop_areas, stop_area, file)
+                    );
                 };
-                skip_error_and_warn!(stop_points.push(stop_point));
-            }
-            StopLocationType::StopArea => {
-                skip_error_and_warn!(stop_areas.push(StopArea::try_from(stop)?));
-            }
-            _ => {
-                skip_error_and_warn!(stop_locations.push(StopLocation::try_from(stop)?));
+                skip_error_and_report!(
+                    file_handler,
+                    push_object(&mut stop_points, stop_point, file)
+                );
             }
+            StopLocationType::StopArea => match StopArea::try_from(stop) {
+                Ok(stop_area) => {
+                    skip_error_and_report!(
+                        file_handler,
+                        push_object(&mut stop_areas, stop_area, file)
+                    );
+                }
+                Err(error) => (&mut *file_handler).on_invalid_object(invalid_stop(error))?,
+            },
+            _ => match StopLocation::try_from(stop) {
+                Ok(stop_location) => {
+                    skip_error_and_report!(
+                        file_handler,
+                        push_object(&mut stop_locations, stop_location, file)
+                    );
+                }
+                Err(error) => (&mut *file_handler).on_invalid_object(invalid_stop(error))?,
+            },
         }
     }
     collections.stop_areas = stop_areas;
@@ -250,10 +286,310 @@ where
     }
     collections.od_fares_v1 = read_fares_v1(file_handler, file_od_fares, true)?;
     collections.fares_v1 = read_fares_v1(file_handler, file_fares, true)?;
+    convert_fares_v1_to_v2(collections);
 
     Ok(())
 }
 
+/// Constraints of a fares v1 ticket, converted into a fares v2 `TicketUse`
+#[derive(Default)]
+struct TicketUseV1 {
+    included_networks: BTreeSet<String>,
+    included_lines: BTreeSet<String>,
+    excluded_lines: BTreeSet<String>,
+    max_transfers: Option<u32>,
+    boarding_time_limit: Option<u32>,
+    alighting_time_limit: Option<u32>,
+    restrictions: Vec<(RestrictionType, String, String)>,
+}
+
+impl TicketUseV1 {
+    // A state of fares.csv is the object the traveler is in, for example
+    // `line=line:L1`
+    fn add_state(&mut self, state: &str, lines_by_mode: &HashMap<&str, BTreeSet<String>>) {
+        if let Some(network_id) = state.strip_prefix("network=network:") {
+            self.included_networks.insert(network_id.to_string());
+        } else if let Some(line_id) = state.strip_prefix("line=line:") {
+            self.included_lines.insert(line_id.to_string());
+        } else if let Some(mode_id) = state.strip_prefix("mode=physical_mode:") {
+            self.included_lines
+                .extend(lines_by_mode.get(mode_id).into_iter().flatten().cloned());
+        } else {
+            warn!("fares v1: unsupported state {:?} is ignored", state);
+        }
+    }
+}
+
+/// Parses a fares v1 condition like `nb_changes<2`, returning `2`
+fn condition_value(condition: &str, prefix: &str) -> Option<u32> {
+    let value = condition.strip_prefix(prefix)?;
+    match value.parse::<u32>() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            warn!("fares v1: invalid condition {:?} is ignored", condition);
+            None
+        }
+    }
+}
+
+/// Time limit in seconds of a `duration<N` condition, `N` being in minutes
+fn duration_limit(condition: &str) -> Option<u32> {
+    condition_value(condition, "duration<")
+        .map(|minutes| minutes.saturating_mul(60).saturating_sub(1))
+}
+
+/// Currency and number of decimal digits of the prices of `prices.csv`
+/// (prices are integers in the minor unit of the currency)
+fn currency_v1(currency_type: Option<&str>) -> Option<(&'static str, u32)> {
+    match currency_type {
+        None | Some("") | Some("centime") => Some(("EUR", 2)),
+        Some(code) => iso4217::alpha3(&code.to_uppercase())
+            .map(|currency| (currency.alpha3, u32::try_from(currency.exp).unwrap_or(0))),
+    }
+}
+
+/// Converts the fares v1 (`prices.csv`, `fares.csv` and `od_fares.csv`)
+/// into fares v2 objects. See `documentation/deprecated_ntfs_fares_reading.md`.
+fn convert_fares_v1_to_v2(collections: &mut Collections) {
+    let mut lines_by_mode: HashMap<&str, BTreeSet<String>> = HashMap::new();
+    for vj in collections.vehicle_journeys.values() {
+        if let Some(route) = collections.routes.get(&vj.route_id) {
+            lines_by_mode
+                .entry(vj.physical_mode_id.as_str())
+                .or_default()
+                .insert(route.line_id.clone());
+        }
+    }
+
+    let mut ticket_uses: BTreeMap<String, TicketUseV1> = BTreeMap::new();
+    let mut transfer_allowed = HashSet::new();
+    let mut od_states = Vec::new();
+    for fare in collections.fares_v1.values() {
+        if fare.ticket_id.is_empty() {
+            if fare.before_change == "*" && fare.global_condition == "with_changes" {
+                // states where the origin-destination tickets can be used
+                od_states.push(fare.after_change.as_str());
+            } else {
+                // transfer with a ticket already bought
+                transfer_allowed.extend(
+                    fare.start_trip
+                        .split('&')
+                        .filter_map(|condition| condition.strip_prefix("ticket=")),
+                );
+            }
+            continue;
+        }
+        if fare.before_change != "*" {
+            warn!(
+                "fares v1: transition from {:?} for ticket {} is ignored",
+                fare.before_change, fare.ticket_id
+            );
+            continue;
+        }
+        let ticket_use = ticket_uses.entry(fare.ticket_id.clone()).or_default();
+        ticket_use.add_state(&fare.after_change, &lines_by_mode);
+        let mut origin = None;
+        for condition in fare.start_trip.split('&').filter(|c| !c.is_empty()) {
+            if let Some(line_id) = condition.strip_prefix("line!=line:") {
+                ticket_use.excluded_lines.insert(line_id.to_string());
+            } else if condition.starts_with("nb_changes<") {
+                ticket_use.max_transfers =
+                    condition_value(condition, "nb_changes<").map(|value| value.saturating_sub(1));
+            } else if condition.starts_with("duration<") {
+                ticket_use.boarding_time_limit = duration_limit(condition);
+            } else if let Some(zone) = condition.strip_prefix("zone=") {
+                origin = Some((RestrictionType::Zone, zone));
+            } else if let Some(stop_area_id) = condition.strip_prefix("stoparea=stop_area:") {
+                origin = Some((RestrictionType::OriginDestination, stop_area_id));
+            } else {
+                warn!(
+                    "fares v1: condition {:?} for ticket {} is ignored",
+                    condition, fare.ticket_id
+                );
+            }
+        }
+        let mut destination = None;
+        for condition in fare.end_trip.split('&').filter(|c| !c.is_empty()) {
+            if condition.starts_with("duration<") {
+                ticket_use.alighting_time_limit = duration_limit(condition);
+            } else if let Some(zone) = condition.strip_prefix("zone=") {
+                destination = Some((RestrictionType::Zone, zone));
+            } else if let Some(stop_area_id) = condition.strip_prefix("stoparea=stop_area:") {
+                destination = Some((RestrictionType::OriginDestination, stop_area_id));
+            } else {
+                warn!(
+                    "fares v1: condition {:?} for ticket {} is ignored",
+                    condition, fare.ticket_id
+                );
+            }
+        }
+        match (origin, destination) {
+            (Some((origin_type, origin)), Some((destination_type, destination)))
+                if origin_type == destination_type =>
+            {
+                ticket_use.restrictions.push((
+                    origin_type,
+                    origin.to_string(),
+                    destination.to_string(),
+                ));
+            }
+            (None, None) => {}
+            _ => warn!(
+                "fares v1: incomplete origin and destination conditions for ticket {} are ignored",
+                fare.ticket_id
+            ),
+        }
+    }
+    for (ticket_id, ticket_use) in &mut ticket_uses {
+        if ticket_use.max_transfers.is_none() && !transfer_allowed.contains(ticket_id.as_str()) {
+            ticket_use.max_transfers = Some(0);
+        }
+    }
+
+    for od_fare in collections.od_fares_v1.values() {
+        let restriction = match (
+            od_fare.origin_mode.as_str(),
+            od_fare.destination_mode.as_str(),
+        ) {
+            ("stop", "stop") => {
+                let strip = |id: &str| id.strip_prefix("stop_area:").unwrap_or(id).to_string();
+                (
+                    RestrictionType::OriginDestination,
+                    strip(&od_fare.origin_stop_area_id),
+                    strip(&od_fare.destination_stop_area_id),
+                )
+            }
+            ("zone", "zone") => (
+                RestrictionType::Zone,
+                od_fare.origin_stop_area_id.clone(),
+                od_fare.destination_stop_area_id.clone(),
+            ),
+            (origin_mode, destination_mode) => {
+                warn!(
+                    "fares v1: origin-destination fare from {:?} to {:?} for ticket {} is ignored",
+                    origin_mode, destination_mode, od_fare.ticket_id
+                );
+                continue;
+            }
+        };
+        let ticket_use = ticket_uses
+            .entry(od_fare.ticket_id.clone())
+            .or_insert_with(|| {
+                let mut ticket_use = TicketUseV1::default();
+                for state in &od_states {
+                    ticket_use.add_state(state, &lines_by_mode);
+                }
+                if od_states.is_empty() {
+                    ticket_use.included_networks = collections
+                        .networks
+                        .values()
+                        .map(|n| n.id.clone())
+                        .collect();
+                }
+                ticket_use
+            });
+        ticket_use.restrictions.push(restriction);
+    }
+
+    for (ticket_id, ticket_use) in ticket_uses {
+        let mut ticket = None;
+        let mut prices = Vec::new();
+        for price_v1 in collections
+            .prices_v1
+            .values()
+            .filter(|price| price.id == ticket_id)
+        {
+            let (currency, exponent) = match currency_v1(price_v1.currency_type.as_deref()) {
+                Some(currency) => currency,
+                None => {
+                    warn!(
+                        "fares v1: price of ticket {} with unknown currency {:?} is ignored",
+                        ticket_id, price_v1.currency_type
+                    );
+                    continue;
+                }
+            };
+            if ticket.is_none() {
+                ticket = Some(Ticket {
+                    id: ticket_id.clone(),
+                    name: price_v1.name.clone(),
+                    comment: Some(price_v1.comment.clone()).filter(|c| !c.is_empty()),
+                });
+            }
+            prices.push(TicketPrice {
+                ticket_id: ticket_id.clone(),
+                price: Decimal::new(i64::from(price_v1.price), exponent),
+                currency: currency.to_string(),
+                ticket_validity_start: price_v1.start_date,
+                // the end date is excluded in fares v1 and included in fares v2
+                ticket_validity_end: price_v1.end_date.pred_opt().unwrap_or(price_v1.end_date),
+            });
+        }
+        let ticket = match ticket {
+            Some(ticket) => ticket,
+            None => {
+                warn!(
+                    "fares v1: ticket {} without valid price is ignored",
+                    ticket_id
+                );
+                continue;
+            }
+        };
+        if ticket_use.included_networks.is_empty() && ticket_use.included_lines.is_empty() {
+            warn!(
+                "fares v1: ticket {} without any line or network is ignored",
+                ticket_id
+            );
+            continue;
+        }
+        let perimeters = ticket_use
+            .included_networks
+            .into_iter()
+            .map(|id| (ObjectType::Network, id, PerimeterAction::Included))
+            .chain(
+                ticket_use
+                    .included_lines
+                    .into_iter()
+                    .map(|id| (ObjectType::Line, id, PerimeterAction::Included)),
+            )
+            .chain(
+                ticket_use
+                    .excluded_lines
+                    .into_iter()
+                    .map(|id| (ObjectType::Line, id, PerimeterAction::Excluded)),
+            );
+        for (object_type, object_id, perimeter_action) in perimeters {
+            collections.ticket_use_perimeters.push(TicketUsePerimeter {
+                ticket_use_id: ticket_id.clone(),
+                object_type,
+                object_id,
+                perimeter_action,
+            });
+        }
+        for (restriction_type, use_origin, use_destination) in ticket_use.restrictions {
+            collections
+                .ticket_use_restrictions
+                .push(TicketUseRestriction {
+                    ticket_use_id: ticket_id.clone(),
+                    restriction_type,
+                    use_origin,
+                    use_destination,
+                });
+        }
+        for price in prices {
+            collections.ticket_prices.push(price);
+        }
+        skip_error_and_warn!(collections.ticket_uses.push(TicketUse {
+            id: ticket_id.clone(),
+            ticket_id: ticket_id.clone(),
+            max_transfers: ticket_use.max_transfers,
+            boarding_time_limit: ticket_use.boarding_time_limit,
+            alighting_time_limit: ticket_use.alighting_time_limit,
+        }));
+        skip_error_and_warn!(collections.tickets.push(ticket));
+    }
+}
+
 pub(crate) fn manage_stop_times<H>(
     collections: &mut Collections,
     file_handler: &mut H,
@@ -261,30 +597,37 @@ pub(crate) fn manage_stop_times<H>(
 where
     for<'a> &'a mut H: FileHandler,
 {
-    let stop_times = read_objects::<_, StopTime>(file_handler, "stop_times.txt", true)?;
+    let file = "stop_times.txt";
+    let stop_times = read_objects_with_lines::<_, StopTime>(file_handler, file, true)?;
     let mut headsigns = HashMap::new();
     let mut stop_time_ids = HashMap::new();
-    for stop_time in stop_times {
-        let stop_point_idx = collections
-            .stop_points
-            .get_idx(&stop_time.stop_id)
-            .ok_or_else(|| {
-                anyhow!(
-                    "Problem reading {:?}: stop_id={:?} not found",
-                    file_handler.source_name(),
-                    stop_time.stop_id
-                )
-            })?;
-        let vj_idx = collections
-            .vehicle_journeys
-            .get_idx(&stop_time.trip_id)
-            .ok_or_else(|| {
-                anyhow!(
-                    "Problem reading {:?}: trip_id={:?} not found",
-                    file_handler.source_name(),
-                    stop_time.trip_id
-                )
-            })?;
+    for (line, stop_time) in stop_times {
+        let invalid_reference = |field, value: &str| DataError::InvalidReference {
+            file: file.to_string(),
+            line,
+            object_type: object_type::<StopTime>(),
+            id: None,
+            field,
+            value: value.to_string(),
+        };
+        // In lenient mode, the stop times with an unknown stop or trip are
+        // skipped and reported
+        let stop_point_idx = match collections.stop_points.get_idx(&stop_time.stop_id) {
+            Some(stop_point_idx) => stop_point_idx,
+            None => {
+                (&mut *file_handler)
+                    .on_invalid_object(invalid_reference("stop_id", &stop_time.stop_id))?;
+                continue;
+            }
+        };
+        let vj_idx = match collections.vehicle_journeys.get_idx(&stop_time.trip_id) {
+            Some(vj_idx) => vj_idx,
+            None => {
+                (&mut *file_handler)
+                    .on_invalid_object(invalid_reference("trip_id", &stop_time.trip_id))?;
+                continue;
+            }
+        };
 
         if let Some(headsign) = stop_time.stop_headsign {
             headsigns.insert(
@@ -329,6 +672,9 @@ where
                 drop_off_type: stop_time.drop_off_type,
                 local_zone_id: stop_time.local_zone_id,
                 precision,
+                shape_dist_traveled: stop_time.shape_dist_traveled.flatten(),
+                continuous_pickup: stop_time.continuous_pickup,
+                continuous_drop_off: stop_time.continuous_drop_off,
             });
     }
     collections.stop_time_headsigns = headsigns;
@@ -418,30 +764,35 @@ where
     Ok(())
 }
 
+/// The reference `field` of the link at `line` of `file` doesn't exist.
+fn invalid_link<L>(file: &str, line: u64, field: &'static str, value: &str) -> DataError {
+    DataError::InvalidReference {
+        file: file.to_string(),
+        line,
+        object_type: object_type::<L>(),
+        id: None,
+        field,
+        value: value.to_string(),
+    }
+}
+
 fn insert_comment_link<T>(
     collection: &mut CollectionWithId<T>,
     comments: &CollectionWithId<Comment>,
     comment_link: &CommentLink,
-) -> Result<()>
+    line: u64,
+) -> Result<(), DataError>
 where
     T: Links<Comment> + Id<T>,
 {
-    let idx = match collection.get_idx(&comment_link.object_id) {
-        Some(idx) => idx,
-        None => {
-            error!(
-                "comment_links.txt: object_type={} object_id={} not found",
-                comment_link.object_type.as_str(),
-                comment_link.object_id
-            );
-            return Ok(());
-        }
+    let invalid = |field: &'static str, value: &str| {
+        invalid_link::<CommentLink>(COMMENT_LINKS, line, field, value)
     };
+    let idx = collection
+        .get_idx(&comment_link.object_id)
+        .ok_or_else(|| invalid("object_id", &comment_link.object_id))?;
     if !comments.contains_id(&comment_link.comment_id) {
-        bail!(
-            "comment.txt: comment_id={} not found",
-            comment_link.comment_id
-        );
+        return Err(invalid("comment_id", &comment_link.comment_id));
     }
     collection
         .index_mut(idx)
@@ -455,23 +806,18 @@ fn insert_stop_time_comment_link(
     stop_time_ids: &HashMap<&String, (String, u32)>,
     comments: &CollectionWithId<Comment>,
     comment_link: &CommentLink,
-) -> Result<()> {
-    if let Some(vehicle_journey_id) = stop_time_ids.get(&comment_link.object_id) {
-        if comments.contains_id(&comment_link.comment_id) {
-            stop_time_comments.insert(vehicle_journey_id.clone(), comment_link.comment_id.clone());
-        } else {
-            bail!(
-                "comment.txt: comment_id={} not found",
-                comment_link.comment_id
-            )
-        }
-    } else {
-        error!(
-            "comment_links.txt: object_type={} object_id={} not found",
-            comment_link.object_type.as_str(),
-            comment_link.object_id
-        );
+    line: u64,
+) -> Result<(), DataError> {
+    let invalid = |field: &'static str, value: &str| {
+        invalid_link::<CommentLink>(COMMENT_LINKS, line, field, value)
+    };
+    let stop_time = stop_time_ids
+        .get(&comment_link.object_id)
+        .ok_or_else(|| invalid("object_id", &comment_link.object_id))?;
+    if !comments.contains_id(&comment_link.comment_id) {
+        return Err(invalid("comment_id", &comment_link.comment_id));
     }
+    stop_time_comments.insert(stop_time.clone(), comment_link.comment_id.clone());
     Ok(())
 }
 
@@ -479,26 +825,19 @@ fn insert_booking_rule_link<T>(
     collection: &mut CollectionWithId<T>,
     booking_rules: &CollectionWithId<BookingRule>,
     link: &BookingRuleLink,
-) -> Result<()>
+    line: u64,
+) -> Result<(), DataError>
 where
     T: Links<BookingRule> + Id<T>,
 {
-    let idx = match collection.get_idx(&link.object_id) {
-        Some(idx) => idx,
-        None => {
-            error!(
-                "booking_rule_links.txt: object_type={} object_id={} not found",
-                link.object_type.as_str(),
-                link.object_id
-            );
-            return Ok(());
-        }
+    let invalid = |field: &'static str, value: &str| {
+        invalid_link::<BookingRuleLink>(BOOKING_RULE_LINKS, line, field, value)
     };
+    let idx = collection
+        .get_idx(&link.object_id)
+        .ok_or_else(|| invalid("object_id", &link.object_id))?;
     if !booking_rules.contains_id(&link.booking_rule_id) {
-        bail!(
-            "booking_rules.txt: booking_rule_id={} not found",
-            link.booking_rule_id
-        );
+        return Err(invalid("booking_rule_id", &link.booking_rule_id));
     }
     collection
         .index_mut(idx)
@@ -517,7 +856,8 @@ where
         // no need to read the comment_links (and invert the huge stoptimes collection)
         return Ok(());
     }
-    let comment_links = read_objects::<_, CommentLink>(file_handler, "comment_links.txt", false)?;
+    let comment_links =
+        read_objects_with_lines::<_, CommentLink>(file_handler, COMMENT_LINKS, false)?;
 
     // invert the stop_time_ids map to search a stop_time by its id
     let stop_time_ids = collections
@@ -526,41 +866,63 @@ where
         .map(|(k, v)| (v, k.clone()))
         .collect();
     info!("Reading comment_links.txt");
-    for comment_link in comment_links {
+    for (line, comment_link) in comment_links {
         match comment_link.object_type {
-            ObjectType::StopArea => skip_error_and_warn!(insert_comment_link(
-                &mut collections.stop_areas,
-                &collections.comments,
-                &comment_link,
-            )),
-            ObjectType::StopPoint => skip_error_and_warn!(insert_comment_link(
-                &mut collections.stop_points,
-                &collections.comments,
-                &comment_link,
-            )),
-            ObjectType::Line => {
-                skip_error_and_warn!(insert_comment_link(
+            ObjectType::StopArea => skip_error_and_report!(
+                file_handler,
+                insert_comment_link(
+                    &mut collections.stop_areas,
+                    &collections.comments,
+                    &comment_link,
+                    line,
+                )
+            ),
+            ObjectType::StopPoint => skip_error_and_report!(
+                file_handler,
+                insert_comment_link(
+                    &mut collections.stop_points,
+                    &collections.comments,
+                    &comment_link,
+                    line,
+                )
+            ),
+            ObjectType::Line => skip_error_and_report!(
+                file_handler,
+                insert_comment_link(
                     &mut collections.lines,
                     &collections.comments,
-                    &comment_link
-                ))
-            }
-            ObjectType::Route => skip_error_and_warn!(insert_comment_link(
-                &mut collections.routes,
-                &collections.comments,
-                &comment_link,
-            )),
-            ObjectType::VehicleJourney => skip_error_and_warn!(insert_comment_link(
-                &mut collections.vehicle_journeys,
-                &collections.comments,
-                &comment_link,
-            )),
-            ObjectType::StopTime => skip_error_and_warn!(insert_stop_time_comment_link(
-                &mut collections.stop_time_comments,
-                &stop_time_ids,
-                &collections.comments,
-                &comment_link,
-            )),
+                    &comment_link,
+                    line,
+                )
+            ),
+            ObjectType::Route => skip_error_and_report!(
+                file_handler,
+                insert_comment_link(
+                    &mut collections.routes,
+                    &collections.comments,
+                    &comment_link,
+                    line,
+                )
+            ),
+            ObjectType::VehicleJourney => skip_error_and_report!(
+                file_handler,
+                insert_comment_link(
+                    &mut collections.vehicle_journeys,
+                    &collections.comments,
+                    &comment_link,
+                    line,
+                )
+            ),
+            ObjectType::StopTime => skip_error_and_report!(
+                file_handler,
+                insert_stop_time_comment_link(
+                    &mut collections.stop_time_comments,
+                    &stop_time_ids,
+                    &collections.comments,
+                    &comment_link,
+                    line,
+                )
+            ),
             ObjectType::LineGroup => warn!("line_groups.txt is not parsed yet"),
             _ => warn!(
                 "comment does not support {}",
@@ -584,23 +946,29 @@ where
         return Ok(());
     }
     let booking_rule_links =
-        read_objects::<_, BookingRuleLink>(file_handler, "booking_rule_links.txt", false)?;
+        read_objects_with_lines::<_, BookingRuleLink>(file_handler, BOOKING_RULE_LINKS, false)?;
 
     info!("Reading booking_rule_links.txt");
-    for link in booking_rule_links {
+    for (line, link) in booking_rule_links {
         match link.object_type {
-            ObjectType::Line => {
-                skip_error_and_warn!(insert_booking_rule_link(
+            ObjectType::Line => skip_error_and_report!(
+                file_handler,
+                insert_booking_rule_link(
                     &mut collections.lines,
                     &collections.booking_rules,
-                    &link
-                ))
-            }
-            ObjectType::VehicleJourney => skip_error_and_warn!(insert_booking_rule_link(
-                &mut collections.vehicle_journeys,
-                &collections.booking_rules,
-                &link,
-            )),
+                    &link,
+                    line,
+                )
+            ),
+            ObjectType::VehicleJourney => skip_error_and_report!(
+                file_handler,
+                insert_booking_rule_link(
+                    &mut collections.vehicle_journeys,
+                    &collections.booking_rules,
+                    &link,
+                    line,
+                )
+            ),
             _ => warn!(
                 "booking_rule does not support {}",
                 link.object_type.as_str()
@@ -667,8 +1035,9 @@ where
     for<'a> &'a mut H: FileHandler,
 {
     let mut geometries: CollectionWithId<Geometry> = CollectionWithId::default();
-    for geo in read_objects_loose::<_, Geometry>(file_handler, "geometries.txt", false)? {
-        skip_error_and_warn!(geometries.push(geo));
+    let file = "geometries.txt";
+    for geo in read_objects_loose::<_, Geometry>(file_handler, file, false)? {
+        skip_error_and_report!(file_handler, push_object(&mut geometries, geo, file));
     }
     collections.geometries = geometries;
     Ok(())
@@ -703,39 +1072,37 @@ where
 {
     let file = "pathways.txt";
     let mut pathways: CollectionWithId<Pathway> = CollectionWithId::default();
-    let ntfs_pathways = read_objects_loose::<_, Pathway>(file_handler, file, false)?;
-    for mut pathway in ntfs_pathways {
-        pathway.from_stop_type = skip_error_and_warn!(collections
-            .stop_points
-            .get(&pathway.from_stop_id)
-            .map(|st| st.stop_type.clone())
-            .or_else(|| collections
-                .stop_locations
-                .get(&pathway.from_stop_id)
-                .map(|sl| sl.stop_type.clone()))
-            .ok_or_else(|| {
-                anyhow!(
-                    "Problem reading {:?}: from_stop_id={:?} not found",
-                    file,
-                    pathway.from_stop_id
-                )
-            }));
-        pathway.to_stop_type = skip_error_and_warn!(collections
-            .stop_points
-            .get(&pathway.to_stop_id)
-            .map(|st| st.stop_type.clone())
-            .or_else(|| collections
-                .stop_locations
-                .get(&pathway.to_stop_id)
-                .map(|sl| sl.stop_type.clone()))
-            .ok_or_else(|| {
-                anyhow!(
-                    "Problem reading {:?}: to_stop_id={:?} not found",
-                    file,
-                    pathway.to_stop_id
-                )
-            }));
-        skip_error_and_warn!(pathways.push(pathway));
+    let ntfs_pathways = read_objects_loose_with_lines::<_, Pathway>(file_handler, file, false)?;
+    for (line, mut pathway) in ntfs_pathways {
+        let stop_type = |field: &'static str, stop_id: &str| {
+            collections
+                .stop_points
+                .get(stop_id)
+                .map(|st| st.stop_type.clone())
+                .or_else(|| {
+                    collections
+                        .stop_locations
+                        .get(stop_id)
+                        .map(|sl| sl.stop_type.clone())
+                })
+                .ok_or_else(|| DataError::InvalidReference {
+                    file: file.to_string(),
+                    line,
+                    object_type: object_type::<Pathway>(),
+                    id: Some(pathway.id.clone()),
+                    field,
+                    value: stop_id.to_string(),
+                })
+        };
+        let from_stop_type = skip_error_and_report!(
+            file_handler,
+            stop_type("from_stop_id", &pathway.from_stop_id)
+        );
+        let to_stop_type =
+            skip_error_and_report!(file_handler, stop_type("to_stop_id", &pathway.to_stop_id));
+        pathway.from_stop_type = from_stop_type;
+        pathway.to_stop_type = to_stop_type;
+        skip_error_and_report!(file_handler, push_object(&mut pathways, pathway, file));
     }
 
     collections.pathways = pathways;
@@ -844,7 +1211,7 @@ where
 mod tests {
     use super::*;
     use crate::calendars;
-    use crate::file_handler::PathFileHandler;
+    use crate::file_handler::{LenientFileHandler, PathFileHandler};
     use crate::objects;
     use crate::test_utils::*;
     use crate::utils::make_collection_with_id;
@@ -956,6 +1323,91 @@ mod tests {
             assert_eq!("Navitia:sp:01", stop_area.id);
         });
     }
+    #[test]
+    fn skipped_pathways_are_reported() {
+        let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type\n\
+                             sp:01,my stop name 1,0.1,1.2,0\n\
+                             sp:02,my stop name 2,0.1,1.2,0";
+        let pathways_content = "pathway_id,from_stop_id,to_stop_id,pathway_mode,is_bidirectional\n\
+             pw:01,sp:01,sp:02,1,1\n\
+             pw:02,sp:01,sp:03,1,1";
+
+        test_in_tmp_dir(|path| {
+            create_file_with_content(path, "stops.txt", stops_content);
+            create_file_with_content(path, "pathways.txt", pathways_content);
+            let mut collections = Collections::default();
+            let mut handler = LenientFileHandler::new(PathFileHandler::new(path.to_path_buf()));
+            manage_stops(&mut collections, &mut handler).unwrap();
+            manage_pathways(&mut collections, &mut handler).unwrap();
+            assert_eq!(1, collections.pathways.len());
+            assert_eq!(
+                vec![DataError::InvalidReference {
+                    file: "pathways.txt".to_string(),
+                    line: 3,
+                    object_type: "Pathway",
+                    id: Some("pw:02".to_string()),
+                    field: "to_stop_id",
+                    value: "sp:03".to_string(),
+                }],
+                handler.into_errors()
+            );
+        });
+    }
+
+    #[test]
+    fn skipped_stop_times_are_reported() {
+        let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type\n\
+                             sp:01,my stop name 1,0.1,1.2,0\n\
+                             sp:02,my stop name 2,0.1,1.2,0";
+        let stop_times_content = "trip_id,stop_id,stop_sequence,arrival_time,departure_time\n\
+             vj1,sp:01,0,10:00:00,10:00:00\n\
+             vj1,sp:03,1,10:30:00,10:30:00\n\
+             vj2,sp:01,0,10:00:00,10:00:00\n\
+             vj1,sp:02,2,11:00:00,11:00:00";
+
+        test_in_tmp_dir(|path| {
+            create_file_with_content(path, "stops.txt", stops_content);
+            create_file_with_content(path, "stop_times.txt", stop_times_content);
+            let mut collections = Collections::default();
+            collections
+                .vehicle_journeys
+                .push(VehicleJourney {
+                    id: "vj1".to_string(),
+                    ..Default::default()
+                })
+                .unwrap();
+            let mut handler = LenientFileHandler::new(PathFileHandler::new(path.to_path_buf()));
+            manage_stops(&mut collections, &mut handler).unwrap();
+            manage_stop_times(&mut collections, &mut handler).unwrap();
+            let stop_times = &collections.vehicle_journeys.get("vj1").unwrap().stop_times;
+            assert_eq!(
+                vec![0, 2],
+                stop_times.iter().map(|st| st.sequence).collect::<Vec<_>>()
+            );
+            assert_eq!(
+                vec![
+                    DataError::InvalidReference {
+                        file: "stop_times.txt".to_string(),
+                        line: 3,
+                        object_type: "StopTime",
+                        id: None,
+                        field: "stop_id",
+                        value: "sp:03".to_string(),
+                    },
+                    DataError::InvalidReference {
+                        file: "stop_times.txt".to_string(),
+                        line: 4,
+                        object_type: "StopTime",
+                        id: None,
+                        field: "trip_id",
+                        value: "vj2".to_string(),
+                    }
+                ],
+                handler.into_errors()
+            );
+        });
+    }
+
     #[test]
     fn ntfs_stop_times_precision() {
         test_in_tmp_dir(|path| {
@@ -977,6 +1429,9 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                     objects::StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:02").unwrap(),
@@ -991,6 +1446,9 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Approximate),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                     objects::StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:03").unwrap(),
@@ -1005,6 +1463,9 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Estimated),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                     objects::StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:04").unwrap(),
@@ -1019,6 +1480,9 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                     objects::StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:05").unwrap(),
@@ -1033,6 +1497,9 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Estimated),
+                        shape_dist_traveled: None,
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
                     },
                 ],
                 collections.vehicle_journeys.into_vec()[0].stop_times
@@ -1058,4 +1525,106 @@ mod tests {
             assert_eq!(code.1, "source_code");
         });
     }
+
+    #[test]
+    fn fares_v1_ticket_with_constraints() {
+        test_in_tmp_dir(|path| {
+            generate_minimal_ntfs(path);
+            create_file_with_content(
+                path,
+                "prices.csv",
+                "T1;20190101;20200101;350;Ticket Line 1;;;CHF\n\
+                 T2;20190101;20200101;100;Unknown currency;;;FOO",
+            );
+            create_file_with_content(
+                path,
+                "od_fares.csv",
+                "Origin ID;Origin name;Origin mode;Destination ID;Destination name;Destination mode;ticket_id",
+            );
+            create_file_with_content(
+                path,
+                "fares.csv",
+                "avant changement;après changement;début trajet;fin trajet;condition globale;clef ticket\n\
+                 *;line=line:line_1;nb_changes<3&duration<61&zone=Z1;duration<91&zone=Z2;;T1\n\
+                 line=line:line_1;line=line:line_1;ticket=T1;;;\n\
+                 *;line=line:line_1;;;;T2",
+            );
+            let mut collections = make_collection(path);
+            let mut file_handler = PathFileHandler::new(path.to_path_buf());
+            manage_fares_v1(&mut collections, &mut file_handler).unwrap();
+
+            // T2 has no valid price
+            assert_eq!(1, collections.tickets.len());
+            assert_eq!(
+                &TicketUse {
+                    id: "T1".to_string(),
+                    ticket_id: "T1".to_string(),
+                    max_transfers: Some(2),
+                    boarding_time_limit: Some(3659),
+                    alighting_time_limit: Some(5459),
+                },
+                collections.ticket_uses.get("T1").unwrap()
+            );
+            let price = collections.ticket_prices.values().next().unwrap();
+            assert_eq!("CHF", price.currency);
+            assert_eq!(Decimal::new(350, 2), price.price);
+            assert_eq!(
+                Date::from_ymd_opt(2019, 12, 31).unwrap(),
+                price.ticket_validity_end
+            );
+            let perimeter = collections.ticket_use_perimeters.values().next().unwrap();
+            assert_eq!(ObjectType::Line, perimeter.object_type);
+            assert_eq!("line_1", perimeter.object_id);
+            assert_eq!(PerimeterAction::Included, perimeter.perimeter_action);
+            let restriction = collections.ticket_use_restrictions.values().next().unwrap();
+            assert_eq!(RestrictionType::Zone, restriction.restriction_type);
+            assert_eq!("Z1", restriction.use_origin);
+            assert_eq!("Z2", restriction.use_destination);
+        });
+    }
+
+    #[test]
+    fn fares_v1_origin_destination_ticket() {
+        test_in_tmp_dir(|path| {
+            generate_minimal_ntfs(path);
+            create_file_with_content(
+                path,
+                "prices.csv",
+                "OD1;20190101;20200101;150;Ticket Origine-Destination;;;centime",
+            );
+            create_file_with_content(
+                path,
+                "od_fares.csv",
+                "Origin ID;Origin name;Origin mode;Destination ID;Destination name;Destination mode;ticket_id\n\
+                 stop_area:sa:1;;stop;stop_area:sa:2;;stop;OD1",
+            );
+            create_file_with_content(
+                path,
+                "fares.csv",
+                "avant changement;après changement;début trajet;fin trajet;condition globale;clef ticket\n\
+                 *;mode=physical_mode:physical_mode_1;;;with_changes;",
+            );
+            let mut collections = make_collection(path);
+            let mut file_handler = PathFileHandler::new(path.to_path_buf());
+            manage_fares_v1(&mut collections, &mut file_handler).unwrap();
+
+            let ticket_use = collections.ticket_uses.get("OD1").unwrap();
+            assert_eq!(None, ticket_use.max_transfers);
+            let price = collections.ticket_prices.values().next().unwrap();
+            assert_eq!("EUR", price.currency);
+            assert_eq!(Decimal::new(150, 2), price.price);
+            let perimeter = collections.ticket_use_perimeters.values().next().unwrap();
+            assert_eq!(ObjectType::Line, perimeter.object_type);
+            assert_eq!("line_1", perimeter.object_id);
+            let restriction = collections.ticket_use_restrictions.values().next().unwrap();
+            assert_eq!(
+                RestrictionType::OriginDestination,
+                restriction.restriction_type
+            );
+            assert_eq!("sa:1", restriction.use_origin);
+            assert_eq!("sa:2", restriction.use_destination);
+            // The fares v1 are kept to be written as is
+            assert_eq!(1, collections.od_fares_v1.len());
+        });
+    }
 }
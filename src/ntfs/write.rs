@@ -18,6 +18,7 @@ use super::{
 use crate::model::Collections;
 use crate::ntfs::{has_fares_v1, has_fares_v2};
 use crate::objects::*;
+use crate::utils::write_collection_with_id;
 use crate::NTFS_VERSION;
 use anyhow::{anyhow, bail, Context};
 use chrono::{DateTime, Days, FixedOffset};
@@ -74,9 +75,32 @@ pub fn write_feed_infos(
     Ok(())
 }
 
+pub fn write_routes(path: &path::Path, routes: &CollectionWithId<Route>) -> Result<()> {
+    // The continuous stopping columns are only written if there is any
+    // continuous stopping, the number of serialized columns being the same for
+    // all the routes (see skip_serializing_if on Route::continuous_pickup)
+    if !routes
+        .values()
+        .any(|route| route.continuous_pickup.is_some() || route.continuous_drop_off.is_some())
+    {
+        return write_collection_with_id(path, "routes.txt", routes);
+    }
+    let routes = routes
+        .values()
+        .cloned()
+        .map(|mut route| {
+            route.continuous_pickup = Some(route.continuous_pickup.unwrap_or_default());
+            route.continuous_drop_off = Some(route.continuous_drop_off.unwrap_or_default());
+            route
+        })
+        .collect();
+    write_collection_with_id(path, "routes.txt", &CollectionWithId::new(routes)?)
+}
+
 pub fn write_vehicle_journeys_and_stop_times(
     path: &path::Path,
     vehicle_journeys: &CollectionWithId<VehicleJourney>,
+    routes: &CollectionWithId<Route>,
     stop_points: &CollectionWithId<StopPoint>,
     stop_time_headsigns: &HashMap<(String, u32), String>,
     stop_time_ids: &HashMap<(String, u32), String>,
@@ -91,10 +115,25 @@ pub fn write_vehicle_journeys_and_stop_times(
         .with_context(|| format!("Error reading {trip_path:?}"))?;
     let mut st_wtr = csv::Writer::from_path(&stop_times_path)
         .with_context(|| format!("Error reading {stop_times_path:?}"))?;
+    // The continuous stopping of the routes is written on the stop times, and
+    // the columns are only written if there is any continuous stopping
+    let has_continuous_stopping = routes
+        .values()
+        .any(|route| route.continuous_pickup.is_some() || route.continuous_drop_off.is_some())
+        || vehicle_journeys
+            .values()
+            .flat_map(|vj| &vj.stop_times)
+            .any(|st| st.continuous_pickup.is_some() || st.continuous_drop_off.is_some());
+    let has_shape_dist_traveled = vehicle_journeys
+        .values()
+        .flat_map(|vj| &vj.stop_times)
+        .any(|st| st.shape_dist_traveled.is_some());
+    let default_route = Route::default();
     for (vj_idx, vj) in vehicle_journeys.iter() {
         vj_wtr
             .serialize(vj)
             .with_context(|| format!("Error reading {trip_path:?}"))?;
+        let route = routes.get(&vj.route_id).unwrap_or(&default_route);
 
         for st in &vj.stop_times {
             let precision = st.precision;
@@ -120,6 +159,10 @@ pub fn write_vehicle_journeys_and_stop_times(
                         .get(&(vehicle_journeys[vj_idx].id.clone(), st.sequence))
                         .cloned(),
                     precision,
+                    shape_dist_traveled: has_shape_dist_traveled.then_some(st.shape_dist_traveled),
+                    continuous_pickup: has_continuous_stopping.then(|| st.continuous_pickup(route)),
+                    continuous_drop_off: has_continuous_stopping
+                        .then(|| st.continuous_drop_off(route)),
                 })
                 .with_context(|| format!("Error reading {st_wtr:?}"))?;
         }
@@ -501,6 +544,16 @@ fn do_write_fares_v1_from_v2(base_path: &path::Path, fares: &Fares) -> Result<()
 }
 
 pub fn write_fares_v1(base_path: &path::Path, collections: &Collections) -> Result<()> {
+    // Fares v1 read from the input are written as is: the fares v2 converted
+    // from them can't describe everything (e.g. currencies other than EUR)
+    if has_fares_v1(collections) {
+        return do_write_fares_v1(
+            base_path,
+            &collections.prices_v1,
+            &collections.od_fares_v1,
+            &collections.fares_v1,
+        );
+    }
     if has_fares_v2(collections) {
         return do_write_fares_v1_from_v2(
             base_path,
@@ -513,14 +566,6 @@ pub fn write_fares_v1(base_path: &path::Path, collections: &Collections) -> Resu
             },
         );
     }
-    if has_fares_v1(collections) {
-        return do_write_fares_v1(
-            base_path,
-            &collections.prices_v1,
-            &collections.od_fares_v1,
-            &collections.fares_v1,
-        );
-    }
     Ok(())
 }
 
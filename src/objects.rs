@@ -17,7 +17,7 @@
 #![allow(missing_docs)]
 
 use crate::{serde_utils::*, AddPrefix, PrefixConfiguration};
-use chrono::{Days, NaiveDate};
+use chrono::{Days, NaiveDate, Weekday};
 use chrono_tz::Tz;
 use derivative::Derivative;
 use geo::{Geometry as GeoGeometry, Point as GeoPoint};
@@ -554,6 +554,22 @@ pub struct Route {
     pub line_id: String,
     pub geometry_id: Option<String>,
     pub destination_id: Option<String>,
+    /// Continuous pickup between the stops of the vehicle journeys, unless
+    /// specified on their stop times
+    #[serde(
+        default,
+        deserialize_with = "de_with_invalid_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub continuous_pickup: Option<ContinuousStopping>,
+    /// Continuous drop off between the stops of the vehicle journeys, unless
+    /// specified on their stop times
+    #[serde(
+        default,
+        deserialize_with = "de_with_invalid_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub continuous_drop_off: Option<ContinuousStopping>,
 }
 impl_id!(Route);
 impl_id!(Route, Line, line_id);
@@ -661,6 +677,10 @@ impl AddPrefix for VehicleJourney {
             .geometry_id
             .take()
             .map(|id| prefix_conf.schedule_prefix(id.as_str()));
+        self.journey_pattern_id = self
+            .journey_pattern_id
+            .take()
+            .map(|id| prefix_conf.schedule_prefix(id.as_str()));
         self.comment_links.prefix(prefix_conf);
         self.booking_rule_links.prefix(prefix_conf);
     }
@@ -949,6 +969,67 @@ impl std::fmt::Display for Time {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum TimeWindowError {
+    #[error("Time window format should be 'Fri 22:00:00-Sat 06:00:00'")]
+    WrongFormat,
+    #[error("Time of the day should be before 24:00:00")]
+    WrongValue,
+    #[error(transparent)]
+    Time(#[from] TimeError),
+}
+
+/// A time window repeated every week, e.g. from Friday 22:00:00 to Saturday
+/// 06:00:00. The window spans over the end of the week (Sunday to Monday) when
+/// its end is before its start.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimeWindow {
+    pub start: (Weekday, Time),
+    pub end: (Weekday, Time),
+}
+
+impl TimeWindow {
+    const SECONDS_PER_WEEK: u32 = 7 * 24 * 60 * 60;
+
+    fn seconds_in_week(weekday: Weekday, time: Time) -> u32 {
+        weekday.num_days_from_monday() * 24 * 60 * 60 + time.total_seconds()
+    }
+
+    /// Whether the time, on a day of the given weekday, is in the window. The
+    /// time can be after 24:00:00, e.g. Friday 25:00:00 is Saturday 01:00:00.
+    pub fn contains(&self, weekday: Weekday, time: Time) -> bool {
+        let start = Self::seconds_in_week(self.start.0, self.start.1);
+        let end = Self::seconds_in_week(self.end.0, self.end.1);
+        let duration = (end + Self::SECONDS_PER_WEEK - start) % Self::SECONDS_PER_WEEK;
+        let offset = (Self::seconds_in_week(weekday, time) + Self::SECONDS_PER_WEEK - start)
+            % Self::SECONDS_PER_WEEK;
+        offset <= duration
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = TimeWindowError;
+    fn from_str(window: &str) -> Result<Self, Self::Err> {
+        fn parse_weekday_time(weekday_time: &str) -> Result<(Weekday, Time), TimeWindowError> {
+            let (weekday, time) = weekday_time
+                .trim()
+                .split_once(' ')
+                .ok_or(TimeWindowError::WrongFormat)?;
+            let weekday: Weekday = weekday.parse().map_err(|_| TimeWindowError::WrongFormat)?;
+            let time: Time = time.trim().parse()?;
+            if time.hours() >= 24 {
+                return Err(TimeWindowError::WrongValue);
+            }
+            Ok((weekday, time))
+        }
+        let (start, end) = window.split_once('-').ok_or(TimeWindowError::WrongFormat)?;
+        Ok(TimeWindow {
+            start: parse_weekday_time(start)?,
+            end: parse_weekday_time(end)?,
+        })
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct StopTime {
     pub stop_point_idx: Idx<StopPoint>,
@@ -963,6 +1044,33 @@ pub struct StopTime {
     pub drop_off_type: u8,
     pub local_zone_id: Option<u16>,
     pub precision: Option<StopTimePrecision>,
+    /// Distance traveled from the first stop time of the vehicle journey, in
+    /// meters (see `Collections::compute_shape_dist_traveled`).
+    pub shape_dist_traveled: Option<Decimal>,
+    /// Continuous pickup from this stop to the next one (the one of the route
+    /// if not specified)
+    pub continuous_pickup: Option<ContinuousStopping>,
+    /// Continuous drop off from this stop to the next one (the one of the
+    /// route if not specified)
+    pub continuous_drop_off: Option<ContinuousStopping>,
+}
+
+impl StopTime {
+    /// Continuous pickup from this stop to the next one, given the one of the
+    /// route of the vehicle journey.
+    pub fn continuous_pickup(&self, route: &Route) -> ContinuousStopping {
+        self.continuous_pickup
+            .or(route.continuous_pickup)
+            .unwrap_or_default()
+    }
+
+    /// Continuous drop off from this stop to the next one, given the one of
+    /// the route of the vehicle journey.
+    pub fn continuous_drop_off(&self, route: &Route) -> ContinuousStopping {
+        self.continuous_drop_off
+            .or(route.continuous_drop_off)
+            .unwrap_or_default()
+    }
 }
 
 impl Ord for StopTime {
@@ -983,6 +1091,28 @@ impl GetObjectType for StopTime {
     }
 }
 
+/// Whether the passengers can board or alight anywhere along the path of the
+/// vehicle between two stops (e.g. hail-and-ride sections).
+#[derive(
+    Serialize, Deserialize, Debug, Derivative, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy,
+)]
+#[derivative(Default)]
+pub enum ContinuousStopping {
+    /// Continuous stopping
+    #[serde(rename = "0")]
+    Continuous,
+    /// No continuous stopping
+    #[derivative(Default)]
+    #[serde(rename = "1")]
+    NotContinuous,
+    /// Continuous stopping, after phoning the agency
+    #[serde(rename = "2")]
+    PhoneAgency,
+    /// Continuous stopping, after coordinating with the driver
+    #[serde(rename = "3")]
+    CoordinateWithDriver,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum StopTimePrecision {
     #[serde(rename = "0")]
@@ -1432,6 +1562,22 @@ impl AddPrefix for Level {
 }
 impl_id!(Level);
 
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone)]
+pub struct JourneyPattern {
+    #[serde(rename = "journey_pattern_id")]
+    pub id: String,
+    pub route_id: String,
+}
+
+impl AddPrefix for JourneyPattern {
+    fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
+        self.id = prefix_conf.schedule_prefix(self.id.as_str());
+        self.route_id = prefix_conf.referential_prefix(self.route_id.as_str());
+    }
+}
+impl_id!(JourneyPattern);
+impl_id!(JourneyPattern, Route, route_id);
+
 pub type Date = chrono::NaiveDate;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
@@ -1601,6 +1747,21 @@ impl AddPrefix for Comment {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Derivative, PartialEq, Eq, Clone, Copy)]
+#[derivative(Default)]
+pub enum BookingType {
+    /// Booking in real time
+    #[derivative(Default)]
+    #[serde(rename = "0")]
+    RealTime,
+    /// Booking up to the same day, with a prior notice
+    #[serde(rename = "1")]
+    SameDayWithPriorNotice,
+    /// Booking up to a number of days before the trip
+    #[serde(rename = "2")]
+    UpToPreviousDays,
+}
+
 #[derive(Default, Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 pub struct BookingRule {
     #[serde(rename = "booking_rule_id")]
@@ -1615,6 +1776,24 @@ pub struct BookingRule {
     pub message: Option<String>,
     #[serde(rename = "booking_url")]
     pub booking_url: Option<String>,
+    /// When the trip can be booked
+    #[serde(default, deserialize_with = "de_with_invalid_option")]
+    pub booking_type: Option<BookingType>,
+    /// Minimum number of minutes before the trip to book it
+    pub prior_notice_duration_min: Option<u32>,
+    /// Maximum number of minutes before the trip to book it
+    pub prior_notice_duration_max: Option<u32>,
+    /// Last day before the trip to book it
+    pub prior_notice_last_day: Option<u32>,
+    /// Last time on the last day before the trip to book it
+    pub prior_notice_last_time: Option<Time>,
+    /// Earliest day before the trip to book it
+    pub prior_notice_start_day: Option<u32>,
+    /// Earliest time on the earliest day before the trip to book it
+    pub prior_notice_start_time: Option<Time>,
+    /// Calendar of the days counted by `prior_notice_last_day` and
+    /// `prior_notice_start_day` (all the days if not specified)
+    pub prior_notice_service_id: Option<String>,
 }
 
 impl BookingRule {
@@ -1624,6 +1803,14 @@ impl BookingRule {
             && self.phone == other.phone
             && self.message == other.message
             && self.booking_url == other.booking_url
+            && self.booking_type == other.booking_type
+            && self.prior_notice_duration_min == other.prior_notice_duration_min
+            && self.prior_notice_duration_max == other.prior_notice_duration_max
+            && self.prior_notice_last_day == other.prior_notice_last_day
+            && self.prior_notice_last_time == other.prior_notice_last_time
+            && self.prior_notice_start_day == other.prior_notice_start_day
+            && self.prior_notice_start_time == other.prior_notice_start_time
+            && self.prior_notice_service_id == other.prior_notice_service_id
     }
 }
 
@@ -1632,6 +1819,10 @@ impl_id!(BookingRule);
 impl AddPrefix for BookingRule {
     fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
         self.id = prefix_conf.schedule_prefix(self.id.as_str());
+        self.prior_notice_service_id = self
+            .prior_notice_service_id
+            .take()
+            .map(|id| prefix_conf.schedule_prefix(id.as_str()));
     }
 }
 
@@ -2302,6 +2493,37 @@ mod tests {
         assert_eq!(255, rgb.blue);
     }
 
+    #[test]
+    fn time_window_parsing() {
+        let window: TimeWindow = "Fri 22:00:00-Sat 06:00:00".parse().unwrap();
+        assert_eq!(
+            TimeWindow {
+                start: (Weekday::Fri, Time::new(22, 0, 0)),
+                end: (Weekday::Sat, Time::new(6, 0, 0)),
+            },
+            window
+        );
+        assert!("Fri 22:00:00".parse::<TimeWindow>().is_err());
+        assert!("Fri 22:00:00-Sat 26:00:00".parse::<TimeWindow>().is_err());
+        assert!("Day 22:00:00-Sat 06:00:00".parse::<TimeWindow>().is_err());
+    }
+
+    #[test]
+    fn time_window_contains() {
+        let window: TimeWindow = "Fri 22:00:00-Sat 06:00:00".parse().unwrap();
+        assert!(window.contains(Weekday::Fri, Time::new(23, 0, 0)));
+        assert!(window.contains(Weekday::Fri, Time::new(29, 0, 0)));
+        assert!(window.contains(Weekday::Sat, Time::new(5, 0, 0)));
+        assert!(!window.contains(Weekday::Fri, Time::new(21, 0, 0)));
+        assert!(!window.contains(Weekday::Sat, Time::new(7, 0, 0)));
+        assert!(!window.contains(Weekday::Fri, Time::new(31, 0, 0)));
+
+        let window: TimeWindow = "Sun 20:00:00-Mon 02:00:00".parse().unwrap();
+        assert!(window.contains(Weekday::Sun, Time::new(25, 0, 0)));
+        assert!(window.contains(Weekday::Mon, Time::new(1, 0, 0)));
+        assert!(!window.contains(Weekday::Mon, Time::new(20, 0, 0)));
+    }
+
     #[test]
     fn time_serialization() {
         let ser = |h, m, s| serde_json::to_value(Time::new(h, m, s)).unwrap();
@@ -2394,6 +2616,9 @@ mod tests {
                     drop_off_type: 0,
                     local_zone_id: None,
                     precision: None,
+                    shape_dist_traveled: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
                 })
                 .collect()
         }
@@ -13,18 +13,21 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 //! Some utilities for input dataset to the library.
 
-use crate::{file_handler::FileHandler, Result};
-use anyhow::{anyhow, bail, Context};
-use skip_error::SkipError;
+use crate::{
+    error::{object_type, DataError},
+    file_handler::FileHandler,
+    Result,
+};
 use tracing::info;
 use typed_index_collection::{CollectionWithId, Id};
 
-/// Read a vector of objects from a zip in a file_handler
-pub fn read_objects<H, O>(
+/// Read the records of a file in a file_handler, along with their line in the
+/// file. The records which can't be read are returned as errors.
+fn read_records<H, O>(
     file_handler: &mut H,
     file_name: &str,
     required_file: bool,
-) -> Result<Vec<O>>
+) -> Result<(Vec<(u64, O)>, Vec<DataError>)>
 where
     for<'a> &'a mut H: FileHandler,
     O: for<'de> serde::Deserialize<'de>,
@@ -36,25 +39,70 @@ where
     match (reader, required_file) {
         (None, false) => {
             info!(file_name = %basename, "Skipping");
-            Ok(vec![])
-        }
-        (None, true) => {
-            bail!("file {:?} not found", path)
+            Ok((vec![], vec![]))
         }
+        (None, true) => Err(DataError::MissingFile { file: path }.into()),
         (Some(reader), _) => {
             info!(file_name = %basename, "Reading");
             let mut rdr = csv::ReaderBuilder::new()
                 .flexible(true)
                 .trim(csv::Trim::All)
                 .from_reader(reader);
-            Ok(rdr
-                .deserialize()
-                .collect::<Result<_, _>>()
-                .with_context(|| format!("Error reading {path:?}"))?)
+            let headers = rdr.headers().ok().cloned();
+            let mut objects = Vec::new();
+            let mut errors = Vec::new();
+            let mut record = csv::StringRecord::new();
+            loop {
+                let object = match rdr.read_record(&mut record) {
+                    Ok(false) => break,
+                    Ok(true) => record.deserialize(headers.as_ref()),
+                    Err(e) => Err(e),
+                };
+                match object {
+                    Ok(object) => {
+                        let line = record.position().map_or(0, csv::Position::line);
+                        objects.push((line, object));
+                    }
+                    Err(e) => errors.push(DataError::from_csv(path.clone(), headers.as_ref(), &e)),
+                }
+            }
+            Ok((objects, errors))
         }
     }
 }
 
+/// Read a vector of objects from a zip in a file_handler
+pub fn read_objects<H, O>(
+    file_handler: &mut H,
+    file_name: &str,
+    required_file: bool,
+) -> Result<Vec<O>>
+where
+    for<'a> &'a mut H: FileHandler,
+    O: for<'de> serde::Deserialize<'de>,
+{
+    let objects = read_objects_with_lines(file_handler, file_name, required_file)?;
+    Ok(objects.into_iter().map(|(_, object)| object).collect())
+}
+
+/// Read a vector of objects from a zip in a file_handler, along with their
+/// line in the file to report the objects which can't be used
+pub(crate) fn read_objects_with_lines<H, O>(
+    file_handler: &mut H,
+    file_name: &str,
+    required_file: bool,
+) -> Result<Vec<(u64, O)>>
+where
+    for<'a> &'a mut H: FileHandler,
+    O: for<'de> serde::Deserialize<'de>,
+{
+    let (objects, errors) = read_records(file_handler, file_name, required_file)?;
+    for error in errors {
+        (&mut *file_handler).on_invalid_object(error)?;
+    }
+    Ok(objects)
+}
+
 /// Read a vector of objects from a zip in a file_handler ignoring error
 pub fn read_objects_loose<H, O>(
     file_handler: &mut H,
@@ -65,33 +113,28 @@ where
     for<'a> &'a mut H: FileHandler,
     O: for<'de> serde::Deserialize<'de>,
 {
-    let (reader, path) = file_handler.get_file_if_exists(file_name)?;
-    let file_name = path.file_name();
-    let basename = file_name.map_or(path.to_string_lossy(), |b| b.to_string_lossy());
+    let objects = read_objects_loose_with_lines(file_handler, file_name, required_file)?;
+    Ok(objects.into_iter().map(|(_, object)| object).collect())
+}
 
-    match (reader, required_file) {
-        (None, false) => {
-            info!(file_name = %basename, "Skipping");
-            Ok(vec![])
-        }
-        (None, true) => {
-            bail!("file {:?} not found", path)
-        }
-        (Some(reader), _) => {
-            info!(file_name = %basename, "Reading");
-            let mut rdr = csv::ReaderBuilder::new()
-                .flexible(true)
-                .trim(csv::Trim::All)
-                .from_reader(reader);
-            let objects = rdr
-                .deserialize()
-                .map(|object| object.with_context(|| format!("Error reading {path:?}")))
-                .skip_error_and_warn()
-                .collect();
-            Ok(objects)
-        }
+/// Read a vector of objects from a zip in a file_handler ignoring error,
+/// along with their line in the file to report the invalid references
+pub(crate) fn read_objects_loose_with_lines<H, O>(
+    file_handler: &mut H,
+    file_name: &str,
+    required_file: bool,
+) -> Result<Vec<(u64, O)>>
+where
+    for<'a> &'a mut H: FileHandler,
+    O: for<'de> serde::Deserialize<'de>,
+{
+    let (objects, errors) = read_records(file_handler, file_name, required_file)?;
+    for error in errors {
+        (&mut *file_handler).on_skipped_object(error);
     }
+    Ok(objects)
 }
+
 /// Read a CollectionId from a zip in a file_handler
 pub fn read_collection<H, O>(file_handler: &mut H, file_name: &str) -> Result<CollectionWithId<O>>
 where
@@ -99,7 +142,7 @@ where
     O: for<'de> serde::Deserialize<'de> + Id<O>,
 {
     let vec = read_objects(file_handler, file_name, true)?;
-    CollectionWithId::new(vec).map_err(|e| anyhow!("{}", e))
+    make_collection(file_handler, file_name, vec)
 }
 
 /// Read a CollectionId from a optional file in a file_handler
@@ -112,5 +155,136 @@ where
     O: for<'de> serde::Deserialize<'de> + Id<O>,
 {
     let vec = read_objects(file_handler, file_name, false)?;
-    CollectionWithId::new(vec).map_err(|e| anyhow!("{}", e))
+    make_collection(file_handler, file_name, vec)
+}
+
+fn make_collection<H, O>(
+    file_handler: &mut H,
+    file_name: &str,
+    objects: Vec<O>,
+) -> Result<CollectionWithId<O>>
+where
+    for<'a> &'a mut H: FileHandler,
+    O: Id<O>,
+{
+    let mut collection = CollectionWithId::default();
+    for object in objects {
+        let id = object.id().to_string();
+        if collection.push(object).is_err() {
+            (&mut *file_handler).on_invalid_object(DataError::DuplicateIdentifier {
+                file: file_name.to_string(),
+                object_type: object_type::<O>(),
+                id,
+            })?;
+        }
+    }
+    Ok(collection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        file_handler::{LenientFileHandler, PathFileHandler},
+        objects::{Contributor, Frequency},
+        test_utils::*,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn invalid_record() {
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(
+                path,
+                "frequencies.txt",
+                "trip_id,start_time,end_time,headway_secs\n\
+                 vj1,08:00:00,09:00:00,600\n\
+                 vj2,08:00:00,09:00:00,often",
+            );
+            let error =
+                read_objects::<_, Frequency>(&mut handler, "frequencies.txt", true).unwrap_err();
+            match DataError::find(&error).unwrap() {
+                DataError::InvalidRecord {
+                    file, line, column, ..
+                } => {
+                    assert_eq!(path.join("frequencies.txt"), *file);
+                    assert_eq!(Some(3), *line);
+                    assert_eq!(Some("headway_secs"), column.as_deref());
+                }
+                error => panic!("unexpected error {:?}", error),
+            }
+        });
+    }
+
+    #[test]
+    fn duplicate_identifier() {
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(
+                path,
+                "contributors.txt",
+                "contributor_id,contributor_name\n\
+                 c1,Contributor 1\n\
+                 c1,Contributor 2",
+            );
+            let error = read_opt_collection::<_, Contributor>(&mut handler, "contributors.txt")
+                .unwrap_err();
+            assert_eq!(
+                Some(&DataError::DuplicateIdentifier {
+                    file: "contributors.txt".to_string(),
+                    object_type: "Contributor",
+                    id: "c1".to_string(),
+                }),
+                DataError::find(&error)
+            );
+        });
+    }
+
+    #[test]
+    fn lenient_invalid_record() {
+        test_in_tmp_dir(|path| {
+            let mut handler = LenientFileHandler::new(PathFileHandler::new(path.to_path_buf()));
+            create_file_with_content(
+                path,
+                "frequencies.txt",
+                "trip_id,start_time,end_time,headway_secs\n\
+                 vj1,08:00:00,09:00:00,600\n\
+                 vj2,08:00:00,09:00:00,often",
+            );
+            let frequencies =
+                read_objects::<_, Frequency>(&mut handler, "frequencies.txt", true).unwrap();
+            assert_eq!(1, frequencies.len());
+            assert_eq!("vj1", frequencies[0].vehicle_journey_id);
+            let errors = handler.into_errors();
+            assert_eq!(1, errors.len());
+            assert!(matches!(
+                &errors[0],
+                DataError::InvalidRecord { line: Some(3), .. }
+            ));
+        });
+    }
+
+    #[test]
+    fn loose_objects_with_lines() {
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(
+                path,
+                "frequencies.txt",
+                "trip_id,start_time,end_time,headway_secs\n\
+                 vj1,08:00:00,09:00:00,often\n\
+                 vj2,08:00:00,09:00:00,600",
+            );
+            let frequencies = read_objects_loose_with_lines::<_, Frequency>(
+                &mut handler,
+                "frequencies.txt",
+                true,
+            )
+            .unwrap();
+            assert_eq!(1, frequencies.len());
+            assert_eq!(3, frequencies[0].0);
+            assert_eq!("vj2", frequencies[0].1.vehicle_journey_id);
+        });
+    }
 }
@@ -14,6 +14,7 @@
 
 //! Helpers to create a report for processes.
 
+use crate::error::DataError;
 use serde::Serialize;
 use std::collections::BTreeSet;
 
@@ -28,6 +29,17 @@ struct ReportRow<R: ReportCategory> {
     message: String,
 }
 
+/// Severity of a report record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// See [`Report::add_info`].
+    Info,
+    /// See [`Report::add_warning`].
+    Warning,
+    /// See [`Report::add_error`].
+    Error,
+}
+
 /// A report is a list of report records with 3 levels of recording.
 #[derive(Debug, Serialize)]
 pub struct Report<R: ReportCategory> {
@@ -68,6 +80,14 @@ impl<R: ReportCategory> Report<R> {
             message: info,
         });
     }
+    /// Number of report records of the given severity.
+    pub fn count(&self, severity: Severity) -> usize {
+        match severity {
+            Severity::Info => self.infos.len(),
+            Severity::Warning => self.warnings.len(),
+            Severity::Error => self.errors.len(),
+        }
+    }
 }
 
 /// Report categories for transfer generation and modification.
@@ -92,6 +112,140 @@ pub enum TransferReportCategory {
 }
 impl ReportCategory for TransferReportCategory {}
 
+/// Report categories for the clustering of stop points into stop areas
+/// (see [`crate::stop_area_clustering::cluster_stop_areas`]).
+#[derive(Debug, Serialize, Eq, PartialEq, Ord, PartialOrd)]
+pub enum StopAreaClusteringReportCategory {
+    // --- Infos ---
+    /// A stop area was created for several stop points.
+    Created,
+    /// A stop area could be created for several stop points.
+    Suggested,
+}
+impl ReportCategory for StopAreaClusteringReportCategory {}
+
+/// Report categories for the computation of the CO2 emissions
+/// (see [`crate::co2::compute_co2_emissions`]).
+#[derive(Debug, Serialize, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Co2ReportCategory {
+    // --- Warnings ---
+    /// A physical mode has no emission factor, the emissions of its vehicle
+    /// journeys are unknown.
+    MissingEmissionFactor,
+    /// A vehicle journey has a stop point without coordinates, its emissions
+    /// are unknown.
+    MissingDistance,
+}
+impl ReportCategory for Co2ReportCategory {}
+
+/// Report categories for the application of a delta
+/// (see [`crate::delta::Delta::apply`]).
+#[derive(Debug, Serialize, Eq, PartialEq, Ord, PartialOrd)]
+pub enum DeltaReportCategory {
+    // --- Errors ---
+    /// An object to add already exists.
+    AlreadyExists,
+    /// An object to update or delete does not exist.
+    ObjectNotFound,
+    /// An object references an object that does not exist.
+    MissingReference,
+    /// The action is not supported for this kind of object.
+    UnsupportedAction,
+
+    // --- Infos ---
+    /// An object was removed because an object it depends on was deleted.
+    OrphanRemoved,
+}
+impl ReportCategory for DeltaReportCategory {}
+
+/// Report categories of the [`crate::validator::Validator`].
+#[derive(Debug, Serialize, Eq, PartialEq, Ord, PartialOrd)]
+pub enum ValidationReportCategory {
+    // --- Errors ---
+    /// A stop time departs before its arrival, or arrives before the
+    /// departure of the previous stop time.
+    BackwardStopTimes,
+    /// A problem found in the data while building the model, the object is
+    /// removed or fixed (see
+    /// [`crate::model::Collections::remove_invalid_objects`]).
+    InvalidObject,
+
+    // --- Warnings ---
+    /// A trip runs faster than possible for its physical mode.
+    SpeedOutlier,
+    /// Several trips have the same stop times and the same active dates.
+    DuplicateTrip,
+    /// Stop points of a line are almost at the same place.
+    OverlappingStops,
+    /// A stop point is far from its stop area.
+    StopPointFarFromStopArea,
+    /// A line has no trip running on any day.
+    LineWithoutService,
+    /// A calendar has no active date.
+    EmptyCalendar,
+    /// The networks have different timezones.
+    MixedTimezones,
+
+    /// A problem found by a check defined outside of this crate.
+    Custom(String),
+}
+impl ReportCategory for ValidationReportCategory {}
+
+/// Report categories for the lenient reading of a NTFS or a GTFS
+/// (see [`crate::ntfs::read_with_report`] and
+/// [`crate::gtfs::Reader::parse_with_report`]).
+#[derive(Debug, Serialize, Eq, PartialEq, Ord, PartialOrd)]
+pub enum ReadReportCategory {
+    // --- Errors ---
+    /// A record of a file can't be read, the object is skipped.
+    InvalidRecord,
+    /// An object has the identifier of another object, the object is skipped.
+    DuplicateIdentifier,
+    /// An object references an object that does not exist, the object is
+    /// removed.
+    InvalidReference,
+    /// The stop times of a trip are not coherent, the trip is removed.
+    InvalidStopTimes,
+
+    // --- Warnings ---
+    /// The coordinates of a stop are not valid and set to (0, 0).
+    InvalidCoordinates,
+    /// A trip can't be used and is removed.
+    DroppedTrip,
+}
+impl ReportCategory for ReadReportCategory {}
+
+impl From<&DataError> for ReadReportCategory {
+    fn from(error: &DataError) -> Self {
+        match error {
+            DataError::InvalidInput { .. }
+            | DataError::MissingFile { .. }
+            | DataError::InvalidRecord { .. } => ReadReportCategory::InvalidRecord,
+            DataError::DuplicateIdentifier { .. } => ReadReportCategory::DuplicateIdentifier,
+            DataError::InvalidReference { .. }
+            | DataError::UnknownReference { .. }
+            | DataError::InconsistentJourneyPattern { .. } => ReadReportCategory::InvalidReference,
+            DataError::InvalidStopTimes { .. } => ReadReportCategory::InvalidStopTimes,
+            DataError::InvalidCoordinates { .. } => ReadReportCategory::InvalidCoordinates,
+            DataError::DroppedTrip { .. } => ReadReportCategory::DroppedTrip,
+        }
+    }
+}
+
+impl Report<ReadReportCategory> {
+    /// Add a problem found in the input data, as an error if data is lost
+    /// or as a warning otherwise.
+    pub fn add_data_error(&mut self, error: &DataError) {
+        let category = ReadReportCategory::from(error);
+        match error {
+            DataError::InvalidCoordinates { .. } | DataError::DroppedTrip { .. } => {
+                self.add_warning(error.to_string(), category)
+            }
+            _ => self.add_error(error.to_string(), category),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -184,6 +338,20 @@ mod tests {
         assert_eq!(report.errors.len(), 1);
     }
 
+    // --- count ---
+
+    #[test]
+    fn test_count_by_severity() {
+        let mut report = Report::default();
+        report.add_info("info".to_string(), TestCategory::TypeA);
+        report.add_error("error 1".to_string(), TestCategory::TypeA);
+        report.add_error("error 2".to_string(), TestCategory::TypeB);
+
+        assert_eq!(report.count(Severity::Info), 1);
+        assert_eq!(report.count(Severity::Warning), 0);
+        assert_eq!(report.count(Severity::Error), 2);
+    }
+
     // --- Default ---
 
     #[test]
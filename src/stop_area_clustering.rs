@@ -0,0 +1,479 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! See function cluster_stop_areas
+
+use crate::{
+    model::{Collections, Model},
+    objects::{Coord, StopArea, StopPoint},
+    report::{Report, StopAreaClusteringReportCategory},
+    Result,
+};
+use rstar::{RTree, RTreeObject, AABB};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tracing::info;
+use typed_index_collection::{CollectionWithId, Idx};
+
+/// Configuration for the clustering of stop points into stop areas.
+#[derive(Debug, Clone)]
+pub struct StopAreaClusteringConfiguration {
+    /// Maximum distance in meters between a stop point and the nearest other
+    /// stop point of its stop area. As the clusters are transitive, 2 stop
+    /// points of the same stop area can be further apart.
+    pub max_distance: f64,
+    /// Minimum similarity (between 0 and 1) of the normalized names of 2 stop
+    /// points of the same stop area.
+    pub min_name_similarity: f64,
+    /// Only report the stop areas that would be created, without modifying
+    /// the data.
+    pub report_only: bool,
+}
+
+impl Default for StopAreaClusteringConfiguration {
+    fn default() -> Self {
+        Self {
+            max_distance: 100.0,
+            min_name_similarity: 0.8,
+            report_only: false,
+        }
+    }
+}
+
+/// Wrapper for stop point with its index for use in R-tree
+#[derive(Debug, Clone)]
+struct StopPointLocation {
+    idx: Idx<StopPoint>,
+    coord: Coord,
+}
+
+impl RTreeObject for StopPointLocation {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point([self.coord.lon, self.coord.lat])
+    }
+}
+
+/// A stop area is considered as generated when it has been created for a
+/// single stop point because the input had no parent for it (see
+/// `From<StopPoint> for StopArea`), possibly prefixed afterwards.
+fn is_generated(stop_area: &StopArea) -> bool {
+    stop_area.id.starts_with("Navitia:") || stop_area.id.contains(":Navitia:")
+}
+
+/// Lowercase the name, remove the most common diacritics and keep only the
+/// alphanumeric words.
+pub(crate) fn normalize_name(name: &str) -> String {
+    let name: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+            'ç' => 'c',
+            'è' | 'é' | 'ê' | 'ë' => 'e',
+            'ì' | 'í' | 'î' | 'ï' => 'i',
+            'ñ' => 'n',
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' => 'o',
+            'ù' | 'ú' | 'û' | 'ü' => 'u',
+            'ý' | 'ÿ' => 'y',
+            c if c.is_alphanumeric() => c,
+            _ => ' ',
+        })
+        .collect();
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Similarity between 0 (completely different) and 1 (identical) of 2
+/// normalized names, based on the Levenshtein distance.
+pub(crate) fn name_similarity(name1: &str, name2: &str) -> f64 {
+    let name1: Vec<char> = name1.chars().collect();
+    let name2: Vec<char> = name2.chars().collect();
+    let max_len = name1.len().max(name2.len());
+    if max_len == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(&name1, &name2) as f64 / max_len as f64
+}
+
+fn find_root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// Group the stop points of generated stop areas that are close and have a
+/// similar name. Only clusters of at least 2 stop points are returned, each
+/// one sorted by stop point identifier.
+fn build_clusters(
+    stop_points: &CollectionWithId<StopPoint>,
+    stop_areas: &CollectionWithId<StopArea>,
+    config: &StopAreaClusteringConfiguration,
+) -> Vec<Vec<Idx<StopPoint>>> {
+    let mut candidates: Vec<(Idx<StopPoint>, &StopPoint)> = stop_points
+        .iter()
+        .filter(|(_, sp)| sp.coord != Coord::default())
+        .filter(|(_, sp)| stop_areas.get(&sp.stop_area_id).is_some_and(is_generated))
+        .collect();
+    candidates.sort_unstable_by(|(_, sp1), (_, sp2)| sp1.id.cmp(&sp2.id));
+    let positions: HashMap<Idx<StopPoint>, usize> = candidates
+        .iter()
+        .enumerate()
+        .map(|(position, (idx, _))| (*idx, position))
+        .collect();
+    let names: Vec<String> = candidates
+        .iter()
+        .map(|(_, sp)| normalize_name(&sp.name))
+        .collect();
+
+    let rtree = RTree::bulk_load(
+        candidates
+            .iter()
+            .map(|(idx, sp)| StopPointLocation {
+                idx: *idx,
+                coord: sp.coord,
+            })
+            .collect(),
+    );
+
+    let sq_max_distance = config.max_distance.powi(2);
+    let mut parents: Vec<usize> = (0..candidates.len()).collect();
+    for (position, (_, sp)) in candidates.iter().enumerate() {
+        // See `generate_missing_transfers_from_sp` for the conversion of the
+        // distance into degrees
+        let approx = sp.coord.approx();
+        let search_distance_lat = config.max_distance / 111_000.0;
+        let search_distance_lon = config.max_distance / (111_000.0 * approx.cos_lat());
+        let search_box = AABB::from_corners(
+            [
+                sp.coord.lon - search_distance_lon,
+                sp.coord.lat - search_distance_lat,
+            ],
+            [
+                sp.coord.lon + search_distance_lon,
+                sp.coord.lat + search_distance_lat,
+            ],
+        );
+        for neighbour in rtree.locate_in_envelope(&search_box) {
+            let neighbour_position = positions[&neighbour.idx];
+            if neighbour_position <= position
+                || approx.sq_distance_to(&neighbour.coord) > sq_max_distance
+                || name_similarity(&names[position], &names[neighbour_position])
+                    < config.min_name_similarity
+            {
+                continue;
+            }
+            let root = find_root(&mut parents, position);
+            let neighbour_root = find_root(&mut parents, neighbour_position);
+            if root != neighbour_root {
+                parents[root.max(neighbour_root)] = root.min(neighbour_root);
+            }
+        }
+    }
+
+    let mut clusters: BTreeMap<usize, Vec<Idx<StopPoint>>> = BTreeMap::new();
+    for (position, (idx, _)) in candidates.iter().enumerate() {
+        let root = find_root(&mut parents, position);
+        clusters.entry(root).or_default().push(*idx);
+    }
+    clusters
+        .into_values()
+        .filter(|cluster| cluster.len() > 1)
+        .collect()
+}
+
+/// Build the stop area of a cluster: its identifier is the one of the
+/// generated stop area of the first stop point, its name is the most frequent
+/// name of the stop points and its coordinates are their centroid.
+fn build_stop_area(
+    cluster: &[Idx<StopPoint>],
+    stop_points: &CollectionWithId<StopPoint>,
+    stop_areas: &CollectionWithId<StopArea>,
+) -> StopArea {
+    let first_stop_point = &stop_points[cluster[0]];
+    let mut stop_area = stop_areas
+        .get(&first_stop_point.stop_area_id)
+        .cloned()
+        .unwrap_or_else(|| StopArea::from(first_stop_point.clone()));
+
+    let mut names: BTreeMap<&str, usize> = BTreeMap::new();
+    for idx in cluster {
+        *names.entry(stop_points[*idx].name.as_str()).or_default() += 1;
+    }
+    // In case of equality, the first name in alphabetical order is kept
+    if let Some((name, _)) = names
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, occurrences)| *occurrences)
+    {
+        stop_area.name = name.to_string();
+    }
+
+    let count = cluster.len() as f64;
+    stop_area.coord = Coord {
+        lon: cluster
+            .iter()
+            .map(|idx| stop_points[*idx].coord.lon)
+            .sum::<f64>()
+            / count,
+        lat: cluster
+            .iter()
+            .map(|idx| stop_points[*idx].coord.lat)
+            .sum::<f64>()
+            / count,
+    };
+    stop_area.visible = cluster.iter().any(|idx| stop_points[*idx].visible);
+    stop_area
+}
+
+/// Cluster into stop areas the stop points whose stop area was generated
+/// because the input had no parent for them (e.g. a GTFS without
+/// `parent_station`).
+///
+/// 2 stop points are in the same stop area if they are closer than
+/// `max_distance` and if the similarity of their normalized names (lowercase,
+/// without diacritics and punctuation) is at least `min_name_similarity`.
+/// The clusters are transitive: A, B and C are in the same stop area if A is
+/// close to B and B is close to C.
+///
+/// The stop points with an explicit parent are never moved, and the stop
+/// points without coordinates are ignored.
+///
+/// Each new stop area is listed in the report. If `report_only` is set, the
+/// data is not modified.
+///
+/// ### Example
+/// Given the following stop points without parent and the default
+/// configuration:
+///
+/// | stop_id | stop_name     | distance to SP1 | stop area           |
+/// | ------- | ------------- | --------------- | ------------------- |
+/// | SP1     | Gare du Nord  |                 | Navitia:SP1         |
+/// | SP2     | GARE DU NORD  | 40m             | Navitia:SP1         |
+/// | SP3     | Gare de l'Est | 60m             | Navitia:SP3 (kept)  |
+/// | SP4     | Gare du Nord  | 800m            | Navitia:SP4 (kept)  |
+pub fn cluster_stop_areas(
+    model: Model,
+    config: &StopAreaClusteringConfiguration,
+    report_opt: Option<&mut Report<StopAreaClusteringReportCategory>>,
+) -> Result<Collections> {
+    info!("Clustering stop points into stop areas...");
+
+    let mut default_report = Report::default();
+    let report = report_opt.unwrap_or(&mut default_report);
+
+    let clusters = build_clusters(&model.stop_points, &model.stop_areas, config);
+    let mut collections = model.into_collections();
+    if clusters.is_empty() {
+        return Ok(collections);
+    }
+
+    let mut new_stop_areas = Vec::new();
+    let mut removed_stop_area_ids = HashSet::new();
+    for cluster in &clusters {
+        let stop_area = build_stop_area(cluster, &collections.stop_points, &collections.stop_areas);
+        let stop_point_ids: Vec<&str> = cluster
+            .iter()
+            .map(|idx| collections.stop_points[*idx].id.as_str())
+            .collect();
+        let message = format!(
+            "stop area {} ({}) for the stop points {}",
+            stop_area.id,
+            stop_area.name,
+            stop_point_ids.join(", ")
+        );
+        if config.report_only {
+            report.add_info(message, StopAreaClusteringReportCategory::Suggested);
+            continue;
+        }
+        report.add_info(message, StopAreaClusteringReportCategory::Created);
+        for idx in cluster {
+            let mut stop_point = collections.stop_points.index_mut(*idx);
+            removed_stop_area_ids.insert(std::mem::replace(
+                &mut stop_point.stop_area_id,
+                stop_area.id.clone(),
+            ));
+        }
+        new_stop_areas.push(stop_area);
+    }
+    if config.report_only {
+        return Ok(collections);
+    }
+
+    // The generated stop areas may still be used if some of their stop points
+    // were not clustered
+    let used_stop_area_ids: HashSet<&str> = collections
+        .stop_points
+        .values()
+        .map(|sp| sp.stop_area_id.as_str())
+        .collect();
+    removed_stop_area_ids.retain(|id| !used_stop_area_ids.contains(id.as_str()));
+    let mut stop_areas = collections.stop_areas.take();
+    stop_areas.retain(|sa| !removed_stop_area_ids.contains(&sa.id));
+    for new_stop_area in new_stop_areas {
+        match stop_areas.iter_mut().find(|sa| sa.id == new_stop_area.id) {
+            Some(stop_area) => *stop_area = new_stop_area,
+            None => stop_areas.push(new_stop_area),
+        }
+    }
+    collections.stop_areas = CollectionWithId::new(stop_areas)?;
+
+    Ok(collections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ModelBuilder;
+    use approx::assert_relative_eq;
+    use pretty_assertions::assert_eq;
+
+    // SP1 - SP2    ~40m
+    // SP1 - SP3    ~60m
+    // SP1 - SP4    ~800m
+    fn model() -> Model {
+        fn stop_point<'a>(id: &'a str, name: &'a str, lat: f64) -> impl 'a + FnMut(&mut StopPoint) {
+            move |sp| {
+                sp.name = name.to_string();
+                sp.coord = Coord { lon: 2.35, lat };
+                sp.stop_area_id = format!("Navitia:{id}");
+            }
+        }
+        ModelBuilder::default()
+            .stop_area("Navitia:SP1", |_| {})
+            .stop_area("Navitia:SP2", |_| {})
+            .stop_area("Navitia:SP3", |_| {})
+            .stop_area("Navitia:SP4", |_| {})
+            .stop_area("SA", |_| {})
+            .stop_point("SP1", stop_point("SP1", "Gare du Nord", 48.8800))
+            .stop_point("SP2", stop_point("SP2", "GARE DU NORD", 48.88036))
+            .stop_point("SP3", stop_point("SP3", "Gare de l'Est", 48.87946))
+            .stop_point("SP4", stop_point("SP4", "Gare du Nord", 48.8872))
+            .stop_point("SP5", |sp| {
+                sp.name = "Gare du Nord".to_string();
+                sp.coord = Coord {
+                    lon: 2.35,
+                    lat: 48.88018,
+                };
+                sp.stop_area_id = "SA".to_string();
+            })
+            .vj("VJ", |vj| {
+                vj.st("SP1", "10:00:00")
+                    .st("SP2", "10:10:00")
+                    .st("SP3", "10:20:00")
+                    .st("SP4", "10:30:00")
+                    .st("SP5", "10:40:00");
+            })
+            .build()
+    }
+
+    #[test]
+    fn normalized_names() {
+        assert_eq!("gare de l est", normalize_name(" Gare de l'Est "));
+        assert_eq!("hotel de ville", normalize_name("HÔTEL-DE-VILLE"));
+        assert_relative_eq!(1.0, name_similarity("gare", "gare"));
+        assert_relative_eq!(0.75, name_similarity("gare", "gere"));
+        assert_relative_eq!(1.0, name_similarity("", ""));
+    }
+
+    #[test]
+    fn close_stop_points_with_similar_names_are_clustered() {
+        let mut report = Report::default();
+        let collections = cluster_stop_areas(
+            model(),
+            &StopAreaClusteringConfiguration::default(),
+            Some(&mut report),
+        )
+        .unwrap();
+
+        let stop_area_of = |id: &str| {
+            collections
+                .stop_points
+                .get(id)
+                .unwrap()
+                .stop_area_id
+                .clone()
+        };
+        assert_eq!("Navitia:SP1", stop_area_of("SP1"));
+        assert_eq!("Navitia:SP1", stop_area_of("SP2"));
+        assert_eq!("Navitia:SP3", stop_area_of("SP3"));
+        assert_eq!("Navitia:SP4", stop_area_of("SP4"));
+        // Explicit parents are kept
+        assert_eq!("SA", stop_area_of("SP5"));
+        assert!(!collections.stop_areas.contains_id("Navitia:SP2"));
+
+        let stop_area = collections.stop_areas.get("Navitia:SP1").unwrap();
+        assert_eq!("GARE DU NORD", stop_area.name);
+        assert_relative_eq!(2.35, stop_area.coord.lon);
+        assert_relative_eq!(48.88018, stop_area.coord.lat, epsilon = 1e-9);
+
+        let report = serde_json::to_value(&report).unwrap();
+        let infos = report["infos"].as_array().unwrap();
+        assert_eq!(1, infos.len());
+        assert_eq!("Created", infos[0]["category"]);
+        assert_eq!(
+            "stop area Navitia:SP1 (GARE DU NORD) for the stop points SP1, SP2",
+            infos[0]["message"]
+        );
+    }
+
+    #[test]
+    fn clustering_is_configurable() {
+        let config = StopAreaClusteringConfiguration {
+            max_distance: 1000.0,
+            min_name_similarity: 0.5,
+            ..Default::default()
+        };
+        let collections = cluster_stop_areas(model(), &config, None).unwrap();
+
+        for id in &["SP1", "SP2", "SP3", "SP4"] {
+            let stop_point = collections.stop_points.get(id).unwrap();
+            assert_eq!("Navitia:SP1", stop_point.stop_area_id);
+        }
+        assert_eq!(2, collections.stop_areas.len());
+    }
+
+    #[test]
+    fn report_only_does_not_modify_the_data() {
+        let mut report = Report::default();
+        let config = StopAreaClusteringConfiguration {
+            report_only: true,
+            ..Default::default()
+        };
+        let collections = cluster_stop_areas(model(), &config, Some(&mut report)).unwrap();
+
+        let stop_point = collections.stop_points.get("SP2").unwrap();
+        assert_eq!("Navitia:SP2", stop_point.stop_area_id);
+        assert_eq!(5, collections.stop_areas.len());
+
+        let report = serde_json::to_value(&report).unwrap();
+        let infos = report["infos"].as_array().unwrap();
+        assert_eq!(1, infos.len());
+        assert_eq!("Suggested", infos[0]["category"]);
+    }
+}
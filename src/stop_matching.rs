@@ -0,0 +1,764 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Match the stops of 2 datasets (see [`match_stops`]) and merge the matched
+//! stops of a dataset built from both (see [`merge_matched_stops`]).
+
+use crate::{
+    model::{Collections, Model},
+    objects::{Codes, Coord, ObjectType, RestrictionType, StopArea, StopPoint},
+    stop_area_clustering::{name_similarity, normalize_name},
+    Result,
+};
+use anyhow::{bail, Context};
+use rstar::{RTree, RTreeObject, AABB};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use tracing::info;
+use typed_index_collection::{Collection, CollectionWithId, Id, Idx};
+
+/// Configuration for the matching of stops.
+#[derive(Debug, Clone)]
+pub struct StopMatchingConfiguration {
+    /// Types of the codes used to match stops (e.g. `UIC`). All the codes are
+    /// used if empty.
+    pub code_types: Vec<String>,
+    /// Maximum distance in meters between 2 matched stops without a common
+    /// code.
+    pub max_distance: f64,
+    /// Minimum similarity (between 0 and 1) of the normalized names of 2
+    /// matched stops without a common code.
+    pub min_name_similarity: f64,
+    /// Type of the code keeping the identifiers of the merged stops on the
+    /// canonical stops.
+    pub merged_code_type: String,
+}
+
+impl Default for StopMatchingConfiguration {
+    fn default() -> Self {
+        Self {
+            code_types: Vec::new(),
+            max_distance: 50.0,
+            min_name_similarity: 0.8,
+            merged_code_type: "merged_stop_id".to_string(),
+        }
+    }
+}
+
+/// How 2 stops have been matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchReason {
+    /// The stops have a common code.
+    Code,
+    /// The stops are close and have similar names.
+    Proximity,
+}
+
+/// A row of the match table.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StopMatch {
+    /// `stop_point` or `stop_area`.
+    pub object_type: ObjectType,
+    /// Identifier of the stop in the reference dataset, the canonical stop.
+    pub reference_id: String,
+    /// Identifier of the stop in the other dataset.
+    pub matched_id: String,
+    /// How the stops have been matched.
+    pub reason: MatchReason,
+    /// Distance in meters between the stops, if both have coordinates.
+    pub distance: Option<f64>,
+}
+
+trait MatchableStop: Id<Self> + Codes + Sized {
+    const OBJECT_TYPE: ObjectType;
+    fn name(&self) -> &str;
+    fn coord(&self) -> Coord;
+}
+
+impl MatchableStop for StopArea {
+    const OBJECT_TYPE: ObjectType = ObjectType::StopArea;
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn coord(&self) -> Coord {
+        self.coord
+    }
+}
+
+impl MatchableStop for StopPoint {
+    const OBJECT_TYPE: ObjectType = ObjectType::StopPoint;
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn coord(&self) -> Coord {
+        self.coord
+    }
+}
+
+/// Wrapper for a reference stop with its position for use in R-tree
+#[derive(Debug, Clone)]
+struct ReferenceStopLocation {
+    position: usize,
+    coord: Coord,
+}
+
+impl RTreeObject for ReferenceStopLocation {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point([self.coord.lon, self.coord.lat])
+    }
+}
+
+fn distance<T: MatchableStop>(stop1: &T, stop2: &T) -> Option<f64> {
+    let (coord1, coord2) = (stop1.coord(), stop2.coord());
+    if coord1 == Coord::default() || coord2 == Coord::default() {
+        None
+    } else {
+        Some(coord1.distance_to(&coord2))
+    }
+}
+
+fn match_collection<T: MatchableStop>(
+    reference: &CollectionWithId<T>,
+    other: &CollectionWithId<T>,
+    config: &StopMatchingConfiguration,
+) -> Vec<StopMatch> {
+    let use_code = |code_type: &str| {
+        config.code_types.is_empty() || config.code_types.iter().any(|t| t == code_type)
+    };
+    let mut reference_by_code: HashMap<(&str, &str), &T> = HashMap::new();
+    for stop in reference.values() {
+        for (code_type, code) in stop.codes() {
+            if use_code(code_type) {
+                reference_by_code
+                    .entry((code_type.as_str(), code.as_str()))
+                    .or_insert(stop);
+            }
+        }
+    }
+
+    let located: Vec<&T> = reference
+        .values()
+        .filter(|stop| stop.coord() != Coord::default())
+        .collect();
+    let names: Vec<String> = located
+        .iter()
+        .map(|stop| normalize_name(stop.name()))
+        .collect();
+    let rtree = RTree::bulk_load(
+        located
+            .iter()
+            .enumerate()
+            .map(|(position, stop)| ReferenceStopLocation {
+                position,
+                coord: stop.coord(),
+            })
+            .collect(),
+    );
+
+    let mut matches = Vec::new();
+    for stop in other.values() {
+        let code_match = stop
+            .codes()
+            .iter()
+            .filter(|(code_type, _)| use_code(code_type))
+            .find_map(|(code_type, code)| {
+                reference_by_code.get(&(code_type.as_str(), code.as_str()))
+            });
+        if let Some(reference_stop) = code_match {
+            matches.push(StopMatch {
+                object_type: T::OBJECT_TYPE,
+                reference_id: reference_stop.id().to_string(),
+                matched_id: stop.id().to_string(),
+                reason: MatchReason::Code,
+                distance: distance(*reference_stop, stop),
+            });
+            continue;
+        }
+
+        let coord = stop.coord();
+        if coord == Coord::default() {
+            continue;
+        }
+        // See `generate_missing_transfers_from_sp` for the conversion of the
+        // distance into degrees
+        let approx = coord.approx();
+        let search_distance_lat = config.max_distance / 111_000.0;
+        let search_distance_lon = config.max_distance / (111_000.0 * approx.cos_lat());
+        let search_box = AABB::from_corners(
+            [
+                coord.lon - search_distance_lon,
+                coord.lat - search_distance_lat,
+            ],
+            [
+                coord.lon + search_distance_lon,
+                coord.lat + search_distance_lat,
+            ],
+        );
+        let name = normalize_name(stop.name());
+        let closest = rtree
+            .locate_in_envelope(&search_box)
+            .filter(|location| {
+                name_similarity(&name, &names[location.position]) >= config.min_name_similarity
+            })
+            .map(|location| {
+                (
+                    approx.sq_distance_to(&location.coord),
+                    located[location.position],
+                )
+            })
+            .filter(|(sq_distance, _)| *sq_distance <= config.max_distance.powi(2))
+            .min_by(|(d1, s1), (d2, s2)| d1.total_cmp(d2).then_with(|| s1.id().cmp(s2.id())));
+        if let Some((_, reference_stop)) = closest {
+            matches.push(StopMatch {
+                object_type: T::OBJECT_TYPE,
+                reference_id: reference_stop.id().to_string(),
+                matched_id: stop.id().to_string(),
+                reason: MatchReason::Proximity,
+                distance: distance(reference_stop, stop),
+            });
+        }
+    }
+    matches
+}
+
+/// Match the stop areas and the stop points of `other` with the ones of
+/// `reference`.
+///
+/// A stop is first matched with a reference stop having a common code (of
+/// one of the `code_types`). Otherwise, it is matched with the closest
+/// reference stop within `max_distance` whose normalized name (lowercase,
+/// without diacritics and punctuation) has a similarity of at least
+/// `min_name_similarity`.
+///
+/// Several stops can be matched with the same reference stop. The stops that
+/// can't be matched are not in the returned table.
+pub fn match_stops(
+    reference: &Model,
+    other: &Model,
+    config: &StopMatchingConfiguration,
+) -> Vec<StopMatch> {
+    info!("Matching stops...");
+    let mut matches = match_collection(&reference.stop_areas, &other.stop_areas, config);
+    matches.extend(match_collection(
+        &reference.stop_points,
+        &other.stop_points,
+        config,
+    ));
+    info!("{} stops matched", matches.len());
+    matches
+}
+
+/// Write the match table in a CSV file.
+pub fn write_matches<P: AsRef<Path>>(matches: &[StopMatch], path: P) -> Result<()> {
+    let path = path.as_ref();
+    let mut wtr =
+        csv::Writer::from_path(path).with_context(|| format!("Error writing {path:?}"))?;
+    for stop_match in matches {
+        wtr.serialize(stop_match)
+            .with_context(|| format!("Error writing {path:?}"))?;
+    }
+    wtr.flush()
+        .with_context(|| format!("Error writing {path:?}"))?;
+    Ok(())
+}
+
+/// Check that the merged stops and their canonical stop exist, and that a
+/// canonical stop is not merged itself.
+fn check_stops<T: MatchableStop>(
+    stops: &CollectionWithId<T>,
+    canonical_ids: &BTreeMap<&str, &str>,
+) -> Result<()> {
+    for (matched_id, reference_id) in canonical_ids {
+        if !stops.contains_id(matched_id) {
+            bail!(
+                "the {} {} to merge is not found",
+                T::OBJECT_TYPE.as_str(),
+                matched_id
+            );
+        }
+        if !stops.contains_id(reference_id) {
+            bail!(
+                "the canonical {} {} is not found",
+                T::OBJECT_TYPE.as_str(),
+                reference_id
+            );
+        }
+        if canonical_ids.contains_key(reference_id) {
+            bail!(
+                "the canonical {} {} is also merged",
+                T::OBJECT_TYPE.as_str(),
+                reference_id
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Move the codes of the merged stops to their canonical stop, with a code
+/// keeping their identifier, and remove them. The stops must have been
+/// checked with `check_stops`.
+fn merge_stops<T: MatchableStop>(
+    stops: &mut CollectionWithId<T>,
+    canonical_ids: &BTreeMap<&str, &str>,
+    merged_code_type: &str,
+) {
+    for (matched_id, reference_id) in canonical_ids {
+        let Some(matched_codes) = stops.get(matched_id).map(|stop| stop.codes().clone()) else {
+            continue;
+        };
+        if let Some(mut reference_stop) = stops.get_mut(reference_id) {
+            let codes = reference_stop.codes_mut();
+            codes.extend(matched_codes);
+            codes.insert((merged_code_type.to_string(), matched_id.to_string()));
+        }
+    }
+    stops.retain(|stop| !canonical_ids.contains_key(stop.id()));
+}
+
+fn rewrite(id: &mut String, canonical_ids: &BTreeMap<&str, &str>) {
+    if let Some(reference_id) = canonical_ids.get(id.as_str()) {
+        *id = reference_id.to_string();
+    }
+}
+
+/// Rewrite an identifier of the deprecated fares, prefixed by `stop_area:`
+/// when `mode` is `stop`.
+fn rewrite_fare_v1_stop_area(id: &mut String, mode: &str, canonical_ids: &BTreeMap<&str, &str>) {
+    if mode != "stop" {
+        return;
+    }
+    if let Some(reference_id) = id
+        .strip_prefix("stop_area:")
+        .and_then(|stop_area_id| canonical_ids.get(stop_area_id))
+    {
+        *id = format!("stop_area:{reference_id}");
+    }
+}
+
+/// In a dataset containing the stops of both sides of `matches` (e.g. after
+/// merging 2 contributors), replace each matched stop by its reference stop.
+///
+/// The references to the matched stops (stop points of the stop areas,
+/// stop times, transfers, pathways, stop locations, occupancies, admin
+/// stations and origin-destination fares) are rewritten, the transfers which become duplicated or within a merged stop
+/// point are removed. The matched stops are removed and their codes are moved
+/// to the reference stop, with a code of type `merged_code_type` keeping their
+/// identifier.
+///
+/// All the matches are checked before any modification: if one of them
+/// refers to an unknown stop, an error is returned and `collections` is left
+/// untouched.
+pub fn merge_matched_stops(
+    collections: &mut Collections,
+    matches: &[StopMatch],
+    config: &StopMatchingConfiguration,
+) -> Result<()> {
+    info!("Merging matched stops...");
+    let canonical_ids = |object_type: ObjectType| -> BTreeMap<&str, &str> {
+        matches
+            .iter()
+            .filter(|stop_match| stop_match.object_type == object_type)
+            .filter(|stop_match| stop_match.reference_id != stop_match.matched_id)
+            .map(|stop_match| {
+                (
+                    stop_match.matched_id.as_str(),
+                    stop_match.reference_id.as_str(),
+                )
+            })
+            .collect()
+    };
+    let stop_area_ids = canonical_ids(ObjectType::StopArea);
+    let stop_point_ids = canonical_ids(ObjectType::StopPoint);
+    check_stops(&collections.stop_areas, &stop_area_ids)?;
+    check_stops(&collections.stop_points, &stop_point_ids)?;
+
+    // Stop areas
+    merge_stops(
+        &mut collections.stop_areas,
+        &stop_area_ids,
+        &config.merged_code_type,
+    );
+    let stop_point_idxs: Vec<Idx<StopPoint>> = collections.stop_points.indexes().collect();
+    for idx in stop_point_idxs {
+        rewrite(
+            &mut collections.stop_points.index_mut(idx).stop_area_id,
+            &stop_area_ids,
+        );
+    }
+    for occupancy in collections.occupancies.values_mut() {
+        rewrite(&mut occupancy.from_stop_area, &stop_area_ids);
+        rewrite(&mut occupancy.to_stop_area, &stop_area_ids);
+    }
+    for admin_station in collections.admin_stations.values_mut() {
+        rewrite(&mut admin_station.stop_id, &stop_area_ids);
+    }
+    for restriction in collections.ticket_use_restrictions.values_mut() {
+        if restriction.restriction_type == RestrictionType::OriginDestination {
+            rewrite(&mut restriction.use_origin, &stop_area_ids);
+            rewrite(&mut restriction.use_destination, &stop_area_ids);
+        }
+    }
+    for od_fare in collections.od_fares_v1.values_mut() {
+        rewrite_fare_v1_stop_area(
+            &mut od_fare.origin_stop_area_id,
+            &od_fare.origin_mode,
+            &stop_area_ids,
+        );
+        rewrite_fare_v1_stop_area(
+            &mut od_fare.destination_stop_area_id,
+            &od_fare.destination_mode,
+            &stop_area_ids,
+        );
+    }
+
+    // Stop points
+    let stop_point_id_by_idx: HashMap<Idx<StopPoint>, String> = collections
+        .stop_points
+        .iter()
+        .map(|(idx, stop_point)| (idx, stop_point.id.clone()))
+        .collect();
+    merge_stops(
+        &mut collections.stop_points,
+        &stop_point_ids,
+        &config.merged_code_type,
+    );
+    let mut vehicle_journeys = collections.vehicle_journeys.take();
+    for vehicle_journey in &mut vehicle_journeys {
+        for stop_time in &mut vehicle_journey.stop_times {
+            let mut stop_point_id = stop_point_id_by_idx[&stop_time.stop_point_idx].clone();
+            rewrite(&mut stop_point_id, &stop_point_ids);
+            stop_time.stop_point_idx = collections
+                .stop_points
+                .get_idx(&stop_point_id)
+                .with_context(|| format!("stop point {stop_point_id} not found"))?;
+        }
+    }
+    collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys)?;
+    // A transfer between 2 merged stops would become a transfer within the
+    // reference stop, and both sides can have a transfer between the same
+    // stops: the first one is kept
+    let mut transfer_stop_ids = HashSet::new();
+    let transfers = collections
+        .transfers
+        .take()
+        .into_iter()
+        .filter_map(|mut transfer| {
+            let within_a_stop = transfer.from_stop_id == transfer.to_stop_id;
+            rewrite(&mut transfer.from_stop_id, &stop_point_ids);
+            rewrite(&mut transfer.to_stop_id, &stop_point_ids);
+            let merged_within_a_stop =
+                !within_a_stop && transfer.from_stop_id == transfer.to_stop_id;
+            let stop_ids = (transfer.from_stop_id.clone(), transfer.to_stop_id.clone());
+            (!merged_within_a_stop && transfer_stop_ids.insert(stop_ids)).then_some(transfer)
+        })
+        .collect();
+    collections.transfers = Collection::new(transfers);
+
+    // Pathways and stop locations can refer to both stop points and stop
+    // areas, and their identifiers can't collide
+    let mut stop_ids = stop_area_ids.clone();
+    stop_ids.extend(stop_point_ids.iter());
+    let mut pathways = collections.pathways.take();
+    for pathway in &mut pathways {
+        rewrite(&mut pathway.from_stop_id, &stop_ids);
+        rewrite(&mut pathway.to_stop_id, &stop_ids);
+    }
+    collections.pathways = CollectionWithId::new(pathways)?;
+    let mut stop_locations = collections.stop_locations.take();
+    for stop_location in &mut stop_locations {
+        if let Some(parent_id) = stop_location.parent_id.as_mut() {
+            rewrite(parent_id, &stop_ids);
+        }
+    }
+    collections.stop_locations = CollectionWithId::new(stop_locations)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        objects::{AdminStation, OdFareV1, TicketUseRestriction, Transfer},
+        ModelBuilder,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn located<'a>(
+        lat: f64,
+        name: &'a str,
+        stop_area_id: &'a str,
+    ) -> impl 'a + FnMut(&mut StopPoint) {
+        move |sp| {
+            sp.name = name.to_string();
+            sp.coord = Coord { lon: 2.35, lat };
+            sp.stop_area_id = stop_area_id.to_string();
+        }
+    }
+
+    fn reference() -> Model {
+        ModelBuilder::default()
+            .stop_area("SA", |sa| {
+                sa.codes.insert(("UIC".to_string(), "87271007".to_string()));
+            })
+            .stop_point("ref:SP1", located(48.8800, "Gare du Nord", "SA"))
+            .stop_point("ref:SP2", located(48.8900, "Porte de la Chapelle", "SA"))
+            .vj("ref:VJ", |vj| {
+                vj.st("ref:SP1", "10:00:00").st("ref:SP2", "10:10:00");
+            })
+            .build()
+    }
+
+    fn other() -> Model {
+        ModelBuilder::default()
+            .stop_area("other:SA", |sa| {
+                sa.codes.insert(("UIC".to_string(), "87271007".to_string()));
+            })
+            // ~22m from ref:SP1
+            .stop_point("other:SP1", located(48.8802, "GARE DU NORD", "other:SA"))
+            // ~22m from ref:SP2 but with another name
+            .stop_point("other:SP2", located(48.8902, "Marx Dormoy", "other:SA"))
+            .vj("other:VJ", |vj| {
+                vj.st("other:SP1", "11:00:00").st("other:SP2", "11:10:00");
+            })
+            .build()
+    }
+
+    #[test]
+    fn match_by_code_and_proximity() {
+        let matches = match_stops(
+            &reference(),
+            &other(),
+            &StopMatchingConfiguration::default(),
+        );
+
+        assert_eq!(2, matches.len());
+        assert_eq!(ObjectType::StopArea, matches[0].object_type);
+        assert_eq!("SA", matches[0].reference_id);
+        assert_eq!("other:SA", matches[0].matched_id);
+        assert_eq!(MatchReason::Code, matches[0].reason);
+        assert_eq!(ObjectType::StopPoint, matches[1].object_type);
+        assert_eq!("ref:SP1", matches[1].reference_id);
+        assert_eq!("other:SP1", matches[1].matched_id);
+        assert_eq!(MatchReason::Proximity, matches[1].reason);
+        assert!((matches[1].distance.unwrap() - 22.2).abs() < 0.1);
+    }
+
+    #[test]
+    fn match_only_with_the_configured_codes() {
+        let config = StopMatchingConfiguration {
+            code_types: vec!["ZDEr".to_string()],
+            ..Default::default()
+        };
+        let matches = match_collection(&reference().stop_areas, &other().stop_areas, &config);
+        assert!(matches.is_empty());
+    }
+
+    #[test]
+    fn merge_matched_stop_points() {
+        let mut collections = reference().into_collections();
+        let mut other = other().into_collections();
+        let mut vehicle_journeys = collections.vehicle_journeys.take();
+        for mut vehicle_journey in other.vehicle_journeys.take() {
+            for stop_time in &mut vehicle_journey.stop_times {
+                let stop_point = other.stop_points[stop_time.stop_point_idx].clone();
+                stop_time.stop_point_idx = collections.stop_points.push(stop_point).unwrap();
+            }
+            vehicle_journeys.push(vehicle_journey);
+        }
+        collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys).unwrap();
+        collections.transfers.push(Transfer {
+            from_stop_id: "other:SP1".to_string(),
+            to_stop_id: "other:SP2".to_string(),
+            min_transfer_time: Some(60),
+            real_min_transfer_time: Some(60),
+            equipment_id: None,
+        });
+        collections.transfers.push(Transfer {
+            from_stop_id: "ref:SP1".to_string(),
+            to_stop_id: "other:SP2".to_string(),
+            min_transfer_time: Some(120),
+            real_min_transfer_time: Some(120),
+            equipment_id: None,
+        });
+        collections.transfers.push(Transfer {
+            from_stop_id: "ref:SP1".to_string(),
+            to_stop_id: "other:SP1".to_string(),
+            min_transfer_time: Some(0),
+            real_min_transfer_time: Some(0),
+            equipment_id: None,
+        });
+        let matches = vec![StopMatch {
+            object_type: ObjectType::StopPoint,
+            reference_id: "ref:SP1".to_string(),
+            matched_id: "other:SP1".to_string(),
+            reason: MatchReason::Proximity,
+            distance: Some(22.2),
+        }];
+
+        merge_matched_stops(
+            &mut collections,
+            &matches,
+            &StopMatchingConfiguration::default(),
+        )
+        .unwrap();
+
+        assert!(!collections.stop_points.contains_id("other:SP1"));
+        let stop_point = collections.stop_points.get("ref:SP1").unwrap();
+        assert!(stop_point
+            .codes
+            .contains(&("merged_stop_id".to_string(), "other:SP1".to_string())));
+        let vehicle_journey = collections.vehicle_journeys.get("other:VJ").unwrap();
+        let stop_point_ids: Vec<&str> = vehicle_journey
+            .stop_times
+            .iter()
+            .map(|st| collections.stop_points[st.stop_point_idx].id.as_str())
+            .collect();
+        assert_eq!(vec!["ref:SP1", "other:SP2"], stop_point_ids);
+        assert_eq!(1, collections.transfers.len());
+        let transfer = collections.transfers.values().next().unwrap();
+        assert_eq!("ref:SP1", transfer.from_stop_id);
+        assert_eq!("other:SP2", transfer.to_stop_id);
+        assert_eq!(Some(60), transfer.min_transfer_time);
+    }
+
+    #[test]
+    fn merge_matched_stop_areas() {
+        let mut collections = reference().into_collections();
+        for stop_area in other().into_collections().stop_areas.take() {
+            collections.stop_areas.push(stop_area).unwrap();
+        }
+        collections.admin_stations.push(AdminStation {
+            admin_id: "admin:1".to_string(),
+            admin_name: "Paris".to_string(),
+            stop_id: "other:SA".to_string(),
+        });
+        collections
+            .ticket_use_restrictions
+            .push(TicketUseRestriction {
+                ticket_use_id: "TU".to_string(),
+                restriction_type: RestrictionType::OriginDestination,
+                use_origin: "other:SA".to_string(),
+                use_destination: "SA".to_string(),
+            });
+        collections
+            .ticket_use_restrictions
+            .push(TicketUseRestriction {
+                ticket_use_id: "TU".to_string(),
+                restriction_type: RestrictionType::Zone,
+                use_origin: "other:SA".to_string(),
+                use_destination: "Z1".to_string(),
+            });
+        collections.od_fares_v1.push(OdFareV1 {
+            origin_stop_area_id: "stop_area:other:SA".to_string(),
+            origin_name: None,
+            origin_mode: "stop".to_string(),
+            destination_stop_area_id: "stop_area:SA".to_string(),
+            destination_name: None,
+            destination_mode: "stop".to_string(),
+            ticket_id: "T".to_string(),
+        });
+        let matches = vec![StopMatch {
+            object_type: ObjectType::StopArea,
+            reference_id: "SA".to_string(),
+            matched_id: "other:SA".to_string(),
+            reason: MatchReason::Code,
+            distance: None,
+        }];
+
+        merge_matched_stops(
+            &mut collections,
+            &matches,
+            &StopMatchingConfiguration::default(),
+        )
+        .unwrap();
+
+        assert!(!collections.stop_areas.contains_id("other:SA"));
+        let admin_station = collections.admin_stations.values().next().unwrap();
+        assert_eq!("SA", admin_station.stop_id);
+        let restrictions: Vec<(&str, &str)> = collections
+            .ticket_use_restrictions
+            .values()
+            .map(|r| (r.use_origin.as_str(), r.use_destination.as_str()))
+            .collect();
+        assert_eq!(vec![("SA", "SA"), ("other:SA", "Z1")], restrictions);
+        let od_fare = collections.od_fares_v1.values().next().unwrap();
+        assert_eq!("stop_area:SA", od_fare.origin_stop_area_id);
+        assert_eq!("stop_area:SA", od_fare.destination_stop_area_id);
+    }
+
+    #[test]
+    fn merge_unknown_stop() {
+        let mut collections = reference().into_collections();
+        let matches = vec![
+            StopMatch {
+                object_type: ObjectType::StopPoint,
+                reference_id: "ref:SP1".to_string(),
+                matched_id: "ref:SP2".to_string(),
+                reason: MatchReason::Proximity,
+                distance: Some(1111.9),
+            },
+            StopMatch {
+                object_type: ObjectType::StopArea,
+                reference_id: "SA".to_string(),
+                matched_id: "unknown".to_string(),
+                reason: MatchReason::Code,
+                distance: None,
+            },
+        ];
+        let error = merge_matched_stops(
+            &mut collections,
+            &matches,
+            &StopMatchingConfiguration::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            "the stop_area unknown to merge is not found",
+            error.to_string()
+        );
+        // Nothing is merged
+        assert!(collections.stop_points.contains_id("ref:SP2"));
+    }
+
+    #[test]
+    fn merge_into_a_merged_stop() {
+        let mut collections = reference().into_collections();
+        let stop_match = |reference_id: &str, matched_id: &str| StopMatch {
+            object_type: ObjectType::StopPoint,
+            reference_id: reference_id.to_string(),
+            matched_id: matched_id.to_string(),
+            reason: MatchReason::Proximity,
+            distance: Some(1111.9),
+        };
+        let matches = vec![
+            stop_match("ref:SP1", "ref:SP2"),
+            stop_match("ref:SP2", "ref:SP1"),
+        ];
+        let error = merge_matched_stops(
+            &mut collections,
+            &matches,
+            &StopMatchingConfiguration::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            "the canonical stop_point ref:SP2 is also merged",
+            error.to_string()
+        );
+        assert_eq!(2, collections.stop_points.len());
+    }
+}
@@ -0,0 +1,326 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Transposition of the dates of a dataset from a period onto another one,
+//! e.g. to reuse the timetable of the last year from September.
+
+use crate::{
+    model::Collections,
+    objects::{Date, ValidityPeriod},
+    serde_utils::*,
+    Result,
+};
+use anyhow::{bail, Context};
+use chrono::Days;
+use serde::Deserialize;
+use std::{cmp, collections::BTreeMap, path::Path};
+use tracing::info;
+use typed_index_collection::Collection;
+
+const DAYS_PER_WEEK: i64 = 7;
+
+/// Mapping of the dates of a source period onto a target period.
+///
+/// The dates are shifted by a whole number of weeks, to run on the same
+/// weekdays: the number of weeks closest to the difference between the start
+/// of the target period and the start of the source period. The exceptions
+/// (e.g. holidays) override this mapping.
+#[derive(Debug, Clone)]
+pub struct Transposition {
+    source: ValidityPeriod,
+    days: i64,
+    exceptions: BTreeMap<Date, Option<Date>>,
+}
+
+impl Transposition {
+    /// Transposition of the `source` period onto the period starting on
+    /// `target_start_date`.
+    pub fn new(source: ValidityPeriod, target_start_date: Date) -> Result<Self> {
+        if source.start_date > source.end_date {
+            bail!(
+                "the source period starts on {} after its end on {}",
+                source.start_date,
+                source.end_date
+            );
+        }
+        let days = target_start_date
+            .signed_duration_since(source.start_date)
+            .num_days();
+        let weeks = (days + DAYS_PER_WEEK / 2).div_euclid(DAYS_PER_WEEK);
+        Ok(Transposition {
+            source,
+            days: weeks * DAYS_PER_WEEK,
+            exceptions: BTreeMap::new(),
+        })
+    }
+
+    /// Dates of the source period mapped onto a given date of the target
+    /// period, or removed (`None`), instead of being shifted.
+    pub fn with_exceptions(mut self, exceptions: BTreeMap<Date, Option<Date>>) -> Self {
+        self.exceptions = exceptions;
+        self
+    }
+
+    fn shift(&self, date: Date) -> Date {
+        if self.days >= 0 {
+            date + Days::new(self.days as u64)
+        } else {
+            date - Days::new(self.days.unsigned_abs())
+        }
+    }
+
+    /// Date of the target period of a date, `None` if the date is outside
+    /// the source period or removed by an exception.
+    pub fn transpose_date(&self, date: Date) -> Option<Date> {
+        if let Some(&target_date) = self.exceptions.get(&date) {
+            return target_date;
+        }
+        if date < self.source.start_date || date > self.source.end_date {
+            return None;
+        }
+        Some(self.shift(date))
+    }
+
+    /// Period of the target period of a period, restricted to the source
+    /// period, `None` if it doesn't overlap the source period.
+    pub fn transpose_period(&self, start_date: Date, end_date: Date) -> Option<(Date, Date)> {
+        let start_date = cmp::max(start_date, self.source.start_date);
+        let end_date = cmp::min(end_date, self.source.end_date);
+        if start_date > end_date {
+            return None;
+        }
+        Some((self.shift(start_date), self.shift(end_date)))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DateException {
+    #[serde(deserialize_with = "de_from_date_string")]
+    source_date: Date,
+    #[serde(default, deserialize_with = "de_option_empty_string")]
+    target_date: Option<String>,
+}
+
+/// Read a CSV file of exceptions, with the columns `source_date` and
+/// `target_date` (in the `YYYYMMDD` format), an empty `target_date` removing
+/// the `source_date`.
+pub fn read_exceptions<P: AsRef<Path>>(path: P) -> Result<BTreeMap<Date, Option<Date>>> {
+    let path = path.as_ref();
+    info!("Reading date exceptions from {:?}", path);
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .with_context(|| format!("Error reading {path:?}"))?;
+    let mut exceptions = BTreeMap::new();
+    for exception in rdr.deserialize() {
+        let exception: DateException =
+            exception.with_context(|| format!("Error reading {path:?}"))?;
+        let target_date = exception
+            .target_date
+            .map(|date| Date::parse_from_str(&date, "%Y%m%d"))
+            .transpose()
+            .with_context(|| format!("Error reading {path:?}"))?;
+        exceptions.insert(exception.source_date, target_date);
+    }
+    Ok(exceptions)
+}
+
+/// Transpose the dates of the calendars, the validity periods of the datasets,
+/// the periods and exception dates of the grid calendars and the date ranges of
+/// the occupancies.
+///
+/// The dates outside the source period are removed, as well as the grid
+/// periods, the grid exception dates and the occupancies outside it. The
+/// validity period of a dataset outside the source period becomes the target
+/// period.
+pub fn transpose(collections: &mut Collections, transposition: &Transposition) {
+    for calendar_idx in collections.calendars.indexes() {
+        let mut calendar = collections.calendars.index_mut(calendar_idx);
+        calendar.dates = calendar
+            .dates
+            .iter()
+            .filter_map(|&date| transposition.transpose_date(date))
+            .collect();
+    }
+    for dataset_idx in collections.datasets.indexes() {
+        let mut dataset = collections.datasets.index_mut(dataset_idx);
+        let (start_date, end_date) = transposition
+            .transpose_period(dataset.start_date, dataset.end_date)
+            .unwrap_or_else(|| {
+                (
+                    transposition.shift(transposition.source.start_date),
+                    transposition.shift(transposition.source.end_date),
+                )
+            });
+        dataset.start_date = start_date;
+        dataset.end_date = end_date;
+    }
+
+    let mut grid_periods = collections.grid_periods.take();
+    grid_periods.retain_mut(|grid_period| {
+        match transposition.transpose_period(grid_period.start_date, grid_period.end_date) {
+            Some((start_date, end_date)) => {
+                grid_period.start_date = start_date;
+                grid_period.end_date = end_date;
+                true
+            }
+            None => false,
+        }
+    });
+    collections.grid_periods = Collection::new(grid_periods);
+
+    let mut grid_exception_dates = collections.grid_exception_dates.take();
+    grid_exception_dates.retain_mut(|grid_exception_date| {
+        match transposition.transpose_date(grid_exception_date.date) {
+            Some(date) => {
+                grid_exception_date.date = date;
+                true
+            }
+            None => false,
+        }
+    });
+    collections.grid_exception_dates = Collection::new(grid_exception_dates);
+
+    let mut occupancies = collections.occupancies.take();
+    occupancies.retain_mut(|occupancy| {
+        match transposition.transpose_period(occupancy.from_date, occupancy.to_date) {
+            Some((from_date, to_date)) => {
+                occupancy.from_date = from_date;
+                occupancy.to_date = to_date;
+                true
+            }
+            None => false,
+        }
+    });
+    collections.occupancies = Collection::new(occupancies);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        objects::{GridExceptionDate, GridPeriod},
+        ModelBuilder,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn date(date: &str) -> Date {
+        date.parse().unwrap()
+    }
+
+    // 2019-09-02 and 2020-08-31 are Mondays
+    fn transposition() -> Transposition {
+        Transposition::new(
+            ValidityPeriod {
+                start_date: date("2019-09-02"),
+                end_date: date("2020-07-04"),
+            },
+            date("2020-09-01"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn shift_by_whole_weeks() {
+        let transposition = transposition();
+        assert_eq!(
+            Some(date("2020-08-31")),
+            transposition.transpose_date(date("2019-09-02"))
+        );
+        assert_eq!(
+            Some(date("2020-12-24")),
+            transposition.transpose_date(date("2019-12-26"))
+        );
+        assert_eq!(None, transposition.transpose_date(date("2019-08-30")));
+        assert_eq!(None, transposition.transpose_date(date("2020-07-05")));
+    }
+
+    #[test]
+    fn exceptions_override_shift() {
+        let exceptions = BTreeMap::from([
+            (date("2019-12-25"), Some(date("2020-12-25"))),
+            (date("2019-12-26"), None),
+        ]);
+        let transposition = transposition().with_exceptions(exceptions);
+        assert_eq!(
+            Some(date("2020-12-25")),
+            transposition.transpose_date(date("2019-12-25"))
+        );
+        assert_eq!(None, transposition.transpose_date(date("2019-12-26")));
+    }
+
+    #[test]
+    fn transpose_collections() {
+        let mut collections = ModelBuilder::default()
+            .calendar("C", &["2019-08-30", "2019-09-02", "2019-09-03"])
+            .vj("VJ", |vj| {
+                vj.calendar("C").st("SP1", "10:00:00").st("SP2", "10:10:00");
+            })
+            .build()
+            .into_collections();
+        collections.grid_periods.push(GridPeriod {
+            grid_calendar_id: "GC1".to_string(),
+            start_date: date("2019-07-01"),
+            end_date: date("2019-12-31"),
+        });
+        collections.grid_periods.push(GridPeriod {
+            grid_calendar_id: "GC2".to_string(),
+            start_date: date("2019-07-01"),
+            end_date: date("2019-08-31"),
+        });
+        collections.grid_exception_dates.push(GridExceptionDate {
+            grid_calendar_id: "GC1".to_string(),
+            date: date("2019-12-26"),
+            r#type: false,
+        });
+        collections.grid_exception_dates.push(GridExceptionDate {
+            grid_calendar_id: "GC1".to_string(),
+            date: date("2019-08-15"),
+            r#type: false,
+        });
+        transpose(&mut collections, &transposition());
+
+        let dates: Vec<_> = collections
+            .calendars
+            .get("C")
+            .unwrap()
+            .dates
+            .iter()
+            .map(|date| date.to_string())
+            .collect();
+        assert_eq!(vec!["2020-08-31", "2020-09-01"], dates);
+        let grid_periods: Vec<_> = collections
+            .grid_periods
+            .values()
+            .map(|period| {
+                (
+                    period.grid_calendar_id.as_str(),
+                    period.start_date.to_string(),
+                    period.end_date.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![("GC1", "2020-08-31".to_string(), "2020-12-29".to_string())],
+            grid_periods
+        );
+        // The exception dates outside the source period are removed
+        let grid_exception_dates: Vec<_> = collections
+            .grid_exception_dates
+            .values()
+            .map(|exception| exception.date.to_string())
+            .collect();
+        assert_eq!(vec!["2020-12-24"], grid_exception_dates);
+    }
+}
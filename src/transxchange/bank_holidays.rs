@@ -0,0 +1,205 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Bank holidays of the United Kingdom, as named in the `BankHolidayOperation`
+//! of the TransXChange operating profiles.
+
+use crate::objects::Date;
+use chrono::{Datelike, Days, Weekday};
+
+/// Groups of bank holidays, with their members
+const GROUPS: &[(&str, &[&str])] = &[
+    (
+        "AllBankHolidays",
+        &[
+            "Christmas",
+            "AllHolidaysExceptChristmas",
+            "ChristmasDayHoliday",
+            "BoxingDayHoliday",
+        ],
+    ),
+    (
+        "AllHolidaysExceptChristmas",
+        &[
+            "NewYearsDay",
+            "Jan2ndScotland",
+            "GoodFriday",
+            "StAndrewsDay",
+            "HolidayMondays",
+            "NewYearsDayHoliday",
+            "Jan2ndScotlandHoliday",
+            "StAndrewsDayHoliday",
+        ],
+    ),
+    ("Christmas", &["ChristmasDay", "BoxingDay"]),
+    ("EarlyRunOff", &["ChristmasEve", "NewYearsEve"]),
+    (
+        "HolidayMondays",
+        &[
+            "EasterMonday",
+            "MayDay",
+            "SpringBank",
+            "LateSummerBankHolidayNotScotland",
+            "AugustBankHolidayScotland",
+        ],
+    ),
+    (
+        "DisplacementHolidays",
+        &[
+            "ChristmasDayHoliday",
+            "BoxingDayHoliday",
+            "NewYearsDayHoliday",
+            "Jan2ndScotlandHoliday",
+            "StAndrewsDayHoliday",
+        ],
+    ),
+];
+
+fn ymd(year: i32, month: u32, day: u32) -> Date {
+    Date::from_ymd_opt(year, month, day).expect("valid date")
+}
+
+fn add_days(date: Date, days: u64) -> Date {
+    date + Days::new(days)
+}
+
+/// Easter Sunday, with the anonymous Gregorian algorithm
+fn easter_sunday(year: i32) -> Date {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    ymd(year, month as u32, day as u32)
+}
+
+fn first_monday(year: i32, month: u32) -> Date {
+    let first = ymd(year, month, 1);
+    let days = (7 - first.weekday().num_days_from_monday()) % 7;
+    add_days(first, u64::from(days))
+}
+
+fn last_monday(year: i32, month: u32) -> Date {
+    let last = ymd(year, month + 1, 1).pred_opt().expect("valid date");
+    last - Days::new(u64::from(last.weekday().num_days_from_monday()))
+}
+
+/// Day off given instead of a holiday falling on a weekend, `days_if_saturday`
+/// or `days_if_sunday` days later.
+fn displacement(date: Date, days_if_saturday: u64, days_if_sunday: u64) -> Option<Date> {
+    match date.weekday() {
+        Weekday::Sat => Some(add_days(date, days_if_saturday)),
+        Weekday::Sun => Some(add_days(date, days_if_sunday)),
+        _ => None,
+    }
+}
+
+/// Dates of a bank holiday (e.g. `GoodFriday`) or of a group of bank holidays
+/// (e.g. `AllBankHolidays`) in a year, `None` if the name is unknown.
+///
+/// The displacement holidays are the days off given when a holiday falls on a
+/// weekend (e.g. `ChristmasDayHoliday` on the 27th of December when Christmas
+/// is on a Saturday or a Sunday).
+pub(super) fn bank_holidays(name: &str, year: i32) -> Option<Vec<Date>> {
+    if let Some((_, members)) = GROUPS.iter().find(|(group, _)| *group == name) {
+        let mut dates = Vec::new();
+        for member in members.iter() {
+            dates.extend(bank_holidays(member, year)?);
+        }
+        dates.sort();
+        dates.dedup();
+        return Some(dates);
+    }
+    let date = match name {
+        "ChristmasEve" => Some(ymd(year, 12, 24)),
+        "ChristmasDay" => Some(ymd(year, 12, 25)),
+        "BoxingDay" => Some(ymd(year, 12, 26)),
+        "NewYearsEve" => Some(ymd(year, 12, 31)),
+        "NewYearsDay" => Some(ymd(year, 1, 1)),
+        "Jan2ndScotland" => Some(ymd(year, 1, 2)),
+        "StAndrewsDay" => Some(ymd(year, 11, 30)),
+        "GoodFriday" => Some(easter_sunday(year) - Days::new(2)),
+        "EasterMonday" => Some(add_days(easter_sunday(year), 1)),
+        "MayDay" => Some(first_monday(year, 5)),
+        "SpringBank" => Some(last_monday(year, 5)),
+        "LateSummerBankHolidayNotScotland" => Some(last_monday(year, 8)),
+        "AugustBankHolidayScotland" => Some(first_monday(year, 8)),
+        // Christmas Day and Boxing Day are displaced one after the other
+        "ChristmasDayHoliday" => displacement(ymd(year, 12, 25), 2, 2),
+        "BoxingDayHoliday" => displacement(ymd(year, 12, 26), 2, 2),
+        "NewYearsDayHoliday" => displacement(ymd(year, 1, 1), 2, 1),
+        // When the 2nd of January is on a Sunday, New Year's Day is displaced
+        // to Monday
+        "Jan2ndScotlandHoliday" => displacement(ymd(year, 1, 2), 2, 2),
+        "StAndrewsDayHoliday" => displacement(ymd(year, 11, 30), 2, 1),
+        _ => return None,
+    };
+    Some(date.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn dates(name: &str, year: i32) -> Vec<String> {
+        bank_holidays(name, year)
+            .unwrap()
+            .iter()
+            .map(|date| date.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn easter() {
+        assert_eq!(vec!["2024-03-29"], dates("GoodFriday", 2024));
+        assert_eq!(vec!["2025-04-21"], dates("EasterMonday", 2025));
+    }
+
+    #[test]
+    fn displacement_holidays() {
+        // The 2nd of January and Christmas 2021 are on a Saturday, as well as the
+        // New Year's Day 2022
+        assert_eq!(
+            vec!["2021-01-04", "2021-12-27", "2021-12-28"],
+            dates("DisplacementHolidays", 2021)
+        );
+        assert_eq!(vec!["2022-01-03"], dates("NewYearsDayHoliday", 2022));
+        assert!(dates("ChristmasDayHoliday", 2024).is_empty());
+    }
+
+    #[test]
+    fn holiday_mondays() {
+        assert_eq!(
+            vec![
+                "2024-04-01",
+                "2024-05-06",
+                "2024-05-27",
+                "2024-08-05",
+                "2024-08-26"
+            ],
+            dates("HolidayMondays", 2024)
+        );
+        assert!(bank_holidays("NotAHoliday", 2024).is_none());
+    }
+}
@@ -0,0 +1,130 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! [TransXChange](https://www.gov.uk/government/collections/transxchange)
+//! format management, the standard for the bus timetables of the United
+//! Kingdom.
+
+mod bank_holidays;
+mod read;
+
+use crate::{
+    model::{Collections, Model},
+    objects::{Contributor, Dataset},
+    validity_period,
+    xml_reader::read_xml_files,
+    AddPrefix, PrefixConfiguration, Result,
+};
+use anyhow::Context;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use tracing::info;
+use typed_index_collection::CollectionWithId;
+
+///parameters consolidation
+#[derive(Default)]
+pub struct Configuration {
+    /// The Contributor providing the Dataset
+    pub contributor: Contributor,
+    /// Describe the Dataset being parsed
+    pub dataset: Dataset,
+    /// Additional key-values for the 'feed_infos.txt'
+    pub feed_infos: BTreeMap<String, String>,
+    /// used to prefix objects
+    pub prefix_conf: Option<PrefixConfiguration>,
+    /// `Stops.csv` file of the NaPTAN database, completing the names, the
+    /// coordinates and the NaPTAN codes of the stop points
+    pub naptan: Option<PathBuf>,
+}
+
+fn read_to_collections(path: &Path, configuration: Configuration) -> Result<Collections> {
+    let Configuration {
+        contributor,
+        mut dataset,
+        feed_infos,
+        prefix_conf,
+        naptan,
+    } = configuration;
+    let naptan = match naptan {
+        Some(naptan) => read::read_naptan(naptan)?,
+        None => Default::default(),
+    };
+
+    let mut collections = Collections::default();
+    for (file_path, content) in read_xml_files(path, "transxchange")? {
+        info!("Reading {:?}", file_path);
+        let document = roxmltree::Document::parse(&content)
+            .with_context(|| format!("Error reading {file_path:?}"))?;
+        read::read_document(&mut collections, &document, &naptan, &dataset.id)
+            .with_context(|| format!("Error reading {file_path:?}"))?;
+    }
+    validity_period::compute_dataset_validity_period(&mut dataset, &collections.calendars)?;
+
+    collections.contributors = CollectionWithId::from(contributor);
+    collections.datasets = CollectionWithId::from(dataset);
+    collections.feed_infos = feed_infos;
+
+    //add prefixes
+    if let Some(prefix_conf) = prefix_conf {
+        collections.prefix(&prefix_conf);
+    }
+
+    collections.calendar_deduplication();
+    Ok(collections)
+}
+
+/// Imports a `Model` from the
+/// [TransXChange](https://www.gov.uk/government/collections/transxchange)
+/// files of a directory, of a zip archive or from a single XML file.
+pub fn read<P: AsRef<Path>>(p: P) -> Result<Model> {
+    Reader::default().parse(p)
+}
+
+/// Structure to configure the TransXChange reading
+#[derive(Default)]
+pub struct Reader {
+    configuration: Configuration,
+}
+
+impl Reader {
+    /// Build a Reader with a custom configuration
+    pub fn new(configuration: Configuration) -> Self {
+        Self { configuration }
+    }
+
+    /// Imports a `Model` from the
+    /// [TransXChange](https://www.gov.uk/government/collections/transxchange)
+    /// files of a directory (with its subdirectories), of a zip archive or
+    /// from a single XML file.
+    ///
+    /// Each `Operator` gives a `Company` and a `Network`, each `Line` of a
+    /// `Service` a `Line` with a `Route` by direction of its journey
+    /// patterns, and each `VehicleJourney` a `VehicleJourney` running on the
+    /// dates of its operating profile (including the bank holidays).
+    pub fn parse(self, path: impl AsRef<Path>) -> Result<Model> {
+        let collections = self.parse_collections(path)?;
+        Model::new(collections)
+    }
+
+    /// Imports `Collections` from the
+    /// [TransXChange](https://www.gov.uk/government/collections/transxchange)
+    /// files like [Reader::parse].
+    pub fn parse_collections(self, path: impl AsRef<Path>) -> Result<Collections> {
+        let p = path.as_ref();
+        read_to_collections(p, self.configuration)
+            .with_context(|| format!("impossible to read transxchange from {p:?}"))
+    }
+}
@@ -0,0 +1,938 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use super::bank_holidays::bank_holidays;
+use crate::{
+    model::Collections,
+    objects::{
+        Calendar, CommercialMode, Company, Coord, Date, KeysValues, Line, Network, PhysicalMode,
+        Route, StopArea, StopPoint, StopTime, StopTimePrecision, StopType, Time, ValidityPeriod,
+        VehicleJourney,
+    },
+    utils::required_stop_point_coord,
+    xml_reader::{child, children, elements, optional_duration, required_text, text},
+    Result,
+};
+use anyhow::{anyhow, bail, Context};
+use chrono::{Datelike, Days, Weekday};
+use roxmltree::{Document, Node};
+use serde::Deserialize;
+use skip_error::skip_error_and_warn;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::Path,
+};
+use tracing::{info, warn};
+
+/// Validity of the services without an end date, in days
+const DEFAULT_VALIDITY_DAYS: u64 = 365;
+const WEEK: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// Stop of the NaPTAN (National Public Transport Access Nodes) database
+#[derive(Debug, Deserialize)]
+pub(super) struct NaptanStop {
+    #[serde(rename = "ATCOCode")]
+    atco_code: String,
+    #[serde(rename = "NaptanCode")]
+    naptan_code: Option<String>,
+    #[serde(rename = "CommonName")]
+    common_name: Option<String>,
+    #[serde(rename = "Longitude")]
+    longitude: Option<f64>,
+    #[serde(rename = "Latitude")]
+    latitude: Option<f64>,
+}
+
+/// NaPTAN stops by ATCO code
+pub(super) type NaptanStops = HashMap<String, NaptanStop>;
+
+/// Read the `Stops.csv` file of the NaPTAN database.
+pub(super) fn read_naptan<P: AsRef<Path>>(path: P) -> Result<NaptanStops> {
+    let path = path.as_ref();
+    info!("Reading NaPTAN stops from {:?}", path);
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .with_context(|| format!("Error reading {path:?}"))?;
+    let mut stops = NaptanStops::new();
+    for stop in rdr.deserialize() {
+        let stop: NaptanStop = stop.with_context(|| format!("Error reading {path:?}"))?;
+        stops.insert(stop.atco_code.clone(), stop);
+    }
+    Ok(stops)
+}
+
+fn parse_date(date: &str) -> Result<Date> {
+    date.parse()
+        .with_context(|| format!("invalid date {date:?}"))
+}
+
+fn coord(node: Node) -> Option<Coord> {
+    let coordinate = |name: &str| {
+        node.descendants()
+            .find(|descendant| descendant.has_tag_name(name))
+            .and_then(|descendant| descendant.text())
+            .and_then(|text| text.trim().parse().ok())
+    };
+    Some(Coord {
+        lon: coordinate("Longitude")?,
+        lat: coordinate("Latitude")?,
+    })
+}
+
+fn push_stop_point(
+    collections: &mut Collections,
+    atco_code: &str,
+    name: Option<&str>,
+    naptan_code: Option<&str>,
+    coord: Option<Coord>,
+    naptan: &NaptanStops,
+) -> Result<()> {
+    let naptan_stop = naptan.get(atco_code);
+    let name = name
+        .or_else(|| naptan_stop.and_then(|stop| stop.common_name.as_deref()))
+        .unwrap_or(atco_code);
+    let naptan_code =
+        naptan_code.or_else(|| naptan_stop.and_then(|stop| stop.naptan_code.as_deref()));
+    let coord = required_stop_point_coord(
+        atco_code,
+        coord.or_else(|| {
+            let stop = naptan_stop?;
+            Some(Coord {
+                lon: stop.longitude?,
+                lat: stop.latitude?,
+            })
+        }),
+    )?;
+    let mut codes = KeysValues::default();
+    codes.insert(("naptan_atco_code".to_string(), atco_code.to_string()));
+    if let Some(naptan_code) = naptan_code {
+        codes.insert(("naptan_code".to_string(), naptan_code.to_string()));
+    }
+    let mut stop_point = StopPoint {
+        id: atco_code.to_string(),
+        name: name.to_string(),
+        code: naptan_code.map(str::to_string),
+        codes,
+        coord,
+        visible: true,
+        stop_type: StopType::Point,
+        ..Default::default()
+    };
+    let stop_area = StopArea::from(stop_point.clone());
+    stop_point.stop_area_id.clone_from(&stop_area.id);
+    if !collections.stop_areas.contains_id(&stop_area.id) {
+        collections.stop_areas.push(stop_area)?;
+    }
+    collections.stop_points.push(stop_point)?;
+    Ok(())
+}
+
+/// Stop points declared in a document, either as `AnnotatedStopPointRef`
+/// (referencing the NaPTAN database) or as complete `StopPoint`
+fn read_stop_points(collections: &mut Collections, root: Node, naptan: &NaptanStops) -> Result<()> {
+    let stop_points = match child(root, "StopPoints") {
+        Some(stop_points) => stop_points,
+        None => return Ok(()),
+    };
+    for stop_point in elements(stop_points) {
+        let (atco_code, name, naptan_code) = match stop_point.tag_name().name() {
+            "AnnotatedStopPointRef" => (
+                skip_error_and_warn!(required_text(stop_point, &["StopPointRef"])),
+                text(stop_point, &["CommonName"]),
+                None,
+            ),
+            "StopPoint" => (
+                skip_error_and_warn!(required_text(stop_point, &["AtcoCode"])),
+                text(stop_point, &["Descriptor", "CommonName"]),
+                text(stop_point, &["NaptanCode"]),
+            ),
+            _ => continue,
+        };
+        if collections.stop_points.contains_id(atco_code) {
+            continue;
+        }
+        skip_error_and_warn!(push_stop_point(
+            collections,
+            atco_code,
+            name,
+            naptan_code,
+            coord(stop_point),
+            naptan,
+        ));
+    }
+    Ok(())
+}
+
+/// Companies and networks of the operators, returning the identifier of each
+/// operator of the document
+fn read_operators<'a>(
+    collections: &mut Collections,
+    root: Node<'a, '_>,
+) -> Result<HashMap<&'a str, String>> {
+    let mut operator_ids = HashMap::new();
+    let operators = match child(root, "Operators") {
+        Some(operators) => operators,
+        None => return Ok(operator_ids),
+    };
+    for operator in elements(operators) {
+        let reference = skip_error_and_warn!(operator
+            .attribute("id")
+            .ok_or_else(|| anyhow!("missing id of operator")));
+        let id = text(operator, &["NationalOperatorCode"])
+            .or_else(|| text(operator, &["OperatorCode"]))
+            .unwrap_or(reference)
+            .to_string();
+        let name = text(operator, &["TradingName"])
+            .or_else(|| text(operator, &["OperatorShortName"]))
+            .or_else(|| text(operator, &["OperatorNameOnLicence"]))
+            .unwrap_or(&id)
+            .to_string();
+        if !collections.companies.contains_id(&id) {
+            let mut codes = KeysValues::default();
+            codes.insert(("source".to_string(), reference.to_string()));
+            collections.companies.push(Company {
+                id: id.clone(),
+                name: name.clone(),
+                phone: text(operator, &["ContactTelephoneNumber", "TelNationalNumber"])
+                    .or_else(|| text(operator, &["EnquiryTelephoneNumber", "TelNationalNumber"]))
+                    .map(str::to_string),
+                url: text(operator, &["WebSite"]).map(str::to_string),
+                codes,
+                ..Default::default()
+            })?;
+        }
+        if !collections.networks.contains_id(&id) {
+            collections.networks.push(Network {
+                id: id.clone(),
+                name,
+                url: text(operator, &["WebSite"]).map(str::to_string),
+                timezone: Some(chrono_tz::Europe::London),
+                ..Default::default()
+            })?;
+        }
+        operator_ids.insert(reference, id);
+    }
+    Ok(operator_ids)
+}
+
+/// Physical and commercial mode of a `Mode` of TransXChange
+fn mode(mode: Option<&str>) -> &'static str {
+    match mode {
+        Some("coach") => "Coach",
+        Some("tram") => "Tramway",
+        Some("underground") | Some("metro") => "Metro",
+        Some("rail") => "Train",
+        Some("ferry") => "Ferry",
+        _ => "Bus",
+    }
+}
+
+fn push_modes(collections: &mut Collections, mode: &str) -> Result<()> {
+    if !collections.commercial_modes.contains_id(mode) {
+        collections.commercial_modes.push(CommercialMode {
+            id: mode.to_string(),
+            name: mode.to_string(),
+        })?;
+    }
+    if !collections.physical_modes.contains_id(mode) {
+        collections.physical_modes.push(PhysicalMode {
+            id: mode.to_string(),
+            name: mode.to_string(),
+            co2_emission: None,
+        })?;
+    }
+    Ok(())
+}
+
+/// Usage of a stop point by a timing link
+struct StopUsage<'a> {
+    stop_point_ref: &'a str,
+    activity: Option<&'a str>,
+    timing_status: Option<&'a str>,
+    wait_time: u32,
+}
+
+impl<'a> StopUsage<'a> {
+    fn read(node: Node<'a, '_>) -> Result<Self> {
+        Ok(StopUsage {
+            stop_point_ref: required_text(node, &["StopPointRef"])?,
+            activity: text(node, &["Activity"]),
+            timing_status: text(node, &["TimingStatus"]),
+            wait_time: optional_duration(node, &["WaitTime"])?.unwrap_or_default(),
+        })
+    }
+}
+
+struct TimingLink<'a> {
+    id: Option<&'a str>,
+    from: StopUsage<'a>,
+    to: StopUsage<'a>,
+    run_time: u32,
+}
+
+/// Timing links of each journey pattern section
+fn read_journey_pattern_sections<'a>(
+    root: Node<'a, '_>,
+) -> Result<HashMap<&'a str, Vec<TimingLink<'a>>>> {
+    let mut sections = HashMap::new();
+    let journey_pattern_sections = match child(root, "JourneyPatternSections") {
+        Some(sections) => sections,
+        None => return Ok(sections),
+    };
+    for section in children(journey_pattern_sections, "JourneyPatternSection") {
+        let id = skip_error_and_warn!(section
+            .attribute("id")
+            .ok_or_else(|| anyhow!("missing id of journey pattern section")));
+        let mut links = Vec::new();
+        for link in children(section, "JourneyPatternTimingLink") {
+            let from = child(link, "From").ok_or_else(|| anyhow!("missing From in {id}"))?;
+            let to = child(link, "To").ok_or_else(|| anyhow!("missing To in {id}"))?;
+            links.push(TimingLink {
+                id: link.attribute("id"),
+                from: StopUsage::read(from)?,
+                to: StopUsage::read(to)?,
+                run_time: optional_duration(link, &["RunTime"])?.unwrap_or_default(),
+            });
+        }
+        sections.insert(id, links);
+    }
+    Ok(sections)
+}
+
+struct JourneyPattern<'a> {
+    backward: bool,
+    section_refs: Vec<&'a str>,
+    destination_display: Option<&'a str>,
+    operator_ref: Option<&'a str>,
+}
+
+struct Service<'a, 'input> {
+    lines: Vec<(&'a str, String)>,
+    journey_patterns: HashMap<&'a str, JourneyPattern<'a>>,
+    period: ValidityPeriod,
+    operating_profile: Option<Node<'a, 'input>>,
+    operator_ref: Option<&'a str>,
+    mode: &'static str,
+}
+
+/// Lines of a service, with its journey patterns
+fn read_service<'a, 'input>(
+    collections: &mut Collections,
+    service: Node<'a, 'input>,
+    operator_ids: &HashMap<&str, String>,
+) -> Result<(&'a str, Service<'a, 'input>)> {
+    let service_code = required_text(service, &["ServiceCode"])?;
+    let start_date = parse_date(required_text(service, &["OperatingPeriod", "StartDate"])?)?;
+    let end_date = match text(service, &["OperatingPeriod", "EndDate"]) {
+        Some(end_date) => parse_date(end_date)?,
+        None => start_date + Days::new(DEFAULT_VALIDITY_DAYS),
+    };
+    let operator_ref = text(service, &["RegisteredOperatorRef"]);
+    let network_id = operator_ref
+        .and_then(|operator_ref| operator_ids.get(operator_ref))
+        .or_else(|| operator_ids.values().min())
+        .ok_or_else(|| anyhow!("no operator for service {service_code}"))?;
+    let mode = mode(text(service, &["Mode"]));
+    push_modes(collections, mode)?;
+
+    let mut lines = Vec::new();
+    for line in child(service, "Lines")
+        .into_iter()
+        .flat_map(|lines| children(lines, "Line"))
+    {
+        let reference = line.attribute("id").unwrap_or(service_code);
+        let line_id = format!("{service_code}:{reference}");
+        // A service can be split across several documents
+        if !collections.lines.contains_id(&line_id) {
+            let name = text(line, &["LineName"]).unwrap_or(reference);
+            let mut codes = KeysValues::default();
+            codes.insert(("source".to_string(), reference.to_string()));
+            collections.lines.push(Line {
+                id: line_id.clone(),
+                code: Some(name.to_string()),
+                codes,
+                name: text(service, &["Description"]).unwrap_or(name).to_string(),
+                forward_name: text(line, &["OutboundDescription", "Description"])
+                    .map(str::to_string),
+                backward_name: text(line, &["InboundDescription", "Description"])
+                    .map(str::to_string),
+                network_id: network_id.clone(),
+                commercial_mode_id: mode.to_string(),
+                ..Default::default()
+            })?;
+        }
+        lines.push((reference, line_id));
+    }
+    if lines.is_empty() {
+        bail!("no line in service {service_code}");
+    }
+
+    let mut journey_patterns = HashMap::new();
+    for journey_pattern in child(service, "StandardService")
+        .into_iter()
+        .flat_map(|standard_service| children(standard_service, "JourneyPattern"))
+    {
+        let id = skip_error_and_warn!(journey_pattern
+            .attribute("id")
+            .ok_or_else(|| anyhow!("missing id of journey pattern in {service_code}")));
+        let section_refs = children(journey_pattern, "JourneyPatternSectionRefs")
+            .filter_map(|section_refs| section_refs.text())
+            .flat_map(str::split_whitespace)
+            .collect();
+        journey_patterns.insert(
+            id,
+            JourneyPattern {
+                backward: matches!(
+                    text(journey_pattern, &["Direction"]),
+                    Some("inbound") | Some("antiClockwise")
+                ),
+                section_refs,
+                destination_display: text(journey_pattern, &["DestinationDisplay"]),
+                operator_ref: text(journey_pattern, &["OperatorRef"]),
+            },
+        );
+    }
+
+    Ok((
+        service_code,
+        Service {
+            lines,
+            journey_patterns,
+            period: ValidityPeriod {
+                start_date,
+                end_date,
+            },
+            operating_profile: child(service, "OperatingProfile"),
+            operator_ref,
+            mode,
+        },
+    ))
+}
+
+fn days_of_week(days_of_week: Node) -> HashSet<Weekday> {
+    let mut weekdays = HashSet::new();
+    for day in elements(days_of_week) {
+        let name = day.tag_name().name();
+        match name {
+            "MondayToFriday" => weekdays.extend(&WEEK[..5]),
+            "MondayToSaturday" => weekdays.extend(&WEEK[..6]),
+            "MondayToSunday" => weekdays.extend(&WEEK),
+            "Weekend" => weekdays.extend(&WEEK[5..]),
+            _ => match name.strip_prefix("Not") {
+                Some(excluded) => match excluded.parse::<Weekday>() {
+                    Ok(excluded) => weekdays.extend(WEEK.iter().filter(|&&day| day != excluded)),
+                    Err(_) => warn!("unknown day of week {}", name),
+                },
+                None => match name.parse::<Weekday>() {
+                    Ok(weekday) => {
+                        weekdays.insert(weekday);
+                    }
+                    Err(_) => warn!("unknown day of week {}", name),
+                },
+            },
+        }
+    }
+    weekdays
+}
+
+fn period_dates(period: &ValidityPeriod) -> impl Iterator<Item = Date> {
+    let end_date = period.end_date;
+    period
+        .start_date
+        .iter_days()
+        .take_while(move |&date| date <= end_date)
+}
+
+/// Dates of the `DateRange` of some special days
+fn special_days(special_days: Node) -> Result<Vec<Date>> {
+    let mut dates = Vec::new();
+    for date_range in children(special_days, "DateRange") {
+        let start_date = parse_date(required_text(date_range, &["StartDate"])?)?;
+        let end_date = match text(date_range, &["EndDate"]) {
+            Some(end_date) => parse_date(end_date)?,
+            None => start_date,
+        };
+        dates.extend(period_dates(&ValidityPeriod {
+            start_date,
+            end_date,
+        }));
+    }
+    Ok(dates)
+}
+
+/// Dates of the bank holidays of some days of (non-)operation in the period
+fn bank_holiday_dates(days: Node, period: &ValidityPeriod) -> Result<Vec<Date>> {
+    let mut dates = Vec::new();
+    for bank_holiday in elements(days) {
+        let name = bank_holiday.tag_name().name();
+        if name == "OtherPublicHoliday" {
+            dates.push(parse_date(required_text(bank_holiday, &["Date"])?)?);
+            continue;
+        }
+        for year in period.start_date.year()..=period.end_date.year() {
+            match bank_holidays(name, year) {
+                Some(holidays) => dates.extend(holidays),
+                None => {
+                    warn!("unknown bank holiday {}", name);
+                    break;
+                }
+            }
+        }
+    }
+    Ok(dates)
+}
+
+/// Dates of an operating profile in the operating period of a service.
+///
+/// The days of week of the `RegularDayType` (every day if not specified) are
+/// completed by the bank holidays and special days of operation, then the
+/// bank holidays and special days of non-operation are removed.
+fn operating_dates(profile: Option<Node>, period: &ValidityPeriod) -> Result<BTreeSet<Date>> {
+    let profile = match profile {
+        Some(profile) => profile,
+        None => return Ok(period_dates(period).collect()),
+    };
+    let weekdays = match child(profile, "RegularDayType") {
+        Some(regular_day_type) if child(regular_day_type, "HolidaysOnly").is_some() => {
+            HashSet::new()
+        }
+        Some(regular_day_type) => match child(regular_day_type, "DaysOfWeek") {
+            Some(days) => days_of_week(days),
+            None => WEEK.iter().copied().collect(),
+        },
+        None => WEEK.iter().copied().collect(),
+    };
+    let mut dates: BTreeSet<Date> = period_dates(period)
+        .filter(|date| weekdays.contains(&date.weekday()))
+        .collect();
+    let in_period = |date: &Date| period.start_date <= *date && *date <= period.end_date;
+    if let Some(operation) = child(profile, "BankHolidayOperation") {
+        if let Some(days) = child(operation, "DaysOfOperation") {
+            dates.extend(
+                bank_holiday_dates(days, period)?
+                    .into_iter()
+                    .filter(in_period),
+            );
+        }
+        if let Some(days) = child(operation, "DaysOfNonOperation") {
+            for date in bank_holiday_dates(days, period)? {
+                dates.remove(&date);
+            }
+        }
+    }
+    if let Some(operation) = child(profile, "SpecialDaysOperation") {
+        if let Some(days) = child(operation, "DaysOfOperation") {
+            dates.extend(special_days(days)?.into_iter().filter(in_period));
+        }
+        if let Some(days) = child(operation, "DaysOfNonOperation") {
+            for date in special_days(days)? {
+                dates.remove(&date);
+            }
+        }
+    }
+    Ok(dates)
+}
+
+/// Pickup and drop-off types of an `Activity`, the first stop being
+/// `pickUp` and the last stop `setDown` by default
+fn pickup_drop_off_types(activity: Option<&str>, first: bool, last: bool) -> (u8, u8) {
+    let activity = activity.unwrap_or(match (first, last) {
+        (true, _) => "pickUp",
+        (_, true) => "setDown",
+        _ => "pickUpAndSetDown",
+    });
+    match activity {
+        "pickUp" => (0, 1),
+        "setDown" => (1, 0),
+        "pass" => (1, 1),
+        _ => (0, 0),
+    }
+}
+
+/// Run time and wait times of a timing link, overridden by the vehicle journey
+struct LinkTimes {
+    run_time: u32,
+    from_wait_time: u32,
+    to_wait_time: u32,
+}
+
+fn stop_times(
+    collections: &Collections,
+    vehicle_journey: Node,
+    links: &[&TimingLink],
+    departure_time: Time,
+) -> Result<Vec<StopTime>> {
+    let mut overrides = HashMap::new();
+    for timing_link in children(vehicle_journey, "VehicleJourneyTimingLink") {
+        if let Some(link_ref) = text(timing_link, &["JourneyPatternTimingLinkRef"]) {
+            overrides.insert(link_ref, timing_link);
+        }
+    }
+    let mut times = Vec::new();
+    for link in links {
+        let (run_time, from_wait_time, to_wait_time) =
+            match link.id.and_then(|id| overrides.get(id)) {
+                Some(&timing_link) => (
+                    optional_duration(timing_link, &["RunTime"])?,
+                    optional_duration(timing_link, &["From", "WaitTime"])?,
+                    optional_duration(timing_link, &["To", "WaitTime"])?,
+                ),
+                None => (None, None, None),
+            };
+        times.push(LinkTimes {
+            run_time: run_time.unwrap_or(link.run_time),
+            from_wait_time: from_wait_time.unwrap_or(link.from.wait_time),
+            to_wait_time: to_wait_time.unwrap_or(link.to.wait_time),
+        });
+    }
+
+    let stop_count = links.len() + 1;
+    let mut stop_times = Vec::with_capacity(stop_count);
+    let mut time = departure_time;
+    for index in 0..stop_count {
+        // The stop point is the start of the following link, or the end of
+        // the last link. The vehicle journey leaves the first stop point at
+        // its departure time.
+        let (usage, wait_time) = match (links.get(index), index.checked_sub(1)) {
+            (Some(link), None) => (&link.from, 0),
+            (Some(link), Some(previous)) => {
+                time = time + Time::new(0, 0, times[previous].run_time);
+                (
+                    &link.from,
+                    times[previous].to_wait_time + times[index].from_wait_time,
+                )
+            }
+            (None, Some(previous)) => {
+                time = time + Time::new(0, 0, times[previous].run_time);
+                (&links[previous].to, times[previous].to_wait_time)
+            }
+            (None, None) => unreachable!("at least one timing link"),
+        };
+        let stop_point_idx = collections
+            .stop_points
+            .get_idx(usage.stop_point_ref)
+            .ok_or_else(|| anyhow!("unknown stop point {}", usage.stop_point_ref))?;
+        let (pickup_type, drop_off_type) =
+            pickup_drop_off_types(usage.activity, index == 0, index + 1 == stop_count);
+        let arrival_time = time;
+        time = time + Time::new(0, 0, wait_time);
+        stop_times.push(StopTime {
+            stop_point_idx,
+            sequence: index as u32,
+            arrival_time: Some(arrival_time),
+            departure_time: Some(time),
+            start_pickup_drop_off_window: None,
+            end_pickup_drop_off_window: None,
+            boarding_duration: 0,
+            alighting_duration: 0,
+            pickup_type,
+            drop_off_type,
+            local_zone_id: None,
+            precision: Some(match usage.timing_status {
+                Some("OTH") => StopTimePrecision::Approximate,
+                _ => StopTimePrecision::Exact,
+            }),
+            shape_dist_traveled: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
+        });
+    }
+    Ok(stop_times)
+}
+
+fn push_route(collections: &mut Collections, line_id: &str, backward: bool) -> Result<String> {
+    let direction = if backward { "backward" } else { "forward" };
+    let route_id = format!("{line_id}:{direction}");
+    if !collections.routes.contains_id(&route_id) {
+        let line = collections
+            .lines
+            .get(line_id)
+            .ok_or_else(|| anyhow!("unknown line {line_id}"))?;
+        let name = if backward {
+            &line.backward_name
+        } else {
+            &line.forward_name
+        };
+        collections.routes.push(Route {
+            id: route_id.clone(),
+            name: name.clone().unwrap_or_else(|| line.name.clone()),
+            direction_type: Some(direction.to_string()),
+            line_id: line_id.to_string(),
+            ..Default::default()
+        })?;
+    }
+    Ok(route_id)
+}
+
+/// Identifier of a vehicle journey, `<id>:<n>` when the same code is used by
+/// several vehicle journeys of a service (e.g. in several documents).
+fn unique_vehicle_journey_id(collections: &Collections, id: String) -> String {
+    let is_used = |id: &str| {
+        collections.vehicle_journeys.contains_id(id) || collections.calendars.contains_id(id)
+    };
+    if !is_used(&id) {
+        return id;
+    }
+    let unique_id = (2..)
+        .map(|n| format!("{id}:{n}"))
+        .find(|unique_id| !is_used(unique_id))
+        .expect("an unused identifier");
+    warn!(
+        "vehicle journey {} already exists, the duplicate is identified by {}",
+        id, unique_id
+    );
+    unique_id
+}
+
+fn read_vehicle_journey(
+    collections: &mut Collections,
+    vehicle_journey: Node,
+    referenced_journeys: &HashMap<&str, Node>,
+    services: &HashMap<&str, Service>,
+    sections: &HashMap<&str, Vec<TimingLink>>,
+    operator_ids: &HashMap<&str, String>,
+    dataset_id: &str,
+) -> Result<()> {
+    let code = required_text(vehicle_journey, &["VehicleJourneyCode"])?;
+    let service_ref = required_text(vehicle_journey, &["ServiceRef"])?;
+    let id = format!("{service_ref}:{code}");
+    let service = services
+        .get(service_ref)
+        .ok_or_else(|| anyhow!("vehicle journey {id}: unknown service {service_ref}"))?;
+    // A vehicle journey can reuse the journey pattern of another one
+    let journey_pattern_ref = match text(vehicle_journey, &["JourneyPatternRef"]) {
+        Some(journey_pattern_ref) => journey_pattern_ref,
+        None => text(vehicle_journey, &["VehicleJourneyRef"])
+            .and_then(|vehicle_journey_ref| referenced_journeys.get(vehicle_journey_ref))
+            .and_then(|&referenced| text(referenced, &["JourneyPatternRef"]))
+            .ok_or_else(|| anyhow!("vehicle journey {id}: missing JourneyPatternRef"))?,
+    };
+    let journey_pattern = service
+        .journey_patterns
+        .get(journey_pattern_ref)
+        .ok_or_else(|| {
+            anyhow!("vehicle journey {id}: unknown journey pattern {journey_pattern_ref}")
+        })?;
+    let line_id = match text(vehicle_journey, &["LineRef"]) {
+        Some(line_ref) => service
+            .lines
+            .iter()
+            .find(|(reference, _)| *reference == line_ref)
+            .map(|(_, line_id)| line_id)
+            .ok_or_else(|| anyhow!("vehicle journey {id}: unknown line {line_ref}"))?,
+        None => &service.lines[0].1,
+    };
+    let company_id = text(vehicle_journey, &["OperatorRef"])
+        .or(journey_pattern.operator_ref)
+        .or(service.operator_ref)
+        .and_then(|operator_ref| operator_ids.get(operator_ref))
+        .or_else(|| operator_ids.values().min())
+        .ok_or_else(|| anyhow!("vehicle journey {id}: no operator"))?;
+
+    let profile = child(vehicle_journey, "OperatingProfile").or(service.operating_profile);
+    let dates = operating_dates(profile, &service.period)
+        .with_context(|| format!("vehicle journey {id}: invalid operating profile"))?;
+    if dates.is_empty() {
+        warn!("vehicle journey {}: no date of operation", id);
+        return Ok(());
+    }
+
+    let links = journey_pattern
+        .section_refs
+        .iter()
+        .map(|section_ref| {
+            sections
+                .get(section_ref)
+                .ok_or_else(|| anyhow!("vehicle journey {id}: unknown section {section_ref}"))
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    if links.is_empty() {
+        bail!("vehicle journey {id}: no timing link");
+    }
+    let mut departure_time: Time = required_text(vehicle_journey, &["DepartureTime"])?
+        .parse()
+        .with_context(|| format!("vehicle journey {id}: invalid DepartureTime"))?;
+    if let Some(day_shift) = text(vehicle_journey, &["DepartureDayShift"]) {
+        let day_shift: u32 = day_shift
+            .parse()
+            .with_context(|| format!("vehicle journey {id}: invalid DepartureDayShift"))?;
+        departure_time = departure_time + Time::new(24 * day_shift, 0, 0);
+    }
+    let stop_times = stop_times(collections, vehicle_journey, &links, departure_time)
+        .with_context(|| format!("vehicle journey {id}: invalid timing links"))?;
+
+    let route_id = push_route(collections, line_id, journey_pattern.backward)?;
+    let id = unique_vehicle_journey_id(collections, id);
+    collections.calendars.push(Calendar {
+        id: id.clone(),
+        dates,
+    })?;
+    let mut codes = KeysValues::default();
+    codes.insert(("source".to_string(), code.to_string()));
+    collections.vehicle_journeys.push(VehicleJourney {
+        id: id.clone(),
+        codes,
+        route_id,
+        physical_mode_id: service.mode.to_string(),
+        dataset_id: dataset_id.to_string(),
+        service_id: id,
+        headsign: text(vehicle_journey, &["DestinationDisplay"])
+            .or(journey_pattern.destination_display)
+            .map(str::to_string),
+        company_id: company_id.clone(),
+        stop_times,
+        ..Default::default()
+    })?;
+    Ok(())
+}
+
+/// Read a TransXChange document into the collections.
+///
+/// The stop points not declared in the document are looked up in the NaPTAN
+/// stops.
+pub(super) fn read_document(
+    collections: &mut Collections,
+    document: &Document,
+    naptan: &NaptanStops,
+    dataset_id: &str,
+) -> Result<()> {
+    let root = document.root_element();
+    read_stop_points(collections, root, naptan)?;
+    let operator_ids = read_operators(collections, root)?;
+    let sections = read_journey_pattern_sections(root)?;
+
+    let mut services = HashMap::new();
+    for service in child(root, "Services")
+        .into_iter()
+        .flat_map(|services| children(services, "Service"))
+    {
+        let (service_code, service) =
+            skip_error_and_warn!(read_service(collections, service, &operator_ids));
+        services.insert(service_code, service);
+    }
+
+    // The stop points only referenced by the journey pattern sections
+    for links in sections.values() {
+        for link in links {
+            for stop_point_ref in [link.from.stop_point_ref, link.to.stop_point_ref] {
+                if !collections.stop_points.contains_id(stop_point_ref)
+                    && naptan.contains_key(stop_point_ref)
+                {
+                    skip_error_and_warn!(push_stop_point(
+                        collections,
+                        stop_point_ref,
+                        None,
+                        None,
+                        None,
+                        naptan
+                    ));
+                }
+            }
+        }
+    }
+
+    let vehicle_journeys: Vec<Node> = child(root, "VehicleJourneys")
+        .into_iter()
+        .flat_map(|vehicle_journeys| children(vehicle_journeys, "VehicleJourney"))
+        .collect();
+    let referenced_journeys: HashMap<&str, Node> = vehicle_journeys
+        .iter()
+        .filter_map(|&vehicle_journey| {
+            text(vehicle_journey, &["VehicleJourneyCode"]).map(|code| (code, vehicle_journey))
+        })
+        .collect();
+    for vehicle_journey in vehicle_journeys {
+        skip_error_and_warn!(read_vehicle_journey(
+            collections,
+            vehicle_journey,
+            &referenced_journeys,
+            &services,
+            &sections,
+            &operator_ids,
+            dataset_id,
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn dates(profile: &str) -> Vec<String> {
+        let document = Document::parse(profile).unwrap();
+        let period = ValidityPeriod {
+            start_date: "2021-12-20".parse().unwrap(),
+            end_date: "2022-01-09".parse().unwrap(),
+        };
+        operating_dates(Some(document.root_element()), &period)
+            .unwrap()
+            .iter()
+            .map(|date| date.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn operating_profile_with_bank_holidays() {
+        // Christmas 2021 and the New Year's Day 2022 are on a Saturday
+        let profile = r#"<OperatingProfile>
+            <RegularDayType><DaysOfWeek><Saturday/></DaysOfWeek></RegularDayType>
+            <BankHolidayOperation>
+                <DaysOfOperation><DisplacementHolidays/></DaysOfOperation>
+                <DaysOfNonOperation><Christmas/><NewYearsDay/></DaysOfNonOperation>
+            </BankHolidayOperation>
+            <SpecialDaysOperation>
+                <DaysOfNonOperation>
+                    <DateRange><StartDate>2022-01-08</StartDate></DateRange>
+                </DaysOfNonOperation>
+            </SpecialDaysOperation>
+        </OperatingProfile>"#;
+        assert_eq!(
+            vec!["2021-12-27", "2021-12-28", "2022-01-03", "2022-01-04"],
+            dates(profile)
+        );
+    }
+
+    #[test]
+    fn operating_profile_of_holidays_only() {
+        let profile = r#"<OperatingProfile>
+            <RegularDayType><HolidaysOnly/></RegularDayType>
+            <BankHolidayOperation>
+                <DaysOfOperation><ChristmasDay/><BoxingDay/></DaysOfOperation>
+            </BankHolidayOperation>
+        </OperatingProfile>"#;
+        assert_eq!(vec!["2021-12-25", "2021-12-26"], dates(profile));
+    }
+
+    #[test]
+    fn regular_days_of_week() {
+        let profile = r#"<OperatingProfile>
+            <RegularDayType><DaysOfWeek><NotSaturday/></DaysOfWeek></RegularDayType>
+        </OperatingProfile>"#;
+        assert_eq!(18, dates(profile).len());
+    }
+}
@@ -12,18 +12,55 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
-use crate::{file_handler::FileHandler, parser::read_objects};
+use crate::{
+    error::{object_type, DataError},
+    file_handler::{FileHandler, LenientFileHandler},
+    model::{Collections, Model},
+    objects::{Coord, StopPoint},
+    parser::read_objects,
+    report::{ReadReportCategory, Report},
+};
 use anyhow::Context;
-use skip_error::skip_error_and_warn;
 use std::{
     fs,
     io::{Read, Write},
     path,
 };
 use tracing::{debug, info};
-use typed_index_collection::{Collection, CollectionWithId, Id};
+use typed_index_collection::{Collection, CollectionWithId, Id, Idx};
 use walkdir::WalkDir;
 
+/// Coordinates of a stop point, required by the formats whose stop points
+/// are not located by a parent. A stop point without coordinates can't be
+/// used: the error is meant to skip it, along with the trips using it.
+pub(crate) fn required_stop_point_coord(
+    stop_point_id: &str,
+    coord: Option<Coord>,
+) -> crate::Result<Coord> {
+    coord.ok_or_else(|| {
+        DataError::InvalidCoordinates {
+            object_type: object_type::<StopPoint>(),
+            id: stop_point_id.to_string(),
+        }
+        .into()
+    })
+}
+
+/// Unwraps a `Result<_, DataError>`, or passes the error to
+/// `FileHandler::on_skipped_object` and continues the loop, so that the
+/// skipped object ends up in the report of a lenient reading.
+macro_rules! skip_error_and_report {
+    ($file_handler:expr, $result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(error) => {
+                $crate::file_handler::FileHandler::on_skipped_object(&mut *$file_handler, error);
+                continue;
+            }
+        }
+    };
+}
+
 pub fn zip_to<P, R>(source_path: P, zip_file: R) -> crate::Result<()>
 where
     P: AsRef<path::Path>,
@@ -54,6 +91,23 @@ where
     Ok(())
 }
 
+/// Pushes `object` read from `file` in `collection`, failing if its
+/// identifier already exists.
+pub(crate) fn push_object<T: Id<T>>(
+    collection: &mut CollectionWithId<T>,
+    object: T,
+    file: &str,
+) -> Result<Idx<T>, DataError> {
+    let id = object.id().to_string();
+    collection
+        .push(object)
+        .map_err(|_| DataError::DuplicateIdentifier {
+            file: file.to_string(),
+            object_type: object_type::<T>(),
+            id,
+        })
+}
+
 pub(crate) fn make_collection_with_id<T, H>(
     file_handler: &mut H,
     file: &str,
@@ -64,7 +118,7 @@ where
 {
     let mut collection = CollectionWithId::<T>::default();
     for object in read_objects::<_, T>(file_handler, file, true)? {
-        skip_error_and_warn!(collection.push(object));
+        skip_error_and_report!(file_handler, push_object(&mut collection, object, file));
     }
     Ok(collection)
 }
@@ -91,11 +145,33 @@ where
 {
     let mut collection = CollectionWithId::<T>::default();
     for object in read_objects::<_, T>(file_handler, file, false)? {
-        skip_error_and_warn!(collection.push(object));
+        skip_error_and_report!(file_handler, push_object(&mut collection, object, file));
     }
     Ok(collection)
 }
 
+/// Reads the collections with `read`, skipping the invalid objects instead
+/// of failing, and builds the model. The problems found in the data are
+/// recorded in the returned report.
+pub(crate) fn read_leniently<H, F>(
+    file_handler: H,
+    read: F,
+) -> crate::Result<(Model, Report<ReadReportCategory>)>
+where
+    for<'a> &'a mut H: FileHandler,
+    F: FnOnce(&mut LenientFileHandler<H>) -> crate::Result<Collections>,
+{
+    let mut file_handler = LenientFileHandler::new(file_handler);
+    let mut collections = read(&mut file_handler)?;
+    let mut errors = file_handler.into_errors();
+    errors.extend(collections.remove_invalid_objects());
+    let mut report = Report::default();
+    for error in &errors {
+        report.add_data_error(error);
+    }
+    Ok((Model::new(collections)?, report))
+}
+
 pub fn write_collection_with_id<T>(
     path: &path::Path,
     file: &str,
@@ -0,0 +1,878 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Quality checks on a [`Model`].
+//!
+//! The [`Model`] only guarantees that the data is consistent (every reference
+//! points to an existing object). A [`Validator`] runs semantic checks on top
+//! of it (impossible speeds, duplicated trips, misplaced stops...) and
+//! records the problems found in a [`Report`].
+//!
+//! Some problems can't exist in a [`Model`] because [`Model::new`] rejects or
+//! removes the faulty objects (stop times going backwards, calendars without
+//! dates, lines without trips). They are found by a [`CollectionsCheck`], run
+//! on the [`Collections`] before the [`Model`] is built.
+//!
+//! ```
+//! use transit_model::validator::{DuplicateTrips, EmptyCalendars, Validator};
+//!
+//! let collections = transit_model::ntfs::read_collections("tests/fixtures/minimal_ntfs/").unwrap();
+//! // All the built-in checks
+//! let (model, report) = Validator::default().validate_collections(collections).unwrap();
+//! // Only some checks
+//! let report = Validator::new().with_check(DuplicateTrips).validate(&model);
+//! println!("{}", serde_json::to_string_pretty(&report).unwrap());
+//! ```
+
+use crate::{
+    error::DataError,
+    model::{
+        Collections, Model, AIR_PHYSICAL_MODE, BOAT_PHYSICAL_MODE, BUS_PHYSICAL_MODE,
+        BUS_RAPID_TRANSIT_PHYSICAL_MODE, COACH_PHYSICAL_MODE, FERRY_PHYSICAL_MODE,
+        FUNICULAR_PHYSICAL_MODE, LOCAL_TRAIN_PHYSICAL_MODE, LONG_DISTANCE_TRAIN_PHYSICAL_MODE,
+        METRO_PHYSICAL_MODE, RAIL_SHUTTLE_PHYSICAL_MODE, RAPID_TRANSIT_PHYSICAL_MODE,
+        SHUTTLE_PHYSICAL_MODE, SUSPENDED_CABLE_CAR_PHYSICAL_MODE, TAXI_PHYSICAL_MODE,
+        TRAIN_PHYSICAL_MODE, TRAMWAY_PHYSICAL_MODE,
+    },
+    objects::{Coord, Line, StopPoint, Time, VehicleJourney},
+    report::{Report, ValidationReportCategory},
+    Result,
+};
+use rstar::{RTree, RTreeObject, AABB};
+use rust_decimal::prelude::ToPrimitive;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// A quality check on a [`Model`].
+///
+/// Implement this trait to add your own checks to a [`Validator`].
+pub trait Check {
+    /// Runs the check and records the problems found in `report`.
+    fn check(&self, model: &Model, report: &mut Report<ValidationReportCategory>);
+}
+
+/// A quality check on the [`Collections`] before the [`Model`] is built,
+/// for the problems which can't exist in a [`Model`].
+pub trait CollectionsCheck {
+    /// Runs the check and records the problems found in `report`.
+    fn check(&self, collections: &Collections, report: &mut Report<ValidationReportCategory>);
+}
+
+/// Runs a list of [`Check`] on a [`Model`], and a list of
+/// [`CollectionsCheck`] on the [`Collections`] it is built from.
+pub struct Validator {
+    collections_checks: Vec<Box<dyn CollectionsCheck>>,
+    checks: Vec<Box<dyn Check>>,
+}
+
+impl Default for Validator {
+    /// A validator with all the checks of this module, with their default
+    /// configuration.
+    fn default() -> Self {
+        Validator::with_all_checks(
+            OverlappingStops::default(),
+            StopPointsFarFromStopArea::default(),
+        )
+    }
+}
+
+impl Validator {
+    /// A validator without any check.
+    pub fn new() -> Self {
+        Validator {
+            collections_checks: Vec::new(),
+            checks: Vec::new(),
+        }
+    }
+
+    /// A validator with all the checks of this module, with the given
+    /// configuration for the checks on distances and the default one for the
+    /// others.
+    pub fn with_all_checks(
+        overlapping_stops: OverlappingStops,
+        stop_points_far_from_stop_area: StopPointsFarFromStopArea,
+    ) -> Self {
+        Validator::new()
+            .with_collections_check(BackwardStopTimes)
+            .with_collections_check(LinesWithoutService)
+            .with_collections_check(EmptyCalendars)
+            .with_check(SpeedOutliers::default())
+            .with_check(DuplicateTrips)
+            .with_check(overlapping_stops)
+            .with_check(stop_points_far_from_stop_area)
+            .with_check(MixedTimezones)
+    }
+
+    /// Add a check of the [`Model`] to the validator.
+    pub fn with_check<C: Check + 'static>(mut self, check: C) -> Self {
+        self.checks.push(Box::new(check));
+        self
+    }
+
+    /// Add a check of the [`Collections`] to the validator.
+    pub fn with_collections_check<C: CollectionsCheck + 'static>(mut self, check: C) -> Self {
+        self.collections_checks.push(Box::new(check));
+        self
+    }
+
+    /// Runs the checks of the [`Model`] on `model`.
+    pub fn validate(&self, model: &Model) -> Report<ValidationReportCategory> {
+        let mut report = Report::default();
+        for check in &self.checks {
+            check.check(model, &mut report);
+        }
+        report
+    }
+
+    /// Runs the checks of the [`Collections`] on `collections`, builds the
+    /// [`Model`] and runs the checks of the [`Model`] on it. The objects
+    /// which would make [`Model::new`] fail are removed and reported (see
+    /// [`Collections::remove_invalid_objects`]).
+    pub fn validate_collections(
+        &self,
+        collections: Collections,
+    ) -> Result<(Model, Report<ValidationReportCategory>)> {
+        self.validate_read_collections(collections, Vec::new())
+    }
+
+    /// Like [`Validator::validate_collections`], also reporting the
+    /// `read_errors` of the objects skipped while reading the collections
+    /// (see [`crate::ntfs::read_collections_with_errors`]).
+    pub fn validate_read_collections(
+        &self,
+        mut collections: Collections,
+        read_errors: Vec<DataError>,
+    ) -> Result<(Model, Report<ValidationReportCategory>)> {
+        let mut report = Report::default();
+        for check in &self.collections_checks {
+            check.check(&collections, &mut report);
+        }
+        let invalid_objects = collections.remove_invalid_objects();
+        for error in read_errors.into_iter().chain(invalid_objects) {
+            let category = ValidationReportCategory::InvalidObject;
+            match error {
+                DataError::InvalidCoordinates { .. } | DataError::DroppedTrip { .. } => {
+                    report.add_warning(error.to_string(), category)
+                }
+                _ => report.add_error(error.to_string(), category),
+            }
+        }
+        let model = Model::new(collections)?;
+        for check in &self.checks {
+            check.check(&model, &mut report);
+        }
+        Ok((model, report))
+    }
+}
+
+/// A (0, 0) coordinate means that the position of the stop is unknown.
+fn is_located(coord: &Coord) -> bool {
+    *coord != Coord::default()
+}
+
+/// Reports the trips running faster than the maximum speed of their
+/// physical mode between 2 consecutive stops.
+pub struct SpeedOutliers {
+    /// Maximum speed in km/h by physical mode identifier.
+    pub max_speeds: HashMap<String, f64>,
+    /// Maximum speed in km/h of the physical modes not in `max_speeds`.
+    pub default_max_speed: f64,
+}
+
+impl Default for SpeedOutliers {
+    fn default() -> Self {
+        let max_speeds = [
+            (AIR_PHYSICAL_MODE, 1000.),
+            (BOAT_PHYSICAL_MODE, 80.),
+            (BUS_PHYSICAL_MODE, 120.),
+            (BUS_RAPID_TRANSIT_PHYSICAL_MODE, 120.),
+            (COACH_PHYSICAL_MODE, 130.),
+            (FERRY_PHYSICAL_MODE, 80.),
+            (FUNICULAR_PHYSICAL_MODE, 50.),
+            (LOCAL_TRAIN_PHYSICAL_MODE, 200.),
+            (LONG_DISTANCE_TRAIN_PHYSICAL_MODE, 350.),
+            (METRO_PHYSICAL_MODE, 100.),
+            (RAIL_SHUTTLE_PHYSICAL_MODE, 200.),
+            (RAPID_TRANSIT_PHYSICAL_MODE, 160.),
+            (SHUTTLE_PHYSICAL_MODE, 120.),
+            (SUSPENDED_CABLE_CAR_PHYSICAL_MODE, 50.),
+            (TAXI_PHYSICAL_MODE, 150.),
+            (TRAIN_PHYSICAL_MODE, 350.),
+            (TRAMWAY_PHYSICAL_MODE, 80.),
+        ]
+        .iter()
+        .map(|&(mode, speed)| (mode.to_string(), speed))
+        .collect();
+        SpeedOutliers {
+            max_speeds,
+            default_max_speed: 350.,
+        }
+    }
+}
+
+impl Check for SpeedOutliers {
+    fn check(&self, model: &Model, report: &mut Report<ValidationReportCategory>) {
+        for vj in model.vehicle_journeys.values() {
+            let max_speed = self
+                .max_speeds
+                .get(&vj.physical_mode_id)
+                .copied()
+                .unwrap_or(self.default_max_speed);
+            // Only the fastest section of each trip is reported
+            let fastest = vj
+                .stop_times
+                .windows(2)
+                .filter_map(|w| {
+                    let from = &model.stop_points[w[0].stop_point_idx];
+                    let to = &model.stop_points[w[1].stop_point_idx];
+                    if !is_located(&from.coord) || !is_located(&to.coord) {
+                        return None;
+                    }
+                    let departure = w[0].departure_time?.total_seconds();
+                    let arrival = w[1].arrival_time?.total_seconds();
+                    // Times are often rounded to the minute
+                    let duration = arrival.saturating_sub(departure).max(60);
+                    // Distances along the geometry are more accurate than
+                    // the crow-fly distance when they are known
+                    let distance = match (w[0].shape_dist_traveled, w[1].shape_dist_traveled) {
+                        (Some(d0), Some(d1)) if d1 >= d0 => (d1 - d0).to_f64()?,
+                        _ => from.coord.distance_to(&to.coord),
+                    };
+                    let speed = distance / f64::from(duration) * 3.6;
+                    Some((speed, from, to))
+                })
+                .filter(|&(speed, _, _)| speed > max_speed)
+                .max_by(|(s1, _, _), (s2, _, _)| s1.total_cmp(s2));
+            if let Some((speed, from, to)) = fastest {
+                report.add_warning(
+                    format!(
+                        "trip {:?} runs at {:.0} km/h between stop points {:?} and {:?}, above {} km/h for physical mode {:?}",
+                        vj.id, speed, from.id, to.id, max_speed, vj.physical_mode_id
+                    ),
+                    ValidationReportCategory::SpeedOutlier,
+                );
+            }
+        }
+    }
+}
+
+/// Reports the trips with the same stop times (stop points and times) and
+/// the same active dates.
+pub struct DuplicateTrips;
+
+impl Check for DuplicateTrips {
+    fn check(&self, model: &Model, report: &mut Report<ValidationReportCategory>) {
+        type StopTimesKey<'a> = Vec<(&'a str, Option<Time>, Option<Time>)>;
+        let mut trips: BTreeMap<_, Vec<&str>> = BTreeMap::new();
+        for vj in model.vehicle_journeys.values() {
+            let dates = match model.calendars.get(&vj.service_id) {
+                Some(calendar) => &calendar.dates,
+                None => continue,
+            };
+            let stop_times: StopTimesKey = vj
+                .stop_times
+                .iter()
+                .map(|st| {
+                    let stop_point = &model.stop_points[st.stop_point_idx];
+                    (stop_point.id.as_str(), st.arrival_time, st.departure_time)
+                })
+                .collect();
+            trips
+                .entry((stop_times, dates))
+                .or_default()
+                .push(vj.id.as_str());
+        }
+        for vj_ids in trips.values().filter(|vj_ids| vj_ids.len() > 1) {
+            report.add_warning(
+                format!(
+                    "trips {:?} have the same stop times and active dates",
+                    vj_ids
+                ),
+                ValidationReportCategory::DuplicateTrip,
+            );
+        }
+    }
+}
+
+/// Reports the different stop points of a line which are closer than a
+/// given distance.
+pub struct OverlappingStops {
+    /// Minimum distance in meters between 2 stop points of a line.
+    pub min_distance: f64,
+}
+
+impl Default for OverlappingStops {
+    fn default() -> Self {
+        OverlappingStops { min_distance: 5. }
+    }
+}
+
+/// A stop point of a line in a R-tree, identified by its position in the
+/// stop points of the line.
+struct StopPointLocation {
+    position: usize,
+    coord: Coord,
+}
+
+impl RTreeObject for StopPointLocation {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point([self.coord.lon, self.coord.lat])
+    }
+}
+
+impl Check for OverlappingStops {
+    fn check(&self, model: &Model, report: &mut Report<ValidationReportCategory>) {
+        for (line_idx, line) in model.lines.iter() {
+            let mut stop_points: Vec<&StopPoint> = model
+                .get_corresponding_from_idx::<Line, StopPoint>(line_idx)
+                .into_iter()
+                .map(|sp_idx| &model.stop_points[sp_idx])
+                .filter(|sp| is_located(&sp.coord))
+                .collect();
+            stop_points.sort_unstable_by(|sp1, sp2| sp1.id.cmp(&sp2.id));
+            let rtree = RTree::bulk_load(
+                stop_points
+                    .iter()
+                    .enumerate()
+                    .map(|(position, sp)| StopPointLocation {
+                        position,
+                        coord: sp.coord,
+                    })
+                    .collect(),
+            );
+            for (i, sp1) in stop_points.iter().enumerate() {
+                // See `generate_missing_transfers_from_sp` for the conversion
+                // of the distance into degrees
+                let search_distance_lat = self.min_distance / 111_000.0;
+                let search_distance_lon =
+                    self.min_distance / (111_000.0 * sp1.coord.approx().cos_lat());
+                let search_box = AABB::from_corners(
+                    [
+                        sp1.coord.lon - search_distance_lon,
+                        sp1.coord.lat - search_distance_lat,
+                    ],
+                    [
+                        sp1.coord.lon + search_distance_lon,
+                        sp1.coord.lat + search_distance_lat,
+                    ],
+                );
+                let mut neighbours: Vec<usize> = rtree
+                    .locate_in_envelope(&search_box)
+                    .map(|location| location.position)
+                    .filter(|&position| position > i)
+                    .collect();
+                neighbours.sort_unstable();
+                for sp2 in neighbours.into_iter().map(|position| stop_points[position]) {
+                    let distance = sp1.coord.distance_to(&sp2.coord);
+                    if distance < self.min_distance {
+                        report.add_warning(
+                            format!(
+                                "stop points {:?} and {:?} of line {:?} are {:.1} meters apart",
+                                sp1.id, sp2.id, line.id, distance
+                            ),
+                            ValidationReportCategory::OverlappingStops,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Reports the stop times going backwards in time.
+pub struct BackwardStopTimes;
+
+impl CollectionsCheck for BackwardStopTimes {
+    fn check(&self, collections: &Collections, report: &mut Report<ValidationReportCategory>) {
+        fn report_backward(
+            report: &mut Report<ValidationReportCategory>,
+            vj: &VehicleJourney,
+            sequence: u32,
+            message: &str,
+        ) {
+            report.add_error(
+                format!(
+                    "trip {:?} goes backwards at stop sequence {}: {}",
+                    vj.id, sequence, message
+                ),
+                ValidationReportCategory::BackwardStopTimes,
+            );
+        }
+        for vj in collections.vehicle_journeys.values() {
+            for st in &vj.stop_times {
+                if let (Some(arrival), Some(departure)) = (st.arrival_time, st.departure_time) {
+                    if departure < arrival {
+                        report_backward(report, vj, st.sequence, "departure before arrival");
+                    }
+                }
+            }
+            for w in vj.stop_times.windows(2) {
+                if let (Some(departure), Some(arrival)) = (w[0].departure_time, w[1].arrival_time) {
+                    if arrival < departure {
+                        report_backward(
+                            report,
+                            vj,
+                            w[1].sequence,
+                            "arrival before the departure of the previous stop",
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Reports the stop points farther than a given distance from their stop
+/// area.
+pub struct StopPointsFarFromStopArea {
+    /// Maximum distance in meters between a stop point and its stop area.
+    pub max_distance: f64,
+}
+
+impl Default for StopPointsFarFromStopArea {
+    fn default() -> Self {
+        StopPointsFarFromStopArea { max_distance: 500. }
+    }
+}
+
+impl Check for StopPointsFarFromStopArea {
+    fn check(&self, model: &Model, report: &mut Report<ValidationReportCategory>) {
+        for sp in model.stop_points.values() {
+            let stop_area = match model.stop_areas.get(&sp.stop_area_id) {
+                Some(stop_area) => stop_area,
+                None => continue,
+            };
+            if !is_located(&sp.coord) || !is_located(&stop_area.coord) {
+                continue;
+            }
+            let distance = sp.coord.distance_to(&stop_area.coord);
+            if distance > self.max_distance {
+                report.add_warning(
+                    format!(
+                        "stop point {:?} is {:.0} meters away from its stop area {:?}",
+                        sp.id, distance, stop_area.id
+                    ),
+                    ValidationReportCategory::StopPointFarFromStopArea,
+                );
+            }
+        }
+    }
+}
+
+/// Reports the lines without any trip running on at least one day.
+pub struct LinesWithoutService;
+
+impl CollectionsCheck for LinesWithoutService {
+    fn check(&self, collections: &Collections, report: &mut Report<ValidationReportCategory>) {
+        let lines_with_service: HashSet<&str> = collections
+            .vehicle_journeys
+            .values()
+            .filter(|vj| {
+                collections
+                    .calendars
+                    .get(&vj.service_id)
+                    .is_some_and(|calendar| !calendar.dates.is_empty())
+            })
+            .filter_map(|vj| collections.routes.get(&vj.route_id))
+            .map(|route| route.line_id.as_str())
+            .collect();
+        for line in collections.lines.values() {
+            if !lines_with_service.contains(line.id.as_str()) {
+                report.add_warning(
+                    format!("line {:?} has no service on any day", line.id),
+                    ValidationReportCategory::LineWithoutService,
+                );
+            }
+        }
+    }
+}
+
+/// Reports the calendars without any active date.
+pub struct EmptyCalendars;
+
+impl CollectionsCheck for EmptyCalendars {
+    fn check(&self, collections: &Collections, report: &mut Report<ValidationReportCategory>) {
+        for calendar in collections.calendars.values() {
+            if calendar.dates.is_empty() {
+                report.add_warning(
+                    format!("calendar {:?} has no active date", calendar.id),
+                    ValidationReportCategory::EmptyCalendar,
+                );
+            }
+        }
+    }
+}
+
+/// Reports the models whose networks, stop areas and stop points have
+/// different timezones (see [`crate::model::Collections::normalize_timezones`]).
+/// The networks are listed by id, the stops are counted.
+pub struct MixedTimezones;
+
+#[derive(Default)]
+struct TimezoneUsers<'a> {
+    networks: Vec<&'a str>,
+    stop_areas: usize,
+    stop_points: usize,
+}
+
+impl std::fmt::Display for TimezoneUsers<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut users = Vec::new();
+        if !self.networks.is_empty() {
+            users.push(format!("networks {}", self.networks.join(", ")));
+        }
+        if self.stop_areas > 0 {
+            users.push(format!("{} stop areas", self.stop_areas));
+        }
+        if self.stop_points > 0 {
+            users.push(format!("{} stop points", self.stop_points));
+        }
+        write!(f, "{}", users.join("; "))
+    }
+}
+
+impl Check for MixedTimezones {
+    fn check(&self, model: &Model, report: &mut Report<ValidationReportCategory>) {
+        let mut users_by_timezone: BTreeMap<&str, TimezoneUsers> = BTreeMap::new();
+        for network in model.networks.values() {
+            if let Some(timezone) = network.timezone {
+                users_by_timezone
+                    .entry(timezone.name())
+                    .or_default()
+                    .networks
+                    .push(&network.id);
+            }
+        }
+        for timezone in model.stop_areas.values().filter_map(|sa| sa.timezone) {
+            users_by_timezone
+                .entry(timezone.name())
+                .or_default()
+                .stop_areas += 1;
+        }
+        for timezone in model.stop_points.values().filter_map(|sp| sp.timezone) {
+            users_by_timezone
+                .entry(timezone.name())
+                .or_default()
+                .stop_points += 1;
+        }
+        if users_by_timezone.len() > 1 {
+            let timezones: Vec<String> = users_by_timezone
+                .iter()
+                .map(|(timezone, users)| format!("{timezone} ({users})"))
+                .collect();
+            report.add_warning(
+                format!("different timezones are used: {}", timezones.join(", ")),
+                ValidationReportCategory::MixedTimezones,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{objects::StopArea, report::Severity, ModelBuilder};
+    use pretty_assertions::assert_eq;
+
+    fn located(lon: f64, lat: f64) -> impl FnMut(&mut StopPoint) {
+        move |sp| {
+            sp.coord = Coord { lon, lat };
+            sp.stop_area_id = "sa".to_string();
+        }
+    }
+
+    fn messages(
+        report: &Report<ValidationReportCategory>,
+        category: ValidationReportCategory,
+    ) -> Vec<String> {
+        let category = serde_json::to_value(category).unwrap();
+        let report = serde_json::to_value(report).unwrap();
+        ["errors", "warnings", "infos"]
+            .iter()
+            .flat_map(|severity| report[severity].as_array().unwrap())
+            .filter(|row| row["category"] == category)
+            .map(|row| row["message"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn speed_outlier() {
+        // SP1 and SP2 are ~11 km apart
+        let model = ModelBuilder::default()
+            .stop_area("sa", |_| {})
+            .stop_point("SP1", located(2.35, 48.85))
+            .stop_point("SP2", located(2.35, 48.95))
+            .stop_point("SP3", located(2.35, 49.05))
+            .vj("slow", |vj| {
+                vj.physical_mode(BUS_PHYSICAL_MODE)
+                    .st("SP1", "10:00:00")
+                    .st("SP2", "10:20:00");
+            })
+            .vj("fast", |vj| {
+                vj.physical_mode(BUS_PHYSICAL_MODE)
+                    .st("SP1", "10:00:00")
+                    .st("SP2", "10:20:00")
+                    .st("SP3", "10:21:00");
+            })
+            .build();
+        let report = Validator::new()
+            .with_check(SpeedOutliers::default())
+            .validate(&model);
+        let messages = messages(&report, ValidationReportCategory::SpeedOutlier);
+        assert_eq!(1, messages.len());
+        assert!(messages[0]
+            .starts_with("trip \"fast\" runs at 667 km/h between stop points \"SP2\" and \"SP3\""));
+    }
+
+    #[test]
+    fn duplicate_trips() {
+        let model = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("SP1", "10:00:00").st("SP2", "11:00:00");
+            })
+            .vj("vj2", |vj| {
+                vj.st("SP1", "10:00:00").st("SP2", "11:00:00");
+            })
+            .vj("vj3", |vj| {
+                vj.st("SP1", "10:00:00").st("SP2", "11:30:00");
+            })
+            .build();
+        let report = Validator::new().with_check(DuplicateTrips).validate(&model);
+        assert_eq!(
+            vec![r#"trips ["vj1", "vj2"] have the same stop times and active dates"#],
+            messages(&report, ValidationReportCategory::DuplicateTrip)
+        );
+    }
+
+    #[test]
+    fn overlapping_stops() {
+        let model = ModelBuilder::default()
+            .stop_area("sa", |_| {})
+            .stop_point("SP1", located(2.35, 48.85))
+            .stop_point("SP2", located(2.35001, 48.85001))
+            .stop_point("SP3", located(2.36, 48.85))
+            .vj("vj1", |vj| {
+                vj.line("line1")
+                    .st("SP1", "10:00:00")
+                    .st("SP2", "10:10:00")
+                    .st("SP3", "10:20:00");
+            })
+            .build();
+        let report = Validator::new()
+            .with_check(OverlappingStops::default())
+            .validate(&model);
+        assert_eq!(
+            vec![r#"stop points "SP1" and "SP2" of line "line1" are 1.3 meters apart"#],
+            messages(&report, ValidationReportCategory::OverlappingStops)
+        );
+    }
+
+    #[test]
+    fn read_errors_are_reported() {
+        let collections = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("SP1", "10:00:00").st("SP2", "11:00:00");
+            })
+            .build()
+            .into_collections();
+        let read_errors = vec![DataError::InvalidReference {
+            file: "stop_times.txt".to_string(),
+            line: 3,
+            object_type: "StopTime",
+            id: None,
+            field: "stop_id",
+            value: "SP3".to_string(),
+        }];
+        let (_, report) = Validator::default()
+            .validate_read_collections(collections, read_errors)
+            .unwrap();
+        assert_eq!(
+            vec![r#"Invalid id in stop_times.txt line 3: stop_time.stop_id="SP3""#],
+            messages(&report, ValidationReportCategory::InvalidObject)
+        );
+    }
+
+    #[test]
+    fn backward_stop_times() {
+        let mut collections = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("SP1", "10:00:00").st("SP2", "11:00:00");
+            })
+            .vj("vj2", |vj| {
+                vj.st("SP1", "10:00:00").st("SP2", "11:00:00");
+            })
+            .build()
+            .into_collections();
+        collections
+            .vehicle_journeys
+            .get_mut("vj1")
+            .unwrap()
+            .stop_times[1]
+            .arrival_time = Some(Time::new(9, 0, 0));
+        let (model, report) = Validator::new()
+            .with_collections_check(BackwardStopTimes)
+            .validate_collections(collections)
+            .unwrap();
+        assert_eq!(
+            vec![
+                r#"trip "vj1" goes backwards at stop sequence 1: arrival before the departure of the previous stop"#
+            ],
+            messages(&report, ValidationReportCategory::BackwardStopTimes)
+        );
+        // The trip can't be in the model
+        assert_eq!(
+            1,
+            messages(&report, ValidationReportCategory::InvalidObject).len()
+        );
+        assert!(!model.vehicle_journeys.contains_id("vj1"));
+        assert!(model.vehicle_journeys.contains_id("vj2"));
+    }
+
+    #[test]
+    fn stop_point_far_from_stop_area() {
+        let model = ModelBuilder::default()
+            .stop_area("sa:SP1", |sa: &mut StopArea| {
+                sa.coord = Coord {
+                    lon: 2.35,
+                    lat: 48.85,
+                }
+            })
+            .stop_point("SP1", located(2.35, 48.86))
+            .vj("vj1", |vj| {
+                vj.st("SP1", "10:00:00").st("SP2", "11:00:00");
+            })
+            .build();
+        let report = Validator::new()
+            .with_check(StopPointsFarFromStopArea::default())
+            .validate(&model);
+        assert_eq!(
+            vec![r#"stop point "SP1" is 1112 meters away from its stop area "sa:SP1""#],
+            messages(&report, ValidationReportCategory::StopPointFarFromStopArea)
+        );
+    }
+
+    #[test]
+    fn lines_without_service_and_empty_calendars() {
+        let mut collections = ModelBuilder::default()
+            .calendar("c1", &["2020-01-01"])
+            .calendar("c2", &["2020-01-01"])
+            .vj("vj1", |vj| {
+                vj.line("line1")
+                    .calendar("c1")
+                    .st("SP1", "10:00:00")
+                    .st("SP2", "11:00:00");
+            })
+            .vj("vj2", |vj| {
+                vj.line("line2")
+                    .calendar("c2")
+                    .st("SP1", "10:00:00")
+                    .st("SP2", "11:00:00");
+            })
+            .build()
+            .into_collections();
+        collections.calendars.get_mut("c1").unwrap().dates.clear();
+        let (model, report) = Validator::default()
+            .validate_collections(collections)
+            .unwrap();
+        assert_eq!(
+            vec![r#"line "line1" has no service on any day"#],
+            messages(&report, ValidationReportCategory::LineWithoutService)
+        );
+        assert_eq!(
+            vec![r#"calendar "c1" has no active date"#],
+            messages(&report, ValidationReportCategory::EmptyCalendar)
+        );
+        // Model::new removes them
+        assert!(!model.lines.contains_id("line1"));
+        assert!(!model.calendars.contains_id("c1"));
+    }
+
+    #[test]
+    fn custom_check() {
+        struct NoTrip;
+        impl Check for NoTrip {
+            fn check(&self, model: &Model, report: &mut Report<ValidationReportCategory>) {
+                if model.vehicle_journeys.is_empty() {
+                    report.add_error(
+                        "no trip".to_string(),
+                        ValidationReportCategory::Custom("NoTrip".to_string()),
+                    );
+                }
+            }
+        }
+        let model = ModelBuilder::default().build();
+        let report = Validator::default().with_check(NoTrip).validate(&model);
+        assert_eq!(1, report.count(Severity::Error));
+        assert_eq!(0, report.count(Severity::Warning));
+    }
+
+    #[test]
+    fn mixed_timezones() {
+        let model = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.network("N1")
+                    .timezone(chrono_tz::Europe::Paris)
+                    .st("SP1", "10:00:00");
+            })
+            .vj("vj2", |vj| {
+                vj.network("N2")
+                    .timezone(chrono_tz::Europe::London)
+                    .st("SP1", "11:00:00");
+            })
+            .vj("vj3", |vj| {
+                vj.network("N3")
+                    .timezone(chrono_tz::Europe::Paris)
+                    .st("SP1", "12:00:00");
+            })
+            .build();
+        let report = Validator::new().with_check(MixedTimezones).validate(&model);
+        assert_eq!(
+            vec![
+                "different timezones are used: Europe/London (networks N2), \
+                 Europe/Paris (networks N1, N3)"
+            ],
+            messages(&report, ValidationReportCategory::MixedTimezones)
+        );
+    }
+
+    #[test]
+    fn mixed_stop_timezones() {
+        let model = ModelBuilder::default()
+            .stop_area("sa:SP1", |sa: &mut StopArea| {
+                sa.timezone = Some(chrono_tz::Europe::Paris)
+            })
+            .stop_point("SP1", |sp: &mut StopPoint| {
+                sp.timezone = Some(chrono_tz::Europe::Paris)
+            })
+            .stop_point("SP2", |sp: &mut StopPoint| {
+                sp.timezone = Some(chrono_tz::Europe::London)
+            })
+            .vj("vj1", |vj| {
+                vj.network("N1")
+                    .timezone(chrono_tz::Europe::Paris)
+                    .st("SP1", "10:00:00")
+                    .st("SP2", "11:00:00");
+            })
+            .build();
+        let report = Validator::new().with_check(MixedTimezones).validate(&model);
+        assert_eq!(
+            vec![
+                "different timezones are used: Europe/London (1 stop points), \
+                 Europe/Paris (networks N1; 1 stop areas; 1 stop points)"
+            ],
+            messages(&report, ValidationReportCategory::MixedTimezones)
+        );
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! [VDV 452](https://www.vdv.de/ip-kom-oev.aspx) format management, the
+//! planning data of the German and Austrian public transport operators,
+//! exchanged as x10 files (VDV 451).
+
+mod read;
+mod x10;
+
+use crate::{
+    error::DataError,
+    model::{Collections, Model},
+    objects::{Contributor, Dataset},
+    validity_period, AddPrefix, PrefixConfiguration, Result,
+};
+use anyhow::Context;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+use tracing::info;
+use typed_index_collection::CollectionWithId;
+
+///parameters consolidation
+#[derive(Default)]
+pub struct Configuration {
+    /// The Contributor providing the Dataset
+    pub contributor: Contributor,
+    /// Describe the Dataset being parsed
+    pub dataset: Dataset,
+    /// Additional key-values for the 'feed_infos.txt'
+    pub feed_infos: BTreeMap<String, String>,
+    /// used to prefix objects
+    pub prefix_conf: Option<PrefixConfiguration>,
+}
+
+fn is_x10(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("x10"))
+}
+
+/// Tables of the x10 files of a directory or of a zip archive
+fn read_x10_files(path: &Path) -> Result<x10::Tables> {
+    let mut files: Vec<(PathBuf, Vec<u8>)> = Vec::new();
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path).with_context(|| format!("Error reading {path:?}"))? {
+            let entry = entry.with_context(|| format!("Error reading {path:?}"))?;
+            let file_path = entry.path();
+            if file_path.is_file() && is_x10(&file_path) {
+                let content = std::fs::read(&file_path)
+                    .with_context(|| format!("Error reading {file_path:?}"))?;
+                files.push((file_path, content));
+            }
+        }
+    } else if path.is_file() {
+        // if it's a file, we consider it to be a zip (and an error will be
+        // returned if it is not)
+        let reader = File::open(path).with_context(|| format!("Error reading {path:?}"))?;
+        let mut archive = zip::ZipArchive::new(reader)?;
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index)?;
+            let file_path = path.join(file.name());
+            if !file.is_dir() && is_x10(&file_path) {
+                let mut content = Vec::new();
+                file.read_to_end(&mut content)
+                    .with_context(|| format!("Error reading {file_path:?}"))?;
+                files.push((file_path, content));
+            }
+        }
+    } else {
+        return Err(DataError::InvalidInput {
+            path: path.to_path_buf(),
+            format: "vdv452",
+        }
+        .into());
+    }
+    files.sort_by(|(path, _), (other_path, _)| path.cmp(other_path));
+
+    let mut tables = x10::Tables::new();
+    for (file_path, content) in files {
+        info!("Reading {:?}", file_path);
+        x10::read_tables(&mut tables, content)
+            .with_context(|| format!("Error reading {file_path:?}"))?;
+    }
+    Ok(tables)
+}
+
+fn read_to_collections(path: &Path, configuration: Configuration) -> Result<Collections> {
+    let Configuration {
+        contributor,
+        mut dataset,
+        feed_infos,
+        prefix_conf,
+    } = configuration;
+
+    let tables = read_x10_files(path)?;
+    let mut collections = Collections::default();
+    read::read_planning_data(&mut collections, &tables, &dataset.id)?;
+    validity_period::compute_dataset_validity_period(&mut dataset, &collections.calendars)?;
+
+    collections.contributors = CollectionWithId::from(contributor);
+    collections.datasets = CollectionWithId::from(dataset);
+    collections.feed_infos = feed_infos;
+
+    //add prefixes
+    if let Some(prefix_conf) = prefix_conf {
+        collections.prefix(&prefix_conf);
+    }
+
+    collections.calendar_deduplication();
+    Ok(collections)
+}
+
+/// Imports a `Model` from the [VDV 452](https://www.vdv.de/ip-kom-oev.aspx)
+/// x10 files of a directory or of a zip archive.
+pub fn read<P: AsRef<Path>>(p: P) -> Result<Model> {
+    Reader::default().parse(p)
+}
+
+/// Structure to configure the VDV 452 reading
+#[derive(Default)]
+pub struct Reader {
+    configuration: Configuration,
+}
+
+impl Reader {
+    /// Build a Reader with a custom configuration
+    pub fn new(configuration: Configuration) -> Self {
+        Self { configuration }
+    }
+
+    /// Imports a `Model` from the [VDV 452](https://www.vdv.de/ip-kom-oev.aspx)
+    /// x10 files of a directory or of a zip archive.
+    ///
+    /// Each trip of `REC_FRT` gives a `VehicleJourney` running on the days of
+    /// its day type in `FIRMENKALENDER`. Its stop times are computed from its
+    /// start time along the points of its route variant (`LID_VERLAUF`), with
+    /// the run times (`SEL_FZT_FELD`) and dwell times (`ORT_HZTF` and
+    /// `REC_FRT_HZT`) of its time group.
+    pub fn parse(self, path: impl AsRef<Path>) -> Result<Model> {
+        let collections = self.parse_collections(path)?;
+        Model::new(collections)
+    }
+
+    /// Imports `Collections` from the
+    /// [VDV 452](https://www.vdv.de/ip-kom-oev.aspx) x10 files like
+    /// [Reader::parse].
+    pub fn parse_collections(self, path: impl AsRef<Path>) -> Result<Collections> {
+        let p = path.as_ref();
+        read_to_collections(p, self.configuration)
+            .with_context(|| format!("impossible to read vdv452 from {p:?}"))
+    }
+}
@@ -0,0 +1,541 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use super::x10::{records, Record, Tables};
+use crate::{
+    model::Collections,
+    objects::{
+        Calendar, CommercialMode, Company, Coord, Date, KeysValues, Line, Network, PhysicalMode,
+        Route, StopArea, StopPoint, StopTime, StopType, Time, VehicleJourney,
+    },
+    utils::required_stop_point_coord,
+    Result,
+};
+use anyhow::{anyhow, bail, Context};
+use skip_error::skip_error_and_warn;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tracing::warn;
+
+/// `ONR_TYP_NR` of the stop points (`Haltepunkt`), the other types of points
+/// of the routes being only used for the computation of the times
+const STOP_POINT_TYPE: &str = "1";
+/// Mode of the vehicle journeys, VDV 452 not describing it
+const MODE: &str = "Bus";
+
+/// Tables of the x10 files, restricted to a basis version
+struct Data<'a> {
+    tables: &'a Tables,
+    version: Option<&'a str>,
+}
+
+impl<'a> Data<'a> {
+    /// The latest basis version of `BASIS_VER_GUELTIGKEIT` is used, all the
+    /// records being used without this table
+    fn new(tables: &'a Tables) -> Self {
+        let version = records(tables, "BASIS_VER_GUELTIGKEIT")
+            .filter_map(|record| {
+                Some((
+                    record.get("VER_GUELTIGKEIT")?.parse::<u32>().ok()?,
+                    record.get("BASIS_VERSION")?,
+                ))
+            })
+            .max()
+            .map(|(_, version)| version);
+        Data { tables, version }
+    }
+
+    fn records(&self, name: &'a str) -> impl Iterator<Item = Record<'a>> {
+        let version = self.version;
+        records(self.tables, name).filter(move |record| {
+            version.is_none_or(|version| {
+                record
+                    .get("BASIS_VERSION")
+                    .is_none_or(|record_version| record_version == version)
+            })
+        })
+    }
+}
+
+/// Point of a route: its `ONR_TYP_NR` and its `ORT_NR`
+type Point<'a> = (&'a str, &'a str);
+
+fn point<'a>(record: &Record<'a>) -> Result<Point<'a>> {
+    Ok((record.required("ONR_TYP_NR")?, record.required("ORT_NR")?))
+}
+
+fn parse_date(date: &str) -> Result<Date> {
+    Date::parse_from_str(date, "%Y%m%d").with_context(|| format!("invalid date {date:?}"))
+}
+
+/// Coordinate in the `GGGMMSSnnn` format of VDV 452 (degrees, minutes,
+/// seconds and thousandths of seconds)
+fn parse_coordinate(coordinate: &str) -> Result<f64> {
+    let value: i64 = coordinate
+        .parse()
+        .with_context(|| format!("invalid coordinate {coordinate:?}"))?;
+    let absolute = value.abs();
+    let degrees = (absolute / 10_000_000) as f64;
+    let minutes = (absolute / 100_000 % 100) as f64;
+    let seconds = (absolute % 100_000) as f64 / 1000.0;
+    let coordinate = degrees + minutes / 60.0 + seconds / 3600.0;
+    Ok(if value < 0 { -coordinate } else { coordinate })
+}
+
+/// A calendar for each day type (`TAGESART_NR`) of `FIRMENKALENDER`
+fn read_calendars(collections: &mut Collections, data: &Data) -> Result<()> {
+    let mut calendars: BTreeMap<&str, BTreeSet<Date>> = BTreeMap::new();
+    for record in data.records("FIRMENKALENDER") {
+        let day_type = skip_error_and_warn!(record.required("TAGESART_NR"));
+        let date = skip_error_and_warn!(record.required("BETRIEBSTAG").and_then(parse_date));
+        calendars.entry(day_type).or_default().insert(date);
+    }
+    for (day_type, dates) in calendars {
+        collections.calendars.push(Calendar {
+            id: day_type.to_string(),
+            dates,
+        })?;
+    }
+    Ok(())
+}
+
+fn push_stop_point(collections: &mut Collections, record: &Record) -> Result<()> {
+    let id = record.required("ORT_NR")?;
+    let name = record.get("ORT_NAME").unwrap_or(id);
+    let coord = match (record.get("ORT_POS_LAENGE"), record.get("ORT_POS_BREITE")) {
+        (Some(lon), Some(lat)) => Some(Coord {
+            lon: parse_coordinate(lon)?,
+            lat: parse_coordinate(lat)?,
+        }),
+        _ => None,
+    };
+    let coord = required_stop_point_coord(id, coord)?;
+    let mut codes = KeysValues::default();
+    codes.insert(("source".to_string(), id.to_string()));
+    let mut stop_point = StopPoint {
+        id: id.to_string(),
+        name: name.to_string(),
+        code: record.get("ORT_REF_ORT_KUERZEL").map(str::to_string),
+        codes,
+        coord,
+        visible: true,
+        stop_type: StopType::Point,
+        ..Default::default()
+    };
+    // The stop points of a stop (`Haltestelle`) share the same `ORT_REF_ORT`
+    let stop_area = match record.get("ORT_REF_ORT") {
+        Some(reference) => {
+            let mut codes = KeysValues::default();
+            codes.insert(("source".to_string(), reference.to_string()));
+            StopArea {
+                id: format!("stop_area:{reference}"),
+                name: record.get("ORT_REF_ORT_NAME").unwrap_or(name).to_string(),
+                codes,
+                visible: true,
+                ..Default::default()
+            }
+        }
+        None => StopArea::from(stop_point.clone()),
+    };
+    stop_point.stop_area_id.clone_from(&stop_area.id);
+    if !collections.stop_areas.contains_id(&stop_area.id) {
+        collections.stop_areas.push(stop_area)?;
+    }
+    collections.stop_points.push(stop_point)?;
+    Ok(())
+}
+
+/// A stop point for each stop point (`Haltepunkt`) of `REC_ORT`
+fn read_stop_points(collections: &mut Collections, data: &Data) {
+    for record in data
+        .records("REC_ORT")
+        .filter(|record| record.get("ONR_TYP_NR") == Some(STOP_POINT_TYPE))
+    {
+        skip_error_and_warn!(push_stop_point(collections, &record)
+            .with_context(|| format!("REC_ORT: invalid stop point {:?}", record.get("ORT_NR"))));
+    }
+}
+
+/// The company and the network of the transport company of
+/// `ZUL_VERKEHRSBETRIEB`, or the default ones, returning their identifiers
+fn read_company(collections: &mut Collections, data: &Data) -> Result<(String, String)> {
+    let (company, network) = match data.records("ZUL_VERKEHRSBETRIEB").next() {
+        Some(record) => {
+            let id = record.required("UNTERNEHMEN")?;
+            let name = record
+                .get("BETRIEBSGEBIET_BEZ")
+                .or_else(|| record.get("ABK_UNTERNEHMEN"))
+                .unwrap_or(id);
+            (
+                Company {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    ..Default::default()
+                },
+                Network {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    ..Default::default()
+                },
+            )
+        }
+        None => (Company::default(), Network::default()),
+    };
+    let ids = (company.id.clone(), network.id.clone());
+    collections.companies.push(company)?;
+    collections.networks.push(Network {
+        timezone: Some(chrono_tz::Europe::Berlin),
+        ..network
+    })?;
+    Ok(ids)
+}
+
+/// Route variants (`STR_LI_VAR`) of the lines of `REC_LID`
+type LineVariants<'a> = HashMap<(&'a str, &'a str), Record<'a>>;
+
+/// Identifier of the route of a variant of a line, creating the line and the
+/// route if needed
+fn push_line_and_route(
+    collections: &mut Collections,
+    line_number: &str,
+    variant: &str,
+    line_variants: &LineVariants,
+    network_id: &str,
+) -> Result<String> {
+    let line_variant = line_variants.get(&(line_number, variant));
+    let line_code = line_variant.and_then(|record| record.get("LI_KUERZEL"));
+    if !collections.lines.contains_id(line_number) {
+        collections.lines.push(Line {
+            id: line_number.to_string(),
+            code: line_code.map(str::to_string),
+            name: line_code.unwrap_or(line_number).to_string(),
+            commercial_mode_id: MODE.to_string(),
+            network_id: network_id.to_string(),
+            ..Default::default()
+        })?;
+    }
+    let route_id = format!("{line_number}:{variant}");
+    if !collections.routes.contains_id(&route_id) {
+        let direction_type = match line_variant.and_then(|record| record.get("LI_RI_NR")) {
+            Some("1") => Some("forward".to_string()),
+            Some("2") => Some("backward".to_string()),
+            _ => None,
+        };
+        collections.routes.push(Route {
+            id: route_id.clone(),
+            name: line_variant
+                .and_then(|record| record.get("LIDNAME"))
+                .or(line_code)
+                .unwrap_or(line_number)
+                .to_string(),
+            direction_type,
+            line_id: line_number.to_string(),
+            ..Default::default()
+        })?;
+    }
+    Ok(route_id)
+}
+
+/// Points of the route variants of `LID_VERLAUF`, by line and variant
+type Paths<'a> = HashMap<(&'a str, &'a str), Vec<Point<'a>>>;
+
+/// Line, route variant, sequence and point of a record of `LID_VERLAUF`
+fn path_point<'a>(record: &Record<'a>) -> Result<((&'a str, &'a str), u32, Point<'a>)> {
+    Ok((
+        (record.required("LI_NR")?, record.required("STR_LI_VAR")?),
+        record.parse("LI_LFD_NR")?,
+        point(record)?,
+    ))
+}
+
+fn read_paths<'a>(data: &Data<'a>) -> Paths<'a> {
+    let mut points: HashMap<(&str, &str), Vec<(u32, Point)>> = HashMap::new();
+    for record in data.records("LID_VERLAUF") {
+        let (key, sequence, point) = skip_error_and_warn!(path_point(&record));
+        points.entry(key).or_default().push((sequence, point));
+    }
+    points
+        .into_iter()
+        .map(|(key, mut points)| {
+            points.sort_by_key(|(sequence, _)| *sequence);
+            (key, points.into_iter().map(|(_, point)| point).collect())
+        })
+        .collect()
+}
+
+/// Run times (`SEL_FZT_FELD`) and dwell times (`ORT_HZTF` and
+/// `REC_FRT_HZT`) in seconds
+#[derive(Default)]
+struct Timings<'a> {
+    /// Run times by time group (`FGR_NR`) and section
+    run_times: HashMap<(&'a str, Point<'a>, Point<'a>), u32>,
+    /// Dwell times by time group (`FGR_NR`) and point
+    dwell_times: HashMap<(&'a str, Point<'a>), u32>,
+    /// Dwell times specific to a trip (`FRT_FID`), by point
+    trip_dwell_times: HashMap<(&'a str, Point<'a>), u32>,
+}
+
+/// Run time of a section of a record of `SEL_FZT_FELD`
+fn run_time<'a>(record: &Record<'a>) -> Result<((&'a str, Point<'a>, Point<'a>), u32)> {
+    Ok((
+        (
+            record.required("FGR_NR")?,
+            point(record)?,
+            (
+                record.required("SEL_ZIEL_TYP")?,
+                record.required("SEL_ZIEL")?,
+            ),
+        ),
+        record.parse("SEL_FZT")?,
+    ))
+}
+
+/// Dwell time at a point, for the group (time group or trip) of the column
+/// `group`
+fn dwell_time<'a>(
+    record: &Record<'a>,
+    group: &str,
+    time: &str,
+) -> Result<((&'a str, Point<'a>), u32)> {
+    Ok((
+        (record.required(group)?, point(record)?),
+        record.parse(time)?,
+    ))
+}
+
+fn read_timings<'a>(data: &Data<'a>) -> Timings<'a> {
+    let mut timings = Timings::default();
+    for record in data.records("SEL_FZT_FELD") {
+        let (key, run_time) = skip_error_and_warn!(run_time(&record));
+        timings.run_times.insert(key, run_time);
+    }
+    for record in data.records("ORT_HZTF") {
+        let (key, dwell_time) = skip_error_and_warn!(dwell_time(&record, "FGR_NR", "HP_HZT"));
+        timings.dwell_times.insert(key, dwell_time);
+    }
+    for record in data.records("REC_FRT_HZT") {
+        let (key, dwell_time) =
+            skip_error_and_warn!(dwell_time(&record, "FRT_FID", "FRT_HZT_ZEIT"));
+        timings.trip_dwell_times.insert(key, dwell_time);
+    }
+    timings
+}
+
+/// Stop times of a trip along the points of its route variant, from its
+/// start time (in seconds) and with the run and dwell times of its time group.
+fn stop_times(
+    collections: &Collections,
+    trip_id: &str,
+    time_group: &str,
+    start: u32,
+    path: &[Point],
+    timings: &Timings,
+) -> Result<Vec<StopTime>> {
+    let mut stop_times = Vec::with_capacity(path.len());
+    let mut time = start;
+    for (index, point) in path.iter().enumerate() {
+        let arrival_time = time;
+        // The trip leaves its first point at its start time
+        if index > 0 && index + 1 < path.len() {
+            time += timings
+                .trip_dwell_times
+                .get(&(trip_id, *point))
+                .or_else(|| timings.dwell_times.get(&(time_group, *point)))
+                .copied()
+                .unwrap_or_default();
+        }
+        let (point_type, point_number) = *point;
+        if point_type == STOP_POINT_TYPE {
+            let stop_point_idx = collections
+                .stop_points
+                .get_idx(point_number)
+                .ok_or_else(|| anyhow!("unknown stop point {point_number}"))?;
+            stop_times.push(StopTime {
+                stop_point_idx,
+                sequence: stop_times.len() as u32,
+                arrival_time: Some(Time::new(0, 0, arrival_time)),
+                departure_time: Some(Time::new(0, 0, time)),
+                start_pickup_drop_off_window: None,
+                end_pickup_drop_off_window: None,
+                boarding_duration: 0,
+                alighting_duration: 0,
+                pickup_type: 0,
+                drop_off_type: 0,
+                local_zone_id: None,
+                precision: None,
+                shape_dist_traveled: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
+            });
+        }
+        if let Some(next) = path.get(index + 1) {
+            time += timings
+                .run_times
+                .get(&(time_group, *point, *next))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "no run time from {point_number} to {} in the time group {time_group}",
+                        next.1
+                    )
+                })?;
+        }
+    }
+    if stop_times.len() < 2 {
+        bail!("less than 2 stop points");
+    }
+    Ok(stop_times)
+}
+
+/// Data of the planning used to read the trips
+struct Referential<'a> {
+    line_variants: LineVariants<'a>,
+    paths: Paths<'a>,
+    timings: Timings<'a>,
+    company_id: String,
+    network_id: String,
+}
+
+fn push_vehicle_journey(
+    collections: &mut Collections,
+    trip: &Record,
+    referential: &Referential,
+    dataset_id: &str,
+) -> Result<()> {
+    let id = trip.required("FRT_FID")?;
+    let line_number = trip.required("LI_NR")?;
+    let variant = trip.required("STR_LI_VAR")?;
+    let time_group = trip.required("FGR_NR")?;
+    let day_type = trip.required("TAGESART_NR")?;
+    let start: u32 = trip.parse("FRT_START")?;
+    if !collections.calendars.contains_id(day_type) {
+        bail!("trip {id}: unknown day type {day_type}");
+    }
+    let path = referential
+        .paths
+        .get(&(line_number, variant))
+        .ok_or_else(|| {
+            anyhow!("trip {id}: unknown route variant {variant} of line {line_number}")
+        })?;
+    let stop_times = stop_times(
+        collections,
+        id,
+        time_group,
+        start,
+        path,
+        &referential.timings,
+    )
+    .with_context(|| format!("trip {id}: invalid route variant {variant} of line {line_number}"))?;
+    let route_id = push_line_and_route(
+        collections,
+        line_number,
+        variant,
+        &referential.line_variants,
+        &referential.network_id,
+    )?;
+    let headsign = stop_times.last().map(|stop_time| {
+        collections.stop_points[stop_time.stop_point_idx]
+            .name
+            .clone()
+    });
+    let mut codes = KeysValues::default();
+    codes.insert(("source".to_string(), id.to_string()));
+    collections.vehicle_journeys.push(VehicleJourney {
+        id: id.to_string(),
+        codes,
+        route_id,
+        physical_mode_id: MODE.to_string(),
+        dataset_id: dataset_id.to_string(),
+        service_id: day_type.to_string(),
+        headsign,
+        short_name: trip.get("ZUGNR").map(str::to_string),
+        company_id: referential.company_id.clone(),
+        stop_times,
+        ..Default::default()
+    })?;
+    Ok(())
+}
+
+/// Read the planning data of the tables of the x10 files into the
+/// collections.
+///
+/// The trips of `REC_FRT` run on the days of their day type in
+/// `FIRMENKALENDER`, along the points of their route variant in
+/// `LID_VERLAUF`.
+pub(super) fn read_planning_data(
+    collections: &mut Collections,
+    tables: &Tables,
+    dataset_id: &str,
+) -> Result<()> {
+    for table in [
+        "REC_ORT",
+        "LID_VERLAUF",
+        "REC_FRT",
+        "FIRMENKALENDER",
+        "SEL_FZT_FELD",
+    ] {
+        if !tables.contains_key(table) {
+            bail!("the table {table} is required");
+        }
+    }
+    let data = Data::new(tables);
+    read_calendars(collections, &data)?;
+    read_stop_points(collections, &data);
+    let (company_id, network_id) = read_company(collections, &data)?;
+    collections.commercial_modes.push(CommercialMode {
+        id: MODE.to_string(),
+        name: MODE.to_string(),
+    })?;
+    collections.physical_modes.push(PhysicalMode {
+        id: MODE.to_string(),
+        name: MODE.to_string(),
+        co2_emission: None,
+    })?;
+
+    let referential = Referential {
+        line_variants: data
+            .records("REC_LID")
+            .filter_map(|record| Some(((record.get("LI_NR")?, record.get("STR_LI_VAR")?), record)))
+            .collect(),
+        paths: read_paths(&data),
+        timings: read_timings(&data),
+        company_id,
+        network_id,
+    };
+    for trip in data.records("REC_FRT") {
+        skip_error_and_warn!(push_vehicle_journey(
+            collections,
+            &trip,
+            &referential,
+            dataset_id
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn coordinates() {
+        // 13° 54' 32.100"
+        assert_relative_eq!(
+            13.908_916_7,
+            parse_coordinate("0135432100").unwrap(),
+            epsilon = 1e-7
+        );
+        assert_relative_eq!(-3.5, parse_coordinate("-0033000000").unwrap());
+        assert!(parse_coordinate("N52").is_err());
+    }
+}
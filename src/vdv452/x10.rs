@@ -0,0 +1,208 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Reading of the tables of the x10 files (VDV 451), e.g.
+//! ```text
+//! tbl; MENGE_TAGESART
+//! atr; BASIS_VERSION; TAGESART_NR; TAGESART_TEXT
+//! frm; num[9.0]; num[3.0]; char[40]
+//! rec; 1; 1; "Montag - Freitag"
+//! end; 1
+//! ```
+
+use crate::Result;
+use anyhow::{anyhow, bail, Context};
+use std::{collections::HashMap, str::FromStr};
+
+/// Table of a x10 file
+#[derive(Debug, Default)]
+pub(super) struct Table {
+    columns: HashMap<String, usize>,
+    records: Vec<Vec<String>>,
+}
+
+/// Tables of the x10 files, by name
+pub(super) type Tables = HashMap<String, Table>;
+
+/// Record of a table
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Record<'a> {
+    table: &'a str,
+    columns: &'a HashMap<String, usize>,
+    values: &'a [String],
+}
+
+impl<'a> Record<'a> {
+    /// Value of a column, `None` if the column doesn't exist or if the value
+    /// is empty
+    pub(super) fn get(&self, column: &str) -> Option<&'a str> {
+        let index = *self.columns.get(column)?;
+        self.values
+            .get(index)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    }
+
+    pub(super) fn required(&self, column: &str) -> Result<&'a str> {
+        self.get(column)
+            .ok_or_else(|| anyhow!("{}: missing value of {column}", self.table))
+    }
+
+    pub(super) fn parse<T>(&self, column: &str) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let value = self.required(column)?;
+        value
+            .parse()
+            .with_context(|| format!("{}: invalid {column} {value:?}", self.table))
+    }
+}
+
+/// Records of a table, empty if the table doesn't exist
+pub(super) fn records<'a>(tables: &'a Tables, name: &'a str) -> impl Iterator<Item = Record<'a>> {
+    tables.get(name).into_iter().flat_map(move |table| {
+        table.records.iter().map(move |values| Record {
+            table: name,
+            columns: &table.columns,
+            values,
+        })
+    })
+}
+
+/// Values of a line, separated by `;`, the strings being quoted (`""` being
+/// a quote in a string)
+fn split_values(line: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                value.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ';' if !quoted => values.push(std::mem::take(&mut value)),
+            c if quoted || !c.is_whitespace() => value.push(c),
+            _ => {}
+        }
+    }
+    values.push(value);
+    values
+}
+
+/// Read the tables of the content of a x10 file.
+///
+/// The files are encoded in UTF-8, or in ISO-8859-1 for the older ones.
+pub(super) fn read_tables(tables: &mut Tables, content: Vec<u8>) -> Result<()> {
+    let content = match String::from_utf8(content) {
+        Ok(content) => content,
+        Err(error) => error.into_bytes().into_iter().map(char::from).collect(),
+    };
+    let mut table: Option<(String, Table)> = None;
+    for line in content.lines() {
+        let mut values = split_values(line);
+        let kind = values.remove(0);
+        match kind.as_str() {
+            "tbl" => {
+                let name = values
+                    .first()
+                    .map(|name| name.to_uppercase())
+                    .ok_or_else(|| anyhow!("missing name of table"))?;
+                table = Some((name, Table::default()));
+            }
+            "atr" => {
+                let (name, table) = table
+                    .as_mut()
+                    .ok_or_else(|| anyhow!("attributes outside of a table"))?;
+                table.columns = values
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, column)| (column.to_uppercase(), index))
+                    .collect();
+                if table.columns.is_empty() {
+                    bail!("{name}: no attribute");
+                }
+            }
+            "rec" => {
+                let (_, table) = table
+                    .as_mut()
+                    .ok_or_else(|| anyhow!("record outside of a table"))?;
+                table.records.push(values);
+            }
+            "end" => {
+                if let Some((name, table)) = table.take() {
+                    push_table(tables, name, table)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, table)) = table {
+        push_table(tables, name, table)?;
+    }
+    Ok(())
+}
+
+/// The records of a table split in several files are gathered
+fn push_table(tables: &mut Tables, name: String, table: Table) -> Result<()> {
+    match tables.get_mut(&name) {
+        Some(existing) if existing.columns == table.columns => {
+            existing.records.extend(table.records);
+        }
+        Some(_) => bail!("{name}: tables with different attributes"),
+        None => {
+            tables.insert(name, table);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn values() {
+        assert_eq!(
+            vec!["rec", "1", "", "Gare \"Sud\"; Quai 1"],
+            split_values(r#"rec; 1; ; "Gare ""Sud""; Quai 1""#)
+        );
+    }
+
+    #[test]
+    fn table_records() {
+        let content = "mod; 01.12.2023; 10:00:00; free\n\
+            tbl; menge_tagesart\n\
+            atr; BASIS_VERSION; TAGESART_NR; TAGESART_TEXT\n\
+            frm; num[9.0]; num[3.0]; char[40]\n\
+            rec; 1; 1; \"Montag - Freitag\"\n\
+            rec; 1; 2; \"\"\n\
+            end; 2\n\
+            eof; 1\n";
+        let mut tables = Tables::new();
+        read_tables(&mut tables, content.as_bytes().to_vec()).unwrap();
+        let day_types: Vec<_> = records(&tables, "MENGE_TAGESART").collect();
+        assert_eq!(2, day_types.len());
+        assert_eq!(1, day_types[1].parse::<u32>("BASIS_VERSION").unwrap());
+        assert_eq!(Some("Montag - Freitag"), day_types[0].get("TAGESART_TEXT"));
+        assert_eq!(None, day_types[1].get("TAGESART_TEXT"));
+        assert!(day_types[1].required("TAGESART_TEXT").is_err());
+        assert_eq!(0, records(&tables, "REC_ORT").count());
+    }
+}
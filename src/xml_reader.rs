@@ -0,0 +1,163 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Utilities to read the XML formats (TransXChange, NeTEx)
+
+use crate::{error::DataError, Result};
+use anyhow::{anyhow, Context};
+use roxmltree::Node;
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+fn is_xml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("xml"))
+}
+
+/// Paths and contents of the XML files of a directory (with its
+/// subdirectories), of a zip archive or of a single XML file
+pub(crate) fn read_xml_files(path: &Path, format: &'static str) -> Result<Vec<(PathBuf, String)>> {
+    let mut files = Vec::new();
+    if path.is_dir() {
+        for entry in WalkDir::new(path).sort_by_file_name() {
+            let entry = entry.with_context(|| format!("Error reading {path:?}"))?;
+            if entry.file_type().is_file() && is_xml(entry.path()) {
+                let content = std::fs::read_to_string(entry.path())
+                    .with_context(|| format!("Error reading {:?}", entry.path()))?;
+                files.push((entry.path().to_path_buf(), content));
+            }
+        }
+    } else if path.is_file() && is_xml(path) {
+        let content =
+            std::fs::read_to_string(path).with_context(|| format!("Error reading {path:?}"))?;
+        files.push((path.to_path_buf(), content));
+    } else if path.is_file() {
+        // if it's not an XML file, we consider it to be a zip (and an error
+        // will be returned if it is not)
+        let reader = File::open(path).with_context(|| format!("Error reading {path:?}"))?;
+        let mut archive = zip::ZipArchive::new(reader)?;
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index)?;
+            let file_path = path.join(file.name());
+            if !file.is_dir() && is_xml(&file_path) {
+                let mut content = String::new();
+                file.read_to_string(&mut content)
+                    .with_context(|| format!("Error reading {file_path:?}"))?;
+                files.push((file_path, content));
+            }
+        }
+    } else {
+        return Err(DataError::InvalidInput {
+            path: path.to_path_buf(),
+            format,
+        }
+        .into());
+    }
+    Ok(files)
+}
+
+pub(crate) fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+pub(crate) fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.has_tag_name(name))
+}
+
+pub(crate) fn elements<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(Node::is_element)
+}
+
+/// Trimmed text of the element at the end of a path of children, `None` if
+/// it doesn't exist or is empty
+pub(crate) fn text<'a>(node: Node<'a, '_>, path: &[&str]) -> Option<&'a str> {
+    let mut node = node;
+    for name in path {
+        node = child(node, name)?;
+    }
+    node.text().map(str::trim).filter(|text| !text.is_empty())
+}
+
+pub(crate) fn required_text<'a>(node: Node<'a, '_>, path: &[&str]) -> Result<&'a str> {
+    text(node, path).ok_or_else(|| {
+        anyhow!(
+            "missing {} in {} at position {}",
+            path.join("/"),
+            node.tag_name().name(),
+            node.document().text_pos_at(node.range().start)
+        )
+    })
+}
+
+/// Seconds of an ISO 8601 duration, e.g. `PT1M30S`
+pub(crate) fn parse_duration(duration: &str) -> Result<u32> {
+    let invalid = || anyhow!("invalid duration {duration:?}");
+    let designators = duration.strip_prefix('P').ok_or_else(invalid)?;
+    let mut seconds = 0;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in designators.chars() {
+        match c {
+            'T' if number.is_empty() && !in_time => in_time = true,
+            '0'..='9' => number.push(c),
+            _ => {
+                let value: u32 = number.parse().map_err(|_| invalid())?;
+                let unit = match (c, in_time) {
+                    ('D', false) => 24 * 60 * 60,
+                    ('H', true) => 60 * 60,
+                    ('M', true) => 60,
+                    ('S', true) => 1,
+                    _ => return Err(invalid()),
+                };
+                seconds += value * unit;
+                number.clear();
+            }
+        }
+    }
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+    Ok(seconds)
+}
+
+pub(crate) fn optional_duration(node: Node, path: &[&str]) -> Result<Option<u32>> {
+    text(node, path).map(parse_duration).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn durations() {
+        assert_eq!(90, parse_duration("PT1M30S").unwrap());
+        assert_eq!(3600, parse_duration("PT1H").unwrap());
+        assert_eq!(0, parse_duration("PT0S").unwrap());
+        assert_eq!(86400 + 60, parse_duration("P1DT1M").unwrap());
+        assert!(parse_duration("1M").is_err());
+        assert!(parse_duration("PT1M3").is_err());
+        assert!(parse_duration("P1M").is_err());
+    }
+}
@@ -12,7 +12,13 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
+use geo::{polygon, Geometry as GeoGeometry};
 use pretty_assertions::assert_eq;
+use transit_model::{
+    objects::{Coord, StopType, Time},
+    test_utils::test_in_tmp_dir,
+    Model,
+};
 
 #[test]
 fn simple_gtfs_reading() {
@@ -69,3 +75,127 @@ fn directory_not_a_gtfs() {
     // reading a directory that does not contain the gtfs files will lead to an error
     let _ = transit_model::gtfs::read("tests/fixtures/netex_france").unwrap();
 }
+
+fn zones_and_stop_times(model: &Model) -> Vec<(&str, &str, Option<Time>, Option<Time>)> {
+    model
+        .vehicle_journeys
+        .values()
+        .flat_map(|vj| {
+            vj.stop_times.iter().map(move |stop_time| {
+                (
+                    vj.id.as_str(),
+                    model.stop_points[stop_time.stop_point_idx].id.as_str(),
+                    stop_time.start_pickup_drop_off_window,
+                    stop_time.end_pickup_drop_off_window,
+                )
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn gtfs_flex_locations_reading() {
+    let model = transit_model::gtfs::read("tests/fixtures/gtfs_flex_locations").unwrap();
+    let zone = model.stop_points.get("zone:1").unwrap();
+    assert_eq!(StopType::Zone, zone.stop_type);
+    assert_eq!("Zone Nord", zone.name);
+    assert_eq!("Navitia:zone:1", zone.stop_area_id);
+    // The coordinates of a zone are the centroid of its polygon
+    assert_eq!(
+        Coord {
+            lon: 2.5,
+            lat: 48.5
+        },
+        zone.coord
+    );
+    let geometry = model
+        .geometries
+        .get(zone.geometry_id.as_deref().unwrap())
+        .unwrap();
+    assert_eq!(
+        GeoGeometry::Polygon(polygon![
+            (x: 2.0, y: 48.0),
+            (x: 3.0, y: 48.0),
+            (x: 3.0, y: 49.0),
+            (x: 2.0, y: 49.0),
+        ]),
+        geometry.geometry
+    );
+    let comment = model.comments.get("stop:zone:1").unwrap();
+    assert_eq!("Réservation obligatoire", comment.name);
+    // Without name, the zone is named after its id
+    let zone = model.stop_points.get("zone:2").unwrap();
+    assert_eq!("zone:2", zone.name);
+    assert_eq!(
+        Coord {
+            lon: 5.5,
+            lat: 48.5
+        },
+        zone.coord
+    );
+
+    assert_eq!(
+        vec![
+            ("trip:1", "stop:1", None, None),
+            (
+                "trip:1",
+                "zone:1",
+                Some(Time::new(8, 10, 0)),
+                Some(Time::new(9, 0, 0))
+            ),
+            (
+                "trip:2",
+                "zone:1",
+                Some(Time::new(10, 0, 0)),
+                Some(Time::new(12, 0, 0))
+            ),
+            (
+                "trip:2",
+                "zone:2",
+                Some(Time::new(10, 0, 0)),
+                Some(Time::new(12, 0, 0))
+            ),
+        ],
+        zones_and_stop_times(&model)
+    );
+}
+
+#[test]
+fn gtfs_flex_locations_writing() {
+    test_in_tmp_dir(|path| {
+        let model = transit_model::gtfs::read("tests/fixtures/gtfs_flex_locations").unwrap();
+        let expected_stop_times: Vec<_> = zones_and_stop_times(&model)
+            .into_iter()
+            .map(|(vj_id, stop_id, start, end)| {
+                (vj_id.to_string(), stop_id.to_string(), start, end)
+            })
+            .collect();
+        transit_model::gtfs::write(model, path, false).unwrap();
+
+        // The zones are only written as locations
+        let stops = std::fs::read_to_string(path.join("stops.txt")).unwrap();
+        assert!(!stops.contains("zone:"));
+        let stop_times = std::fs::read_to_string(path.join("stop_times.txt")).unwrap();
+        assert!(stop_times.lines().next().unwrap().contains("location_id"));
+
+        let model = transit_model::gtfs::read(path).unwrap();
+        let zone = model.stop_points.get("zone:2").unwrap();
+        assert_eq!(StopType::Zone, zone.stop_type);
+        let geometry = model
+            .geometries
+            .get(zone.geometry_id.as_deref().unwrap())
+            .unwrap();
+        assert!(matches!(geometry.geometry, GeoGeometry::MultiPolygon(_)));
+        assert_eq!(
+            "Réservation obligatoire",
+            model.comments.get("stop:zone:1").unwrap().name
+        );
+        let stop_times: Vec<_> = zones_and_stop_times(&model)
+            .into_iter()
+            .map(|(vj_id, stop_id, start, end)| {
+                (vj_id.to_string(), stop_id.to_string(), start, end)
+            })
+            .collect();
+        assert_eq!(expected_stop_times, stop_times);
+    });
+}
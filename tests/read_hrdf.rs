@@ -0,0 +1,181 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use pretty_assertions::assert_eq;
+use transit_model::{
+    model::Model,
+    objects::{Availability, Coord, Time},
+};
+
+fn read() -> Model {
+    transit_model::hrdf::read("tests/fixtures/hrdf/input").unwrap()
+}
+
+#[test]
+fn stations_and_administrations() {
+    let model = read();
+    assert_eq!(4, model.stop_points.len());
+    let stop_point = model.stop_points.get("8500090").unwrap();
+    assert_eq!("Basel Bad Bf", stop_point.name);
+    assert_eq!(
+        Coord {
+            lon: 7.607369,
+            lat: 47.567290
+        },
+        stop_point.coord
+    );
+    assert_eq!("Navitia:8500090", stop_point.stop_area_id);
+
+    let company = model.companies.get("000011").unwrap();
+    assert_eq!("Schweizerische Bundesbahnen SBB", company.name);
+    let network = model.networks.get("000080").unwrap();
+    assert_eq!("DB Fernverkehr AG", network.name);
+    assert_eq!(Some(chrono_tz::Europe::Zurich), network.timezone);
+
+    let line = model.lines.get("000011:IR:37").unwrap();
+    assert_eq!("IR 37", line.name);
+    assert_eq!("IR", line.commercial_mode_id);
+    assert!(model.routes.contains_id("000080:ICE:backward"));
+}
+
+#[test]
+fn vehicle_journeys_with_bitfields() {
+    let model = read();
+    assert_eq!(3, model.vehicle_journeys.len());
+    let times = |vj_id: &str| -> Vec<_> {
+        model
+            .vehicle_journeys
+            .get(vj_id)
+            .unwrap()
+            .stop_times
+            .iter()
+            .map(|stop_time| {
+                (
+                    stop_time.arrival_time.unwrap(),
+                    stop_time.departure_time.unwrap(),
+                    stop_time.pickup_type,
+                    stop_time.drop_off_type,
+                )
+            })
+            .collect()
+    };
+    let dates = |vj_id: &str| -> Vec<_> {
+        let vj = model.vehicle_journeys.get(vj_id).unwrap();
+        model
+            .calendars
+            .get(&vj.service_id)
+            .unwrap()
+            .dates
+            .iter()
+            .map(|date| date.to_string())
+            .collect()
+    };
+
+    let vj = model.vehicle_journeys.get("000011:002359").unwrap();
+    assert_eq!(Some("2359"), vj.short_name.as_deref());
+    assert_eq!(Some("Basel SBB"), vj.headsign.as_deref());
+    assert_eq!("LongDistanceTrain", vj.physical_mode_id);
+    assert_eq!(
+        vec![
+            (Time::new(6, 2, 0), Time::new(6, 2, 0), 0, 1),
+            (Time::new(6, 7, 0), Time::new(6, 8, 0), 1, 0),
+            (Time::new(7, 10, 0), Time::new(7, 10, 0), 1, 0),
+        ],
+        times("000011:002359")
+    );
+    assert_eq!(
+        vec![
+            "2023-12-10",
+            "2023-12-11",
+            "2023-12-12",
+            "2023-12-13",
+            "2023-12-14",
+            "2023-12-15",
+            "2023-12-16",
+            "2023-12-17",
+        ],
+        dates("000011:002359")
+    );
+
+    // Repetition of the cyclic journey, 60 minutes later
+    assert_eq!(
+        vec![
+            (Time::new(7, 2, 0), Time::new(7, 2, 0), 0, 1),
+            (Time::new(7, 7, 0), Time::new(7, 8, 0), 1, 0),
+            (Time::new(8, 10, 0), Time::new(8, 10, 0), 1, 0),
+        ],
+        times("000011:002359:1")
+    );
+
+    assert_eq!(Time::new(24, 43, 0), times("000080:000271")[2].0);
+    assert_eq!(vec!["2023-12-11", "2023-12-13"], dates("000080:000271"));
+}
+
+#[test]
+fn trip_properties_from_attributes() {
+    let model = read();
+    let trip_property = |vj_id: &str| {
+        let vj = model.vehicle_journeys.get(vj_id).unwrap();
+        model
+            .trip_properties
+            .get(vj.trip_property_id.as_deref().unwrap())
+            .unwrap()
+    };
+    let trip_property_ic = trip_property("000011:002359");
+    assert_eq!(
+        Availability::Available,
+        trip_property_ic.wheelchair_accessible
+    );
+    assert_eq!(Availability::Available, trip_property_ic.air_conditioned);
+    assert_eq!(
+        Availability::InformationNotAvailable,
+        trip_property_ic.bike_accepted
+    );
+    let trip_property_ice = trip_property("000080:000271");
+    assert_eq!(Availability::Available, trip_property_ice.bike_accepted);
+}
+
+#[test]
+fn transfers_from_umsteigb_and_metabhf() {
+    let model = read();
+    let mut transfers: Vec<_> = model
+        .transfers
+        .values()
+        .map(|transfer| {
+            (
+                transfer.from_stop_id.as_str(),
+                transfer.to_stop_id.as_str(),
+                transfer.min_transfer_time,
+            )
+        })
+        .collect();
+    transfers.sort();
+    assert_eq!(
+        vec![
+            ("8500010", "8500010", Some(180)),
+            ("8500010", "8500090", Some(480)),
+            ("8500090", "8500010", Some(480)),
+            ("8500090", "8500090", Some(180)),
+            ("8503000", "8503000", Some(420)),
+            ("8503006", "8503006", Some(180)),
+        ],
+        transfers
+    );
+}
+
+#[test]
+#[should_panic(expected = "neither a file nor a directory, cannot read a hrdf from it")]
+fn unexistent_file() {
+    let _ = transit_model::hrdf::read("tests/fixtures/i_m_not_here").unwrap();
+}
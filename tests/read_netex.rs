@@ -0,0 +1,188 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use pretty_assertions::assert_eq;
+use transit_model::{
+    objects::{Coord, Rgb, Time},
+    Model,
+};
+
+fn read() -> Model {
+    transit_model::netex::read("tests/fixtures/netex/input").unwrap()
+}
+
+#[test]
+fn stop_places_and_quays() {
+    let model = read();
+    assert_eq!(2, model.stop_areas.len());
+    assert_eq!(3, model.stop_points.len());
+    let stop_point = model.stop_points.get("NSR:Quay:11").unwrap();
+    assert_eq!("Jernbanetorget", stop_point.name);
+    assert_eq!(Some("A"), stop_point.code.as_deref());
+    assert_eq!("NSR:StopPlace:1", stop_point.stop_area_id);
+    // Without its own centroid, the quay is at its stop place
+    let stop_point = model.stop_points.get("NSR:Quay:12").unwrap();
+    assert_eq!("Jernbanetorget B", stop_point.name);
+    assert_eq!(
+        Coord {
+            lon: 10.75,
+            lat: 59.91
+        },
+        stop_point.coord
+    );
+    let stop_area = model.stop_areas.get("NSR:StopPlace:2").unwrap();
+    assert_eq!("Storo", stop_area.name);
+}
+
+#[test]
+fn lines_and_routes_across_files() {
+    let model = read();
+    let company = model.companies.get("RUT:Operator:1").unwrap();
+    assert_eq!("Ruter", company.name);
+    // The timezone is given by the frame defaults of another file
+    let network = model.networks.get("RUT:Network:1").unwrap();
+    assert_eq!(Some(chrono_tz::Europe::Oslo), network.timezone);
+    let line = model.lines.get("RUT:Line:31").unwrap();
+    assert_eq!(Some("31"), line.code.as_deref());
+    assert_eq!("Tonsenhagen - Fornebu", line.name);
+    assert_eq!("RUT:Network:1", line.network_id);
+    assert_eq!("Bus", line.commercial_mode_id);
+    assert_eq!(
+        Some(Rgb {
+            red: 230,
+            green: 0,
+            blue: 0
+        }),
+        line.color
+    );
+    let route = model.routes.get("RUT:Route:31-1").unwrap();
+    assert_eq!("Storo", route.name);
+    assert_eq!(Some("forward"), route.direction_type.as_deref());
+    // A route is created for the journey pattern without route
+    let route = model.routes.get("RUT:Line:31:route").unwrap();
+    assert_eq!("RUT:Line:31", route.line_id);
+}
+
+#[test]
+fn service_journeys_with_passing_times() {
+    let model = read();
+    // The service journey without day type is ignored
+    assert_eq!(2, model.vehicle_journeys.len());
+    let stop_times = |vj_id: &str| -> Vec<_> {
+        model
+            .vehicle_journeys
+            .get(vj_id)
+            .unwrap()
+            .stop_times
+            .iter()
+            .map(|stop_time| {
+                (
+                    model.stop_points[stop_time.stop_point_idx].id.as_str(),
+                    stop_time.arrival_time.unwrap(),
+                    stop_time.departure_time.unwrap(),
+                    stop_time.pickup_type,
+                    stop_time.drop_off_type,
+                )
+            })
+            .collect()
+    };
+    let dates = |vj_id: &str| -> Vec<_> {
+        let vj = model.vehicle_journeys.get(vj_id).unwrap();
+        model
+            .calendars
+            .get(&vj.service_id)
+            .unwrap()
+            .dates
+            .iter()
+            .map(|date| date.to_string())
+            .collect()
+    };
+
+    // The passing times are ordered along the journey pattern
+    assert_eq!(
+        vec![
+            (
+                "NSR:Quay:11",
+                Time::new(23, 50, 0),
+                Time::new(23, 50, 0),
+                0,
+                1
+            ),
+            (
+                "NSR:Quay:21",
+                Time::new(24, 5, 0),
+                Time::new(24, 5, 0),
+                1,
+                0
+            ),
+        ],
+        stop_times("RUT:ServiceJourney:1")
+    );
+    // The weekdays of the operating period, except the unavailable one
+    assert_eq!(
+        vec!["2023-12-11", "2023-12-12", "2023-12-14", "2023-12-15"],
+        dates("RUT:ServiceJourney:1")
+    );
+    let vj = model.vehicle_journeys.get("RUT:ServiceJourney:1").unwrap();
+    assert_eq!("RUT:Route:31-1", vj.route_id);
+    assert_eq!(Some("Storo"), vj.headsign.as_deref());
+    assert_eq!(Some("3101"), vj.short_name.as_deref());
+    assert_eq!("RUT:Operator:1", vj.company_id);
+
+    // The passing times without reference are in the order of the journey
+    // pattern, and the dated service journeys complete the day types
+    assert_eq!(
+        vec![
+            ("NSR:Quay:21", Time::new(8, 0, 0), Time::new(8, 0, 0), 0, 1),
+            (
+                "NSR:Quay:12",
+                Time::new(8, 10, 0),
+                Time::new(8, 11, 0),
+                1,
+                0
+            ),
+        ],
+        stop_times("RUT:ServiceJourney:2")
+    );
+    assert_eq!(
+        vec!["2023-12-24", "2023-12-25"],
+        dates("RUT:ServiceJourney:2")
+    );
+    let vj = model.vehicle_journeys.get("RUT:ServiceJourney:2").unwrap();
+    assert_eq!("RUT:Line:31:route", vj.route_id);
+    assert_eq!("RUT:Operator:1", vj.company_id);
+}
+
+#[test]
+fn interchanges() {
+    let model = read();
+    let transfers: Vec<_> = model
+        .transfers
+        .values()
+        .map(|transfer| {
+            (
+                transfer.from_stop_id.as_str(),
+                transfer.to_stop_id.as_str(),
+                transfer.min_transfer_time,
+            )
+        })
+        .collect();
+    assert_eq!(vec![("NSR:Quay:21", "NSR:Quay:11", Some(120))], transfers);
+}
+
+#[test]
+#[should_panic(expected = "neither a file nor a directory, cannot read a netex from it")]
+fn unexistent_file() {
+    let _ = transit_model::netex::read("tests/fixtures/i_m_not_here").unwrap();
+}
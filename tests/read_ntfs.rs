@@ -165,6 +165,42 @@ fn test_minimal_fares_stay_same() {
     });
 }
 
+#[test]
+fn test_fares_v1_are_written_with_fares_v2() {
+    let ntm = transit_model::ntfs::read("tests/fixtures/ntfs2ntfs/fares").unwrap();
+    test_in_tmp_dir(|output_dir| {
+        transit_model::ntfs::write(&ntm, output_dir, get_test_datetime()).unwrap();
+        for file in [
+            "prices.csv",
+            "od_fares.csv",
+            "fares.csv",
+            "tickets.txt",
+            "ticket_prices.txt",
+        ] {
+            assert!(output_dir.join(file).exists(), "{file} is not written");
+        }
+    });
+}
+
+#[test]
+fn test_fares_v1_are_written_from_fares_v2() {
+    let ntm = transit_model::ntfs::read("tests/fixtures/ntfs").unwrap();
+    test_in_tmp_dir(|output_dir| {
+        transit_model::ntfs::write(&ntm, output_dir, get_test_datetime()).unwrap();
+        for file in [
+            "tickets.txt",
+            "ticket_uses.txt",
+            "ticket_prices.txt",
+            "ticket_use_perimeters.txt",
+            "prices.csv",
+            "od_fares.csv",
+            "fares.csv",
+        ] {
+            assert!(output_dir.join(file).exists(), "{file} is not written");
+        }
+    });
+}
+
 #[test]
 fn test_minimal_platforms_stay_same() {
     let ntm = transit_model::ntfs::read("tests/fixtures/ntfs2ntfs/platforms").unwrap();
@@ -267,6 +303,9 @@ fn ntfs() {
             phone: Some(String::from("01 02 03 04 03")),
             message: None,
             booking_url: None,
+            booking_type: Some(BookingType::SameDayWithPriorNotice),
+            prior_notice_duration_min: Some(30),
+            ..Default::default()
         }
     );
 
@@ -302,6 +341,12 @@ fn ntfs() {
             booking_url: Some(String::from(
                 "https://deeplink1/search?departure-address={from_name}"
             )),
+            booking_type: Some(BookingType::UpToPreviousDays),
+            prior_notice_last_day: Some(1),
+            prior_notice_last_time: Some(Time::new(17, 0, 0)),
+            prior_notice_start_day: Some(7),
+            prior_notice_start_time: Some(Time::new(8, 0, 0)),
+            ..Default::default()
         }
     );
 
@@ -323,6 +368,7 @@ fn ntfs() {
             phone: None,
             message: None,
             booking_url: None,
+            ..Default::default()
         }
     );
 }
@@ -0,0 +1,187 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use pretty_assertions::assert_eq;
+use transit_model::{
+    objects::{Coord, Time},
+    transxchange::{Configuration, Reader},
+    Model,
+};
+
+fn read_with_naptan() -> Model {
+    let configuration = Configuration {
+        naptan: Some("tests/fixtures/transxchange/naptan/Stops.csv".into()),
+        ..Default::default()
+    };
+    Reader::new(configuration)
+        .parse("tests/fixtures/transxchange/input")
+        .unwrap()
+}
+
+#[test]
+fn operators_and_lines() {
+    let model = read_with_naptan();
+    let company = model.companies.get("FBRI").unwrap();
+    assert_eq!("First West of England", company.name);
+    let network = model.networks.get("FBRI").unwrap();
+    assert_eq!(Some(chrono_tz::Europe::London), network.timezone);
+    let line = model.lines.get("PB0000001:1:SL1").unwrap();
+    assert_eq!(Some("1"), line.code.as_deref());
+    assert_eq!("FBRI", line.network_id);
+    assert_eq!("Bus", line.commercial_mode_id);
+    let route = model.routes.get("PB0000001:1:SL1:forward").unwrap();
+    assert_eq!("Temple Meads - Bath Road", route.name);
+}
+
+#[test]
+fn stop_points_with_naptan_codes() {
+    let model = read_with_naptan();
+    let stop_point = model.stop_points.get("0100BRP90310").unwrap();
+    assert_eq!("Temple Meads", stop_point.name);
+    assert_eq!(
+        Coord {
+            lon: -2.5823,
+            lat: 51.4493
+        },
+        stop_point.coord
+    );
+    assert!(stop_point
+        .codes
+        .contains(&("naptan_code".to_string(), "bstjawa".to_string())));
+    // Completed from the NaPTAN stops
+    let stop_point = model.stop_points.get("0100BRP90340").unwrap();
+    assert_eq!("Redcliffe Hill", stop_point.name);
+    assert_eq!(
+        Coord {
+            lon: -2.5905,
+            lat: 51.4451
+        },
+        stop_point.coord
+    );
+    assert_eq!("Navitia:0100BRP90340", stop_point.stop_area_id);
+}
+
+#[test]
+fn vehicle_journeys_with_operating_profiles() {
+    let model = read_with_naptan();
+    let times_and_dates = |vj_id: &str| {
+        let vj = model.vehicle_journeys.get(vj_id).unwrap();
+        let times: Vec<_> = vj
+            .stop_times
+            .iter()
+            .map(|stop_time| {
+                (
+                    stop_time.arrival_time.unwrap(),
+                    stop_time.departure_time.unwrap(),
+                )
+            })
+            .collect();
+        let dates: Vec<_> = model
+            .calendars
+            .get(&vj.service_id)
+            .unwrap()
+            .dates
+            .iter()
+            .map(|date| date.to_string())
+            .collect();
+        (times, dates)
+    };
+
+    // 2021-12-27, 2021-12-28, 2022-01-03 and 2022-01-04 are bank holidays
+    let (times, dates) = times_and_dates("PB0000001:1:VJ1");
+    assert_eq!(
+        vec![
+            (Time::new(8, 0, 0), Time::new(8, 0, 0)),
+            (Time::new(8, 5, 0), Time::new(8, 6, 0)),
+            (Time::new(8, 18, 0), Time::new(8, 18, 0)),
+        ],
+        times
+    );
+    assert_eq!(
+        vec![
+            "2021-12-20",
+            "2021-12-21",
+            "2021-12-22",
+            "2021-12-23",
+            "2021-12-24",
+            "2021-12-29",
+            "2021-12-30",
+            "2021-12-31",
+            "2022-01-05",
+            "2022-01-06",
+            "2022-01-07",
+        ],
+        dates
+    );
+
+    // Same journey pattern as VJ1, after midnight
+    let (times, dates) = times_and_dates("PB0000001:1:VJ2");
+    assert_eq!(
+        vec![
+            (Time::new(23, 55, 0), Time::new(23, 55, 0)),
+            (Time::new(24, 0, 0), Time::new(24, 1, 0)),
+            (Time::new(24, 11, 0), Time::new(24, 11, 0)),
+        ],
+        times
+    );
+    assert_eq!(vec!["2021-12-25", "2022-01-01", "2022-01-08"], dates);
+
+    let vj = model.vehicle_journeys.get("PB0000001:1:VJ1").unwrap();
+    let pickup_drop_off_types: Vec<_> = vj
+        .stop_times
+        .iter()
+        .map(|stop_time| (stop_time.pickup_type, stop_time.drop_off_type))
+        .collect();
+    assert_eq!(vec![(0, 1), (0, 0), (1, 0)], pickup_drop_off_types);
+}
+
+#[test]
+fn service_split_across_documents() {
+    let input_dir = tempfile::tempdir().unwrap();
+    for file_name in ["PB0000001_1.xml", "PB0000001_2.xml"] {
+        std::fs::copy(
+            "tests/fixtures/transxchange/input/PB0000001_1.xml",
+            input_dir.path().join(file_name),
+        )
+        .unwrap();
+    }
+    let configuration = Configuration {
+        naptan: Some("tests/fixtures/transxchange/naptan/Stops.csv".into()),
+        ..Default::default()
+    };
+    let model = Reader::new(configuration).parse(input_dir.path()).unwrap();
+    assert_eq!(1, model.lines.len());
+    // The vehicle journeys with the same code are all kept
+    let mut vj_ids: Vec<_> = model
+        .vehicle_journeys
+        .values()
+        .map(|vj| vj.id.as_str())
+        .collect();
+    vj_ids.sort_unstable();
+    assert_eq!(
+        vec![
+            "PB0000001:1:VJ1",
+            "PB0000001:1:VJ1:2",
+            "PB0000001:1:VJ2",
+            "PB0000001:1:VJ2:2"
+        ],
+        vj_ids
+    );
+}
+
+#[test]
+#[should_panic(expected = "neither a file nor a directory, cannot read a transxchange from it")]
+fn unexistent_file() {
+    let _ = transit_model::transxchange::read("tests/fixtures/i_m_not_here").unwrap();
+}
@@ -0,0 +1,131 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use approx::assert_relative_eq;
+use pretty_assertions::assert_eq;
+use transit_model::{model::Model, objects::Time};
+
+fn read() -> Model {
+    transit_model::vdv452::read("tests/fixtures/vdv452/input").unwrap()
+}
+
+#[test]
+fn stop_points_and_stop_areas() {
+    let model = read();
+    // The point of type 5 (route point) is not a stop point
+    assert_eq!(3, model.stop_points.len());
+    let stop_point = model.stop_points.get("101").unwrap();
+    assert_eq!("Hauptbahnhof Steig A", stop_point.name);
+    assert_eq!(Some("HBF"), stop_point.code.as_deref());
+    assert_eq!("stop_area:10", stop_point.stop_area_id);
+    // 13° 54' 32.100" and 52° 30' 12.000"
+    assert_relative_eq!(13.908_916_7, stop_point.coord.lon, epsilon = 1e-7);
+    assert_relative_eq!(52.503_333_3, stop_point.coord.lat, epsilon = 1e-7);
+    let stop_area = model.stop_areas.get("stop_area:10").unwrap();
+    assert_eq!("Hauptbahnhof", stop_area.name);
+    assert_eq!(
+        "Navitia:103",
+        model.stop_points.get("103").unwrap().stop_area_id
+    );
+}
+
+#[test]
+fn lines_routes_and_company() {
+    let model = read();
+    let company = model.companies.get("7").unwrap();
+    assert_eq!("Verkehrsbetriebe X", company.name);
+    let network = model.networks.get("7").unwrap();
+    assert_eq!(Some(chrono_tz::Europe::Berlin), network.timezone);
+    let line = model.lines.get("42").unwrap();
+    assert_eq!(Some("42"), line.code.as_deref());
+    assert_eq!("7", line.network_id);
+    let route = model.routes.get("42:2").unwrap();
+    assert_eq!("Flughafen - Hauptbahnhof", route.name);
+    assert_eq!(Some("backward"), route.direction_type.as_deref());
+}
+
+#[test]
+fn trips_with_run_and_dwell_times() {
+    let model = read();
+    // The trip 9999 of the previous basis version is ignored
+    assert_eq!(3, model.vehicle_journeys.len());
+    let times = |vj_id: &str| -> Vec<_> {
+        model
+            .vehicle_journeys
+            .get(vj_id)
+            .unwrap()
+            .stop_times
+            .iter()
+            .map(|stop_time| {
+                (
+                    model.stop_points[stop_time.stop_point_idx].id.as_str(),
+                    stop_time.arrival_time.unwrap(),
+                    stop_time.departure_time.unwrap(),
+                )
+            })
+            .collect()
+    };
+    let dates = |vj_id: &str| -> Vec<_> {
+        let vj = model.vehicle_journeys.get(vj_id).unwrap();
+        model
+            .calendars
+            .get(&vj.service_id)
+            .unwrap()
+            .dates
+            .iter()
+            .map(|date| date.to_string())
+            .collect()
+    };
+
+    // Through the route point 900, with the dwell time of the trip at 102
+    assert_eq!(
+        vec![
+            ("101", Time::new(7, 0, 0), Time::new(7, 0, 0)),
+            ("102", Time::new(7, 3, 0), Time::new(7, 4, 0)),
+            ("103", Time::new(7, 9, 0), Time::new(7, 9, 0)),
+        ],
+        times("1001")
+    );
+    assert_eq!(vec!["2023-12-11", "2023-12-12"], dates("1001"));
+    let vj = model.vehicle_journeys.get("1001").unwrap();
+    assert_eq!(Some("Flughafen"), vj.headsign.as_deref());
+    assert_eq!("7", vj.company_id);
+
+    // Another time group, after midnight
+    assert_eq!(
+        vec![
+            ("101", Time::new(23, 55, 0), Time::new(23, 55, 0)),
+            ("102", Time::new(23, 59, 0), Time::new(23, 59, 0)),
+            ("103", Time::new(24, 5, 0), Time::new(24, 5, 0)),
+        ],
+        times("1002")
+    );
+    assert_eq!(vec!["2023-12-16"], dates("1002"));
+
+    // With the dwell time of the time group at 102
+    assert_eq!(
+        vec![
+            ("103", Time::new(8, 0, 0), Time::new(8, 0, 0)),
+            ("102", Time::new(8, 5, 0), Time::new(8, 5, 30)),
+            ("101", Time::new(8, 9, 30), Time::new(8, 9, 30)),
+        ],
+        times("1003")
+    );
+}
+
+#[test]
+#[should_panic(expected = "neither a file nor a directory, cannot read a vdv452 from it")]
+fn unexistent_file() {
+    let _ = transit_model::vdv452::read("tests/fixtures/i_m_not_here").unwrap();
+}
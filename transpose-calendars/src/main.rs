@@ -0,0 +1,131 @@
+// Copyright 2020 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see
+// <http://www.gnu.org/licenses/>.
+
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use clap::Parser;
+use std::path::PathBuf;
+use tracing::info;
+use tracing_subscriber::{
+    filter::{EnvFilter, LevelFilter},
+    layer::SubscriberExt as _,
+    util::SubscriberInitExt as _,
+};
+use transit_model::{
+    objects::ValidityPeriod,
+    transposition::{self, Transposition},
+    Model, Result,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "transpose-calendars",
+    about = "Transpose the calendars of a NTFS onto another period.",
+    rename_all = "kebab-case"
+)]
+struct Opt {
+    /// input directory.
+    #[arg(short, long, default_value = ".")]
+    input: PathBuf,
+
+    /// start of the period to transpose [included], e.g. 2019-09-02
+    #[arg(short, long)]
+    start_date: NaiveDate,
+
+    /// end of the period to transpose [included], e.g. 2020-07-04
+    #[arg(short, long)]
+    end_date: NaiveDate,
+
+    /// start of the target period, e.g. 2020-09-01 (the dates are shifted by
+    /// whole weeks to keep their weekdays)
+    #[arg(short, long)]
+    target_start_date: NaiveDate,
+
+    /// CSV file of exceptions (e.g. holidays), with the columns `source_date`
+    /// and `target_date`, an empty `target_date` removing the `source_date`
+    #[arg(long)]
+    exceptions: Option<PathBuf>,
+
+    /// output directory
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// current datetime
+    #[arg(
+        short = 'x',
+        long,
+        default_value = &**transit_model::CURRENT_DATETIME
+    )]
+    current_datetime: DateTime<FixedOffset>,
+}
+
+fn init_logger() {
+    let default_level = LevelFilter::INFO;
+    let rust_log =
+        std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| default_level.to_string());
+    let env_filter_subscriber = EnvFilter::try_new(rust_log).unwrap_or_else(|e| {
+        eprintln!(
+            "invalid {}, falling back to level '{}' - {}",
+            EnvFilter::DEFAULT_ENV,
+            default_level,
+            e,
+        );
+        EnvFilter::new(default_level.to_string())
+    });
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer())
+        .with(env_filter_subscriber)
+        .init();
+}
+
+fn run(opt: Opt) -> Result<()> {
+    info!("Launching transpose-calendars...");
+
+    let source = ValidityPeriod {
+        start_date: opt.start_date,
+        end_date: opt.end_date,
+    };
+    let mut transposition = Transposition::new(source, opt.target_start_date)?;
+    if let Some(exceptions_path) = opt.exceptions {
+        transposition =
+            transposition.with_exceptions(transposition::read_exceptions(exceptions_path)?);
+    }
+
+    let model = transit_model::ntfs::read(opt.input)?;
+    let mut collections = model.into_collections();
+    collections.transpose_calendars(&transposition);
+    let model = Model::new(collections)?;
+
+    match opt.output.extension() {
+        Some(ext) if ext == "zip" => {
+            transit_model::ntfs::write_to_zip(&model, opt.output, opt.current_datetime)?;
+        }
+        _ => {
+            transit_model::ntfs::write(&model, opt.output, opt.current_datetime)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    init_logger();
+    if let Err(err) = run(Opt::parse()) {
+        for cause in err.chain() {
+            eprintln!("{cause}");
+        }
+        std::process::exit(1);
+    }
+}
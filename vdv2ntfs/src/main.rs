@@ -0,0 +1,169 @@
+// Copyright 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see
+// <http://www.gnu.org/licenses/>.
+
+use chrono::{DateTime, FixedOffset};
+use clap::Parser;
+use std::path::PathBuf;
+use tracing::info;
+use tracing_subscriber::{
+    filter::{EnvFilter, LevelFilter},
+    layer::SubscriberExt as _,
+    util::SubscriberInitExt as _,
+};
+use transit_model::{
+    configuration,
+    transfers::{generates_transfers, TransfersConfiguration},
+    PrefixConfiguration, Result,
+};
+
+lazy_static::lazy_static! {
+    pub static ref GIT_VERSION: String = transit_model::binary_full_version(env!("CARGO_PKG_VERSION"));
+}
+
+fn get_version() -> &'static str {
+    &GIT_VERSION
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "vdv2ntfs", about = "Convert a VDV 452 to an NTFS.", version = get_version())]
+struct Opt {
+    /// Input directory or zip archive.
+    #[arg(short, long, default_value = ".")]
+    input: PathBuf,
+
+    /// Output directory.
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// JSON file containing additional configuration.
+    ///
+    /// For more information, see
+    /// https://github.com/hove-io/transit_model/blob/master/documentation/common_ntfs_rules.md#configuration-of-each-converter
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Prefix added to all the identifiers (`123` turned into `prefix:123`).
+    #[arg(short, long)]
+    prefix: Option<String>,
+
+    /// Schedule subprefix added after the prefix on all scheduled objects (`123` turned into `prefix::schedule_subprefix::123`).
+    #[arg(long)]
+    schedule_subprefix: Option<String>,
+
+    /// Current datetime.
+    #[arg(
+        short = 'x',
+        long,
+        default_value = &**transit_model::CURRENT_DATETIME
+    )]
+    current_datetime: DateTime<FixedOffset>,
+
+    /// Maximum total walking distance in meters to consider generating a transfer.
+    /// This includes both open-air segments (crow-fly × manhattan_factor) and
+    /// indoor pathway segments (like entrances).
+    #[arg(long, short = 'd', default_value_t = transit_model::TRANSFER_MAX_DISTANCE)]
+    max_distance: f64,
+
+    /// The walking speed in meters per second.
+    #[arg(long, short = 's', default_value_t = transit_model::TRANSFER_WALKING_SPEED)]
+    walking_speed: f64,
+
+    /// Waiting time at stop in seconds.
+    #[arg(long, short = 't', default_value_t = transit_model::TRANSFER_WAITING_TIME)]
+    waiting_time: u32,
+
+    /// Factor applied to the euclidean distance to compute the manhattan distance.
+    #[arg(long, default_value_t = transit_model::TRANSFER_MANHATTAN_FACTOR)]
+    manhattan_factor: f64,
+
+    /// Don't compute transfers even the transfers of the stop point to itself (max_distance = 0.0)
+    #[arg(long)]
+    ignore_transfers: bool,
+}
+
+fn run(opt: Opt) -> Result<()> {
+    info!("Launching vdv2ntfs...");
+
+    let (contributor, dataset, feed_infos) = configuration::read_config(opt.config)?;
+    let mut prefix_conf = PrefixConfiguration::default();
+    if let Some(data_prefix) = opt.prefix {
+        prefix_conf.set_data_prefix(data_prefix);
+    }
+    if let Some(schedule_subprefix) = opt.schedule_subprefix {
+        prefix_conf.set_schedule_subprefix(schedule_subprefix);
+    }
+    let configuration = transit_model::vdv452::Configuration {
+        contributor,
+        dataset,
+        feed_infos,
+        prefix_conf: Some(prefix_conf),
+    };
+
+    let model = transit_model::vdv452::Reader::new(configuration).parse(opt.input)?;
+
+    let model = if opt.ignore_transfers {
+        model
+    } else {
+        let config = TransfersConfiguration {
+            max_distance: opt.max_distance,
+            walking_speed: opt.walking_speed,
+            waiting_time: opt.waiting_time,
+            manhattan_factor: opt.manhattan_factor,
+            ..Default::default()
+        };
+        let collections = generates_transfers(model, config, None)?;
+        transit_model::Model::new(collections)?
+    };
+
+    match opt.output.extension() {
+        Some(ext) if ext == "zip" => {
+            transit_model::ntfs::write_to_zip(&model, opt.output, opt.current_datetime)?;
+        }
+        _ => {
+            transit_model::ntfs::write(&model, opt.output, opt.current_datetime)?;
+        }
+    };
+    Ok(())
+}
+
+fn init_logger() {
+    let default_level = LevelFilter::INFO;
+    let rust_log =
+        std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| default_level.to_string());
+    let env_filter_subscriber = EnvFilter::try_new(rust_log).unwrap_or_else(|e| {
+        eprintln!(
+            "invalid {}, falling back to level '{}' - {}",
+            EnvFilter::DEFAULT_ENV,
+            default_level,
+            e,
+        );
+        EnvFilter::new(default_level.to_string())
+    });
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer())
+        .with(env_filter_subscriber)
+        .init();
+}
+
+fn main() {
+    init_logger();
+    if let Err(err) = run(Opt::parse()) {
+        for cause in err.chain() {
+            eprintln!("{cause}");
+        }
+        std::process::exit(1);
+    }
+}